@@ -0,0 +1,841 @@
+//! 标记认证与加密
+//!
+//! 两种保护方式，都以标记后附带的 key ID 选择密钥:
+//!
+//! ```text
+//! hmac: 标记编码 || key_id(1) || mac(N)                       flags 置位 FLAG_AUTHENTICATED
+//! aead: 标记编码 || key_id(1) || 密文 tag(2) || poly1305(16)  flags 置位 FLAG_ENCRYPTED
+//! ```
+//!
+//! HMAC 为截断的 HMAC-SHA256，覆盖标记编码、key ID 以及转发过程中不变的首部字段
+//! (源 / 目的地址、协议号、端口)，防止标记被伪造或搬到别的流上。截断长度由
+//! `BIAOSHI_MAC_LEN` 配置，收发两端必须一致；接收端拒绝其他长度的 MAC，攻击者
+//! 无法改发更短的 MAC 来降低伪造难度。
+//!
+//! AEAD 为 ChaCha20-Poly1305，只加密 tag (明文中的 tag 字段置 0)，其余标记字段、
+//! key ID 和首部字段作为关联数据。nonce 取 `sequence(4) || timestamp(8)`，不额外占用
+//! 选项空间；同一密钥下发送端不会在同一微秒内重复序号，nonce 不会重复。
+//! 加密后的标记共 35 字节，可以放进 IPv4 选项 (37 字节) 和 TCP 实验选项 (39 字节)。
+//!
+//! 不认识保护数据的旧接收端仍能解码标记本身。要求 hmac 或 aead 时发送端必须有
+//! 活动密钥，否则报错而不是退回明文，以免 tag 在线路上可读。
+
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr};
+
+use chacha20poly1305::aead::{AeadInPlace, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Nonce, Tag};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::keyring::{KeyError, KeyRing};
+use crate::marker::{self, FLAG_AUTHENTICATED, FLAG_ENCRYPTED, Marker, MarkerError};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// 默认的 MAC 截断长度
+pub const DEFAULT_MAC_LEN: usize = 8;
+/// 允许的最短 MAC
+pub const MIN_MAC_LEN: usize = 4;
+/// 允许的最长 MAC
+pub const MAX_MAC_LEN: usize = 16;
+/// Poly1305 认证标签长度
+pub const AEAD_TAG_LEN: usize = 16;
+
+/// 选择标记保护方式的环境变量名
+pub const PROTECTION_ENV: &str = "BIAOSHI_PROTECTION";
+/// MAC 截断长度的环境变量名
+pub const MAC_LEN_ENV: &str = "BIAOSHI_MAC_LEN";
+
+/// 派生 AEAD 密钥时使用的标签，使同一密钥用于 HMAC 和 AEAD 时互不相关
+const AEAD_KEY_LABEL: &[u8] = b"biaoshi marker aead";
+
+/// MAC 绑定的首部字段
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AuthContext {
+    pub source: IpAddr,
+    pub destination: IpAddr,
+    pub protocol: u8,
+    /// 非 TCP / UDP 时为 0
+    pub source_port: u16,
+    pub destination_port: u16,
+}
+
+impl AuthContext {
+    /// 从完整的 IPv4 数据包中提取首部字段
+    ///
+    /// 分片包只有第一个分片带端口，其余分片端口记为 0。
+    pub fn from_ipv4(packet: &[u8]) -> Option<Self> {
+        if packet.len() < 20 {
+            return None;
+        }
+        let header_len = (packet[0] & 0x0f) as usize * 4;
+        let protocol = packet[9];
+        let fragment_offset = u16::from_be_bytes([packet[6], packet[7]]) & 0x1fff;
+        let ports = match protocol {
+            6 | 17 if fragment_offset == 0 => packet.get(header_len..header_len + 4),
+            _ => None,
+        };
+        let (source_port, destination_port) = ports
+            .map(|p| {
+                (
+                    u16::from_be_bytes([p[0], p[1]]),
+                    u16::from_be_bytes([p[2], p[3]]),
+                )
+            })
+            .unwrap_or_default();
+        Some(AuthContext {
+            source: Ipv4Addr::new(packet[12], packet[13], packet[14], packet[15]).into(),
+            destination: Ipv4Addr::new(packet[16], packet[17], packet[18], packet[19]).into(),
+            protocol,
+            source_port,
+            destination_port,
+        })
+    }
+
+    fn write_to(&self, out: &mut Vec<u8>) {
+        for addr in [self.source, self.destination] {
+            match addr {
+                IpAddr::V4(v4) => out.extend_from_slice(&v4.octets()),
+                IpAddr::V6(v6) => out.extend_from_slice(&v6.octets()),
+            }
+        }
+        out.push(self.protocol);
+        out.extend_from_slice(&self.source_port.to_be_bytes());
+        out.extend_from_slice(&self.destination_port.to_be_bytes());
+    }
+}
+
+/// 发送端对标记的保护方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Protection {
+    /// 不附带任何保护数据
+    Plain,
+    /// 截断的 HMAC-SHA256
+    #[default]
+    Hmac,
+    /// ChaCha20-Poly1305 加密 tag
+    Aead,
+}
+
+impl Protection {
+    /// 按名称解析 (`plain` / `hmac` / `aead`)
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "plain" => Some(Protection::Plain),
+            "hmac" => Some(Protection::Hmac),
+            "aead" => Some(Protection::Aead),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Protection::Plain => "plain",
+            Protection::Hmac => "hmac",
+            Protection::Aead => "aead",
+        }
+    }
+}
+
+/// 认证失败的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthFailure {
+    /// 认证数据不完整
+    Truncated,
+    /// 本端没有该 key ID 的密钥
+    UnknownKey,
+    /// MAC 长度与配置不符
+    BadMacLen(usize),
+    /// MAC 不匹配
+    BadMac,
+    /// AEAD 解密 / 认证失败
+    DecryptFailed,
+}
+
+impl fmt::Display for AuthFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthFailure::Truncated => write!(f, "认证数据不完整"),
+            AuthFailure::UnknownKey => write!(f, "未知密钥"),
+            AuthFailure::BadMacLen(len) => write!(f, "MAC 长度 {} 字节与配置不符", len),
+            AuthFailure::BadMac => write!(f, "MAC 不匹配"),
+            AuthFailure::DecryptFailed => write!(f, "解密失败"),
+        }
+    }
+}
+
+/// 接收端对标记的认证结论
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthStatus {
+    /// `encrypted` 表示 tag 是解密得到的
+    Valid { key_id: u8, encrypted: bool },
+    Invalid {
+        key_id: Option<u8>,
+        reason: AuthFailure,
+    },
+    /// 标记未携带认证数据
+    Unauthenticated,
+}
+
+impl AuthStatus {
+    pub fn is_valid(&self) -> bool {
+        matches!(self, AuthStatus::Valid { .. })
+    }
+
+    /// 简短的英文标识，用于日志或机器可读输出
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuthStatus::Valid {
+                encrypted: false, ..
+            } => "valid",
+            AuthStatus::Valid {
+                encrypted: true, ..
+            } => "decrypted",
+            AuthStatus::Invalid { .. } => "invalid",
+            AuthStatus::Unauthenticated => "unauthenticated",
+        }
+    }
+}
+
+impl fmt::Display for AuthStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthStatus::Valid {
+                key_id,
+                encrypted: false,
+            } => write!(f, "认证通过 (key {})", key_id),
+            AuthStatus::Valid {
+                key_id,
+                encrypted: true,
+            } => write!(f, "解密通过 (key {})", key_id),
+            AuthStatus::Invalid {
+                key_id: Some(key_id),
+                reason,
+            } => write!(f, "认证失败 (key {}): {}", key_id, reason),
+            AuthStatus::Invalid {
+                key_id: None,
+                reason,
+            } => write!(f, "认证失败: {}", reason),
+            AuthStatus::Unauthenticated => write!(f, "未认证"),
+        }
+    }
+}
+
+/// 受保护的数据：标记编码 || key_id || 首部字段
+fn protected_data(encoded: &[u8], key_id: u8, ctx: &AuthContext) -> Vec<u8> {
+    let mut data = encoded.to_vec();
+    data.push(key_id);
+    ctx.write_to(&mut data);
+    data
+}
+
+/// 计算完整的 HMAC-SHA256
+fn compute_mac(secret: &[u8], encoded: &[u8], key_id: u8, ctx: &AuthContext) -> HmacSha256 {
+    let mut mac = <HmacSha256 as Mac>::new_from_slice(secret).expect("HMAC 接受任意长度的密钥");
+    mac.update(&protected_data(encoded, key_id, ctx));
+    mac
+}
+
+/// 由任意长度的密钥派生 ChaCha20-Poly1305 实例
+fn aead_cipher(secret: &[u8]) -> ChaCha20Poly1305 {
+    let mut kdf = <HmacSha256 as Mac>::new_from_slice(secret).expect("HMAC 接受任意长度的密钥");
+    kdf.update(AEAD_KEY_LABEL);
+    ChaCha20Poly1305::new(&kdf.finalize().into_bytes())
+}
+
+/// nonce = sequence(4) || timestamp(8)
+fn aead_nonce(marker: &Marker) -> Nonce {
+    let mut nonce = Nonce::default();
+    nonce[..4].copy_from_slice(&marker.sequence.to_be_bytes());
+    nonce[4..].copy_from_slice(&marker.timestamp.to_be_bytes());
+    nonce
+}
+
+/// 生成带认证数据的标记载荷
+///
+/// # 参数
+/// - `mac_len`: MAC 截断长度，限制在 [`MIN_MAC_LEN`] ~ [`MAX_MAC_LEN`] 之间
+pub fn sign(
+    marker: &Marker,
+    ctx: &AuthContext,
+    key_id: u8,
+    secret: &[u8],
+    mac_len: usize,
+) -> Vec<u8> {
+    let mac_len = mac_len.clamp(MIN_MAC_LEN, MAX_MAC_LEN);
+    let mut marker = *marker;
+    marker.flags |= FLAG_AUTHENTICATED;
+    let mut out = marker.encode();
+    let tag = compute_mac(secret, &out, key_id, ctx)
+        .finalize()
+        .into_bytes();
+    out.push(key_id);
+    out.extend_from_slice(&tag[..mac_len]);
+    out
+}
+
+/// 生成 tag 加密的标记载荷
+pub fn encrypt(marker: &Marker, ctx: &AuthContext, key_id: u8, secret: &[u8]) -> Vec<u8> {
+    let mut clear = *marker;
+    clear.flags |= FLAG_ENCRYPTED;
+    clear.tag = 0;
+    let mut out = clear.encode();
+    let aad = protected_data(&out, key_id, ctx);
+    let mut tag = marker.tag.to_be_bytes();
+    let auth_tag = aead_cipher(secret)
+        .encrypt_in_place_detached(&aead_nonce(marker), &aad, &mut tag)
+        .expect("2 字节明文不会超出 ChaCha20 的长度上限");
+    out.push(key_id);
+    out.extend_from_slice(&tag);
+    out.extend_from_slice(&auth_tag);
+    out
+}
+
+/// 解码标记载荷并验证认证数据
+///
+/// # 参数
+/// - `mac_len`: 发送端使用的 MAC 截断长度，限制方式同 [`sign`]；其他长度的 MAC 无效
+///
+/// # 返回
+/// 标记本身无法解码时返回错误；否则返回标记和认证结论。
+/// 加密的标记解密成功时 `tag` 为明文，失败时为 0。
+pub fn verify(
+    payload: &[u8],
+    ctx: &AuthContext,
+    ring: &KeyRing,
+    mac_len: usize,
+) -> Result<(Marker, AuthStatus), MarkerError> {
+    let mut marker = Marker::decode(payload)?;
+    let encrypted = marker.flags & FLAG_ENCRYPTED != 0;
+    if !encrypted && marker.flags & FLAG_AUTHENTICATED == 0 {
+        return Ok((marker, AuthStatus::Unauthenticated));
+    }
+
+    let marker_len = marker::wire_len(marker.version).unwrap_or(payload.len());
+    let (encoded, trailer) = payload.split_at(marker_len);
+    let Some((&key_id, rest)) = trailer.split_first() else {
+        return Ok((
+            marker,
+            AuthStatus::Invalid {
+                key_id: None,
+                reason: AuthFailure::Truncated,
+            },
+        ));
+    };
+    let invalid = |reason| AuthStatus::Invalid {
+        key_id: Some(key_id),
+        reason,
+    };
+    if encrypted && rest.len() != 2 + AEAD_TAG_LEN {
+        return Ok((marker, invalid(AuthFailure::Truncated)));
+    }
+    if !encrypted && rest.len() != mac_len.clamp(MIN_MAC_LEN, MAX_MAC_LEN) {
+        return Ok((marker, invalid(AuthFailure::BadMacLen(rest.len()))));
+    }
+    let Some(secret) = ring.get(key_id) else {
+        return Ok((marker, invalid(AuthFailure::UnknownKey)));
+    };
+
+    let status = if encrypted {
+        let aad = protected_data(encoded, key_id, ctx);
+        let mut tag = [rest[0], rest[1]];
+        let auth_tag = Tag::from_slice(&rest[2..]);
+        match aead_cipher(secret).decrypt_in_place_detached(
+            &aead_nonce(&marker),
+            &aad,
+            &mut tag,
+            auth_tag,
+        ) {
+            Ok(()) => {
+                marker.tag = u16::from_be_bytes(tag);
+                AuthStatus::Valid {
+                    key_id,
+                    encrypted: true,
+                }
+            }
+            Err(_) => invalid(AuthFailure::DecryptFailed),
+        }
+    } else {
+        match compute_mac(secret, encoded, key_id, ctx).verify_truncated_left(rest) {
+            Ok(()) => AuthStatus::Valid {
+                key_id,
+                encrypted: false,
+            },
+            Err(_) => invalid(AuthFailure::BadMac),
+        }
+    };
+    Ok((marker, status))
+}
+
+/// 密钥环加保护方式，发送端和接收端共用
+#[derive(Debug, Clone)]
+pub struct MarkerCodec {
+    pub keys: KeyRing,
+    pub protection: Protection,
+    /// HMAC 截断长度，收发两端一致
+    pub mac_len: usize,
+}
+
+impl Default for MarkerCodec {
+    fn default() -> Self {
+        MarkerCodec {
+            keys: KeyRing::default(),
+            protection: Protection::default(),
+            mac_len: DEFAULT_MAC_LEN,
+        }
+    }
+}
+
+impl MarkerCodec {
+    /// 从环境变量读取密钥 (见 [`KeyRing::from_env`])、保护方式
+    /// (`BIAOSHI_PROTECTION`，配置了密钥时默认 hmac，否则默认 plain) 和 MAC 截断长度
+    /// (`BIAOSHI_MAC_LEN`，默认 8)
+    pub fn from_env() -> Result<Self, KeyError> {
+        Self::from_lookup(|name| std::env::var(name).ok())
+    }
+
+    /// 同 [`MarkerCodec::from_env`]，变量值由 `lookup` 提供
+    pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Result<Self, KeyError> {
+        let keys = KeyRing::from_lookup(&lookup)?;
+        let protection = match lookup(PROTECTION_ENV) {
+            Some(name) => Protection::from_name(&name).ok_or(KeyError::BadProtection(name))?,
+            None if keys.is_empty() => Protection::Plain,
+            None => Protection::default(),
+        };
+        let mac_len = match lookup(MAC_LEN_ENV) {
+            Some(value) => value
+                .parse()
+                .ok()
+                .filter(|len| (MIN_MAC_LEN..=MAX_MAC_LEN).contains(len))
+                .ok_or(KeyError::BadMacLen(value))?,
+            None => DEFAULT_MAC_LEN,
+        };
+        Ok(MarkerCodec {
+            keys,
+            protection,
+            mac_len,
+        })
+    }
+
+    /// 检查发送端能否按保护方式编码标记：hmac / aead 需要活动密钥
+    pub fn check_sender(&self) -> Result<(), KeyError> {
+        match (self.protection, self.keys.active()) {
+            (Protection::Hmac | Protection::Aead, None) => {
+                Err(KeyError::NoActiveKey(self.protection.name().to_string()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// 编码发送端的标记；需要保护而没有活动密钥时返回错误，不会退回明文
+    pub fn encode(&self, marker: &Marker, ctx: &AuthContext) -> Result<Vec<u8>, KeyError> {
+        self.check_sender()?;
+        Ok(match (self.protection, self.keys.active()) {
+            (Protection::Hmac, Some((key_id, secret))) => {
+                sign(marker, ctx, key_id, secret, self.mac_len)
+            }
+            (Protection::Aead, Some((key_id, secret))) => encrypt(marker, ctx, key_id, secret),
+            _ => marker.encode(),
+        })
+    }
+
+    /// 解码并验证标记，接收端接受密钥环中的任意 key ID
+    pub fn verify(
+        &self,
+        payload: &[u8],
+        ctx: &AuthContext,
+    ) -> Result<(Marker, AuthStatus), MarkerError> {
+        verify(payload, ctx, &self.keys, self.mac_len)
+    }
+}
+
+/// 认证结论的累计计数
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AuthCounters {
+    pub valid: u64,
+    pub invalid: u64,
+    pub unauthenticated: u64,
+}
+
+impl AuthCounters {
+    pub fn record(&mut self, status: &AuthStatus) {
+        match status {
+            AuthStatus::Valid { .. } => self.valid += 1,
+            AuthStatus::Invalid { .. } => self.invalid += 1,
+            AuthStatus::Unauthenticated => self.unauthenticated += 1,
+        }
+    }
+}
+
+impl fmt::Display for AuthCounters {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "通过 {} / 失败 {} / 未认证 {}",
+            self.valid, self.invalid, self.unauthenticated
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::{PacketBuilder, UdpLayer};
+    use crate::keyring::{KEY_FILE_ENV, KEYS_ENV};
+    use std::collections::HashMap;
+
+    fn context() -> AuthContext {
+        let packet = PacketBuilder::ipv4(Ipv4Addr::new(10, 0, 0, 1), Ipv4Addr::new(10, 0, 0, 2))
+            .udp(UdpLayer::new(40000, 8001))
+            .build()
+            .unwrap();
+        AuthContext::from_ipv4(&packet).unwrap()
+    }
+
+    fn marker() -> Marker {
+        Marker {
+            timestamp: 1_700_000_000_123_456,
+            ..Marker::new(0x1234, 42)
+        }
+    }
+
+    fn ring(spec: &str) -> KeyRing {
+        KeyRing::parse(spec).unwrap()
+    }
+
+    fn codec(spec: &str, protection: Protection) -> MarkerCodec {
+        MarkerCodec {
+            keys: ring(spec),
+            protection,
+            ..MarkerCodec::default()
+        }
+    }
+
+    fn invalid(key_id: u8, reason: AuthFailure) -> AuthStatus {
+        AuthStatus::Invalid {
+            key_id: Some(key_id),
+            reason,
+        }
+    }
+
+    #[test]
+    fn context_from_ipv4() {
+        let ctx = context();
+        assert_eq!(ctx.source, IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)));
+        assert_eq!(ctx.destination, IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)));
+        assert_eq!(ctx.protocol, 17);
+        assert_eq!((ctx.source_port, ctx.destination_port), (40000, 8001));
+        assert_eq!(AuthContext::from_ipv4(&[0x45; 19]), None);
+    }
+
+    #[test]
+    fn encode_verify_round_trip() {
+        let ctx = context();
+        for (protection, status, len) in [
+            (Protection::Plain, AuthStatus::Unauthenticated, 16),
+            (
+                Protection::Hmac,
+                AuthStatus::Valid {
+                    key_id: 3,
+                    encrypted: false,
+                },
+                16 + 1 + DEFAULT_MAC_LEN,
+            ),
+            (
+                Protection::Aead,
+                AuthStatus::Valid {
+                    key_id: 3,
+                    encrypted: true,
+                },
+                16 + 1 + 2 + AEAD_TAG_LEN,
+            ),
+        ] {
+            let codec = codec("3:000102030405060708090a0b0c0d0e0f", protection);
+            let payload = codec.encode(&marker(), &ctx).unwrap();
+            assert_eq!(payload.len(), len, "{}", protection.name());
+            let (decoded, verified) = codec.verify(&payload, &ctx).unwrap();
+            assert_eq!(verified, status, "{}", protection.name());
+            assert_eq!(decoded.tag, 0x1234);
+            assert_eq!(decoded.sequence, 42);
+            assert_eq!(decoded.timestamp, marker().timestamp);
+        }
+        // 需要保护而没有活动密钥时报错，不退回明文
+        for protection in [Protection::Hmac, Protection::Aead] {
+            let codec = codec("", protection);
+            assert_eq!(
+                codec.encode(&marker(), &ctx),
+                Err(KeyError::NoActiveKey(protection.name().into()))
+            );
+        }
+        // 删除活动密钥而没有另选一个
+        let mut retired = codec("1:aa,2:bb", Protection::Hmac);
+        retired.keys.remove(1);
+        assert!(retired.check_sender().is_err());
+        let payload = codec("", Protection::Plain)
+            .encode(&marker(), &ctx)
+            .unwrap();
+        assert_eq!(payload, marker().encode());
+    }
+
+    #[test]
+    fn aead_hides_the_tag() {
+        let ctx = context();
+        let payload = encrypt(&marker(), &ctx, 1, b"secret");
+        let (clear, status) = verify(&payload, &ctx, &KeyRing::default(), DEFAULT_MAC_LEN).unwrap();
+        assert_eq!(clear.tag, 0);
+        assert_eq!(status, invalid(1, AuthFailure::UnknownKey));
+    }
+
+    #[test]
+    fn mac_lengths_are_clamped() {
+        let ctx = context();
+        let keys = ring("1:aa");
+        for (requested, len) in [(0, MIN_MAC_LEN), (12, 12), (64, MAX_MAC_LEN)] {
+            let payload = sign(&marker(), &ctx, 1, b"\xaa", requested);
+            assert_eq!(payload.len(), 16 + 1 + len);
+            assert!(
+                verify(&payload, &ctx, &keys, requested)
+                    .unwrap()
+                    .1
+                    .is_valid()
+            );
+        }
+    }
+
+    #[test]
+    fn other_mac_lengths_are_rejected() {
+        let ctx = context();
+        let keys = ring("1:aa");
+        // 按 4 字节截断的正确 MAC 也不能冒充配置的 8 字节 MAC
+        for len in [MIN_MAC_LEN, 12] {
+            let payload = sign(&marker(), &ctx, 1, b"\xaa", len);
+            assert_eq!(
+                verify(&payload, &ctx, &keys, DEFAULT_MAC_LEN).unwrap().1,
+                invalid(1, AuthFailure::BadMacLen(len))
+            );
+        }
+    }
+
+    #[test]
+    fn tampering_is_detected() {
+        let ctx = context();
+        let keys = ring("1:aa");
+        for (protection, reason) in [
+            (Protection::Hmac, AuthFailure::BadMac),
+            (Protection::Aead, AuthFailure::DecryptFailed),
+        ] {
+            let codec = MarkerCodec {
+                keys: keys.clone(),
+                protection,
+                ..MarkerCodec::default()
+            };
+            let payload = codec.encode(&marker(), &ctx).unwrap();
+            let name = protection.name();
+
+            // 标记搬到别的流上
+            let mut moved = ctx;
+            moved.destination_port = 8002;
+            assert_eq!(
+                codec.verify(&payload, &moved).unwrap().1,
+                invalid(1, reason)
+            );
+            moved = ctx;
+            moved.source = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 9));
+            assert_eq!(
+                codec.verify(&payload, &moved).unwrap().1,
+                invalid(1, reason)
+            );
+
+            // 改动序号
+            let mut changed = payload.clone();
+            changed[7] ^= 1;
+            assert_eq!(
+                codec.verify(&changed, &ctx).unwrap().1,
+                invalid(1, reason),
+                "{}",
+                name
+            );
+
+            // 改动认证数据
+            let mut changed = payload.clone();
+            *changed.last_mut().unwrap() ^= 1;
+            assert_eq!(
+                codec.verify(&changed, &ctx).unwrap().1,
+                invalid(1, reason),
+                "{}",
+                name
+            );
+
+            // 截断：少一个字节的 MAC 与配置的长度不符
+            let cut = &payload[..payload.len() - 1];
+            let truncated = if protection == Protection::Aead {
+                invalid(1, AuthFailure::Truncated)
+            } else {
+                invalid(1, AuthFailure::BadMacLen(DEFAULT_MAC_LEN - 1))
+            };
+            assert_eq!(codec.verify(cut, &ctx).unwrap().1, truncated, "{}", name);
+            assert_eq!(
+                codec.verify(&payload[..16], &ctx).unwrap().1,
+                AuthStatus::Invalid {
+                    key_id: None,
+                    reason: AuthFailure::Truncated,
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn wrong_key_id_is_rejected() {
+        let ctx = context();
+        let payload = sign(&marker(), &ctx, 1, b"\xaa", DEFAULT_MAC_LEN);
+        assert_eq!(
+            verify(&payload, &ctx, &ring("2:aa"), DEFAULT_MAC_LEN)
+                .unwrap()
+                .1,
+            invalid(1, AuthFailure::UnknownKey)
+        );
+        // 另一个 key ID 下的同名密钥也不行：key ID 在 MAC 覆盖范围内
+        let mut relabeled = payload.clone();
+        relabeled[16] = 2;
+        assert_eq!(
+            verify(&relabeled, &ctx, &ring("1:aa,2:aa"), DEFAULT_MAC_LEN)
+                .unwrap()
+                .1,
+            invalid(2, AuthFailure::BadMac)
+        );
+        assert_eq!(
+            verify(&payload, &ctx, &ring("1:bb"), DEFAULT_MAC_LEN)
+                .unwrap()
+                .1,
+            invalid(1, AuthFailure::BadMac)
+        );
+    }
+
+    #[test]
+    fn old_markers_verify_after_rotation() {
+        let ctx = context();
+        let mut codec = codec("1:aa", Protection::Hmac);
+        let old = codec.encode(&marker(), &ctx).unwrap();
+
+        // 先把新密钥加入，再切换活动密钥
+        codec.keys.insert(2, vec![0xbb]);
+        assert!(codec.keys.set_active(2));
+        let new = codec.encode(&marker(), &ctx).unwrap();
+        assert_eq!(new[16], 2);
+        for (payload, key_id) in [(&old, 1), (&new, 2)] {
+            assert_eq!(
+                codec.verify(payload, &ctx).unwrap().1,
+                AuthStatus::Valid {
+                    key_id,
+                    encrypted: false,
+                }
+            );
+        }
+
+        // 删除旧密钥后旧标记不再通过
+        codec.keys.remove(1);
+        assert_eq!(
+            codec.verify(&old, &ctx).unwrap().1,
+            invalid(1, AuthFailure::UnknownKey)
+        );
+        assert!(codec.verify(&new, &ctx).unwrap().1.is_valid());
+    }
+
+    #[test]
+    fn counters() {
+        let mut counters = AuthCounters::default();
+        counters.record(&AuthStatus::Unauthenticated);
+        counters.record(&invalid(1, AuthFailure::BadMac));
+        counters.record(&AuthStatus::Valid {
+            key_id: 1,
+            encrypted: true,
+        });
+        counters.record(&AuthStatus::Valid {
+            key_id: 1,
+            encrypted: false,
+        });
+        assert_eq!(
+            (counters.valid, counters.invalid, counters.unauthenticated),
+            (2, 1, 1)
+        );
+    }
+
+    #[test]
+    fn codec_from_lookup() {
+        let load = |keys: Option<&str>, file: Option<&str>, protection: Option<&str>| {
+            load_with_mac_len(keys, file, protection, None)
+        };
+
+        let codec = load(None, None, None).unwrap();
+        assert!(codec.keys.is_empty());
+        assert_eq!(codec.protection, Protection::Plain);
+        assert_eq!(
+            load(Some("1:aa"), None, None).unwrap().protection,
+            Protection::Hmac
+        );
+        assert!(
+            load(None, None, Some("hmac"))
+                .unwrap()
+                .check_sender()
+                .is_err()
+        );
+        assert_eq!(codec.mac_len, DEFAULT_MAC_LEN);
+
+        assert_eq!(
+            load_with_mac_len(None, None, None, Some("12"))
+                .unwrap()
+                .mac_len,
+            12
+        );
+        for value in ["3", "17", "eight"] {
+            assert_eq!(
+                load_with_mac_len(None, None, None, Some(value)).unwrap_err(),
+                KeyError::BadMacLen(value.into())
+            );
+        }
+
+        let codec = load(Some("1:aa,*7:0a0b"), None, Some("aead")).unwrap();
+        assert_eq!(codec.keys.active(), Some((7, &[0x0a, 0x0b][..])));
+        assert_eq!(codec.protection, Protection::Aead);
+        assert_eq!(
+            load(Some("1:aa"), None, Some("rsa")).unwrap_err(),
+            KeyError::BadProtection("rsa".into())
+        );
+        assert_eq!(
+            load(Some("1:zz"), None, None).unwrap_err(),
+            KeyError::BadSecret(1)
+        );
+
+        let path = std::env::temp_dir().join(format!("biaoshi-keys-{}", std::process::id()));
+        std::fs::write(&path, "# 轮换中\n1:aa\n*2:bb\n").unwrap();
+        let file = path.to_str();
+        let codec = load(None, file, Some("plain")).unwrap();
+        assert_eq!(codec.keys.key_ids().collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(codec.keys.active().map(|(id, _)| id), Some(2));
+        assert_eq!(codec.protection, Protection::Plain);
+        // BIAOSHI_KEYS 优先于密钥文件
+        let codec = load(Some("5:cc"), file, None).unwrap();
+        assert_eq!(codec.keys.key_ids().collect::<Vec<_>>(), vec![5]);
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(load(None, file, None), Err(KeyError::Io(_))));
+    }
+
+    fn load_with_mac_len(
+        keys: Option<&str>,
+        file: Option<&str>,
+        protection: Option<&str>,
+        mac_len: Option<&str>,
+    ) -> Result<MarkerCodec, KeyError> {
+        let vars: HashMap<&str, &str> = [
+            (KEYS_ENV, keys),
+            (KEY_FILE_ENV, file),
+            (PROTECTION_ENV, protection),
+            (MAC_LEN_ENV, mac_len),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| Some((name, value?)))
+        .collect();
+        MarkerCodec::from_lookup(|name| vars.get(name).map(|value| value.to_string()))
+    }
+}
@@ -0,0 +1,801 @@
+//! ipsend: 按命令行参数构造并发送 IPv4 数据包
+//!
+//! ```text
+//! ipsend udp 127.0.0.1 -p 8001 --payload "Hello, Biaoshi!"
+//! ipsend tcp 106.54.227.154 -s 172.23.25.59 -p 8001 --flags syn -t mss:1460
+//! ipsend icmp 8.8.8.8 -i eth0 --df
+//! ipsend raw 10.0.0.1 --protocol 253 --payload-hex 0102
+//! ipsend template sweep.toml --dry-run
+//! ipsend ping 8.8.8.8 -c 4 -o rr:9
+//! ipsend udp 10.0.0.2 -p 8001 --pps 10k --duration 30s --burst 8 --jitter 50us
+//! ipsend udp 192.0.2.1 -p 8001 --datalink --id fixed:0
+//! ipsend replay capture.pcapng --dst 10.0.0.2 --marker ip --speed 2
+//! ```
+//!
+//! 未指定 `-s` 时按路由表选择源地址，`-i` 限定出接口。`--datalink` 时直接写以太网帧，
+//! 下一跳 MAC 地址查邻居表或发 ARP 解析，IP 首部不经内核改写。
+//!
+//! 标记默认放在 IP 选项 0x79 中 (TCP 默认放在实验选项 253 中)，按环境变量
+//! `BIAOSHI_KEYS` / `BIAOSHI_PROTECTION` / `BIAOSHI_MAC_LEN` 附带 HMAC 或加密。
+
+mod ping;
+mod replay;
+mod spec;
+mod template;
+
+use std::io;
+use std::net::Ipv4Addr;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::{Context, Result, bail};
+use clap::{Args, Parser, Subcommand};
+use ip_header::auth::{AuthContext, MarkerCodec};
+use ip_header::echo;
+use ip_header::fragment::{FragmentOrder, Fragmenter};
+use ip_header::hex;
+use ip_header::ip_id::{FlowTuple, IdStrategy, strategy_from_name};
+use ip_header::ipv4_option::{self, Ipv4Option};
+use ip_header::marker::{Ipv4OptionCarrier, TcpOptionCarrier};
+use ip_header::pacing::{self, Pacer, SendStats};
+use ip_header::rewrite::Mapping;
+use ip_header::route;
+use ip_header::sender::{LinkSender, RawSender};
+use ip_header::tcp_option::{self, TcpOption};
+use ip_header::{IcmpLayer, Marker, MarkerCarrier, PacketBuilder, TcpLayer, UdpLayer};
+use pnet::util::MacAddr;
+
+use template::{
+    Field, IcmpTemplate, IpTemplate, MarkerPlace, MarkerTemplate, PayloadSource, RateTemplate,
+    RawTemplate, TcpTemplate, Template, TransportTemplate, UdpTemplate,
+};
+
+#[derive(Parser)]
+#[command(name = "ipsend", version, about = "构造并发送 IPv4 数据包")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+
+    #[command(flatten)]
+    rate: RateArgs,
+}
+
+/// 流量生成：限速、限时发送，结束时输出汇总
+#[derive(Args)]
+struct RateArgs {
+    /// 目标包速率 (每秒发出的 IP 包数，分片时每个分片算一个包，可带 k / M 后缀)
+    #[arg(long, global = true, value_parser = spec::parse_scaled, conflicts_with = "bps")]
+    pps: Option<f64>,
+
+    /// 目标比特速率 (按 IP 包长，分片时计入每个分片的首部，可带 k / M / G 后缀)
+    #[arg(long, global = true, value_parser = spec::parse_scaled)]
+    bps: Option<f64>,
+
+    /// 持续发送的时长 (如 10s、500ms)，与 -c 先到者为准
+    #[arg(long, global = true, value_parser = spec::parse_duration)]
+    duration: Option<Duration>,
+
+    /// 每个突发连续发送的 IP 包数 (分片时按分片计)
+    #[arg(long, global = true, value_parser = clap::value_parser!(u32).range(1..))]
+    burst: Option<u32>,
+
+    /// 每个突发发送时刻的随机偏移范围 (±，如 100us)
+    #[arg(long, global = true, value_parser = spec::parse_duration)]
+    jitter: Option<Duration>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// 发送 UDP 数据报
+    Udp(UdpArgs),
+    /// 发送 TCP 报文段
+    Tcp(TcpArgs),
+    /// 发送 ICMP 报文
+    Icmp(IcmpArgs),
+    /// 发送任意协议号的数据包，负载即 IP 负载
+    Raw(RawArgs),
+    /// 按模板文件 (TOML / JSON / YAML) 构造并发送
+    Template(TemplateArgs),
+    /// 发送 ICMP echo 请求并统计应答 RTT，未指定 -c 时持续发送到 Ctrl-C
+    Ping(PingArgs),
+    /// 回放 pcap / pcapng 中的数据包，可改写地址端口并插入标记
+    Replay(ReplayArgs),
+}
+
+/// 各协议共用的 IP 层参数
+#[derive(Args)]
+struct IpArgs {
+    /// 目的地址
+    destination: Ipv4Addr,
+
+    /// 源地址，默认按路由表选择
+    #[arg(short, long)]
+    source: Option<Ipv4Addr>,
+
+    /// 出接口，默认按路由表选择
+    #[arg(short, long)]
+    interface: Option<String>,
+
+    #[arg(long, default_value_t = 64)]
+    ttl: u8,
+
+    /// 服务类型 / DSCP+ECN
+    #[arg(long, default_value = "0", value_parser = spec::parse_u8)]
+    tos: u8,
+
+    /// 设置 Don't Fragment 标志
+    #[arg(long)]
+    df: bool,
+
+    /// IP ID 编码策略: seconds | millis | counter | random | flow-hash | fixed[:<值>]
+    #[arg(long = "id", default_value = "seconds", value_parser = parse_strategy_name)]
+    id_strategy: String,
+
+    #[arg(short = 'o', long = "ip-option", help = spec::IP_OPTION_HELP, value_parser = spec::parse_ip_option)]
+    ip_options: Vec<Ipv4Option>,
+
+    /// 经数据链路层发送以太网帧，IP 首部按构造的原样发出 (内核不填 ID、源地址和校验和)
+    #[arg(long)]
+    datalink: bool,
+
+    /// 下一跳 MAC 地址，默认查邻居表或发 ARP 解析
+    #[arg(long, value_parser = spec::parse_mac, requires = "datalink")]
+    dst_mac: Option<MacAddr>,
+
+    /// 标记中的 tag
+    #[arg(long, default_value = "1", value_parser = spec::parse_u16)]
+    tag: u16,
+
+    /// 不携带标记
+    #[arg(long)]
+    no_marker: bool,
+
+    /// 发送的包数，标记序号和 ICMP 序号逐包递增；默认 1，指定 --duration 时不限
+    #[arg(short, long, value_parser = clap::value_parser!(u32).range(1..))]
+    count: Option<u32>,
+
+    #[command(flatten)]
+    fragment: FragmentArgs,
+}
+
+/// 分片参数
+#[derive(Args)]
+#[command(next_help_heading = "分片")]
+struct FragmentArgs {
+    /// 按该 MTU (分片总长度) 分片发送
+    #[arg(long = "fragment", value_name = "MTU", conflicts_with = "df")]
+    mtu: Option<usize>,
+
+    /// 第一个分片的数据长度 (8 的倍数)，用于构造极小分片
+    #[arg(long = "frag-first", value_name = "BYTES", requires = "mtu")]
+    first: Option<usize>,
+
+    /// 后续分片向前重叠的字节数 (8 的倍数)
+    #[arg(
+        long = "frag-overlap",
+        value_name = "BYTES",
+        default_value_t = 0,
+        requires = "mtu"
+    )]
+    overlap: usize,
+
+    /// 重叠部分改写为该字节
+    #[arg(long = "frag-fill", value_name = "BYTE", value_parser = spec::parse_u8, requires = "mtu")]
+    fill: Option<u8>,
+
+    /// 分片发送顺序: in-order | reverse | random
+    #[arg(long = "frag-order", default_value = "in-order", value_parser = parse_fragment_order, requires = "mtu")]
+    order: FragmentOrder,
+}
+
+/// 负载来源，三者最多指定一个，都不指定时负载为空
+#[derive(Args)]
+#[group(multiple = false)]
+struct PayloadArgs {
+    /// 文本负载
+    #[arg(long)]
+    payload: Option<String>,
+
+    /// 十六进制负载
+    // 写全路径，避免 clap 把 Vec<u8> 当作多值参数
+    #[arg(long, value_parser = spec::parse_hex)]
+    payload_hex: Option<::std::vec::Vec<u8>>,
+
+    /// 从文件读取负载
+    #[arg(long)]
+    payload_file: Option<PathBuf>,
+}
+
+#[derive(Args)]
+struct UdpArgs {
+    #[command(flatten)]
+    ip: IpArgs,
+
+    #[arg(long, default_value_t = 54321)]
+    sport: u16,
+
+    /// 目的端口
+    #[arg(short = 'p', long)]
+    dport: u16,
+
+    #[command(flatten)]
+    payload: PayloadArgs,
+}
+
+#[derive(Args)]
+struct TcpArgs {
+    #[command(flatten)]
+    ip: IpArgs,
+
+    #[arg(long, default_value_t = 54321)]
+    sport: u16,
+
+    /// 目的端口
+    #[arg(short = 'p', long)]
+    dport: u16,
+
+    /// 逗号分隔的标志: fin,syn,rst,psh,ack,urg,ece,cwr 或 none
+    #[arg(long, default_value = "syn", value_parser = spec::parse_tcp_flags)]
+    flags: u8,
+
+    #[arg(long, default_value = "0", value_parser = spec::parse_u32)]
+    seq: u32,
+
+    #[arg(long, default_value = "0", value_parser = spec::parse_u32)]
+    ack: u32,
+
+    #[arg(long, default_value_t = 65535)]
+    window: u16,
+
+    #[arg(short = 't', long = "tcp-option", help = spec::TCP_OPTION_HELP, value_parser = spec::parse_tcp_option)]
+    tcp_options: Vec<TcpOption>,
+
+    /// 标记放在 IP 选项还是 TCP 选项中
+    #[arg(long, value_enum, default_value = "tcp")]
+    marker_in: MarkerPlace,
+
+    #[command(flatten)]
+    payload: PayloadArgs,
+}
+
+#[derive(Args)]
+struct IcmpArgs {
+    #[command(flatten)]
+    ip: IpArgs,
+
+    /// ICMP 类型 (默认 8, echo request)
+    #[arg(long = "type", default_value_t = 8)]
+    icmp_type: u8,
+
+    #[arg(long, default_value_t = 0)]
+    code: u8,
+
+    /// echo identifier
+    #[arg(long = "icmp-id", default_value = "0", value_parser = spec::parse_u16)]
+    identifier: u16,
+
+    /// 第一个包的 echo sequence
+    #[arg(long = "icmp-seq", default_value_t = 0)]
+    sequence: u16,
+
+    #[command(flatten)]
+    payload: PayloadArgs,
+}
+
+#[derive(Args)]
+struct RawArgs {
+    #[command(flatten)]
+    ip: IpArgs,
+
+    /// IP 协议号
+    #[arg(long, value_parser = spec::parse_u8)]
+    protocol: u8,
+
+    #[command(flatten)]
+    payload: PayloadArgs,
+}
+
+#[derive(Args)]
+struct TemplateArgs {
+    /// 模板文件，格式由扩展名决定
+    file: PathBuf,
+
+    /// 只展开并以十六进制打印数据包，不发送
+    #[arg(long)]
+    dry_run: bool,
+}
+
+#[derive(Args)]
+struct PingArgs {
+    #[command(flatten)]
+    ip: IpArgs,
+
+    /// echo identifier，默认取进程号
+    #[arg(long = "icmp-id", value_parser = spec::parse_u16)]
+    identifier: Option<u16>,
+
+    /// 请求的发送间隔
+    #[arg(long, default_value = "1s", value_parser = spec::parse_duration)]
+    interval: Duration,
+
+    /// 最后一个请求发出后等待应答的时间
+    #[arg(long, default_value = "1s", value_parser = spec::parse_duration)]
+    wait: Duration,
+
+    #[command(flatten)]
+    payload: PayloadArgs,
+}
+
+#[derive(Args)]
+struct ReplayArgs {
+    /// pcap / pcapng 抓包文件
+    file: PathBuf,
+
+    /// 出接口；经数据链路层发送时必须指定
+    #[arg(short, long)]
+    interface: Option<String>,
+
+    /// 经数据链路层发送整个以太网帧 (保留抓包中的 MAC 地址)，默认经三层原始套接字
+    #[arg(long, requires = "interface")]
+    datalink: bool,
+
+    /// 改写源地址 (可重复，取第一条匹配的规则)
+    #[arg(long = "src", value_name = "[OLD=]NEW", value_parser = spec::parse_address_mapping)]
+    source: Vec<Mapping<Ipv4Addr>>,
+
+    /// 改写目的地址 (可重复)
+    #[arg(long = "dst", value_name = "[OLD=]NEW", value_parser = spec::parse_address_mapping)]
+    destination: Vec<Mapping<Ipv4Addr>>,
+
+    /// 改写 TCP / UDP 源端口 (可重复)
+    #[arg(long = "sport", value_name = "[OLD=]NEW", value_parser = spec::parse_port_mapping)]
+    source_port: Vec<Mapping<u16>>,
+
+    /// 改写 TCP / UDP 目的端口 (可重复)
+    #[arg(long = "dport", value_name = "[OLD=]NEW", value_parser = spec::parse_port_mapping)]
+    destination_port: Vec<Mapping<u16>>,
+
+    /// 插入标记: ip 为 IP 选项 0x79；tcp 为 TCP 选项 253，非 TCP 包仍放在 IP 选项中
+    #[arg(long, value_enum)]
+    marker: Option<MarkerPlace>,
+
+    /// 标记中的 tag
+    #[arg(long, default_value = "1", value_parser = spec::parse_u16)]
+    tag: u16,
+
+    /// 按原始时间间隔回放的倍速，2 表示两倍速
+    #[arg(long, default_value = "1", value_parser = spec::parse_scaled)]
+    speed: f64,
+
+    /// 忽略原始时间间隔，尽快发送
+    #[arg(long, conflicts_with = "speed")]
+    topspeed: bool,
+
+    /// 回放的遍数
+    #[arg(long = "loop", default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    loops: u32,
+
+    /// 只改写并以十六进制打印，不发送
+    #[arg(long)]
+    dry_run: bool,
+}
+
+fn parse_fragment_order(name: &str) -> Result<FragmentOrder, String> {
+    FragmentOrder::from_name(name).ok_or_else(|| format!("未知的分片顺序: {}", name))
+}
+
+fn parse_strategy_name(name: &str) -> Result<String, String> {
+    strategy_from_name(name)
+        .map(|_| name.to_string())
+        .ok_or_else(|| format!("未知的 IP ID 策略: {}", name))
+}
+
+impl PayloadArgs {
+    fn source(&self) -> PayloadSource {
+        if let Some(text) = &self.payload {
+            PayloadSource::Bytes(text.as_bytes().to_vec())
+        } else if let Some(bytes) = &self.payload_hex {
+            PayloadSource::Bytes(bytes.clone())
+        } else if let Some(path) = &self.payload_file {
+            PayloadSource::File(path.clone())
+        } else {
+            PayloadSource::Empty
+        }
+    }
+}
+
+impl RateArgs {
+    /// 命令行参数覆盖模板中的同名设置
+    fn apply(&self, rate: &mut RateTemplate) {
+        if self.pps.is_some() || self.bps.is_some() {
+            rate.pps = self.pps;
+            rate.bps = self.bps;
+        }
+        rate.duration = self.duration.or(rate.duration);
+        rate.burst = self.burst.or(rate.burst);
+        rate.jitter = self.jitter.or(rate.jitter);
+    }
+}
+
+impl FragmentArgs {
+    fn fragmenter(&self) -> Result<Option<Fragmenter>> {
+        let Some(mtu) = self.mtu else {
+            return Ok(None);
+        };
+        let fragmenter = Fragmenter {
+            mtu,
+            first_size: self.first,
+            overlap: self.overlap,
+            overlap_fill: self.fill,
+            order: self.order,
+        };
+        fragmenter.validate().context("分片参数错误")?;
+        Ok(Some(fragmenter))
+    }
+}
+
+impl IpArgs {
+    fn template(&self) -> IpTemplate {
+        IpTemplate {
+            destination: self.destination,
+            source: self.source,
+            interface: self.interface.clone(),
+            ttl: Field::Fixed(self.ttl),
+            tos: Field::Fixed(self.tos),
+            df: self.df,
+            id: self.id_strategy.clone(),
+            options: self.ip_options.clone(),
+            datalink: self.datalink,
+            destination_mac: self.dst_mac,
+        }
+    }
+
+    fn marker(&self, place: Option<MarkerPlace>) -> MarkerTemplate {
+        MarkerTemplate {
+            enabled: !self.no_marker,
+            tag: self.tag,
+            place,
+        }
+    }
+}
+
+impl Command {
+    /// 命令行参数等价于只有固定字段的模板
+    fn template(&self) -> Result<Template> {
+        let (ip, marker, transport, payload) = match self {
+            Command::Udp(args) => (
+                &args.ip,
+                args.ip.marker(None),
+                TransportTemplate::Udp(UdpTemplate {
+                    sport: Field::Fixed(args.sport),
+                    dport: Field::Fixed(args.dport),
+                }),
+                &args.payload,
+            ),
+            Command::Tcp(args) => (
+                &args.ip,
+                args.ip.marker(Some(args.marker_in)),
+                TransportTemplate::Tcp(TcpTemplate {
+                    sport: Field::Fixed(args.sport),
+                    dport: Field::Fixed(args.dport),
+                    flags: args.flags,
+                    seq: Field::Fixed(args.seq),
+                    ack: Field::Fixed(args.ack),
+                    window: Field::Fixed(args.window),
+                    options: args.tcp_options.clone(),
+                }),
+                &args.payload,
+            ),
+            Command::Icmp(args) => (
+                &args.ip,
+                args.ip.marker(None),
+                TransportTemplate::Icmp(IcmpTemplate {
+                    icmp_type: args.icmp_type,
+                    code: args.code,
+                    id: Field::Fixed(args.identifier),
+                    seq: Field::Step {
+                        start: args.sequence,
+                        step: 1,
+                    },
+                }),
+                &args.payload,
+            ),
+            Command::Raw(args) => (
+                &args.ip,
+                args.ip.marker(None),
+                TransportTemplate::Raw(RawTemplate {
+                    protocol: args.protocol,
+                }),
+                &args.payload,
+            ),
+            Command::Ping(args) => (
+                &args.ip,
+                args.ip.marker(None),
+                TransportTemplate::Icmp(IcmpTemplate {
+                    icmp_type: echo::ECHO_REQUEST,
+                    code: 0,
+                    id: Field::Fixed(args.identifier.unwrap_or(std::process::id() as u16)),
+                    seq: Field::Step { start: 0, step: 1 },
+                }),
+                &args.payload,
+            ),
+            Command::Template(args) => return Template::load(&args.file),
+            Command::Replay(_) => unreachable!("replay 不经过模板"),
+        };
+        Ok(Template {
+            count: ip.count,
+            ip: ip.template(),
+            marker,
+            transport,
+            payload: payload.source(),
+            rate: RateTemplate::default(),
+            fragment: ip.fragment.fragmenter()?,
+        })
+    }
+}
+
+/// 发送途径
+enum Backend {
+    Raw(RawSender),
+    /// 经数据链路层发往下一跳的 MAC 地址
+    Link(LinkSender, MacAddr),
+}
+
+impl Backend {
+    fn send(&mut self, packet: &[u8]) -> io::Result<usize> {
+        match self {
+            Backend::Raw(raw) => raw.send(packet),
+            Backend::Link(link, mac) => link.send(packet, *mac),
+        }
+    }
+}
+
+/// 发送前准备好的状态
+struct Sender {
+    template: Template,
+    payload: Vec<u8>,
+    /// 显式指定或按路由表选出的源地址
+    source: Ipv4Addr,
+    codec: MarkerCodec,
+    id_strategy: Box<dyn IdStrategy>,
+    /// 为 `None` 时只打印数据包，不发送
+    backend: Option<Backend>,
+}
+
+impl Sender {
+    /// 构造第 `index` 个数据包
+    fn build(&mut self, index: u32) -> Result<Vec<u8>> {
+        let ip = &self.template.ip;
+        let transport = &self.template.transport;
+        let protocol = transport.protocol();
+        let (source_port, destination_port) = transport.ports(index);
+
+        let identification = self.id_strategy.next_id(&FlowTuple {
+            source: self.source,
+            destination: ip.destination,
+            protocol: protocol.0,
+            source_port,
+            destination_port,
+        });
+
+        // 标记选项排在用户指定的选项之前
+        let marker = self.template.marker.enabled.then(|| {
+            let ctx = AuthContext {
+                source: self.source.into(),
+                destination: ip.destination.into(),
+                protocol: protocol.0,
+                source_port,
+                destination_port,
+            };
+            self.codec
+                .encode(&Marker::new(self.template.marker.tag, index), &ctx)
+        });
+        let marker = marker.transpose().context("无法编码标记")?;
+        let mut ip_options = Vec::new();
+        let mut tcp_marker = None;
+        match (marker, self.template.marker_place()) {
+            (Some(marker), MarkerPlace::Ip) => {
+                ip_options.push(Ipv4OptionCarrier::default().wrap(marker))
+            }
+            (Some(marker), MarkerPlace::Tcp) => {
+                tcp_marker = Some(TcpOptionCarrier::default().wrap(marker))
+            }
+            (None, _) => {}
+        }
+        ip_options.extend(ip.options.iter().cloned());
+
+        let mut builder = PacketBuilder::ipv4(self.source, ip.destination)
+            .ttl(ip.ttl.at(index))
+            .tos(ip.tos.at(index))
+            .dont_fragment(ip.df)
+            .identification(identification)
+            .protocol(protocol)
+            .ip_options(ipv4_option::serialize_options(&ip_options).context("IP 选项无法编码")?)
+            .payload(self.payload.clone());
+
+        builder = match transport {
+            TransportTemplate::Udp(_) => builder.udp(UdpLayer::new(source_port, destination_port)),
+            TransportTemplate::Tcp(args) => {
+                let mut tcp = TcpLayer::new(source_port, destination_port);
+                tcp.flags = args.flags;
+                tcp.sequence = args.seq.at(index);
+                tcp.acknowledgement = args.ack.at(index);
+                tcp.window = args.window.at(index);
+                let options: Vec<TcpOption> = tcp_marker
+                    .into_iter()
+                    .chain(args.options.iter().cloned())
+                    .collect();
+                tcp.options = tcp_option::serialize_options(&options).context("TCP 选项过长")?;
+                builder.tcp(tcp)
+            }
+            TransportTemplate::Icmp(args) => {
+                let mut icmp = IcmpLayer::new(args.icmp_type, args.code);
+                icmp.rest_of_header[..2].copy_from_slice(&args.id.at(index).to_be_bytes());
+                icmp.rest_of_header[2..].copy_from_slice(&args.seq.at(index).to_be_bytes());
+                builder.icmp(icmp)
+            }
+            TransportTemplate::Raw(_) => builder,
+        };
+        Ok(builder.build()?)
+    }
+
+    /// 按模板的分片设置切分数据报，不分片时原样返回
+    fn fragment(&self, packet: Vec<u8>) -> Result<Vec<Vec<u8>>> {
+        let Some(fragmenter) = &self.template.fragment else {
+            return Ok(vec![packet]);
+        };
+        let fragments = fragmenter.fragment(&packet).context("分片失败")?;
+        let kernel_id = !matches!(self.backend, Some(Backend::Link(..)));
+        if kernel_id && fragments.len() > 1 && packet[4..6] == [0, 0] {
+            eprintln!("警告: IP ID 为 0, 内核会为每个分片另选 ID, 接收端无法重组");
+        }
+        Ok(fragments)
+    }
+
+    /// 逐包发送；流量生成模式下按速率发送，发送错误只计数，结束时输出汇总
+    fn run(&mut self) -> Result<()> {
+        let rate = self.template.rate.clone();
+        let generator = rate.is_generator();
+        let limit = match (self.template.count, rate.duration) {
+            (None, Some(_)) => None,
+            (count, _) => Some(count.unwrap_or(1)),
+        };
+        if generator {
+            pacing::catch_interrupt();
+        }
+
+        let mut pacer = Pacer::new(
+            rate.rate(),
+            rate.burst.unwrap_or(1),
+            rate.jitter.unwrap_or_default(),
+        );
+        let mut stats = SendStats::default();
+        let mut index: u32 = 0;
+        while limit.is_none_or(|limit| index < limit)
+            && rate.duration.is_none_or(|duration| pacer.span() < duration)
+            && !pacing::interrupted()
+        {
+            // 先等待再构造，标记中的时间戳贴近实际发送时刻；速率按线上的包计，
+            // 其余分片各自等待
+            pacer.wait();
+            let packet = self.build(index)?;
+            let identification = u16::from_be_bytes([packet[4], packet[5]]);
+            let fragments = self.fragment(packet)?;
+            let mut sent = 0;
+            for (i, fragment) in fragments.iter().enumerate() {
+                if i > 0 {
+                    pacer.wait();
+                }
+                match &mut self.backend {
+                    None => println!(
+                        "#{} {} 字节: {}",
+                        index,
+                        fragment.len(),
+                        hex::encode(fragment)
+                    ),
+                    Some(backend) if generator => stats.record(&backend.send(fragment)),
+                    Some(backend) => sent += backend.send(fragment).context("发送失败")?,
+                }
+                pacer.sent(fragment.len());
+            }
+            if self.backend.is_some() && !generator {
+                print!(
+                    "已发送 {} 字节: {} -> {} 协议 {} ID 0x{:04x}",
+                    sent,
+                    self.source,
+                    self.template.ip.destination,
+                    self.template.transport.protocol(),
+                    identification,
+                );
+                if fragments.len() > 1 {
+                    print!(" ({} 个分片)", fragments.len());
+                }
+                println!();
+            }
+            index = index.wrapping_add(1);
+        }
+
+        if generator && self.backend.is_some() {
+            stats.elapsed = pacer.span();
+            if let Some(target) = rate.rate() {
+                println!("目标速率 {}", target);
+            }
+            println!("{}", stats);
+        }
+        Ok(())
+    }
+}
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+    if let Command::Replay(args) = &cli.command {
+        let mut rate = RateTemplate::default();
+        cli.rate.apply(&mut rate);
+        rate.validate()?;
+        return replay::run(args, &rate);
+    }
+    let mut template = cli.command.template()?;
+    cli.rate.apply(&mut template.rate);
+    template.rate.validate()?;
+    if matches!(cli.command, Command::Ping(_)) && template.rate.is_generator() {
+        bail!("ping 用 --interval 控制发送间隔，不支持 --pps / --bps / --duration");
+    }
+    let dry_run = matches!(&cli.command, Command::Template(args) if args.dry_run);
+    let payload = template.payload.load()?;
+    let codec = MarkerCodec::from_env().context("标记密钥配置错误")?;
+    if template.marker.enabled {
+        codec.check_sender().context("标记密钥配置错误")?;
+    }
+    let id_strategy = strategy_from_name(&template.ip.id).expect("策略名已在解析时检查");
+    // 显式参数优先，否则查询路由表；二层发送总要知道出接口和下一跳
+    let ip = &template.ip;
+    let (source, egress) = match (ip.source, &ip.interface, ip.datalink) {
+        (Some(source), None, false) => (source, None),
+        (source, interface, _) => {
+            let egress = match interface {
+                Some(name) => route::lookup_via(ip.destination, name)?,
+                None => route::lookup(ip.destination)?,
+            };
+            println!("{} -> {}", ip.destination, egress);
+            (source.unwrap_or(egress.source), Some(egress))
+        }
+    };
+
+    let backend = if dry_run {
+        None
+    } else if ip.datalink {
+        let egress = egress.expect("datalink 时已查询路由");
+        let mut link = LinkSender::new(&egress.interface)
+            .with_context(|| format!("无法在 {} 上创建数据链路层通道", egress.interface))?;
+        let mac = match ip.destination_mac {
+            Some(mac) => mac,
+            None => {
+                let next_hop = egress.next_hop(ip.destination);
+                link.resolve(next_hop, egress.source)
+                    .with_context(|| format!("无法解析 {} 的 MAC 地址", next_hop))?
+            }
+        };
+        println!("{} -> {} ({})", link.source_mac(), mac, egress.interface);
+        Some(Backend::Link(link, mac))
+    } else {
+        let raw = RawSender::new().context("无法创建原始套接字 (需要 root 或 CAP_NET_RAW)")?;
+        if let Some(name) = &ip.interface {
+            raw.bind_to_device(name)
+                .with_context(|| format!("无法绑定接口 {}", name))?;
+        }
+        Some(Backend::Raw(raw))
+    };
+
+    let mut sender = Sender {
+        template,
+        payload,
+        source,
+        codec,
+        id_strategy,
+        backend,
+    };
+    match &cli.command {
+        Command::Ping(args) => ping::run(&mut sender, args.interval, args.wait),
+        _ => sender.run(),
+    }
+}
@@ -0,0 +1,102 @@
+//! ping: 发送 echo 请求，等待并配对应答，结束时输出 RTT 统计
+
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result, bail};
+use ip_header::echo::{EchoMatch, EchoTracker, IcmpListener, parse_echo_reply};
+use ip_header::ipv4_option::{format_options, parse_options};
+use ip_header::pacing;
+use ip_header::timestamp::unix_micros;
+
+use crate::Sender;
+use crate::template::TransportTemplate;
+
+/// 按 `interval` 逐个发送请求，每个请求发出后接收应答直到下一个发送时刻；
+/// 最后一个请求发出后最多再等 `wait`。未指定包数时发送到 Ctrl-C 为止。
+pub fn run(sender: &mut Sender, interval: Duration, wait: Duration) -> Result<()> {
+    let TransportTemplate::Icmp(icmp) = &sender.template.transport else {
+        bail!("ping 只能发送 ICMP echo 请求");
+    };
+    let (identifier, sequence) = (icmp.id, icmp.seq);
+    let destination = sender.template.ip.destination;
+    let limit = sender.template.count;
+
+    let listener = IcmpListener::new().context("无法创建 ICMP 接收套接字")?;
+    let mut tracker = EchoTracker::new(identifier.at(0), destination);
+    let mut buf = vec![0u8; 65535];
+    pacing::catch_interrupt();
+
+    let start = Instant::now();
+    let mut index: u32 = 0;
+    while limit.is_none_or(|limit| index < limit) && !pacing::interrupted() {
+        let packet = sender.build(index)?;
+        let fragments = sender.fragment(packet)?;
+        let backend = sender.backend.as_mut().context("ping 不支持只打印")?;
+        // 本机回环时应答可能在 sendto 返回前就已收到，发送时间取在发送之前
+        let sent_us = unix_micros();
+        match fragments
+            .iter()
+            .try_for_each(|fragment| backend.send(fragment).map(drop))
+        {
+            Ok(()) => tracker.sent(sequence.at(index), sent_us),
+            Err(e) => eprintln!("icmp_seq={} 发送失败: {}", sequence.at(index), e),
+        }
+        index += 1;
+
+        let last = limit == Some(index);
+        let deadline = if last {
+            Instant::now() + wait
+        } else {
+            start + interval * index
+        };
+        while !pacing::interrupted() {
+            // 最后一个请求之后，应答到齐就不必再等
+            if last && tracker.received() == tracker.transmitted() {
+                break;
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                break;
+            }
+            let Some((len, received_us)) = listener.recv(&mut buf, remaining)? else {
+                continue;
+            };
+            let Some(reply) = parse_echo_reply(&buf[..len]) else {
+                continue;
+            };
+            let rtt = match tracker.reply(&reply, received_us) {
+                EchoMatch::Reply(rtt) => rtt,
+                EchoMatch::Duplicate => {
+                    println!(
+                        "{} 字节来自 {}: icmp_seq={} 重复应答",
+                        reply.len, reply.source, reply.sequence
+                    );
+                    continue;
+                }
+                EchoMatch::Unrelated => continue,
+            };
+            print!(
+                "{} 字节来自 {}: icmp_seq={} ttl={} 时间={:.3}ms",
+                reply.len,
+                reply.source,
+                reply.sequence,
+                reply.ttl,
+                rtt as f64 / 1000.0
+            );
+            if !reply.checksum_ok {
+                print!(" (ICMP 校验和错误)");
+            }
+            if !reply.ip_options.is_empty() {
+                match parse_options(&reply.ip_options) {
+                    Ok(options) => print!(" 选项: {}", format_options(&options)),
+                    Err(e) => print!(" 选项无法解析: {}", e),
+                }
+            }
+            println!();
+        }
+    }
+
+    println!("--- {} ping 统计 ---", destination);
+    println!("{}", tracker);
+    Ok(())
+}
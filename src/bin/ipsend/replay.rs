@@ -0,0 +1,255 @@
+//! replay: 回放 pcap / pcapng 中的 IPv4 数据包
+//!
+//! 每个包可按规则改写地址和端口、插入标记，改动过的包重新计算校验和，再经三层
+//! 原始套接字或数据链路层发出。默认按抓包中的时间间隔 (可用 `--speed` 缩放) 发送，
+//! 指定 `--pps` / `--bps` 或 `--topspeed` 时忽略原始间隔。
+
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use ip_header::auth::{AuthContext, MarkerCodec};
+use ip_header::hex;
+use ip_header::marker::{Ipv4OptionCarrier, TcpOptionCarrier};
+use ip_header::pacing::{self, Pacer, SendStats};
+use ip_header::pcap::{self, LINKTYPE_ETHERNET, PcapReader, PcapRecord};
+use ip_header::rewrite::{self, RewriteRules};
+use ip_header::sender::{LinkSender, RawSender};
+use ip_header::{Marker, MarkerCarrier};
+
+use crate::ReplayArgs;
+use crate::template::{MarkerPlace, RateTemplate};
+
+/// 发送途径
+enum Output {
+    /// 只打印改写后的包
+    DryRun,
+    Raw(RawSender),
+    /// 以太网帧原样发出，保留抓包中的 MAC 地址
+    Datalink(LinkSender),
+}
+
+struct Replayer<'a> {
+    args: &'a ReplayArgs,
+    rules: RewriteRules,
+    codec: MarkerCodec,
+    output: Output,
+    /// 已插入标记的包数，作为标记序号
+    marked: u32,
+    /// 未发送的记录，按原因计数
+    skipped: BTreeMap<String, u64>,
+    /// 发送了但没能插入标记的包，按原因计数
+    unmarked: BTreeMap<String, u64>,
+}
+
+impl Replayer<'_> {
+    /// 改写一条记录，返回要发送的数据：三层途径为 IPv4 包，数据链路层途径为整个帧
+    fn prepare(&mut self, record: &PcapRecord) -> Result<Vec<u8>, String> {
+        if record.is_truncated() {
+            return Err("抓包时被截断".to_string());
+        }
+        let datalink = matches!(self.output, Output::Datalink(_));
+        if datalink && record.link_type != LINKTYPE_ETHERNET {
+            return Err(format!("链路类型 {} 不是以太网", record.link_type));
+        }
+        let Some(offset) = pcap::ipv4_offset(record.link_type, &record.data) else {
+            // 数据链路层途径原样发送 ARP 等非 IPv4 帧
+            if datalink {
+                return Ok(record.data.clone());
+            }
+            return Err("不是 IPv4".to_string());
+        };
+
+        let frame = &record.data;
+        let ip = &frame[offset..];
+        let total_len = ip
+            .get(2..4)
+            .map(|len| u16::from_be_bytes([len[0], len[1]]) as usize)
+            .filter(|len| *len >= 20 && *len <= ip.len())
+            .ok_or("IP 总长度与抓包长度不符")?;
+        let mut packet = ip[..total_len].to_vec();
+        let mut modified = false;
+
+        if !self.rules.is_empty() {
+            rewrite::rewrite(&mut packet, &self.rules).map_err(|e| e.to_string())?;
+            modified = true;
+        }
+        if let Some(place) = self.args.marker {
+            match self.insert_marker(&mut packet, place) {
+                Ok(()) => modified = true,
+                Err(reason) => *self.unmarked.entry(reason).or_default() += 1,
+            }
+        }
+        if modified {
+            rewrite::recompute_checksums(&mut packet).map_err(|e| e.to_string())?;
+        }
+
+        if !datalink {
+            return Ok(packet);
+        }
+        // 保留链路层首部和尾部填充
+        let mut out = frame[..offset].to_vec();
+        out.extend_from_slice(&packet);
+        out.extend_from_slice(&frame[offset + total_len..]);
+        Ok(out)
+    }
+
+    /// 插入标记；TCP 选项放不下或不是 TCP 时改放在 IP 选项中
+    fn insert_marker(&mut self, packet: &mut Vec<u8>, place: MarkerPlace) -> Result<(), String> {
+        if u16::from_be_bytes([packet[6], packet[7]]) & 0x1fff != 0 {
+            return Err("非首个分片".to_string());
+        }
+        let ctx = AuthContext::from_ipv4(packet).ok_or("数据包不完整")?;
+        let encoded = self
+            .codec
+            .encode(&Marker::new(self.args.tag, self.marked), &ctx)
+            .map_err(|e| e.to_string())?;
+        if place == MarkerPlace::Tcp && packet[9] == 6 && !rewrite::is_fragment(packet) {
+            let option = TcpOptionCarrier::default().wrap(encoded.clone());
+            if rewrite::insert_tcp_option(packet, &option).is_ok() {
+                self.marked = self.marked.wrapping_add(1);
+                return Ok(());
+            }
+        }
+        let option = Ipv4OptionCarrier::default().wrap(encoded);
+        rewrite::insert_ip_option(packet, &option).map_err(|e| e.to_string())?;
+        self.marked = self.marked.wrapping_add(1);
+        Ok(())
+    }
+
+    fn send(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        match &mut self.output {
+            Output::DryRun => Ok(data.len()),
+            Output::Raw(raw) => raw.send(data),
+            Output::Datalink(link) => link.send_frame(data),
+        }
+    }
+}
+
+pub fn run(args: &ReplayArgs, rate: &RateTemplate) -> Result<()> {
+    let codec = MarkerCodec::from_env().context("标记密钥配置错误")?;
+    if args.marker.is_some() {
+        codec.check_sender().context("标记密钥配置错误")?;
+    }
+    let output = if args.dry_run {
+        Output::DryRun
+    } else if args.datalink {
+        let name = args
+            .interface
+            .as_deref()
+            .expect("clap 保证 --datalink 带 -i");
+        let link = LinkSender::new(name)
+            .with_context(|| format!("无法在 {} 上创建数据链路层通道", name))?;
+        Output::Datalink(link)
+    } else {
+        let raw = RawSender::new().context("无法创建原始套接字 (需要 root 或 CAP_NET_RAW)")?;
+        if let Some(name) = &args.interface {
+            raw.bind_to_device(name)
+                .with_context(|| format!("无法绑定接口 {}", name))?;
+        }
+        Output::Raw(raw)
+    };
+
+    let mut replayer = Replayer {
+        args,
+        rules: RewriteRules {
+            source: args.source.clone(),
+            destination: args.destination.clone(),
+            source_port: args.source_port.clone(),
+            destination_port: args.destination_port.clone(),
+        },
+        codec,
+        output,
+        marked: 0,
+        skipped: BTreeMap::new(),
+        unmarked: BTreeMap::new(),
+    };
+
+    // 限速时按速率发送，否则按抓包的时间间隔
+    let original_timing = rate.rate().is_none() && !args.topspeed;
+    let mut pacer = Pacer::new(
+        rate.rate(),
+        rate.burst.unwrap_or(1),
+        rate.jitter.unwrap_or_default(),
+    );
+    let start = Instant::now();
+    let elapsed = |pacer: &Pacer| {
+        if original_timing {
+            start.elapsed()
+        } else {
+            pacer.span()
+        }
+    };
+    let mut stats = SendStats::default();
+    let mut records: u64 = 0;
+    pacing::catch_interrupt();
+
+    'passes: for _ in 0..args.loops {
+        let mut reader = PcapReader::open(&args.file)
+            .with_context(|| format!("无法打开 {}", args.file.display()))?;
+        let pass_start = Instant::now();
+        let mut first_timestamp = None;
+        while let Some(record) = reader.next_record().context("读取抓包文件失败")? {
+            if pacing::interrupted()
+                || rate
+                    .duration
+                    .is_some_and(|duration| elapsed(&pacer) >= duration)
+            {
+                break 'passes;
+            }
+            records += 1;
+            if original_timing {
+                let first = *first_timestamp.get_or_insert(record.timestamp);
+                let gap = record.timestamp.saturating_sub(first);
+                let deadline = Duration::try_from_secs_f64(gap.as_secs_f64() / args.speed)
+                    .ok()
+                    .and_then(|offset| pass_start.checked_add(offset))
+                    .with_context(|| {
+                        format!("按 --speed {} 缩放 {:?} 的间隔后超出范围", args.speed, gap)
+                    })?;
+                pacing::sleep_until(deadline);
+            } else {
+                pacer.wait();
+            }
+
+            let data = match replayer.prepare(&record) {
+                Ok(data) => data,
+                Err(reason) => {
+                    *replayer.skipped.entry(reason).or_default() += 1;
+                    continue;
+                }
+            };
+            if args.dry_run {
+                println!(
+                    "#{} {} 字节: {}",
+                    records - 1,
+                    data.len(),
+                    hex::encode(&data)
+                );
+            }
+            stats.record(&replayer.send(&data));
+            pacer.sent(data.len());
+        }
+    }
+
+    stats.elapsed = elapsed(&pacer);
+    println!(
+        "回放 {}: 读取 {} 条记录, 插入 {} 个标记",
+        args.file.display(),
+        records,
+        replayer.marked
+    );
+    if let Some(target) = rate.rate() {
+        println!("目标速率 {}", target);
+    }
+    if !args.dry_run {
+        println!("{}", stats);
+    }
+    for (reason, count) in &replayer.skipped {
+        println!("  跳过 ({}) x{}", reason, count);
+    }
+    for (reason, count) in &replayer.unmarked {
+        println!("  未插入标记 ({}) x{}", reason, count);
+    }
+    Ok(())
+}
@@ -0,0 +1,316 @@
+//! 命令行参数中选项、标志和负载的文本格式
+
+use std::net::Ipv4Addr;
+use std::time::Duration;
+
+use ip_header::hex;
+use ip_header::ipv4_option::{Ipv4Option, TimestampEntry, TimestampFlag};
+use ip_header::rewrite::Mapping;
+use ip_header::tcp_option::{self, TcpOption};
+use pnet::packet::tcp::TcpFlags;
+use pnet::util::MacAddr;
+
+/// IP 选项格式说明，用于 `--help`
+pub const IP_OPTION_HELP: &str = "IP 选项 (可重复): nop | eol | rr:<槽数> | ts:<槽数> | tsaddr:<槽数> | \
+     lsrr:<地址,...> | ssrr:<地址,...> | ra[:<值>] | sid:<值> | sec:<等级>[:<hex>] | <kind>:<hex>";
+
+/// TCP 选项格式说明，用于 `--help`
+pub const TCP_OPTION_HELP: &str = "TCP 选项 (可重复): nop | eol | mss:<值> | ws:<位移> | sackok | \
+     sack:<左>-<右>[,...] | ts:<值>[:<回显>] | tfo[:<hex>] | mptcp:<hex> | exp:<exid>[:<hex>] | \
+     <kind>:<hex>";
+
+/// 解析十进制或 `0x` 前缀的十六进制整数
+pub fn parse_u32(text: &str) -> Result<u32, String> {
+    let parsed = match text.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => text.parse(),
+    };
+    parsed.map_err(|_| format!("无效的数值: {}", text))
+}
+
+pub fn parse_u16(text: &str) -> Result<u16, String> {
+    u16::try_from(parse_u32(text)?).map_err(|_| format!("数值超出 0 ~ 65535: {}", text))
+}
+
+pub fn parse_u8(text: &str) -> Result<u8, String> {
+    u8::try_from(parse_u32(text)?).map_err(|_| format!("数值超出 0 ~ 255: {}", text))
+}
+
+pub fn parse_hex(text: &str) -> Result<Vec<u8>, String> {
+    hex::decode(text).ok_or_else(|| format!("无效的十六进制串: {}", text))
+}
+
+pub fn parse_mac(text: &str) -> Result<MacAddr, String> {
+    text.parse()
+        .map_err(|_| format!("无效的 MAC 地址: {}", text))
+}
+
+/// 解析带可选 k / M / G (10 的幂) 后缀的正数，用于包速率和比特速率
+pub fn parse_scaled(text: &str) -> Result<f64, String> {
+    let text = text.trim();
+    let (number, scale) = match text.char_indices().last() {
+        Some((i, 'k' | 'K')) => (&text[..i], 1e3),
+        Some((i, 'm' | 'M')) => (&text[..i], 1e6),
+        Some((i, 'g' | 'G')) => (&text[..i], 1e9),
+        _ => (text, 1.0),
+    };
+    match number.parse::<f64>() {
+        Ok(value) if value > 0.0 && value.is_finite() => Ok(value * scale),
+        _ => Err(format!("应为正数 (可带 k / M / G 后缀): {}", text)),
+    }
+}
+
+/// 解析时长：`10`、`1.5s`、`500ms`、`200us`、`2m`，不带单位时为秒
+pub fn parse_duration(text: &str) -> Result<Duration, String> {
+    let text = text.trim();
+    let split = text
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let scale = match unit {
+        "" | "s" => 1.0,
+        "ms" => 1e-3,
+        "us" => 1e-6,
+        "m" => 60.0,
+        _ => return Err(format!("未知的时间单位 (s / ms / us / m): {}", text)),
+    };
+    number
+        .parse::<f64>()
+        .ok()
+        .and_then(|value| Duration::try_from_secs_f64(value * scale).ok())
+        .ok_or_else(|| format!("无效的时长: {}", text))
+}
+
+/// 改写规则 `[<原值>=]<新值>`，省略原值时改写所有值
+fn parse_mapping<T>(
+    text: &str,
+    parse: impl Fn(&str) -> Result<T, String>,
+) -> Result<Mapping<T>, String> {
+    match text.split_once('=') {
+        Some((from, to)) => Ok(Mapping {
+            from: Some(parse(from)?),
+            to: parse(to)?,
+        }),
+        None => Ok(Mapping {
+            from: None,
+            to: parse(text)?,
+        }),
+    }
+}
+
+pub fn parse_address_mapping(text: &str) -> Result<Mapping<Ipv4Addr>, String> {
+    parse_mapping(text, |addr| {
+        addr.parse().map_err(|_| format!("无效的地址: {}", addr))
+    })
+}
+
+pub fn parse_port_mapping(text: &str) -> Result<Mapping<u16>, String> {
+    parse_mapping(text, parse_u16)
+}
+
+/// 逗号分隔的地址列表
+fn parse_route(text: &str) -> Result<Vec<Ipv4Addr>, String> {
+    text.split(',')
+        .map(|addr| {
+            addr.trim()
+                .parse()
+                .map_err(|_| format!("无效的地址: {}", addr))
+        })
+        .collect()
+}
+
+/// `<kind>:<hex>` 形式的未识别选项
+///
+/// kind 0 (EOL) 和 1 (NOP) 只有一个字节，不能按 TLV 编码，需用 `eol` / `nop`。
+fn parse_raw(name: &str, arg: Option<&str>) -> Result<(u8, Vec<u8>), String> {
+    let kind = parse_u8(name).map_err(|_| format!("未知的选项: {}", name))?;
+    if kind < 2 {
+        return Err(format!(
+            "选项 kind {} 没有长度字节，请使用 {}",
+            kind,
+            if kind == 0 { "eol" } else { "nop" }
+        ));
+    }
+    let data = match arg {
+        Some(hex) if !hex.is_empty() => parse_hex(hex)?,
+        _ => Vec::new(),
+    };
+    Ok((kind, data))
+}
+
+/// 解析一个 IP 选项，格式见 [`IP_OPTION_HELP`]
+pub fn parse_ip_option(spec: &str) -> Result<Ipv4Option, String> {
+    let (name, arg) = match spec.split_once(':') {
+        Some((name, arg)) => (name, Some(arg)),
+        None => (spec, None),
+    };
+    let need = |what: &str| arg.ok_or_else(|| format!("{} 需要参数: {}", name, what));
+    let option = match name {
+        "nop" => Ipv4Option::Nop,
+        "eol" => Ipv4Option::EndOfList,
+        "rr" => Ipv4Option::RecordRoute {
+            pointer: 4,
+            route: vec![Ipv4Addr::UNSPECIFIED; parse_u8(need("槽数")?)? as usize],
+        },
+        "ts" | "tsaddr" => {
+            let slots = parse_u8(need("槽数")?)? as usize;
+            let (flag, address) = if name == "ts" {
+                (TimestampFlag::TimestampOnly, None)
+            } else {
+                (
+                    TimestampFlag::AddressAndTimestamp,
+                    Some(Ipv4Addr::UNSPECIFIED),
+                )
+            };
+            Ipv4Option::Timestamp {
+                pointer: 5,
+                overflow: 0,
+                flag,
+                entries: vec![
+                    TimestampEntry {
+                        address,
+                        timestamp: 0,
+                    };
+                    slots
+                ],
+            }
+        }
+        "lsrr" => Ipv4Option::LooseSourceRoute {
+            pointer: 4,
+            route: parse_route(need("地址列表")?)?,
+        },
+        "ssrr" => Ipv4Option::StrictSourceRoute {
+            pointer: 4,
+            route: parse_route(need("地址列表")?)?,
+        },
+        "ra" => Ipv4Option::RouterAlert(arg.map(parse_u16).transpose()?.unwrap_or(0)),
+        "sid" => Ipv4Option::StreamId(parse_u16(need("流标识")?)?),
+        "sec" => {
+            let arg = need("等级")?;
+            let (level, authority) = arg.split_once(':').unwrap_or((arg, ""));
+            Ipv4Option::Security {
+                classification: parse_u8(level)?,
+                protection_authority: if authority.is_empty() {
+                    Vec::new()
+                } else {
+                    parse_hex(authority)?
+                },
+            }
+        }
+        _ => {
+            let (kind, data) = parse_raw(name, arg)?;
+            Ipv4Option::Unknown { kind, data }
+        }
+    };
+    Ok(option)
+}
+
+/// 解析一个 TCP 选项，格式见 [`TCP_OPTION_HELP`]
+pub fn parse_tcp_option(spec: &str) -> Result<TcpOption, String> {
+    let (name, arg) = match spec.split_once(':') {
+        Some((name, arg)) => (name, Some(arg)),
+        None => (spec, None),
+    };
+    let need = |what: &str| arg.ok_or_else(|| format!("{} 需要参数: {}", name, what));
+    let option = match name {
+        "nop" => TcpOption::Nop,
+        "eol" => TcpOption::EndOfList,
+        "mss" => TcpOption::Mss(parse_u16(need("MSS")?)?),
+        "ws" => TcpOption::WindowScale(parse_u8(need("位移")?)?),
+        "sackok" => TcpOption::SackPermitted,
+        "sack" => {
+            let blocks = need("块列表")?
+                .split(',')
+                .map(|block| {
+                    let (left, right) = block
+                        .split_once('-')
+                        .ok_or_else(|| format!("SACK 块应为 <左>-<右>: {}", block))?;
+                    Ok((parse_u32(left)?, parse_u32(right)?))
+                })
+                .collect::<Result<_, String>>()?;
+            TcpOption::Sack(blocks)
+        }
+        "ts" => {
+            let arg = need("时间戳")?;
+            let (value, echo) = arg.split_once(':').unwrap_or((arg, "0"));
+            TcpOption::Timestamps {
+                value: parse_u32(value)?,
+                echo_reply: parse_u32(echo)?,
+            }
+        }
+        "tfo" => TcpOption::FastOpen(arg.map(parse_hex).transpose()?.unwrap_or_default()),
+        "mptcp" => TcpOption::Mptcp(parse_hex(need("数据")?)?),
+        "exp" => {
+            let arg = need("ExID")?;
+            let (exid, data) = arg.split_once(':').unwrap_or((arg, ""));
+            TcpOption::Experimental {
+                kind: tcp_option::KIND_EXPERIMENTAL_1,
+                exid: parse_u16(exid)?,
+                data: if data.is_empty() {
+                    Vec::new()
+                } else {
+                    parse_hex(data)?
+                },
+            }
+        }
+        _ => {
+            let (kind, data) = parse_raw(name, arg)?;
+            TcpOption::Unknown { kind, data }
+        }
+    };
+    Ok(option)
+}
+
+/// 解析逗号分隔的 TCP 标志名 (syn,ack,...)，`none` 表示不置任何标志
+pub fn parse_tcp_flags(text: &str) -> Result<u8, String> {
+    let mut flags = 0;
+    for name in text.split(',').map(str::trim) {
+        flags |= match name.to_ascii_lowercase().as_str() {
+            "none" => 0,
+            "fin" => TcpFlags::FIN,
+            "syn" => TcpFlags::SYN,
+            "rst" => TcpFlags::RST,
+            "psh" => TcpFlags::PSH,
+            "ack" => TcpFlags::ACK,
+            "urg" => TcpFlags::URG,
+            "ece" => TcpFlags::ECE,
+            "cwr" => TcpFlags::CWR,
+            _ => return Err(format!("未知的 TCP 标志: {}", name)),
+        };
+    }
+    Ok(flags)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn raw_options_need_a_length_byte() {
+        assert_eq!(
+            parse_ip_option("0x88:0102").unwrap(),
+            Ipv4Option::Unknown {
+                kind: 0x88,
+                data: vec![1, 2]
+            }
+        );
+        assert_eq!(
+            parse_ip_option("0").unwrap_err(),
+            "选项 kind 0 没有长度字节，请使用 eol"
+        );
+        assert_eq!(
+            parse_ip_option("1:00").unwrap_err(),
+            "选项 kind 1 没有长度字节，请使用 nop"
+        );
+        assert_eq!(
+            parse_tcp_option("0x00").unwrap_err(),
+            "选项 kind 0 没有长度字节，请使用 eol"
+        );
+        assert_eq!(
+            parse_tcp_option("1").unwrap_err(),
+            "选项 kind 1 没有长度字节，请使用 nop"
+        );
+        assert_eq!(parse_ip_option("nop").unwrap(), Ipv4Option::Nop);
+        assert_eq!(parse_tcp_option("eol").unwrap(), TcpOption::EndOfList);
+    }
+}
@@ -0,0 +1,856 @@
+//! 数据包模板
+//!
+//! 用 TOML / JSON / YAML 文件描述要发送的数据包，按扩展名选择格式。数值字段既可以
+//! 是固定值，也可以是逐包变化的范围：
+//!
+//! - `8001` 或 `"0x1f41"`: 固定值
+//! - `"40000-40010"`: 在闭区间内逐包递增，到头后回到起点；`"40000-40010/2"` 步长为 2
+//! - `"1000+"`: 从 1000 开始逐包递增，超过字段宽度时回绕；`"1000+4"` 步长为 4
+//!
+//! ```toml
+//! count = 20
+//!
+//! [ip]
+//! destination = "127.0.0.1"
+//! ttl = "32-64"
+//! df = true
+//! id = "counter"
+//! options = ["rr:3"]
+//!
+//! [marker]
+//! tag = 7
+//!
+//! [udp]
+//! sport = "40000-40009"
+//! dport = 8001
+//!
+//! [payload]
+//! text = "Hello, Biaoshi!"
+//!
+//! [rate]
+//! pps = "1k"
+//! duration = "10s"
+//!
+//! [fragment]
+//! mtu = 576
+//! order = "reverse"
+//! ```
+//!
+//! 顶层的 `count` 和 `[rate]` 中的 `duration` 先到者为准，只给 `duration` 时发送到时长结束。
+//!
+//! 传输层 `[udp]` / `[tcp]` / `[icmp]` / `[raw]` 必须且只能出现一个。IP 选项和 TCP
+//! 选项沿用命令行的写法，见 [`spec::IP_OPTION_HELP`] 和 [`spec::TCP_OPTION_HELP`]。
+//! `[ip]` 中设置 `datalink = true` 时经数据链路层发送，可用 `destination_mac` 指定下一跳 MAC。
+
+use std::fmt;
+use std::net::Ipv4Addr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{Context, Result, bail};
+use clap::ValueEnum;
+use ip_header::fragment::{FragmentOrder, Fragmenter};
+use ip_header::ip_id::strategy_from_name;
+use ip_header::ipv4_option::Ipv4Option;
+use ip_header::pacing::{MAX_INTERVAL, Rate};
+use ip_header::tcp_option::TcpOption;
+use pnet::packet::ip::{IpNextHeaderProtocol, IpNextHeaderProtocols};
+use pnet::packet::tcp::TcpFlags;
+use pnet::util::MacAddr;
+use serde::{Deserialize, Deserializer};
+
+use crate::spec;
+
+/// 可按范围变化的整数字段的取值类型
+pub trait FieldInt: Copy {
+    const MAX: u64;
+    /// 调用方保证 `value <= MAX`
+    fn from_u64(value: u64) -> Self;
+    fn to_u64(self) -> u64;
+}
+
+macro_rules! field_int {
+    ($($ty:ty),*) => {
+        $(impl FieldInt for $ty {
+            const MAX: u64 = <$ty>::MAX as u64;
+
+            fn from_u64(value: u64) -> Self {
+                value as $ty
+            }
+
+            fn to_u64(self) -> u64 {
+                self as u64
+            }
+        })*
+    };
+}
+
+field_int!(u8, u16, u32);
+
+/// 整数字段：固定值或逐包变化的范围
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "FieldText", bound = "T: FieldInt")]
+pub enum Field<T> {
+    Fixed(T),
+    /// 在 `[start, end]` 内按步长循环
+    Range {
+        start: T,
+        end: T,
+        step: T,
+    },
+    /// 从 `start` 起按步长递增，按字段宽度回绕
+    Step {
+        start: T,
+        step: T,
+    },
+}
+
+impl<T: FieldInt> Field<T> {
+    /// 第 `index` 个包 (从 0 开始) 的取值
+    pub fn at(&self, index: u32) -> T {
+        let index = index as u64;
+        match *self {
+            Field::Fixed(value) => value,
+            Field::Range { start, end, step } => {
+                let span = end.to_u64() - start.to_u64() + 1;
+                let offset = (index * step.to_u64()) % span;
+                T::from_u64(start.to_u64() + offset)
+            }
+            Field::Step { start, step } => {
+                let value = start.to_u64() + index * step.to_u64();
+                T::from_u64(value % (T::MAX + 1))
+            }
+        }
+    }
+}
+
+/// 模板文件中字段的原始形式：整数或文本
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum FieldText {
+    Number(u64),
+    Text(String),
+}
+
+fn field_number<T: FieldInt>(text: &str) -> Result<T, String> {
+    let value = spec::parse_u32(text.trim())? as u64;
+    if value > T::MAX {
+        return Err(format!("数值超出 0 ~ {}: {}", T::MAX, text));
+    }
+    Ok(T::from_u64(value))
+}
+
+impl<T: FieldInt> TryFrom<FieldText> for Field<T> {
+    type Error = String;
+
+    fn try_from(raw: FieldText) -> Result<Self, String> {
+        let text = match raw {
+            FieldText::Number(value) if value <= T::MAX => {
+                return Ok(Field::Fixed(T::from_u64(value)));
+            }
+            FieldText::Number(value) => return Err(format!("数值超出 0 ~ {}: {}", T::MAX, value)),
+            FieldText::Text(text) => text,
+        };
+        let (body, step) = match text.split_once('/') {
+            Some((body, step)) => (body, Some(field_number::<T>(step)?)),
+            None => (text.as_str(), None),
+        };
+        let field = if let Some((start, increment)) = body.split_once('+') {
+            let step = match (increment, step) {
+                ("", step) => step.unwrap_or(T::from_u64(1)),
+                (increment, None) => field_number::<T>(increment)?,
+                (_, Some(_)) => return Err(format!("步长重复指定: {}", text)),
+            };
+            Field::Step {
+                start: field_number(start)?,
+                step,
+            }
+        } else if let Some((start, end)) = body.split_once('-') {
+            let (start, end) = (field_number::<T>(start)?, field_number::<T>(end)?);
+            if start.to_u64() > end.to_u64() {
+                return Err(format!("范围起点大于终点: {}", text));
+            }
+            Field::Range {
+                start,
+                end,
+                step: step.unwrap_or(T::from_u64(1)),
+            }
+        } else {
+            Field::Fixed(field_number(body)?)
+        };
+        match field {
+            Field::Range { step, .. } | Field::Step { step, .. } if step.to_u64() == 0 => {
+                Err(format!("步长不能为 0: {}", text))
+            }
+            _ => Ok(field),
+        }
+    }
+}
+
+/// 标记的载体
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MarkerPlace {
+    /// IP 选项 0x79
+    Ip,
+    /// TCP 实验选项 253
+    Tcp,
+}
+
+fn ip_options<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Ipv4Option>, D::Error> {
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|text| spec::parse_ip_option(text))
+        .collect::<Result<_, _>>()
+        .map_err(serde::de::Error::custom)
+}
+
+fn tcp_options<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<TcpOption>, D::Error> {
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|text| spec::parse_tcp_option(text))
+        .collect::<Result<_, _>>()
+        .map_err(serde::de::Error::custom)
+}
+
+fn tcp_flags<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u8, D::Error> {
+    spec::parse_tcp_flags(&String::deserialize(deserializer)?).map_err(serde::de::Error::custom)
+}
+
+/// 速率、时长的原始形式：数值或带单位的文本
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum NumberText {
+    Number(f64),
+    Text(String),
+}
+
+/// 数值或带 k / M / G 后缀的文本
+fn scaled<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<f64>, D::Error> {
+    let value = match NumberText::deserialize(deserializer)? {
+        NumberText::Number(value) => value.to_string(),
+        NumberText::Text(text) => text,
+    };
+    spec::parse_scaled(&value)
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
+
+fn duration<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Duration>, D::Error> {
+    let value = match NumberText::deserialize(deserializer)? {
+        NumberText::Number(value) => value.to_string(),
+        NumberText::Text(text) => text,
+    };
+    spec::parse_duration(&value)
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
+
+fn mac_addr<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<MacAddr>, D::Error> {
+    spec::parse_mac(&String::deserialize(deserializer)?)
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
+
+fn fragment_order<'de, D: Deserializer<'de>>(deserializer: D) -> Result<FragmentOrder, D::Error> {
+    let name = String::deserialize(deserializer)?;
+    FragmentOrder::from_name(&name)
+        .ok_or_else(|| serde::de::Error::custom(format!("未知的分片顺序: {}", name)))
+}
+
+fn hex_bytes<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Vec<u8>>, D::Error> {
+    spec::parse_hex(&String::deserialize(deserializer)?)
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
+
+fn default_ttl() -> Field<u8> {
+    Field::Fixed(64)
+}
+
+fn default_id() -> String {
+    "seconds".to_string()
+}
+
+fn default_tag() -> u16 {
+    1
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_port() -> Field<u16> {
+    Field::Fixed(54321)
+}
+
+fn default_flags() -> u8 {
+    TcpFlags::SYN
+}
+
+fn default_window() -> Field<u16> {
+    Field::Fixed(65535)
+}
+
+fn default_icmp_type() -> u8 {
+    8
+}
+
+fn default_sequence() -> Field<u16> {
+    Field::Step { start: 0, step: 1 }
+}
+
+fn zero<T: FieldInt>() -> Field<T> {
+    Field::Fixed(T::from_u64(0))
+}
+
+/// IP 层
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct IpTemplate {
+    pub destination: Ipv4Addr,
+    /// 未指定时按路由表选择
+    #[serde(default)]
+    pub source: Option<Ipv4Addr>,
+    /// 出接口，未指定时按路由表选择
+    #[serde(default)]
+    pub interface: Option<String>,
+    #[serde(default = "default_ttl")]
+    pub ttl: Field<u8>,
+    #[serde(default = "zero")]
+    pub tos: Field<u8>,
+    #[serde(default)]
+    pub df: bool,
+    /// IP ID 编码策略名
+    #[serde(default = "default_id")]
+    pub id: String,
+    #[serde(default, deserialize_with = "ip_options")]
+    pub options: Vec<Ipv4Option>,
+    /// 经数据链路层发送以太网帧，IP 首部不经内核改写
+    #[serde(default)]
+    pub datalink: bool,
+    /// 下一跳的 MAC 地址，未指定时查邻居表或发 ARP 解析
+    #[serde(default, deserialize_with = "mac_addr")]
+    pub destination_mac: Option<MacAddr>,
+}
+
+/// 标记
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MarkerTemplate {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    #[serde(default = "default_tag")]
+    pub tag: u16,
+    /// 未指定时 TCP 放在 TCP 选项中，其余放在 IP 选项中
+    #[serde(default)]
+    pub place: Option<MarkerPlace>,
+}
+
+impl Default for MarkerTemplate {
+    fn default() -> Self {
+        MarkerTemplate {
+            enabled: true,
+            tag: default_tag(),
+            place: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UdpTemplate {
+    #[serde(default = "default_port")]
+    pub sport: Field<u16>,
+    pub dport: Field<u16>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TcpTemplate {
+    #[serde(default = "default_port")]
+    pub sport: Field<u16>,
+    pub dport: Field<u16>,
+    /// 逗号分隔的标志名
+    #[serde(default = "default_flags", deserialize_with = "tcp_flags")]
+    pub flags: u8,
+    #[serde(default = "zero")]
+    pub seq: Field<u32>,
+    #[serde(default = "zero")]
+    pub ack: Field<u32>,
+    #[serde(default = "default_window")]
+    pub window: Field<u16>,
+    #[serde(default, deserialize_with = "tcp_options")]
+    pub options: Vec<TcpOption>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct IcmpTemplate {
+    #[serde(rename = "type", default = "default_icmp_type")]
+    pub icmp_type: u8,
+    #[serde(default)]
+    pub code: u8,
+    /// echo identifier
+    #[serde(default = "zero")]
+    pub id: Field<u16>,
+    /// echo sequence，默认从 0 逐包递增
+    #[serde(default = "default_sequence")]
+    pub seq: Field<u16>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RawTemplate {
+    /// IP 协议号
+    pub protocol: u8,
+}
+
+/// 传输层，模板中只能有一个
+#[derive(Debug, Clone)]
+pub enum TransportTemplate {
+    Udp(UdpTemplate),
+    Tcp(TcpTemplate),
+    Icmp(IcmpTemplate),
+    Raw(RawTemplate),
+}
+
+impl TransportTemplate {
+    pub fn protocol(&self) -> IpNextHeaderProtocol {
+        match self {
+            TransportTemplate::Udp(_) => IpNextHeaderProtocols::Udp,
+            TransportTemplate::Tcp(_) => IpNextHeaderProtocols::Tcp,
+            TransportTemplate::Icmp(_) => IpNextHeaderProtocols::Icmp,
+            TransportTemplate::Raw(raw) => IpNextHeaderProtocol(raw.protocol),
+        }
+    }
+
+    /// 第 `index` 个包的源端口和目的端口，没有端口的协议为 (0, 0)
+    pub fn ports(&self, index: u32) -> (u16, u16) {
+        match self {
+            TransportTemplate::Udp(udp) => (udp.sport.at(index), udp.dport.at(index)),
+            TransportTemplate::Tcp(tcp) => (tcp.sport.at(index), tcp.dport.at(index)),
+            _ => (0, 0),
+        }
+    }
+}
+
+/// 负载来源
+#[derive(Debug, Clone, Default)]
+pub enum PayloadSource {
+    #[default]
+    Empty,
+    Bytes(Vec<u8>),
+    File(PathBuf),
+}
+
+impl PayloadSource {
+    pub fn load(&self) -> Result<Vec<u8>> {
+        match self {
+            PayloadSource::Empty => Ok(Vec::new()),
+            PayloadSource::Bytes(bytes) => Ok(bytes.clone()),
+            PayloadSource::File(path) => {
+                std::fs::read(path).with_context(|| format!("无法读取负载文件 {}", path.display()))
+            }
+        }
+    }
+}
+
+/// 模板文件中的负载写法，三者最多一个
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PayloadFile {
+    text: Option<String>,
+    #[serde(default, deserialize_with = "hex_bytes")]
+    hex: Option<Vec<u8>>,
+    file: Option<PathBuf>,
+}
+
+/// 发送速率；`pps` / `bps` / `duration` 都未指定时逐包发送，不限速
+///
+/// 速率和突发都按线上的 IP 包计：分片发送时每个分片算一个包。
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RateTemplate {
+    /// 目标包速率
+    #[serde(default, deserialize_with = "scaled")]
+    pub pps: Option<f64>,
+    /// 目标比特速率
+    #[serde(default, deserialize_with = "scaled")]
+    pub bps: Option<f64>,
+    /// 持续发送的时长，未指定 `count` 时发送到时长结束
+    #[serde(default, deserialize_with = "duration")]
+    pub duration: Option<Duration>,
+    /// 每个突发连续发送的包数
+    #[serde(default)]
+    pub burst: Option<u32>,
+    /// 每个突发发送时刻的随机偏移范围 (±)
+    #[serde(default, deserialize_with = "duration")]
+    pub jitter: Option<Duration>,
+}
+
+impl RateTemplate {
+    /// 是否为流量生成模式：限速或限时
+    pub fn is_generator(&self) -> bool {
+        self.pps.is_some() || self.bps.is_some() || self.duration.is_some()
+    }
+
+    pub fn rate(&self) -> Option<Rate> {
+        self.pps.map(Rate::Packets).or(self.bps.map(Rate::Bits))
+    }
+
+    pub fn validate(&self) -> Result<(), TemplateError> {
+        let invalid = |message: &str| Err(TemplateError(message.to_string()));
+        if self.pps.is_some() && self.bps.is_some() {
+            return invalid("pps 和 bps 只能指定一个");
+        }
+        if self.burst == Some(0) {
+            return invalid("burst 至少为 1");
+        }
+        if self.rate().is_none() && (self.burst.is_some() || self.jitter.is_some()) {
+            return invalid("burst / jitter 需要同时指定 pps 或 bps");
+        }
+        if self.rate().is_some_and(|rate| !rate.is_plausible()) {
+            return invalid("pps / bps 过低, 每个包的发送间隔不能超过 1 天");
+        }
+        if self.jitter.is_some_and(|jitter| jitter > MAX_INTERVAL) {
+            return invalid("jitter 不能超过 1 天");
+        }
+        Ok(())
+    }
+}
+
+/// 分片设置
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FragmentFile {
+    /// 分片的最大总长度
+    mtu: usize,
+    /// 第一个分片的数据长度
+    first: Option<usize>,
+    /// 后续分片向前重叠的字节数
+    #[serde(default)]
+    overlap: usize,
+    /// 重叠部分改写为该字节
+    fill: Option<u8>,
+    /// in-order / reverse / random
+    #[serde(default, deserialize_with = "fragment_order")]
+    order: FragmentOrder,
+}
+
+/// 模板文件的原始结构，校验后转换为 [`Template`]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TemplateFile {
+    #[serde(default)]
+    count: Option<u32>,
+    ip: IpTemplate,
+    #[serde(default)]
+    marker: MarkerTemplate,
+    udp: Option<UdpTemplate>,
+    tcp: Option<TcpTemplate>,
+    icmp: Option<IcmpTemplate>,
+    raw: Option<RawTemplate>,
+    #[serde(default)]
+    payload: PayloadFile,
+    #[serde(default)]
+    rate: RateTemplate,
+    fragment: Option<FragmentFile>,
+}
+
+/// 校验过的数据包模板
+#[derive(Debug, Clone)]
+pub struct Template {
+    /// 发送的包数，未指定时为 1，限时发送时不限
+    pub count: Option<u32>,
+    pub ip: IpTemplate,
+    pub marker: MarkerTemplate,
+    pub transport: TransportTemplate,
+    pub payload: PayloadSource,
+    pub rate: RateTemplate,
+    /// 按 MTU 分片发送
+    pub fragment: Option<Fragmenter>,
+}
+
+/// 模板内容错误
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TemplateError(String);
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for TemplateError {}
+
+impl TryFrom<TemplateFile> for Template {
+    type Error = TemplateError;
+
+    fn try_from(file: TemplateFile) -> Result<Self, TemplateError> {
+        let invalid = |message: &str| Err(TemplateError(message.to_string()));
+
+        let mut layers: Vec<TransportTemplate> = Vec::new();
+        layers.extend(file.udp.map(TransportTemplate::Udp));
+        layers.extend(file.tcp.map(TransportTemplate::Tcp));
+        layers.extend(file.icmp.map(TransportTemplate::Icmp));
+        layers.extend(file.raw.map(TransportTemplate::Raw));
+        if layers.len() != 1 {
+            return invalid("[udp] / [tcp] / [icmp] / [raw] 必须且只能有一个");
+        }
+        let transport = layers.remove(0);
+
+        if file.count == Some(0) {
+            return invalid("count 至少为 1");
+        }
+        file.rate.validate()?;
+        let fragment = file.fragment.map(|fragment| Fragmenter {
+            mtu: fragment.mtu,
+            first_size: fragment.first,
+            overlap: fragment.overlap,
+            overlap_fill: fragment.fill,
+            order: fragment.order,
+        });
+        if let Some(fragmenter) = &fragment {
+            if file.ip.df {
+                return invalid("设置了 df 的数据报不能分片");
+            }
+            fragmenter
+                .validate()
+                .map_err(|e| TemplateError(format!("分片设置错误: {}", e)))?;
+        }
+        if strategy_from_name(&file.ip.id).is_none() {
+            return Err(TemplateError(format!("未知的 IP ID 策略: {}", file.ip.id)));
+        }
+        if file.ip.destination_mac.is_some() && !file.ip.datalink {
+            return invalid("destination_mac 需要同时设置 datalink = true");
+        }
+        if file.marker.place == Some(MarkerPlace::Tcp)
+            && !matches!(transport, TransportTemplate::Tcp(_))
+        {
+            return invalid("marker.place = \"tcp\" 只能用于 TCP");
+        }
+
+        let PayloadFile {
+            text,
+            hex,
+            file: path,
+        } = file.payload;
+        let payload = match (text, hex, path) {
+            (None, None, None) => PayloadSource::Empty,
+            (Some(text), None, None) => PayloadSource::Bytes(text.into_bytes()),
+            (None, Some(bytes), None) => PayloadSource::Bytes(bytes),
+            (None, None, Some(path)) => PayloadSource::File(path),
+            _ => return invalid("payload 的 text / hex / file 只能指定一个"),
+        };
+
+        Ok(Template {
+            count: file.count,
+            ip: file.ip,
+            marker: file.marker,
+            transport,
+            payload,
+            rate: file.rate,
+            fragment,
+        })
+    }
+}
+
+impl Template {
+    /// 读取并校验模板文件，格式由扩展名决定 (.toml / .json / .yaml / .yml)
+    ///
+    /// 负载文件的相对路径相对于模板文件所在目录。
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("无法读取模板 {}", path.display()))?;
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        let file: TemplateFile = match extension.as_str() {
+            "toml" => toml::from_str(&text).map_err(anyhow::Error::from),
+            "json" => serde_json::from_str(&text).map_err(anyhow::Error::from),
+            "yaml" | "yml" => serde_norway::from_str(&text).map_err(anyhow::Error::from),
+            _ => bail!(
+                "无法识别模板格式 (应为 .toml / .json / .yaml): {}",
+                path.display()
+            ),
+        }
+        .with_context(|| format!("模板格式错误 {}", path.display()))?;
+
+        let mut template =
+            Template::try_from(file).with_context(|| format!("模板内容错误 {}", path.display()))?;
+        if let PayloadSource::File(payload) = &mut template.payload
+            && payload.is_relative()
+            && let Some(dir) = path.parent()
+        {
+            *payload = dir.join(&*payload);
+        }
+        Ok(template)
+    }
+
+    /// 标记的实际载体
+    pub fn marker_place(&self) -> MarkerPlace {
+        match (&self.transport, self.marker.place) {
+            (_, Some(place)) => place,
+            (TransportTemplate::Tcp(_), None) => MarkerPlace::Tcp,
+            _ => MarkerPlace::Ip,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field<T: FieldInt>(text: &str) -> Result<Field<T>, String> {
+        Field::try_from(FieldText::Text(text.to_string()))
+    }
+
+    #[test]
+    fn valid_field_specs() {
+        let cases: &[(&str, Field<u16>)] = &[
+            ("8001", Field::Fixed(8001)),
+            ("0x1f41", Field::Fixed(8001)),
+            (
+                "40000-40010",
+                Field::Range {
+                    start: 40000,
+                    end: 40010,
+                    step: 1,
+                },
+            ),
+            (
+                "40000-40010/2",
+                Field::Range {
+                    start: 40000,
+                    end: 40010,
+                    step: 2,
+                },
+            ),
+            (
+                "7-7",
+                Field::Range {
+                    start: 7,
+                    end: 7,
+                    step: 1,
+                },
+            ),
+            (
+                "1000+",
+                Field::Step {
+                    start: 1000,
+                    step: 1,
+                },
+            ),
+            (
+                "1000+4",
+                Field::Step {
+                    start: 1000,
+                    step: 4,
+                },
+            ),
+            (
+                "1000+/4",
+                Field::Step {
+                    start: 1000,
+                    step: 4,
+                },
+            ),
+            (
+                "65535+",
+                Field::Step {
+                    start: 65535,
+                    step: 1,
+                },
+            ),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(field::<u16>(text).as_ref(), Ok(expected), "{}", text);
+        }
+        assert_eq!(
+            Field::<u8>::try_from(FieldText::Number(255)),
+            Ok(Field::Fixed(255))
+        );
+    }
+
+    #[test]
+    fn invalid_field_specs() {
+        for text in [
+            "", "abc", "-5", "256", "0-256", "1-", "-1", "10-5", "1-3/0", "5+0", "5+/0", "1+2/3",
+            "1+x", "1-3/x", "1-2-3",
+        ] {
+            assert!(field::<u8>(text).is_err(), "{} 应该无效", text);
+        }
+        assert!(Field::<u8>::try_from(FieldText::Number(256)).is_err());
+        assert!(field::<u16>("0-65536").is_err());
+        assert!(field::<u32>("0x100000000").is_err());
+    }
+
+    #[test]
+    fn at_steps_through_range() {
+        assert_eq!(Field::Fixed(9u16).at(1000), 9);
+
+        // 闭区间内逐包递增，到头后回到起点
+        let range = field::<u16>("40000-40010").unwrap();
+        let values: Vec<u16> = [0, 1, 10, 11, 12].map(|i| range.at(i)).to_vec();
+        assert_eq!(values, vec![40000, 40001, 40010, 40000, 40001]);
+
+        // 步长不整除区间长度时按区间长度取模，第二轮从不同的位置开始
+        let range = field::<u16>("40000-40010/2").unwrap();
+        let values: Vec<u16> = (4..8).map(|i| range.at(i)).collect();
+        assert_eq!(values, vec![40008, 40010, 40001, 40003]);
+
+        let single = field::<u8>("7-7/3").unwrap();
+        assert_eq!((single.at(0), single.at(5)), (7, 7));
+
+        // 按字段宽度回绕
+        let step = field::<u8>("250+3").unwrap();
+        let values: Vec<u8> = (0..4).map(|i| step.at(i)).collect();
+        assert_eq!(values, vec![250, 253, 0, 3]);
+        assert_eq!(field::<u32>("4294967295+").unwrap().at(1), 0);
+
+        // 最大的起点、步长和序号都不会溢出
+        let step = Field::Step {
+            start: u32::MAX,
+            step: u32::MAX,
+        };
+        assert_eq!(step.at(u32::MAX), 0);
+        let range = field::<u32>("0-4294967295/4294967295").unwrap();
+        assert_eq!(range.at(u32::MAX), 1);
+    }
+    #[test]
+    fn formats_load_the_same_template() {
+        let files = [
+            (
+                "toml",
+                "count = 3\n[ip]\ndestination = \"127.0.0.1\"\nttl = \"32-64\"\n\
+                 [udp]\nsport = 40000\ndport = \"8001+\"\n",
+            ),
+            (
+                "json",
+                r#"{"count": 3, "ip": {"destination": "127.0.0.1", "ttl": "32-64"},
+                    "udp": {"sport": 40000, "dport": "8001+"}}"#,
+            ),
+            (
+                "yaml",
+                "count: 3\nip:\n  destination: 127.0.0.1\n  ttl: 32-64\n\
+                 udp:\n  sport: 40000\n  dport: 8001+\n",
+            ),
+        ];
+        let dir = std::env::temp_dir().join(format!("ipsend-template-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let loaded: Vec<String> = files
+            .iter()
+            .map(|(extension, text)| {
+                let path = dir.join(format!("packet.{}", extension));
+                std::fs::write(&path, text).unwrap();
+                format!("{:?}", Template::load(&path).unwrap())
+            })
+            .collect();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(loaded[0], loaded[1]);
+        assert_eq!(loaded[0], loaded[2]);
+    }
+}
@@ -0,0 +1,310 @@
+//! ipsniff: 抓取并解码 IPv4 数据包
+//!
+//! ```text
+//! ipsniff -i eth0 -p udp --port 8001
+//! ipsniff -i lo -p tcp -p 253 --id millis
+//! ipsniff -c 100 'src net 10.0.0.0/8 and (ipopt 0x79 or tcpopt 253)'
+//! ipsniff -d udp and port 8001
+//! ipsniff -f json udp and port 8001 | jq .marker_seq
+//! ipsniff -f csv --payload-encoding base64 -c 1000 > packets.csv
+//! ipsniff -w capture.pcapng --rotate-size 100M --rotate-interval 3600 udp
+//! ipsniff -r capture.pcapng -f json 'ipopt 0x79'
+//! ```
+//!
+//! 默认在所有接口 (`any`) 上抓包，可指定 `lo` 等任意接口。`-p`、`--port` 和过滤
+//! 表达式 (语法见 [`ip_header::bpf::parse`]) 编译为 BPF 程序附加到套接字，由内核
+//! 先行过滤。分片经重组器 (环境变量见 [`ip_header::reassembly`]) 收齐后再按同一
+//! 条件过滤和解码，输出 IP 首部、IP 选项、UDP / TCP 首部、TCP 选项、标记和校验和
+//! 验证结果。`-f json` / `-f csv` 改为每包一个 JSON 对象 / 一行 CSV，提示和汇总
+//! 打到标准错误。`-w` 把抓到的包写入 pcap / pcapng 文件 (可按大小或时间切换)，
+//! 只写入通过过滤的数据报 (分片在收齐并通过过滤后一起写入)，pcapng 中每个数据报
+//! 的最后一个帧带有标记和验证结果的注释。`-r` 改为分析其他
+//! 工具抓的 pcap / pcapng 文件，过滤、重组 (超时按抓包时间计算)、解码和输出都与
+//! 实时抓包相同。
+//! 按标记的源、目的地址和 tag 跟踪序号，统计丢失、重复和乱序的包，每隔
+//! `--report-interval` 秒 (读取文件时按抓包时间) 和结束时输出。配置了密钥时只有
+//! 认证通过的标记计入时延和序号统计，认证失败的标记单独计数。
+//! 标记密钥取自 `BIAOSHI_KEYS` / `BIAOSHI_KEY_FILE`，MAC 长度取自 `BIAOSHI_MAC_LEN`，
+//! 须与发送端一致。Ctrl-C 结束并输出汇总。
+
+mod offline;
+mod output;
+mod pipeline;
+
+use std::fs::File;
+use std::io::BufReader;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use clap::Parser;
+use ip_header::auth::MarkerCodec;
+use ip_header::bpf::{self, Direction, Filter, FilterError};
+use ip_header::capture::{ANY_INTERFACE, Capture};
+use ip_header::decode::{PROTOCOL_ICMP, PROTOCOL_TCP, PROTOCOL_UDP};
+use ip_header::ip_id::strategy_from_name;
+use ip_header::pacing;
+use ip_header::pcap::{LINKTYPE_RAW, PcapFormat, PcapReader, RotatingWriter, Rotation};
+use ip_header::reassembly::{Reassembler, ReassemblyConfig};
+use ip_header::timestamp::unix_micros;
+
+use output::{FrameMeta, OutputFormat, PayloadEncoding, Printer, TimeSource};
+use pipeline::Pipeline;
+
+/// 接收超时，到时检查是否收到 Ctrl-C 并清理超时的分片
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+#[derive(Parser)]
+#[command(name = "ipsniff", version, about = "抓取并解码 IPv4 数据包")]
+struct Cli {
+    /// 抓包接口，any 表示所有接口
+    #[arg(short, long, default_value = ANY_INTERFACE)]
+    interface: String,
+
+    /// 不抓包，改为分析 pcap / pcapng 文件
+    #[arg(
+        short = 'r',
+        long = "read",
+        value_name = "FILE",
+        conflicts_with = "interface"
+    )]
+    read: Option<PathBuf>,
+
+    /// 只显示这些协议: udp | tcp | icmp | <协议号>，可重复
+    #[arg(short, long = "protocol", value_parser = parse_protocol)]
+    protocols: Vec<u8>,
+
+    /// 只显示源或目的端口为这些值的 UDP / TCP 包，可重复
+    #[arg(long = "port")]
+    ports: Vec<u16>,
+
+    /// 发送端的 IP ID 编码策略，用于解释 ID 字段
+    #[arg(long = "id", default_value = "seconds", value_parser = parse_strategy_name)]
+    id_strategy: String,
+
+    /// 显示这么多个包后退出
+    #[arg(short = 'c', long)]
+    count: Option<u64>,
+
+    /// 每隔这么多秒输出各流的序号统计 (丢失、乱序、重复)，0 表示只在结束时输出
+    #[arg(long, value_name = "SECONDS", default_value = "10")]
+    report_interval: u64,
+
+    /// 输出格式
+    #[arg(short, long, value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+
+    /// JSON / CSV 中负载的编码
+    #[arg(long, value_enum, default_value_t = PayloadEncoding::Hex)]
+    payload_encoding: PayloadEncoding,
+
+    /// 把抓到的包写入抓包文件
+    #[arg(short = 'w', long = "write", value_name = "FILE")]
+    write: Option<PathBuf>,
+
+    /// 抓包文件格式 pcap | pcapng，默认按扩展名 (.pcap 为 pcap，其他为 pcapng)
+    #[arg(long, value_parser = parse_pcap_format, requires = "write")]
+    write_format: Option<PcapFormat>,
+
+    /// 文件达到这么大后切换到新文件，可带 k / M / G 后缀
+    #[arg(long, value_parser = parse_size, requires = "write")]
+    rotate_size: Option<u64>,
+
+    /// 每个文件最多覆盖这么多秒的抓包时间
+    #[arg(long, value_name = "SECONDS", value_parser = clap::value_parser!(u64).range(1..), requires = "write")]
+    rotate_interval: Option<u64>,
+
+    /// 打印编译出的 BPF 程序后退出
+    #[arg(short = 'd', long)]
+    dump_filter: bool,
+
+    /// 过滤表达式，如 udp and port 8001、src host 10.0.0.1、ipopt 0x79、tcpopt 253
+    #[arg(trailing_var_arg = true)]
+    expression: Vec<String>,
+}
+
+fn parse_protocol(text: &str) -> Result<u8, String> {
+    match text.to_ascii_lowercase().as_str() {
+        "udp" => Ok(PROTOCOL_UDP),
+        "tcp" => Ok(PROTOCOL_TCP),
+        "icmp" => Ok(PROTOCOL_ICMP),
+        other => other
+            .parse()
+            .map_err(|_| format!("未知的协议: {} (udp | tcp | icmp | 协议号)", text)),
+    }
+}
+
+fn parse_strategy_name(name: &str) -> Result<String, String> {
+    strategy_from_name(name)
+        .map(|_| name.to_string())
+        .ok_or_else(|| format!("未知的 IP ID 策略: {}", name))
+}
+
+fn parse_pcap_format(name: &str) -> Result<PcapFormat, String> {
+    PcapFormat::from_name(name)
+        .ok_or_else(|| format!("未知的抓包文件格式: {} (pcap | pcapng)", name))
+}
+
+/// 解析字节数，可带 k / M / G (10 的幂) 后缀
+fn parse_size(text: &str) -> Result<u64, String> {
+    let (number, scale) = match text.char_indices().last() {
+        Some((i, 'k' | 'K')) => (&text[..i], 1_000),
+        Some((i, 'm' | 'M')) => (&text[..i], 1_000_000),
+        Some((i, 'g' | 'G')) => (&text[..i], 1_000_000_000),
+        _ => (text, 1),
+    };
+    number
+        .parse::<u64>()
+        .ok()
+        .and_then(|value| value.checked_mul(scale))
+        .filter(|&size| size > 0)
+        .ok_or_else(|| format!("应为正整数 (可带 k / M / G 后缀): {}", text))
+}
+
+impl Cli {
+    /// `-p`、`--port` 与过滤表达式的合取；没有任何条件时为 `None`
+    fn filter(&self) -> Result<Option<Filter>, FilterError> {
+        let any_of = |filters: Vec<Filter>| {
+            filters
+                .into_iter()
+                .reduce(|a, b| Filter::Or(Box::new(a), Box::new(b)))
+        };
+        let mut parts = Vec::new();
+        parts.extend(any_of(
+            self.protocols
+                .iter()
+                .map(|p| Filter::Protocol(*p))
+                .collect(),
+        ));
+        parts.extend(any_of(
+            self.ports
+                .iter()
+                .map(|p| Filter::Port(Direction::Either, *p))
+                .collect(),
+        ));
+        if !self.expression.is_empty() {
+            parts.push(bpf::parse(&self.expression.join(" "))?);
+        }
+        Ok(parts
+            .into_iter()
+            .reduce(|a, b| Filter::And(Box::new(a), Box::new(b))))
+    }
+}
+
+/// 数据包来源
+enum Source {
+    Live(Capture),
+    File(PcapReader<BufReader<File>>),
+}
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let id_strategy = strategy_from_name(&cli.id_strategy).expect("clap 已校验策略名");
+    let filter = cli.filter().context("过滤表达式错误")?;
+    let compile = || {
+        filter
+            .as_ref()
+            .map(Filter::compile)
+            .transpose()
+            .context("无法编译过滤表达式")
+    };
+    if cli.dump_filter {
+        for instruction in compile()?.iter().flatten() {
+            println!("{}", instruction);
+        }
+        return Ok(());
+    }
+    let codec = MarkerCodec::from_env().context("标记密钥配置错误")?;
+    let config = ReassemblyConfig::from_env().context("分片重组配置错误")?;
+
+    let source = match &cli.read {
+        Some(path) => Source::File(
+            PcapReader::open(path).with_context(|| format!("无法打开 {}", path.display()))?,
+        ),
+        None => Source::Live(
+            Capture::open(&cli.interface, compile()?.as_deref(), POLL_INTERVAL).with_context(
+                || format!("无法在 {} 上抓包 (需要 root 或 CAP_NET_RAW)", cli.interface),
+            )?,
+        ),
+    };
+    let printer = Printer::new(
+        id_strategy,
+        cli.format,
+        cli.payload_encoding,
+        !codec.keys.is_empty(),
+    );
+    match &cli.read {
+        Some(path) => printer.note(&format!("读取文件: {}", path.display())),
+        None => printer.note(&format!("监听接口: {}", cli.interface)),
+    }
+    if let Some(filter) = &filter {
+        printer.note(&format!("过滤条件: {}", filter));
+    }
+    let writer = match &cli.write {
+        Some(path) => {
+            let format = cli
+                .write_format
+                .unwrap_or_else(|| PcapFormat::from_path(path));
+            let rotation = Rotation {
+                max_bytes: cli.rotate_size,
+                interval: cli.rotate_interval.map(Duration::from_secs),
+            };
+            let writer = RotatingWriter::create(path, format, LINKTYPE_RAW, rotation)
+                .with_context(|| format!("无法创建 {}", path.display()))?;
+            printer.note(&format!(
+                "写入文件: {} ({})",
+                writer.path().display(),
+                format.name()
+            ));
+            Some(writer)
+        }
+        None => None,
+    };
+
+    let mut pipeline = Pipeline::new(
+        printer,
+        filter,
+        codec,
+        Reassembler::new(config),
+        writer,
+        (cli.report_interval > 0).then(|| Duration::from_secs(cli.report_interval)),
+    );
+    pacing::catch_interrupt();
+    match source {
+        Source::Live(capture) => capture_live(&capture, &mut pipeline, cli.count)?,
+        Source::File(reader) => {
+            let path = cli.read.as_deref().expect("读取文件时才有 File 来源");
+            let name = path.file_name().map_or_else(
+                || path.display().to_string(),
+                |name| name.to_string_lossy().into_owned(),
+            );
+            offline::run(reader, &name, &mut pipeline, cli.count)?;
+        }
+    }
+    pipeline.finish()
+}
+
+/// 实时抓包，收到 Ctrl-C 或输出 `count` 个包后返回
+fn capture_live(capture: &Capture, pipeline: &mut Pipeline, count: Option<u64>) -> Result<()> {
+    let mut buf = vec![0u8; 65536];
+    while !pacing::interrupted() && count.is_none_or(|count| pipeline.packets() < count) {
+        let Some(frame) = capture.recv(&mut buf).context("接收失败")? else {
+            pipeline.idle(Instant::now())?;
+            continue;
+        };
+        let meta = FrameMeta {
+            received_us: frame.kernel_time.unwrap_or_else(unix_micros),
+            time_source: if frame.kernel_time.is_some() {
+                TimeSource::Kernel
+            } else {
+                TimeSource::User
+            },
+            interface: capture
+                .interface_name(frame.interface_index)
+                .map_or_else(|| format!("#{}", frame.interface_index), str::to_string),
+            outgoing: frame.outgoing,
+        };
+        pipeline.push(&meta, &buf[..frame.len], Instant::now())?;
+    }
+    Ok(())
+}
@@ -0,0 +1,61 @@
+//! 读取 pcap / pcapng 文件中的 IPv4 数据包，按抓包时间送入处理流程
+
+use std::fs::File;
+use std::io::BufReader;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use ip_header::pacing;
+use ip_header::pcap::{self, PcapReader};
+
+use crate::output::{FrameMeta, TimeSource};
+use crate::pipeline::Pipeline;
+
+/// 读完文件、收到 Ctrl-C 或输出 `count` 个包后返回
+///
+/// # 参数
+/// - `name`: 输出中代替接口名的文件名
+pub fn run(
+    mut reader: PcapReader<BufReader<File>>,
+    name: &str,
+    pipeline: &mut Pipeline,
+    count: Option<u64>,
+) -> Result<()> {
+    // 重组超时按抓包时间计算：以第一条记录为起点，把文件中的时间映射到单调时钟
+    let start = Instant::now();
+    let mut first: Option<Duration> = None;
+    let mut now = start;
+    let (mut records, mut truncated, mut not_ipv4) = (0u64, 0u64, 0u64);
+    while !pacing::interrupted() && count.is_none_or(|count| pipeline.packets() < count) {
+        let Some(record) = reader
+            .next_record()
+            .with_context(|| format!("读取 {} 失败", name))?
+        else {
+            break;
+        };
+        records += 1;
+        if record.is_truncated() {
+            truncated += 1;
+            continue;
+        }
+        let Some(offset) = pcap::ipv4_offset(record.link_type, &record.data) else {
+            not_ipv4 += 1;
+            continue;
+        };
+        let first = *first.get_or_insert(record.timestamp);
+        now = now.max(start + record.timestamp.saturating_sub(first));
+        let meta = FrameMeta {
+            received_us: record.timestamp.as_micros() as u64,
+            time_source: TimeSource::File,
+            interface: name.to_string(),
+            outgoing: false,
+        };
+        pipeline.push(&meta, &record.data[offset..], now)?;
+    }
+    pipeline.drain(now);
+    pipeline.note(&format!(
+        "\n读取 {} 条记录, 跳过截断的 {} 条、非 IPv4 的 {} 条",
+        records, truncated, not_ipv4
+    ));
+    Ok(())
+}
@@ -0,0 +1,727 @@
+//! 解码结果的输出和累计统计
+//!
+//! 文本格式供人阅读；JSON lines 每个包一个对象，CSV 每个包一行 (首行为列名)，
+//! 两者字段相同、顺序固定，提示信息和汇总改打到标准错误，标准输出只有数据。
+
+use clap::ValueEnum;
+use ip_header::auth::{AuthCounters, AuthStatus};
+use ip_header::decode::{
+    DecodedPacket, MarkerInfo, PROTOCOL_ICMP, PROTOCOL_TCP, PROTOCOL_UDP, TransportHeader,
+    tcp_flags_name,
+};
+use ip_header::ip_id::{FlowTuple, IdStrategy};
+use ip_header::ipv4_option::format_options;
+use ip_header::latency::{FlowKey, LatencyTracker};
+use ip_header::sequence::{SequenceEvent, SequenceTracker};
+use ip_header::timestamp::format_utc;
+use ip_header::validate::ChecksumStats;
+use ip_header::{base64, hex};
+use serde::ser::{Serialize, SerializeMap, Serializer};
+use serde_json::Value;
+
+/// 输出格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    Text,
+    /// 每行一个 JSON 对象
+    Json,
+    Csv,
+}
+
+/// 机器可读格式中负载的编码
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum PayloadEncoding {
+    Hex,
+    Base64,
+}
+
+/// 接收时间的来源
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeSource {
+    Kernel,
+    /// 内核没有提供时间戳，收到后在用户态取时间
+    User,
+    /// 读取抓包文件时为文件中记录的时间
+    File,
+}
+
+impl TimeSource {
+    pub fn name(&self) -> &'static str {
+        match self {
+            TimeSource::Kernel => "内核时间戳",
+            TimeSource::User => "用户态时间",
+            TimeSource::File => "抓包文件时间戳",
+        }
+    }
+
+    /// 机器可读格式中的取值
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TimeSource::Kernel => "kernel",
+            TimeSource::User => "user",
+            TimeSource::File => "file",
+        }
+    }
+}
+
+/// 一帧的接收信息
+pub struct FrameMeta {
+    /// 接收时间 (Unix 微秒)
+    pub received_us: u64,
+    pub time_source: TimeSource,
+    /// 接口名；读取抓包文件时为文件名
+    pub interface: String,
+    pub outgoing: bool,
+}
+
+fn protocol_name(protocol: u8) -> String {
+    match protocol {
+        PROTOCOL_UDP => "UDP".to_string(),
+        PROTOCOL_TCP => "TCP".to_string(),
+        PROTOCOL_ICMP => "ICMP".to_string(),
+        other => format!("协议 {}", other),
+    }
+}
+
+/// 一个数据报的扁平字段，按固定顺序输出为 JSON 对象或 CSV 行
+struct Record(Vec<(&'static str, Value)>);
+
+impl Serialize for Record {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (name, value) in &self.0 {
+            map.serialize_entry(name, value)?;
+        }
+        map.end()
+    }
+}
+
+impl Record {
+    fn push(&mut self, name: &'static str, value: impl Into<Value>) {
+        self.0.push((name, value.into()));
+    }
+
+    fn csv_header(&self) -> String {
+        let names: Vec<&str> = self.0.iter().map(|(name, _)| *name).collect();
+        names.join(",")
+    }
+
+    fn csv_row(&self) -> String {
+        let fields: Vec<String> = self.0.iter().map(|(_, value)| csv_field(value)).collect();
+        fields.join(",")
+    }
+}
+
+/// CSV 单元格：含逗号、引号或换行时加引号
+fn csv_field(value: &Value) -> String {
+    let text = cell_text(value);
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text
+    }
+}
+
+/// 单元格的原始文本：空值为空串，数组以 `;` 连接
+fn cell_text(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(text) => text.clone(),
+        Value::Array(items) => {
+            let items: Vec<String> = items.iter().map(cell_text).collect();
+            items.join(";")
+        }
+        other => other.to_string(),
+    }
+}
+
+/// 计入本包后得到的标记相关结果
+#[derive(Clone, Copy)]
+struct MarkerStats {
+    /// 单向时延，微秒；超出合理范围时为 `None`，未计入统计
+    delay: Option<i64>,
+    sequence: SequenceEvent,
+}
+
+/// 逐包输出解码结果，并累计校验和、认证、时延和序号统计
+pub struct Printer {
+    id_strategy: Box<dyn IdStrategy>,
+    format: OutputFormat,
+    encoding: PayloadEncoding,
+    packets: u64,
+    checksums: ChecksumStats,
+    auth: AuthCounters,
+    latency: LatencyTracker,
+    sequences: SequenceTracker,
+    /// 配置了密钥：只有认证通过的标记计入时延和序号统计
+    require_auth: bool,
+    /// 没有计入时延和序号统计的标记数
+    untrusted: u64,
+}
+
+impl Printer {
+    pub fn new(
+        id_strategy: Box<dyn IdStrategy>,
+        format: OutputFormat,
+        encoding: PayloadEncoding,
+        require_auth: bool,
+    ) -> Self {
+        Printer {
+            id_strategy,
+            format,
+            encoding,
+            packets: 0,
+            checksums: ChecksumStats::default(),
+            auth: AuthCounters::default(),
+            latency: LatencyTracker::default(),
+            sequences: SequenceTracker::default(),
+            require_auth,
+            untrusted: 0,
+        }
+    }
+
+    /// 已输出的包数
+    pub fn packets(&self) -> u64 {
+        self.packets
+    }
+
+    /// 输出提示信息：文本格式打到标准输出，其他格式打到标准错误，不混入数据
+    pub fn note(&self, message: &str) {
+        if self.format == OutputFormat::Text {
+            println!("{}", message);
+        } else {
+            eprintln!("{}", message);
+        }
+    }
+
+    /// 标记是否可信：认证通过，或没有配置密钥时未认证；伪造或损坏的标记不能
+    /// 影响时延和序号统计
+    fn trusted(&self, status: &AuthStatus) -> bool {
+        match status {
+            AuthStatus::Valid { .. } => true,
+            AuthStatus::Unauthenticated => !self.require_auth,
+            AuthStatus::Invalid { .. } => false,
+        }
+    }
+
+    /// 输出一个数据报
+    ///
+    /// # 参数
+    /// - `fragments`: 重组该数据报用到的分片数，未分片为 1
+    pub fn print(&mut self, meta: &FrameMeta, packet: &DecodedPacket, fragments: usize) {
+        self.packets += 1;
+        // 先累计统计，各格式输出的都是计入本包之后的结果
+        let stats = match &packet.marker {
+            Some(MarkerInfo {
+                result: Ok((marker, status)),
+                ..
+            }) => {
+                self.auth.record(status);
+                if !self.trusted(status) {
+                    self.untrusted += 1;
+                    return self.emit(meta, packet, fragments, None);
+                }
+                let flow = FlowKey {
+                    source: packet.ip.source.into(),
+                    destination: packet.ip.destination.into(),
+                    tag: marker.tag,
+                };
+                Some(MarkerStats {
+                    delay: self
+                        .latency
+                        .record(flow, marker.timestamp, meta.received_us),
+                    sequence: self
+                        .sequences
+                        .record(flow, marker.sequence, marker.timestamp),
+                })
+            }
+            _ => None,
+        };
+        self.emit(meta, packet, fragments, stats);
+    }
+
+    /// 按输出格式输出一个数据报，统计已经计入
+    fn emit(
+        &mut self,
+        meta: &FrameMeta,
+        packet: &DecodedPacket,
+        fragments: usize,
+        stats: Option<MarkerStats>,
+    ) {
+        if let Some(report) = &packet.checksums {
+            self.checksums.record(report);
+        }
+        match self.format {
+            OutputFormat::Text => self.print_text(meta, packet, fragments, stats),
+            OutputFormat::Json => {
+                let record = self.record(meta, packet, fragments, stats);
+                println!(
+                    "{}",
+                    serde_json::to_string(&record).expect("记录总能序列化")
+                );
+            }
+            OutputFormat::Csv => {
+                let record = self.record(meta, packet, fragments, stats);
+                if self.packets == 1 {
+                    println!("{}", record.csv_header());
+                }
+                println!("{}", record.csv_row());
+            }
+        }
+    }
+
+    fn print_text(
+        &self,
+        meta: &FrameMeta,
+        packet: &DecodedPacket,
+        fragments: usize,
+        stats: Option<MarkerStats>,
+    ) {
+        let ip = &packet.ip;
+        let (source, destination) = match packet.ports() {
+            Some((sport, dport)) => (
+                format!("{}:{}", ip.source, sport),
+                format!("{}:{}", ip.destination, dport),
+            ),
+            None => (ip.source.to_string(), ip.destination.to_string()),
+        };
+        println!(
+            "\n{}.{:06} {} {} {} -> {} {} {} 字节",
+            meta.received_us / 1_000_000,
+            meta.received_us % 1_000_000,
+            meta.interface,
+            if meta.outgoing { "发" } else { "收" },
+            source,
+            destination,
+            protocol_name(ip.protocol),
+            ip.total_len
+        );
+
+        let mut flags = Vec::new();
+        if ip.dont_fragment {
+            flags.push("DF");
+        }
+        if ip.more_fragments {
+            flags.push("MF");
+        }
+        println!(
+            "  IP:       ID 0x{:04x} TTL {} TOS 0x{:02x} 标志 {} 偏移 {} 首部 {} 字节 校验和 0x{:04x}",
+            ip.identification,
+            ip.ttl,
+            ip.tos,
+            if flags.is_empty() {
+                "-".to_string()
+            } else {
+                flags.join("|")
+            },
+            ip.fragment_offset,
+            ip.header_len,
+            ip.checksum
+        );
+        let (source_port, destination_port) = packet.ports().unwrap_or_default();
+        let flow = FlowTuple {
+            source: ip.source,
+            destination: ip.destination,
+            protocol: ip.protocol,
+            source_port,
+            destination_port,
+        };
+        println!(
+            "  IP ID:    {} ({})",
+            self.id_strategy
+                .decode(ip.identification, &flow, meta.received_us),
+            self.id_strategy.name()
+        );
+        if fragments > 1 {
+            println!("  重组:     {} 个分片", fragments);
+        }
+        match &ip.options {
+            Ok(options) if options.is_empty() => {}
+            Ok(options) => println!("  IP 选项:  {}", format_options(options)),
+            Err(e) => println!("  IP 选项解析失败: {}", e),
+        }
+
+        match &packet.transport {
+            Some(TransportHeader::Udp {
+                length, checksum, ..
+            }) => println!("  UDP:      长度 {} 校验和 0x{:04x}", length, checksum),
+            Some(TransportHeader::Tcp {
+                sequence,
+                acknowledgement,
+                header_len,
+                flags,
+                window,
+                checksum,
+                urgent,
+                options,
+                ..
+            }) => {
+                println!(
+                    "  TCP:      seq {} ack {} 标志 {} 窗口 {} 首部 {} 字节 校验和 0x{:04x} 紧急指针 {}",
+                    sequence,
+                    acknowledgement,
+                    tcp_flags_name(*flags),
+                    window,
+                    header_len,
+                    checksum,
+                    urgent
+                );
+                if !options.options.is_empty() || !options.is_ok() {
+                    println!("  TCP 选项: {}", options);
+                }
+            }
+            Some(TransportHeader::Icmp {
+                icmp_type,
+                code,
+                checksum,
+                rest,
+            }) => println!(
+                "  ICMP:     类型 {} 代码 {} 校验和 0x{:04x} 其余 0x{:08x}",
+                icmp_type, code, checksum, rest
+            ),
+            None => {}
+        }
+
+        if let Some(marker) = &packet.marker {
+            match &marker.result {
+                Ok((decoded, status)) => {
+                    println!("  标记:     {} ({} 选项)", decoded, marker.location.name());
+                    println!("  认证:     {} (累计 {})", status, self.auth);
+                    if let Some(stats) = stats {
+                        let flow = FlowKey {
+                            source: ip.source.into(),
+                            destination: ip.destination.into(),
+                            tag: decoded.tag,
+                        };
+                        match stats.delay {
+                            Some(delay) => {
+                                println!("  单向时延: {} us ({})", delay, meta.time_source.name())
+                            }
+                            None => println!("  单向时延: 超出合理范围, 未计入统计"),
+                        }
+                        if let Some(latency) = self.latency.get(&flow) {
+                            println!("  时延统计: {}", latency);
+                        }
+                        println!("  序号:     {}", stats.sequence);
+                        println!("  序号统计: {}", self.sequences.get(&flow).unwrap());
+                    } else {
+                        println!("  统计:     标记不可信, 未计入时延和序号统计");
+                    }
+                }
+                Err(e) => println!("  标记解码失败: {}", e),
+            }
+        }
+
+        if let Some(report) = &packet.checksums {
+            println!("  {}", report);
+            println!("  累计校验和: {}", self.checksums);
+        }
+
+        if !packet.payload.is_empty() {
+            println!(
+                "  负载:     {} 字节 {}",
+                packet.payload.len(),
+                hex::encode(&packet.payload)
+            );
+            if let Ok(text) = std::str::from_utf8(&packet.payload) {
+                println!("  负载文本: {}", text);
+            }
+        }
+    }
+
+    /// 机器可读格式的一条记录；每条记录的字段和顺序都相同，缺失的值为 null
+    fn record(
+        &self,
+        meta: &FrameMeta,
+        packet: &DecodedPacket,
+        fragments: usize,
+        stats: Option<MarkerStats>,
+    ) -> Record {
+        let ip = &packet.ip;
+        let mut record = Record(Vec::new());
+        record.push("time", format_utc(meta.received_us));
+        record.push("timestamp_us", meta.received_us);
+        record.push("time_source", meta.time_source.as_str());
+        record.push("interface", meta.interface.as_str());
+        record.push("direction", if meta.outgoing { "out" } else { "in" });
+        record.push("source", ip.source.to_string());
+        record.push("destination", ip.destination.to_string());
+        record.push("protocol", ip.protocol);
+        record.push("length", ip.total_len);
+        record.push("ip_id", ip.identification);
+        record.push("ttl", ip.ttl);
+        record.push("tos", ip.tos);
+        record.push("dont_fragment", ip.dont_fragment);
+        record.push("more_fragments", ip.more_fragments);
+        record.push("fragment_offset", ip.fragment_offset);
+        record.push("fragments", fragments);
+        let (options, options_error) = match &ip.options {
+            Ok(options) => (options.iter().map(|o| o.to_string()).collect(), None),
+            Err(e) => (Vec::new(), Some(e.to_string())),
+        };
+        record.push("ip_options", options);
+        record.push("ip_options_error", options_error);
+
+        let ports = packet.ports();
+        record.push("source_port", ports.map(|(source, _)| source));
+        record.push(
+            "destination_port",
+            ports.map(|(_, destination)| destination),
+        );
+        let tcp = match &packet.transport {
+            Some(TransportHeader::Tcp {
+                sequence,
+                acknowledgement,
+                flags,
+                window,
+                options,
+                ..
+            }) => Some((sequence, acknowledgement, flags, window, options)),
+            _ => None,
+        };
+        record.push("tcp_flags", tcp.map(|t| tcp_flags_name(*t.2)));
+        record.push("tcp_seq", tcp.map(|t| *t.0));
+        record.push("tcp_ack", tcp.map(|t| *t.1));
+        record.push("tcp_window", tcp.map(|t| *t.3));
+        record.push(
+            "tcp_options",
+            tcp.map(|t| {
+                t.4.options
+                    .iter()
+                    .map(|o| o.to_string())
+                    .collect::<Vec<_>>()
+            }),
+        );
+        let icmp = match &packet.transport {
+            Some(TransportHeader::Icmp {
+                icmp_type, code, ..
+            }) => Some((*icmp_type, *code)),
+            _ => None,
+        };
+        record.push("icmp_type", icmp.map(|(icmp_type, _)| icmp_type));
+        record.push("icmp_code", icmp.map(|(_, code)| code));
+
+        let decoded = packet
+            .marker
+            .as_ref()
+            .and_then(|marker| marker.result.as_ref().ok());
+        let marker = decoded.map(|(marker, _)| marker);
+        let status = decoded.map(|(_, status)| status);
+        record.push(
+            "marker_location",
+            packet.marker.as_ref().map(|m| m.location.name()),
+        );
+        record.push("marker_version", marker.map(|m| m.version));
+        record.push("marker_flags", marker.map(|m| m.flags));
+        record.push("marker_tag", marker.map(|m| m.tag));
+        record.push("marker_seq", marker.map(|m| m.sequence));
+        record.push("marker_timestamp_us", marker.map(|m| m.timestamp));
+        record.push("marker_auth", status.map(|s| s.as_str()));
+        record.push(
+            "marker_key_id",
+            status.and_then(|status| match status {
+                AuthStatus::Valid { key_id, .. } => Some(*key_id),
+                AuthStatus::Invalid { key_id, .. } => *key_id,
+                AuthStatus::Unauthenticated => None,
+            }),
+        );
+        let marker_error = match &packet.marker {
+            Some(MarkerInfo { result: Err(e), .. }) => Some(e.to_string()),
+            Some(MarkerInfo {
+                result: Ok((_, AuthStatus::Invalid { reason, .. })),
+                ..
+            }) => Some(reason.to_string()),
+            _ => None,
+        };
+        record.push("marker_error", marker_error);
+        record.push("one_way_delay_us", stats.and_then(|stats| stats.delay));
+        let sequence = stats.map(|stats| stats.sequence);
+        record.push("seq_event", sequence.map(|event| event.as_str()));
+        record.push(
+            "seq_missing",
+            match sequence {
+                Some(SequenceEvent::Gap { missing, .. }) => Some(missing),
+                _ => None,
+            },
+        );
+
+        let checksums = packet.checksums.as_ref();
+        record.push("ip_checksum", checksums.map(|c| c.ip.as_str()));
+        record.push(
+            "transport_checksum",
+            checksums.and_then(|c| c.transport).map(|(_, s)| s.as_str()),
+        );
+        record.push("payload_len", packet.payload.len());
+        record.push(
+            "payload",
+            match self.encoding {
+                PayloadEncoding::Hex => hex::encode(&packet.payload),
+                PayloadEncoding::Base64 => base64::encode(&packet.payload),
+            },
+        );
+        record
+    }
+
+    /// 输出累计统计
+    pub fn summary(&self) {
+        self.note(&format!("\n共显示 {} 个包", self.packets));
+        if self.checksums.packets > 0 {
+            self.note(&format!("校验和: {}", self.checksums));
+        }
+        if self.auth.valid + self.auth.invalid + self.auth.unauthenticated > 0 {
+            self.note(&format!("标记认证: {}", self.auth));
+        }
+        if self.untrusted > 0 {
+            self.note(&format!(
+                "不可信的标记: {} 个, 未计入时延和序号统计",
+                self.untrusted
+            ));
+        }
+        for (flow, stats) in self.latency.flows() {
+            self.note(&format!("时延 {}: {}", flow, stats));
+        }
+        self.sequence_report();
+    }
+
+    /// 输出各流的序号统计，用于定期汇总和结束时的报告
+    pub fn sequence_report(&self) {
+        for (flow, stats) in self.sequences.flows() {
+            self.note(&format!("序号 {}: {}", flow, stats));
+        }
+    }
+}
+
+/// 写入 pcapng 的数据包注释：标记及其认证结论、校验和验证结果
+pub fn annotation(packet: &DecodedPacket) -> String {
+    let mut parts = Vec::new();
+    match &packet.marker {
+        Some(MarkerInfo {
+            location,
+            result: Ok((marker, status)),
+        }) => {
+            parts.push(format!("标记 {} ({} 选项)", marker, location.name()));
+            parts.push(format!("认证: {}", status));
+        }
+        Some(MarkerInfo { result: Err(e), .. }) => parts.push(format!("标记解码失败: {}", e)),
+        None => parts.push("无标记".to_string()),
+    }
+    if let Some(report) = &packet.checksums {
+        parts.push(report.to_string());
+    }
+    parts.join("; ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ip_header::auth::MarkerCodec;
+    use ip_header::builder::{PacketBuilder, TcpLayer, UdpLayer};
+    use ip_header::decode::decode;
+    use ip_header::ip_id::strategy_from_name;
+    use ip_header::marker::{Marker, MarkerCarrier, TcpOptionCarrier};
+    use ip_header::tcp_option;
+    use serde_json::json;
+    use std::net::Ipv4Addr;
+
+    fn builder() -> PacketBuilder {
+        PacketBuilder::ipv4(Ipv4Addr::new(10, 0, 0, 1), Ipv4Addr::new(10, 0, 0, 2))
+            .payload(b"hi".to_vec())
+    }
+
+    fn printer() -> Printer {
+        Printer::new(
+            strategy_from_name("fixed").unwrap(),
+            OutputFormat::Json,
+            PayloadEncoding::Hex,
+            false,
+        )
+    }
+
+    fn meta() -> FrameMeta {
+        FrameMeta {
+            received_us: 1_700_000_000_000_000,
+            time_source: TimeSource::Kernel,
+            interface: "eth0".to_string(),
+            outgoing: false,
+        }
+    }
+
+    #[test]
+    fn csv_field_quoting() {
+        for (value, field) in [
+            (json!("plain"), "plain"),
+            (json!("a,b"), "\"a,b\""),
+            (json!("say \"hi\""), "\"say \"\"hi\"\"\""),
+            (json!("two\nlines"), "\"two\nlines\""),
+            (json!("cr\r"), "\"cr\r\""),
+            (json!(["a,b", "c"]), "\"a,b;c\""),
+            (Value::Null, ""),
+        ] {
+            assert_eq!(csv_field(&value), field, "{}", value);
+        }
+    }
+
+    #[test]
+    fn cell_text_values() {
+        assert_eq!(cell_text(&json!(["nop", "rr[3]", 4])), "nop;rr[3];4");
+        assert_eq!(cell_text(&json!([])), "");
+        assert_eq!(cell_text(&json!(65535)), "65535");
+        assert_eq!(cell_text(&json!(true)), "true");
+        assert_eq!(cell_text(&Value::Null), "");
+    }
+
+    #[test]
+    fn records_share_fields_and_order() {
+        let codec = MarkerCodec::default();
+        let udp = builder().udp(UdpLayer::new(40000, 8001)).build().unwrap();
+        let udp = decode(&udp, &codec).unwrap();
+
+        let mut tcp_layer = TcpLayer::new(40000, 8001);
+        let marker = TcpOptionCarrier::default().wrap(Marker::new(7, 42).encode());
+        tcp_layer.options = tcp_option::serialize_options(&[marker]).unwrap();
+        let tcp = builder().tcp(tcp_layer).build().unwrap();
+        let tcp = decode(&tcp, &codec).unwrap();
+        assert!(tcp.marker.is_some());
+
+        let printer = printer();
+        let udp = printer.record(&meta(), &udp, 1, None);
+        let stats = MarkerStats {
+            delay: Some(120),
+            sequence: SequenceEvent::First,
+        };
+        let tcp = printer.record(&meta(), &tcp, 1, Some(stats));
+
+        let names = |record: &Record| record.0.iter().map(|(name, _)| *name).collect::<Vec<_>>();
+        assert_eq!(names(&udp), names(&tcp));
+        assert_eq!(udp.csv_header(), tcp.csv_header());
+        assert_eq!(udp.csv_header().split(',').count(), udp.0.len());
+
+        let value = |record: &Record, name: &str| {
+            record
+                .0
+                .iter()
+                .find(|(field, _)| *field == name)
+                .map(|(_, value)| value.clone())
+                .unwrap()
+        };
+        for name in ["tcp_flags", "marker_location", "marker_tag", "seq_event"] {
+            assert_eq!(value(&udp, name), Value::Null, "{}", name);
+        }
+        assert_eq!(value(&udp, "source_port"), json!(40000));
+        assert_eq!(value(&tcp, "marker_location"), json!("tcp"));
+        assert_eq!(value(&tcp, "marker_tag"), json!(7));
+        assert_eq!(value(&tcp, "marker_seq"), json!(42));
+        assert_eq!(value(&tcp, "marker_auth"), json!("unauthenticated"));
+        assert_eq!(value(&tcp, "one_way_delay_us"), json!(120));
+        assert_eq!(value(&tcp, "seq_event"), json!("first"));
+        assert_eq!(value(&tcp, "payload"), json!("6869"));
+
+        // JSON 对象的键顺序与 CSV 列名一致
+        let json = serde_json::to_string(&tcp).unwrap();
+        let header = udp.csv_header();
+        let mut at = 0;
+        for key in header.split(',') {
+            at += json[at..].find(&format!("\"{}\":", key)).unwrap();
+        }
+    }
+}
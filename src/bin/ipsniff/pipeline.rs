@@ -0,0 +1,226 @@
+//! 帧从重组到输出的处理流程，实时抓包和读取抓包文件共用
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use ip_header::auth::MarkerCodec;
+use ip_header::bpf::Filter;
+use ip_header::decode;
+use ip_header::pcap::RotatingWriter;
+use ip_header::reassembly::{FragmentKey, Reassembler};
+use ip_header::timestamp::{format_utc, unix_micros};
+
+use crate::output::{self, FrameMeta, Printer};
+
+/// 等待过滤结论、暂不写入抓包文件的分片
+struct HeldFrame {
+    received_us: u64,
+    interface: String,
+    data: Vec<u8>,
+}
+
+/// 重组、过滤、解码，再写入抓包文件和输出
+pub struct Pipeline {
+    printer: Printer,
+    filter: Option<Filter>,
+    codec: MarkerCodec,
+    reassembler: Reassembler,
+    writer: Option<RotatingWriter>,
+    /// 写抓包文件时，尚未收齐的数据报已收到的帧
+    held: HashMap<FragmentKey, Vec<HeldFrame>>,
+    /// 定期输出序号统计的间隔，微秒
+    report_interval: Option<u64>,
+    /// 下次输出序号统计的时间 (Unix 微秒)，收到第一个包时确定
+    next_report: Option<u64>,
+    /// 上次输出序号统计时已输出的包数
+    reported_packets: u64,
+}
+
+impl Pipeline {
+    pub fn new(
+        printer: Printer,
+        filter: Option<Filter>,
+        codec: MarkerCodec,
+        reassembler: Reassembler,
+        writer: Option<RotatingWriter>,
+        report_interval: Option<Duration>,
+    ) -> Self {
+        Pipeline {
+            printer,
+            filter,
+            codec,
+            reassembler,
+            writer,
+            held: HashMap::new(),
+            report_interval: report_interval.map(|interval| interval.as_micros() as u64),
+            next_report: None,
+            reported_packets: 0,
+        }
+    }
+
+    /// 已输出的包数
+    pub fn packets(&self) -> u64 {
+        self.printer.packets()
+    }
+
+    pub fn note(&self, message: &str) {
+        self.printer.note(message);
+    }
+
+    /// 处理一帧
+    ///
+    /// # 参数
+    /// - `frame`: 从 IPv4 首部开始的字节
+    /// - `now`: 重组器判断超时用的时间
+    pub fn push(&mut self, meta: &FrameMeta, frame: &[u8], now: Instant) -> Result<()> {
+        let datagram = self.reassembler.push(frame, now);
+        self.report_anomalies(Some(&meta.interface));
+        // 分片先留着，数据报收齐并通过过滤后才与最后一个帧一起写入抓包文件，
+        // 与未分片的包一样只写入通过过滤的数据报
+        let key =
+            FragmentKey::from_ipv4(frame).filter(|_| self.writer.is_some() && is_fragment(frame));
+        let mut held = key
+            .and_then(|key| self.held.remove(&key))
+            .unwrap_or_default();
+        let Some(datagram) = datagram else {
+            if let Some(key) = key.filter(|key| self.reassembler.is_pending(key)) {
+                held.push(HeldFrame {
+                    received_us: meta.received_us,
+                    interface: meta.interface.clone(),
+                    data: frame.to_vec(),
+                });
+                self.held.insert(key, held);
+            }
+            self.release_held();
+            return Ok(());
+        };
+        // 实时抓包时内核放行了全部分片，重组后再按同一条件过滤
+        if self
+            .filter
+            .as_ref()
+            .is_some_and(|filter| !filter.matches(&datagram.data))
+        {
+            return Ok(());
+        }
+        for frame in &held {
+            self.save(frame.received_us, &frame.interface, &frame.data, None)?;
+        }
+        let decoded = decode::decode(&datagram.data, &self.codec);
+        let comment = match &decoded {
+            Ok(packet) => output::annotation(packet),
+            Err(e) => format!("无法解码: {}", e),
+        };
+        self.save(meta.received_us, &meta.interface, frame, Some(&comment))?;
+        match decoded {
+            Ok(packet) => self.printer.print(meta, &packet, datagram.fragments),
+            Err(e) => self
+                .printer
+                .note(&format!("{} 无法解码: {}", meta.interface, e)),
+        }
+        self.periodic_report(meta.received_us);
+        Ok(())
+    }
+
+    /// 实时抓包没有新的帧时清理超时的分片，把缓冲的数据写入抓包文件，并按时
+    /// 输出序号统计
+    pub fn idle(&mut self, now: Instant) -> Result<()> {
+        self.reassembler.expire(now);
+        self.report_anomalies(None);
+        self.release_held();
+        if let Some(writer) = &mut self.writer {
+            writer.flush().context("无法写入抓包文件")?;
+        }
+        self.periodic_report(unix_micros());
+        Ok(())
+    }
+
+    /// 把尚未收齐的分片全部按超时报告，用于抓包文件读完时
+    pub fn drain(&mut self, now: Instant) {
+        let timeout = self.reassembler.config().timeout;
+        self.reassembler.expire(now + timeout);
+        self.report_anomalies(None);
+        self.release_held();
+    }
+
+    /// 丢掉已超时或被重组器丢弃的数据报的分片
+    fn release_held(&mut self) {
+        if !self.held.is_empty() {
+            let reassembler = &self.reassembler;
+            self.held.retain(|key, _| reassembler.is_pending(key));
+        }
+    }
+
+    /// 到了间隔且有新的包时输出序号统计
+    ///
+    /// # 参数
+    /// - `now_us`: 当前时间 (Unix 微秒)；读取抓包文件时为包的抓包时间
+    fn periodic_report(&mut self, now_us: u64) {
+        let Some(interval) = self.report_interval else {
+            return;
+        };
+        let next = *self.next_report.get_or_insert(now_us + interval);
+        if now_us < next {
+            return;
+        }
+        // 跳过没有包的间隔，下次报告仍与第一个包对齐
+        self.next_report = Some(next + (now_us - next) / interval * interval + interval);
+        if self.printer.packets() > self.reported_packets {
+            self.reported_packets = self.printer.packets();
+            self.printer
+                .note(&format!("\n{} 序号统计:", format_utc(now_us)));
+            self.printer.sequence_report();
+        }
+    }
+
+    /// 结束处理：写完抓包文件并输出汇总
+    pub fn finish(mut self) -> Result<()> {
+        if let Some(writer) = &mut self.writer {
+            writer.flush().context("无法写入抓包文件")?;
+        }
+        self.printer.summary();
+        Ok(())
+    }
+
+    fn report_anomalies(&mut self, interface: Option<&str>) {
+        for anomaly in self.reassembler.take_anomalies() {
+            match interface {
+                Some(interface) => self
+                    .printer
+                    .note(&format!("{} 分片异常: {}", interface, anomaly)),
+                None => self.printer.note(&format!("分片异常: {}", anomaly)),
+            }
+        }
+    }
+
+    /// 写入抓包文件，切换文件时提示
+    fn save(
+        &mut self,
+        received_us: u64,
+        interface: &str,
+        frame: &[u8],
+        comment: Option<&str>,
+    ) -> Result<()> {
+        let Some(writer) = &mut self.writer else {
+            return Ok(());
+        };
+        let rotated = writer
+            .write_packet(
+                Duration::from_micros(received_us),
+                interface,
+                frame,
+                comment,
+            )
+            .with_context(|| format!("无法写入 {}", writer.path().display()))?;
+        if rotated {
+            self.printer
+                .note(&format!("写入文件: {}", writer.path().display()));
+        }
+        Ok(())
+    }
+}
+
+/// MF 置位或偏移非零
+fn is_fragment(frame: &[u8]) -> bool {
+    u16::from_be_bytes([frame[6], frame[7]]) & 0x3fff != 0
+}
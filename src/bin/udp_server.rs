@@ -6,8 +6,8 @@ fn main() -> std::io::Result<()> {
 
     let mut buf = [0u8; 1500]; // 最大 UDP 包的大小
     loop {
-        let (amt, src) = socket.recv_from(&mut buf)?;
-        
+        let (amt, _src) = socket.recv_from(&mut buf)?;
+
         // IPv4包的最小长度为20字节（包头）
         /*if amt < 20 {
             println!("收到来自 {}: 数据包过短，无法解析IPv4包头", src);
@@ -22,15 +22,16 @@ fn main() -> std::io::Result<()> {
 
         // 转换为16进制字符串
         let hex_id = format!("{:02x}{:02x}", identification[0], identification[1]);
-        
+
         println!("收到来自 {}: Identification字段=0x{}", src, hex_id);*/
-        
+
         // 其他数据处理逻辑保持不变...
         println!("数据长度: {} 字节", amt);
 
         // 打印十六进制数据
         print!("数据内容(hex): ");
-        for b in &ip_header[..20] {  // 仅打印IP包头
+        for b in &buf[..amt.min(20)] {
+            // 仅打印前20字节
             print!("{:02x} ", b);
         }
         println!();
@@ -48,4 +49,4 @@ fn main() -> std::io::Result<()> {
             Err(_) => println!("（非 UTF-8 数据）"),
         }
     }
-}
\ No newline at end of file
+}
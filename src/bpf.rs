@@ -0,0 +1,971 @@
+//! 抓包过滤表达式与经典 BPF
+//!
+//! 把一个小的过滤表达式编译为经典 BPF 程序，用 `SO_ATTACH_FILTER` 附加到抓包套接字，
+//! 内核只把相关的包交给用户态；同一个表达式也能在用户态对重组后的数据报求值。
+//!
+//! ```text
+//! udp and port 8001
+//! src host 10.0.0.1 and not icmp
+//! net 192.168.0.0/16 and (ipopt 0x79 or tcpopt 253)
+//! ```
+//!
+//! 抓包套接字是 `SOCK_DGRAM`，程序看到的数据从 IP 首部开始。端口、TCP 选项和不复制
+//! 到分片中的 IP 选项只在第一个分片中，表达式用到它们时所有分片都会交给用户态，
+//! 重组后再按表达式过滤。
+
+use std::fmt;
+use std::net::Ipv4Addr;
+
+/// 内核接受的最大指令数
+pub const MAX_INSTRUCTIONS: usize = 4096;
+
+/// 选项扫描最多检查的选项个数 (40 字节的选项区最多 40 个单字节选项)
+const MAX_OPTIONS: usize = 40;
+
+// 指令类别
+const BPF_LD: u16 = 0x00;
+const BPF_LDX: u16 = 0x01;
+const BPF_ST: u16 = 0x02;
+const BPF_ALU: u16 = 0x04;
+const BPF_JMP: u16 = 0x05;
+const BPF_RET: u16 = 0x06;
+const BPF_MISC: u16 = 0x07;
+// 数据宽度
+const BPF_W: u16 = 0x00;
+const BPF_H: u16 = 0x08;
+const BPF_B: u16 = 0x10;
+// 寻址方式
+const BPF_IMM: u16 = 0x00;
+const BPF_ABS: u16 = 0x20;
+const BPF_IND: u16 = 0x40;
+const BPF_MEM: u16 = 0x60;
+const BPF_MSH: u16 = 0xa0;
+// 运算与跳转
+const BPF_ADD: u16 = 0x00;
+const BPF_AND: u16 = 0x50;
+const BPF_RSH: u16 = 0x70;
+const BPF_JA: u16 = 0x00;
+const BPF_JEQ: u16 = 0x10;
+const BPF_JGT: u16 = 0x20;
+const BPF_JGE: u16 = 0x30;
+const BPF_JSET: u16 = 0x40;
+const BPF_K: u16 = 0x00;
+const BPF_X: u16 = 0x08;
+const BPF_TAX: u16 = 0x00;
+const BPF_TXA: u16 = 0x80;
+
+/// 辅助数据 skb->protocol 的偏移 (SKF_AD_OFF + SKF_AD_PROTOCOL)
+const SKF_AD_PROTOCOL: u32 = 0xffff_f000;
+const ETH_P_IP: u32 = 0x0800;
+/// 接受时返回的抓取长度
+const ACCEPT_LEN: u32 = 0x0004_0000;
+
+const PROTOCOL_TCP: u8 = 6;
+const PROTOCOL_UDP: u8 = 17;
+
+/// 表达式解析或编译错误
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FilterError {
+    Empty,
+    /// 表达式在需要更多内容时结束
+    UnexpectedEnd,
+    Unexpected(String),
+    BadNumber(String),
+    BadAddress(String),
+    BadNet(String),
+    /// 编译后的指令数超过内核限制
+    TooLong(usize),
+}
+
+impl fmt::Display for FilterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FilterError::Empty => write!(f, "过滤表达式为空"),
+            FilterError::UnexpectedEnd => write!(f, "过滤表达式不完整"),
+            FilterError::Unexpected(token) => write!(f, "过滤表达式中意外的 \"{}\"", token),
+            FilterError::BadNumber(text) => write!(f, "非法的数值: {}", text),
+            FilterError::BadAddress(text) => write!(f, "非法的 IPv4 地址: {}", text),
+            FilterError::BadNet(text) => write!(f, "非法的网段: {} (应为 地址/前缀长度)", text),
+            FilterError::TooLong(len) => {
+                write!(f, "BPF 程序有 {} 条指令, 超过 {}", len, MAX_INSTRUCTIONS)
+            }
+        }
+    }
+}
+
+impl std::error::Error for FilterError {}
+
+/// 地址和端口匹配的方向
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Either,
+    Source,
+    Destination,
+}
+
+impl Direction {
+    fn prefix(&self) -> &'static str {
+        match self {
+            Direction::Either => "",
+            Direction::Source => "src ",
+            Direction::Destination => "dst ",
+        }
+    }
+}
+
+/// 过滤表达式
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Filter {
+    Protocol(u8),
+    Host(Direction, Ipv4Addr),
+    /// 网段地址和前缀长度
+    Net(Direction, Ipv4Addr, u8),
+    /// TCP 或 UDP 端口
+    Port(Direction, u16),
+    /// IP 首部含该类型的选项
+    IpOption(u8),
+    /// TCP 首部含该类型的选项
+    TcpOption(u8),
+    Not(Box<Filter>),
+    And(Box<Filter>, Box<Filter>),
+    Or(Box<Filter>, Box<Filter>),
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Filter::Protocol(1) => write!(f, "icmp"),
+            Filter::Protocol(PROTOCOL_TCP) => write!(f, "tcp"),
+            Filter::Protocol(PROTOCOL_UDP) => write!(f, "udp"),
+            Filter::Protocol(protocol) => write!(f, "proto {}", protocol),
+            Filter::Host(direction, address) => write!(f, "{}host {}", direction.prefix(), address),
+            Filter::Net(direction, address, prefix) => {
+                write!(f, "{}net {}/{}", direction.prefix(), address, prefix)
+            }
+            Filter::Port(direction, port) => write!(f, "{}port {}", direction.prefix(), port),
+            Filter::IpOption(kind) => write!(f, "ipopt 0x{:02x}", kind),
+            Filter::TcpOption(kind) => write!(f, "tcpopt {}", kind),
+            Filter::Not(inner) => write!(f, "not ({})", inner),
+            Filter::And(a, b) => write!(f, "({} and {})", a, b),
+            Filter::Or(a, b) => write!(f, "({} or {})", a, b),
+        }
+    }
+}
+
+/// 一条经典 BPF 指令，内存布局与 `struct sock_filter` 相同
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Instruction {
+    pub code: u16,
+    pub jt: u8,
+    pub jf: u8,
+    pub k: u32,
+}
+
+impl fmt::Display for Instruction {
+    /// 与 `tcpdump -dd` 相同的格式
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{{ 0x{:02x}, {}, {}, 0x{:08x} }},",
+            self.code, self.jt, self.jf, self.k
+        )
+    }
+}
+
+/// 解析过滤表达式
+///
+/// 基本项: `udp` `tcp` `icmp` `proto <N>` `[src|dst] host <地址>`
+/// `[src|dst] net <地址/前缀>` `[src|dst] port <N>` `ipopt <类型>` `tcpopt <类型>`，
+/// 用 `and` / `or` / `not` (或 `&&` / `||` / `!`) 和括号组合，`and` 优先于 `or`。
+pub fn parse(text: &str) -> Result<Filter, FilterError> {
+    let spaced = text
+        .replace('(', " ( ")
+        .replace(')', " ) ")
+        .replace('!', " ! ");
+    let tokens: Vec<&str> = spaced.split_whitespace().collect();
+    if tokens.is_empty() {
+        return Err(FilterError::Empty);
+    }
+    let mut parser = Parser { tokens, pos: 0 };
+    let filter = parser.or()?;
+    match parser.peek() {
+        None => Ok(filter),
+        Some(token) => Err(FilterError::Unexpected(token.to_string())),
+    }
+}
+
+struct Parser<'a> {
+    tokens: Vec<&'a str>,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Result<&'a str, FilterError> {
+        let token = self.peek().ok_or(FilterError::UnexpectedEnd)?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn or(&mut self) -> Result<Filter, FilterError> {
+        let mut filter = self.and()?;
+        while matches!(self.peek(), Some("or" | "||")) {
+            self.pos += 1;
+            filter = Filter::Or(Box::new(filter), Box::new(self.and()?));
+        }
+        Ok(filter)
+    }
+
+    fn and(&mut self) -> Result<Filter, FilterError> {
+        let mut filter = self.unary()?;
+        while matches!(self.peek(), Some("and" | "&&")) {
+            self.pos += 1;
+            filter = Filter::And(Box::new(filter), Box::new(self.unary()?));
+        }
+        Ok(filter)
+    }
+
+    fn unary(&mut self) -> Result<Filter, FilterError> {
+        match self.next()? {
+            "not" | "!" => Ok(Filter::Not(Box::new(self.unary()?))),
+            "(" => {
+                let filter = self.or()?;
+                match self.next()? {
+                    ")" => Ok(filter),
+                    token => Err(FilterError::Unexpected(token.to_string())),
+                }
+            }
+            "udp" => Ok(Filter::Protocol(PROTOCOL_UDP)),
+            "tcp" => Ok(Filter::Protocol(PROTOCOL_TCP)),
+            "icmp" => Ok(Filter::Protocol(1)),
+            "proto" => Ok(Filter::Protocol(number(self.next()?)?)),
+            "ipopt" => Ok(Filter::IpOption(number(self.next()?)?)),
+            "tcpopt" => Ok(Filter::TcpOption(number(self.next()?)?)),
+            "src" => self.directed(Direction::Source),
+            "dst" => self.directed(Direction::Destination),
+            "host" | "net" | "port" => {
+                self.pos -= 1;
+                self.directed(Direction::Either)
+            }
+            token => Err(FilterError::Unexpected(token.to_string())),
+        }
+    }
+
+    fn directed(&mut self, direction: Direction) -> Result<Filter, FilterError> {
+        match self.next()? {
+            "host" => {
+                let text = self.next()?;
+                let address = text
+                    .parse()
+                    .map_err(|_| FilterError::BadAddress(text.to_string()))?;
+                Ok(Filter::Host(direction, address))
+            }
+            "net" => {
+                let text = self.next()?;
+                let bad = || FilterError::BadNet(text.to_string());
+                let (address, prefix) = text.split_once('/').ok_or_else(bad)?;
+                let address: Ipv4Addr = address.parse().map_err(|_| bad())?;
+                let prefix: u8 = prefix.parse().ok().filter(|p| *p <= 32).ok_or_else(bad)?;
+                Ok(Filter::Net(direction, address, prefix))
+            }
+            "port" => Ok(Filter::Port(direction, number(self.next()?)?)),
+            token => Err(FilterError::Unexpected(token.to_string())),
+        }
+    }
+}
+
+/// 十进制或 0x 开头的十六进制数
+fn number<T: TryFrom<u32>>(text: &str) -> Result<T, FilterError> {
+    let value = match text.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    };
+    value
+        .and_then(|value| T::try_from(value).ok())
+        .ok_or_else(|| FilterError::BadNumber(text.to_string()))
+}
+
+fn netmask(prefix: u8) -> u32 {
+    u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0)
+}
+
+/// 在 TLV 编码的选项区中查找 `kind`；遇到 EOL 或非法长度时停止
+fn has_option(options: &[u8], kind: u8) -> bool {
+    let mut at = 0;
+    for _ in 0..MAX_OPTIONS {
+        let Some(&current) = options.get(at) else {
+            return false;
+        };
+        if current == kind {
+            return true;
+        }
+        match current {
+            0 => return false,
+            1 => at += 1,
+            _ => match options.get(at + 1) {
+                Some(&len) if len >= 2 => at += len as usize,
+                _ => return false,
+            },
+        }
+    }
+    false
+}
+
+impl Filter {
+    /// 对完整的 IPv4 数据报 (如重组后的) 求值
+    pub fn matches(&self, packet: &[u8]) -> bool {
+        if packet.len() < 20 {
+            return false;
+        }
+        let header_len = (packet[0] & 0x0f) as usize * 4;
+        let source = u32::from_be_bytes([packet[12], packet[13], packet[14], packet[15]]);
+        let destination = u32::from_be_bytes([packet[16], packet[17], packet[18], packet[19]]);
+        let either = |direction: Direction, check: &dyn Fn(u32) -> bool| match direction {
+            Direction::Either => check(source) || check(destination),
+            Direction::Source => check(source),
+            Direction::Destination => check(destination),
+        };
+        let first = u16::from_be_bytes([packet[6], packet[7]]) & 0x1fff == 0;
+        let transport = packet.get(header_len..).filter(|_| first);
+        match self {
+            Filter::Protocol(protocol) => packet[9] == *protocol,
+            Filter::Host(direction, address) => either(*direction, &|a| a == u32::from(*address)),
+            Filter::Net(direction, address, prefix) => {
+                let mask = netmask(*prefix);
+                either(*direction, &|a| a & mask == u32::from(*address) & mask)
+            }
+            Filter::Port(direction, port) => {
+                let ports = transport
+                    .filter(|_| matches!(packet[9], PROTOCOL_TCP | PROTOCOL_UDP))
+                    .and_then(|t| t.get(..4));
+                ports.is_some_and(|p| {
+                    let source = u16::from_be_bytes([p[0], p[1]]);
+                    let destination = u16::from_be_bytes([p[2], p[3]]);
+                    match direction {
+                        Direction::Either => source == *port || destination == *port,
+                        Direction::Source => source == *port,
+                        Direction::Destination => destination == *port,
+                    }
+                })
+            }
+            Filter::IpOption(kind) => packet
+                .get(20..header_len)
+                .is_some_and(|options| has_option(options, *kind)),
+            Filter::TcpOption(kind) => transport
+                .filter(|t| packet[9] == PROTOCOL_TCP && t.len() >= 20)
+                .and_then(|t| t.get(20..(t[12] >> 4) as usize * 4))
+                .is_some_and(|options| has_option(options, *kind)),
+            Filter::Not(inner) => !inner.matches(packet),
+            Filter::And(a, b) => a.matches(packet) && b.matches(packet),
+            Filter::Or(a, b) => a.matches(packet) || b.matches(packet),
+        }
+    }
+
+    /// 是否用到只在第一个分片中出现的字段
+    fn uses_first_fragment(&self) -> bool {
+        match self {
+            Filter::Port(..) | Filter::TcpOption(_) => true,
+            // copied 位为 0 的选项不会复制到后续分片
+            Filter::IpOption(kind) => kind & 0x80 == 0,
+            Filter::Not(inner) => inner.uses_first_fragment(),
+            Filter::And(a, b) | Filter::Or(a, b) => {
+                a.uses_first_fragment() || b.uses_first_fragment()
+            }
+            _ => false,
+        }
+    }
+
+    /// 编译为经典 BPF 程序
+    ///
+    /// 非 IPv4 帧一律丢弃；表达式用到只在第一个分片中的字段时，所有分片都被接受，
+    /// 由用户态重组后再用 [`Filter::matches`] 过滤。
+    pub fn compile(&self) -> Result<Vec<Instruction>, FilterError> {
+        let mut asm = Assembler::default();
+        let accept = asm.label();
+        let reject = asm.label();
+
+        asm.stmt(BPF_LD | BPF_W | BPF_ABS, SKF_AD_PROTOCOL);
+        asm.jump_unless(BPF_JEQ | BPF_K, ETH_P_IP, reject);
+        if self.uses_first_fragment() {
+            asm.stmt(BPF_LD | BPF_H | BPF_ABS, 6);
+            asm.jump_if(BPF_JSET | BPF_K, 0x3fff, accept);
+        }
+        self.emit(&mut asm, accept, reject);
+
+        asm.bind(accept);
+        asm.stmt(BPF_RET | BPF_K, ACCEPT_LEN);
+        asm.bind(reject);
+        asm.stmt(BPF_RET | BPF_K, 0);
+        asm.finish()
+    }
+
+    /// 生成求值代码：为真跳到 `t`，为假跳到 `f`
+    fn emit(&self, asm: &mut Assembler, t: Label, f: Label) {
+        match self {
+            Filter::Protocol(protocol) => {
+                asm.stmt(BPF_LD | BPF_B | BPF_ABS, 9);
+                asm.branch(BPF_JEQ | BPF_K, *protocol as u32, t, f);
+            }
+            Filter::Host(direction, address) => {
+                emit_address(asm, *direction, u32::MAX, u32::from(*address), t, f)
+            }
+            Filter::Net(direction, address, prefix) => {
+                let mask = netmask(*prefix);
+                emit_address(asm, *direction, mask, u32::from(*address) & mask, t, f)
+            }
+            Filter::Port(direction, port) => {
+                let transport = asm.label();
+                asm.stmt(BPF_LD | BPF_B | BPF_ABS, 9);
+                asm.test(BPF_JEQ | BPF_K, PROTOCOL_TCP as u32, Some(transport), None);
+                asm.branch(BPF_JEQ | BPF_K, PROTOCOL_UDP as u32, transport, f);
+                asm.bind(transport);
+                asm.stmt(BPF_LDX | BPF_B | BPF_MSH, 0);
+                let offsets: &[u32] = match direction {
+                    Direction::Either => &[0, 2],
+                    Direction::Source => &[0],
+                    Direction::Destination => &[2],
+                };
+                for (i, offset) in offsets.iter().enumerate() {
+                    asm.stmt(BPF_LD | BPF_H | BPF_IND, *offset);
+                    if i + 1 == offsets.len() {
+                        asm.branch(BPF_JEQ | BPF_K, *port as u32, t, f);
+                    } else {
+                        asm.jump_if(BPF_JEQ | BPF_K, *port as u32, t);
+                    }
+                }
+            }
+            Filter::IpOption(kind) => {
+                // M[0] = IP 首部长度，X = 第一个选项的偏移
+                asm.stmt(BPF_LDX | BPF_B | BPF_MSH, 0);
+                asm.stmt(BPF_MISC | BPF_TXA, 0);
+                asm.stmt(BPF_ST, 0);
+                asm.stmt(BPF_LDX | BPF_IMM, 20);
+                emit_option_scan(asm, *kind, 0, t, f);
+            }
+            Filter::TcpOption(kind) => {
+                asm.stmt(BPF_LD | BPF_B | BPF_ABS, 9);
+                asm.jump_unless(BPF_JEQ | BPF_K, PROTOCOL_TCP as u32, f);
+                // M[1] = TCP 首部结束的偏移，X = 第一个 TCP 选项的偏移
+                asm.stmt(BPF_LDX | BPF_B | BPF_MSH, 0);
+                asm.stmt(BPF_LD | BPF_B | BPF_IND, 12);
+                asm.stmt(BPF_ALU | BPF_AND | BPF_K, 0xf0);
+                asm.stmt(BPF_ALU | BPF_RSH | BPF_K, 2);
+                asm.stmt(BPF_ALU | BPF_ADD | BPF_X, 0);
+                asm.stmt(BPF_ST, 1);
+                asm.stmt(BPF_MISC | BPF_TXA, 0);
+                asm.stmt(BPF_ALU | BPF_ADD | BPF_K, 20);
+                asm.stmt(BPF_MISC | BPF_TAX, 0);
+                emit_option_scan(asm, *kind, 1, t, f);
+            }
+            Filter::Not(inner) => inner.emit(asm, f, t),
+            Filter::And(a, b) => {
+                let next = asm.label();
+                a.emit(asm, next, f);
+                asm.bind(next);
+                b.emit(asm, t, f);
+            }
+            Filter::Or(a, b) => {
+                let next = asm.label();
+                a.emit(asm, t, next);
+                asm.bind(next);
+                b.emit(asm, t, f);
+            }
+        }
+    }
+}
+
+/// 比较源和 / 或目的地址 (与 `mask` 相与后) 是否等于 `value`
+fn emit_address(
+    asm: &mut Assembler,
+    direction: Direction,
+    mask: u32,
+    value: u32,
+    t: Label,
+    f: Label,
+) {
+    let offsets: &[u32] = match direction {
+        Direction::Either => &[12, 16],
+        Direction::Source => &[12],
+        Direction::Destination => &[16],
+    };
+    for (i, offset) in offsets.iter().enumerate() {
+        asm.stmt(BPF_LD | BPF_W | BPF_ABS, *offset);
+        if mask != u32::MAX {
+            asm.stmt(BPF_ALU | BPF_AND | BPF_K, mask);
+        }
+        if i + 1 == offsets.len() {
+            asm.branch(BPF_JEQ | BPF_K, value, t, f);
+        } else {
+            asm.jump_if(BPF_JEQ | BPF_K, value, t);
+        }
+    }
+}
+
+/// 展开的选项扫描：X 为当前选项的偏移，M[`end`] 为选项区结束的偏移
+fn emit_option_scan(asm: &mut Assembler, kind: u8, end: u32, t: Label, f: Label) {
+    for _ in 0..MAX_OPTIONS {
+        let next = asm.label();
+        let found = asm.label();
+        let stop = asm.label();
+        let nop = asm.label();
+        asm.stmt(BPF_LD | BPF_MEM, end);
+        asm.test(BPF_JGT | BPF_X, 0, None, Some(stop));
+        asm.stmt(BPF_LD | BPF_B | BPF_IND, 0);
+        asm.test(BPF_JEQ | BPF_K, kind as u32, Some(found), None);
+        asm.test(BPF_JEQ | BPF_K, 0, Some(stop), None);
+        asm.test(BPF_JEQ | BPF_K, 1, Some(nop), None);
+        // 多字节选项：X += 长度，长度小于 2 时停止
+        asm.stmt(BPF_LD | BPF_B | BPF_IND, 1);
+        asm.test(BPF_JGE | BPF_K, 2, None, Some(stop));
+        asm.stmt(BPF_ALU | BPF_ADD | BPF_X, 0);
+        asm.stmt(BPF_MISC | BPF_TAX, 0);
+        asm.jump(next);
+        asm.bind(nop);
+        asm.stmt(BPF_MISC | BPF_TXA, 0);
+        asm.stmt(BPF_ALU | BPF_ADD | BPF_K, 1);
+        asm.stmt(BPF_MISC | BPF_TAX, 0);
+        asm.jump(next);
+        asm.bind(found);
+        asm.jump(t);
+        asm.bind(stop);
+        asm.jump(f);
+        asm.bind(next);
+    }
+    asm.jump(f);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Label(usize);
+
+enum Op {
+    Stmt(u16, u32),
+    /// 条件跳转，`None` 表示继续执行下一条
+    Branch {
+        code: u16,
+        k: u32,
+        t: Option<Label>,
+        f: Option<Label>,
+    },
+    Jump(Label),
+}
+
+/// 带标签的汇编器，结束时解析跳转偏移
+#[derive(Default)]
+struct Assembler {
+    ops: Vec<Op>,
+    labels: Vec<Option<usize>>,
+}
+
+impl Assembler {
+    fn label(&mut self) -> Label {
+        self.labels.push(None);
+        Label(self.labels.len() - 1)
+    }
+
+    fn bind(&mut self, label: Label) {
+        self.labels[label.0] = Some(self.ops.len());
+    }
+
+    fn stmt(&mut self, code: u16, k: u32) {
+        self.ops.push(Op::Stmt(code, k));
+    }
+
+    fn jump(&mut self, target: Label) {
+        self.ops.push(Op::Jump(target));
+    }
+
+    /// 只用于近距离的条件跳转 (不超过 255 条指令)
+    fn test(&mut self, code: u16, k: u32, t: Option<Label>, f: Option<Label>) {
+        self.ops.push(Op::Branch {
+            code: BPF_JMP | code,
+            k,
+            t,
+            f,
+        });
+    }
+
+    /// 跳到任意远的 `t` / `f`：条件跳转到紧随其后的两条无条件跳转
+    fn branch(&mut self, code: u16, k: u32, t: Label, f: Label) {
+        let on_false = self.label();
+        self.test(code, k, None, Some(on_false));
+        self.jump(t);
+        self.bind(on_false);
+        self.jump(f);
+    }
+
+    /// 条件为真时跳到 `t`，否则继续
+    fn jump_if(&mut self, code: u16, k: u32, t: Label) {
+        let next = self.label();
+        self.branch(code, k, t, next);
+        self.bind(next);
+    }
+
+    /// 条件为假时跳到 `f`，否则继续
+    fn jump_unless(&mut self, code: u16, k: u32, f: Label) {
+        let next = self.label();
+        self.branch(code, k, next, f);
+        self.bind(next);
+    }
+
+    fn finish(self) -> Result<Vec<Instruction>, FilterError> {
+        if self.ops.len() > MAX_INSTRUCTIONS {
+            return Err(FilterError::TooLong(self.ops.len()));
+        }
+        let target = |label: Label| self.labels[label.0].expect("标签未绑定");
+        let relative = |at: usize, label: Option<Label>| -> Result<u8, FilterError> {
+            let to = label.map_or(at + 1, target);
+            u8::try_from(to - at - 1).map_err(|_| FilterError::TooLong(self.ops.len()))
+        };
+        self.ops
+            .iter()
+            .enumerate()
+            .map(|(at, op)| {
+                Ok(match *op {
+                    Op::Stmt(code, k) => Instruction {
+                        code,
+                        jt: 0,
+                        jf: 0,
+                        k,
+                    },
+                    Op::Branch { code, k, t, f } => Instruction {
+                        code,
+                        jt: relative(at, t)?,
+                        jf: relative(at, f)?,
+                        k,
+                    },
+                    Op::Jump(label) => Instruction {
+                        code: BPF_JMP | BPF_JA,
+                        jt: 0,
+                        jf: 0,
+                        k: (target(label) - at - 1) as u32,
+                    },
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::{PacketBuilder, TcpLayer, UdpLayer};
+    use crate::fragment::Fragmenter;
+
+    const SOURCE: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 1);
+    const DESTINATION: Ipv4Addr = Ipv4Addr::new(192, 168, 1, 20);
+    /// Router Alert (copied 位为 1) 和实验用的 30 号选项 (copied 位为 0)
+    const IP_OPTIONS: [u8; 8] = [0x94, 4, 0, 0, 0x1e, 4, 0, 0];
+
+    const EXPRESSIONS: &[&str] = &[
+        "udp",
+        "tcp",
+        "icmp",
+        "proto 47",
+        "host 10.0.0.1",
+        "src host 10.0.0.1",
+        "dst net 192.168.0.0/16",
+        "net 10.0.0.0/8",
+        "port 8001",
+        "src port 40000",
+        "dst port 8001",
+        "ipopt 0x94",
+        "ipopt 0x1e",
+        "ipopt 0x79",
+        "tcpopt 4",
+        "tcpopt 253",
+        "not udp",
+        "udp and dst port 8001",
+        "tcp or icmp",
+        "not (udp or tcp) and host 10.0.0.1",
+        "(src host 10.0.0.1 or dst host 10.0.0.2) and (ipopt 0x94 or tcpopt 2)",
+    ];
+
+    fn udp(options: &[u8]) -> Vec<u8> {
+        PacketBuilder::ipv4(SOURCE, DESTINATION)
+            .ip_options(options.to_vec())
+            .udp(UdpLayer::new(40000, 8001))
+            .payload(vec![0x5a; 64])
+            .build()
+            .unwrap()
+    }
+
+    fn tcp(options: &[u8]) -> Vec<u8> {
+        let mut layer = TcpLayer::new(40000, 8001);
+        // MSS、两个 NOP、SACK permitted
+        layer.options = vec![2, 4, 0x05, 0xb4, 1, 1, 4, 2];
+        PacketBuilder::ipv4(SOURCE, DESTINATION)
+            .ip_options(options.to_vec())
+            .tcp(layer)
+            .build()
+            .unwrap()
+    }
+
+    fn filter(text: &str) -> Filter {
+        parse(text).unwrap_or_else(|e| panic!("{}: {}", text, e))
+    }
+
+    /// 在 IPv4 帧上执行经典 BPF 程序，返回是否接受；越界读取与内核一样视为丢弃
+    fn run(program: &[Instruction], packet: &[u8]) -> bool {
+        let load = |offset: u32, size: usize| {
+            if offset == SKF_AD_PROTOCOL {
+                return Some(ETH_P_IP);
+            }
+            let start = offset as usize;
+            packet
+                .get(start..start + size)
+                .map(|bytes| bytes.iter().fold(0u32, |v, &b| v << 8 | b as u32))
+        };
+        let (mut a, mut x, mut memory) = (0u32, 0u32, [0u32; 16]);
+        let mut pc = 0;
+        loop {
+            let Instruction { code, jt, jf, k } = program[pc];
+            pc += 1;
+            let operand = if code & BPF_X != 0 { x } else { k };
+            match code & 0x07 {
+                BPF_LD => {
+                    let size = match code & 0x18 {
+                        BPF_W => 4,
+                        BPF_H => 2,
+                        _ => 1,
+                    };
+                    let value = match code & 0xe0 {
+                        BPF_ABS => load(k, size),
+                        BPF_IND => load(x.wrapping_add(k), size),
+                        BPF_MEM => Some(memory[k as usize]),
+                        _ => Some(k),
+                    };
+                    let Some(value) = value else {
+                        return false;
+                    };
+                    a = value;
+                }
+                BPF_LDX => {
+                    x = match code & 0xe0 {
+                        BPF_MEM => memory[k as usize],
+                        BPF_MSH => match load(k, 1) {
+                            Some(byte) => (byte & 0x0f) * 4,
+                            None => return false,
+                        },
+                        _ => k,
+                    }
+                }
+                BPF_ST => memory[k as usize] = a,
+                BPF_ALU => {
+                    a = match code & 0xf0 {
+                        BPF_ADD => a.wrapping_add(operand),
+                        BPF_AND => a & operand,
+                        BPF_RSH => a >> operand,
+                        op => panic!("未知的运算 0x{:02x}", op),
+                    }
+                }
+                BPF_JMP => {
+                    let taken = match code & 0xf0 {
+                        BPF_JA => {
+                            pc += k as usize;
+                            continue;
+                        }
+                        BPF_JEQ => a == operand,
+                        BPF_JGT => a > operand,
+                        BPF_JGE => a >= operand,
+                        BPF_JSET => a & operand != 0,
+                        op => panic!("未知的跳转 0x{:02x}", op),
+                    };
+                    pc += if taken { jt } else { jf } as usize;
+                }
+                BPF_RET => return k != 0,
+                BPF_MISC if code & 0xf8 == BPF_TXA => a = x,
+                _ => x = a,
+            }
+        }
+    }
+
+    #[test]
+    fn display_round_trips_through_parse() {
+        for text in EXPRESSIONS {
+            let parsed = filter(text);
+            let printed = parsed.to_string();
+            assert_eq!(
+                parse(&printed),
+                Ok(parsed.clone()),
+                "{} -> {}",
+                text,
+                printed
+            );
+            assert_eq!(filter(&printed).to_string(), printed);
+        }
+    }
+
+    #[test]
+    fn parse_precedence_and_aliases() {
+        assert_eq!(
+            filter("udp or tcp and port 1"),
+            Filter::Or(
+                Box::new(Filter::Protocol(PROTOCOL_UDP)),
+                Box::new(Filter::And(
+                    Box::new(Filter::Protocol(PROTOCOL_TCP)),
+                    Box::new(Filter::Port(Direction::Either, 1)),
+                )),
+            )
+        );
+        assert_eq!(
+            filter("udp && !tcp || icmp"),
+            filter("(udp and not tcp) or icmp")
+        );
+        assert_eq!(filter("ipopt 0x79"), Filter::IpOption(0x79));
+        assert_eq!(filter("ipopt 121"), Filter::IpOption(0x79));
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(parse("  "), Err(FilterError::Empty));
+        assert_eq!(parse("udp and"), Err(FilterError::UnexpectedEnd));
+        assert_eq!(parse("(udp"), Err(FilterError::UnexpectedEnd));
+        assert_eq!(parse("udp )"), Err(FilterError::Unexpected(")".into())));
+        assert_eq!(parse("udp tcp"), Err(FilterError::Unexpected("tcp".into())));
+        assert_eq!(parse("src udp"), Err(FilterError::Unexpected("udp".into())));
+        assert_eq!(
+            parse("port 70000"),
+            Err(FilterError::BadNumber("70000".into()))
+        );
+        assert_eq!(
+            parse("ipopt 0x100"),
+            Err(FilterError::BadNumber("0x100".into()))
+        );
+        assert_eq!(
+            parse("host 10.0.0"),
+            Err(FilterError::BadAddress("10.0.0".into()))
+        );
+        assert_eq!(
+            parse("net 10.0.0.0/33"),
+            Err(FilterError::BadNet("10.0.0.0/33".into()))
+        );
+        assert_eq!(
+            parse("net 10.0.0.0"),
+            Err(FilterError::BadNet("10.0.0.0".into()))
+        );
+    }
+
+    #[test]
+    fn matches_ports_and_options_behind_ip_options() {
+        let packet = udp(&IP_OPTIONS);
+        assert_eq!(packet[0] & 0x0f, 7);
+        for (text, expected) in [
+            ("udp and dst port 8001", true),
+            ("src port 8001", false),
+            ("src port 40000", true),
+            ("src host 10.0.0.1 and dst net 192.168.0.0/16", true),
+            ("dst host 10.0.0.1", false),
+            ("ipopt 0x94", true),
+            ("ipopt 0x1e", true),
+            ("ipopt 0x79", false),
+            ("tcpopt 2", false),
+        ] {
+            assert_eq!(filter(text).matches(&packet), expected, "{}", text);
+        }
+        // 没有选项时只跳过 20 字节首部
+        assert!(filter("dst port 8001 and not ipopt 0x94").matches(&udp(&[])));
+    }
+
+    #[test]
+    fn matches_tcp_options() {
+        for packet in [tcp(&[]), tcp(&IP_OPTIONS)] {
+            for (text, expected) in [
+                ("tcp and port 8001", true),
+                ("tcpopt 2", true),
+                ("tcpopt 4", true),
+                ("tcpopt 1", true),
+                ("tcpopt 8", false),
+            ] {
+                assert_eq!(filter(text).matches(&packet), expected, "{}", text);
+            }
+        }
+    }
+
+    #[test]
+    fn matches_fragments() {
+        let packet = udp(&IP_OPTIONS);
+        let fragments = Fragmenter::new(60).fragment(&packet).unwrap();
+        assert!(fragments.len() > 2);
+        let (first, rest) = fragments.split_first().unwrap();
+        assert!(filter("udp and port 8001 and ipopt 0x1e").matches(first));
+        for fragment in rest {
+            // 后续分片没有 UDP 首部，也不带 copied 位为 0 的选项
+            assert!(!filter("port 8001").matches(fragment));
+            assert!(!filter("ipopt 0x1e").matches(fragment));
+            assert!(filter("udp and host 192.168.1.20 and ipopt 0x94").matches(fragment));
+        }
+    }
+
+    #[test]
+    fn compiled_program_agrees_with_matches() {
+        let packets = [
+            udp(&[]),
+            udp(&IP_OPTIONS),
+            tcp(&[]),
+            tcp(&IP_OPTIONS),
+            PacketBuilder::ipv4(DESTINATION, SOURCE).build().unwrap(),
+        ];
+        for text in EXPRESSIONS {
+            let filter = filter(text);
+            let program = filter.compile().unwrap();
+            for packet in &packets {
+                assert_eq!(run(&program, packet), filter.matches(packet), "{}", text);
+            }
+        }
+    }
+
+    #[test]
+    fn compiled_program_accepts_fragments_it_cannot_judge() {
+        let fragments = Fragmenter::new(60).fragment(&udp(&IP_OPTIONS)).unwrap();
+        let program = filter("udp and port 8001").compile().unwrap();
+        assert!(fragments.iter().all(|fragment| run(&program, fragment)));
+        // 第一个分片同样设置了 MF，端口不符也要交给用户态重组后判断
+        let program = filter("udp and port 9").compile().unwrap();
+        assert!(fragments.iter().all(|fragment| run(&program, fragment)));
+        assert!(!run(&program, &udp(&IP_OPTIONS)));
+        // copied 位为 1 的选项出现在每个分片中，不必放行全部分片
+        let program = filter("not ipopt 0x94").compile().unwrap();
+        assert!(fragments.iter().all(|fragment| !run(&program, fragment)));
+    }
+
+    #[test]
+    fn compiled_jumps_stay_in_program() {
+        for text in EXPRESSIONS {
+            let program = filter(text).compile().unwrap();
+            assert!(program.len() <= MAX_INSTRUCTIONS);
+            assert_eq!(program.last().unwrap().code, BPF_RET | BPF_K, "{}", text);
+            for (at, instruction) in program.iter().enumerate() {
+                if instruction.code & 0x07 != BPF_JMP {
+                    continue;
+                }
+                let targets = if instruction.code & 0xf0 == BPF_JA {
+                    vec![instruction.k as usize]
+                } else {
+                    vec![instruction.jt as usize, instruction.jf as usize]
+                };
+                for offset in targets {
+                    assert!(at + 1 + offset < program.len(), "{}: 第 {} 条", text, at);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn compile_rejects_programs_over_the_limit() {
+        let scans = |count: u8| {
+            (0..count)
+                .map(|kind| Filter::IpOption(kind + 2))
+                .reduce(|a, b| Filter::Or(Box::new(a), Box::new(b)))
+                .unwrap()
+        };
+        assert!(scans(1).compile().is_ok());
+        match scans(8).compile() {
+            Err(FilterError::TooLong(len)) => assert!(len > MAX_INSTRUCTIONS),
+            other => panic!("应超过指令数限制: {:?}", other.map(|p| p.len())),
+        }
+    }
+}
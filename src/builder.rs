@@ -0,0 +1,591 @@
+//! 分层数据包构造器
+//!
+//! 按 IPv4 / UDP / TCP / ICMP / 负载 逐层叠加，`build` 时统一计算 IHL、总长度、
+//! UDP 长度以及各层校验和，发送程序不再需要手工推算头部大小。
+//! 源地址未指定 (0.0.0.0) 时，`build` 按路由表选择出接口的地址。
+
+use std::fmt;
+use std::net::Ipv4Addr;
+
+use pnet::packet::Packet;
+use pnet::packet::icmp::{IcmpCode, IcmpType, MutableIcmpPacket};
+use pnet::packet::ip::{IpNextHeaderProtocol, IpNextHeaderProtocols};
+use pnet::packet::ipv4::MutableIpv4Packet;
+use pnet::packet::tcp::MutableTcpPacket;
+use pnet::packet::udp::MutableUdpPacket;
+
+use crate::checksum;
+use crate::route::{self, RouteError};
+
+/// IPv4 固定头部长度
+pub const IPV4_HEADER_LEN: usize = 20;
+/// IPv4 选项最大长度 (IHL 最大为 15)
+pub const IPV4_MAX_OPTIONS_LEN: usize = 40;
+/// UDP 头部长度
+pub const UDP_HEADER_LEN: usize = 8;
+/// TCP 固定头部长度
+pub const TCP_HEADER_LEN: usize = 20;
+/// TCP 选项最大长度 (data offset 最大为 15)
+pub const TCP_MAX_OPTIONS_LEN: usize = 40;
+/// ICMP 头部长度
+pub const ICMP_HEADER_LEN: usize = 8;
+
+/// 构造数据包时的错误
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BuildError {
+    /// IP 选项 (补齐后) 超过 40 字节
+    Ipv4OptionsTooLong(usize),
+    /// TCP 选项 (补齐后) 超过 40 字节
+    TcpOptionsTooLong(usize),
+    /// IP 总长度超过 65535
+    PacketTooLarge(usize),
+    /// 未指定源地址且无法从路由表选出
+    NoSource(RouteError),
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildError::Ipv4OptionsTooLong(len) => {
+                write!(
+                    f,
+                    "IP 选项长度 {} 字节, 超过上限 {} 字节",
+                    len, IPV4_MAX_OPTIONS_LEN
+                )
+            }
+            BuildError::TcpOptionsTooLong(len) => {
+                write!(
+                    f,
+                    "TCP 选项长度 {} 字节, 超过上限 {} 字节",
+                    len, TCP_MAX_OPTIONS_LEN
+                )
+            }
+            BuildError::PacketTooLarge(len) => {
+                write!(f, "数据包总长度 {} 字节, 超过上限 65535 字节", len)
+            }
+            BuildError::NoSource(e) => write!(f, "无法选择源地址: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+/// IPv4 层
+///
+/// `protocol` 为 `None` 时由上层协议自动推断；长度、IHL 和校验和在 `build` 时计算。
+#[derive(Debug, Clone)]
+pub struct Ipv4Layer {
+    pub tos: u8,
+    pub identification: u16,
+    pub dont_fragment: bool,
+    pub more_fragments: bool,
+    /// 分片偏移，单位为 8 字节
+    pub fragment_offset: u16,
+    pub ttl: u8,
+    pub protocol: Option<IpNextHeaderProtocol>,
+    pub source: Ipv4Addr,
+    pub destination: Ipv4Addr,
+    /// 原始选项字节，不足 4 字节倍数时以 EOL(0) 补齐
+    pub options: Vec<u8>,
+}
+
+impl Ipv4Layer {
+    pub fn new(source: Ipv4Addr, destination: Ipv4Addr) -> Self {
+        Ipv4Layer {
+            tos: 0,
+            identification: 0,
+            dont_fragment: false,
+            more_fragments: false,
+            fragment_offset: 0,
+            ttl: 64,
+            protocol: None,
+            source,
+            destination,
+            options: Vec::new(),
+        }
+    }
+}
+
+/// UDP 层，长度和校验和在 `build` 时计算
+#[derive(Debug, Clone)]
+pub struct UdpLayer {
+    pub source: u16,
+    pub destination: u16,
+}
+
+impl UdpLayer {
+    pub fn new(source: u16, destination: u16) -> Self {
+        UdpLayer {
+            source,
+            destination,
+        }
+    }
+}
+
+/// TCP 层，data offset 和校验和在 `build` 时计算
+#[derive(Debug, Clone)]
+pub struct TcpLayer {
+    pub source: u16,
+    pub destination: u16,
+    pub sequence: u32,
+    pub acknowledgement: u32,
+    pub flags: u8,
+    pub window: u16,
+    pub urgent_ptr: u16,
+    /// 原始选项字节，不足 4 字节倍数时以 EOL(0) 补齐
+    pub options: Vec<u8>,
+}
+
+impl TcpLayer {
+    pub fn new(source: u16, destination: u16) -> Self {
+        TcpLayer {
+            source,
+            destination,
+            sequence: 0,
+            acknowledgement: 0,
+            flags: 0,
+            window: 65535,
+            urgent_ptr: 0,
+            options: Vec::new(),
+        }
+    }
+}
+
+/// ICMP 层，校验和在 `build` 时计算
+#[derive(Debug, Clone)]
+pub struct IcmpLayer {
+    pub icmp_type: u8,
+    pub code: u8,
+    /// 头部后 4 字节 (echo 报文中为 identifier + sequence)
+    pub rest_of_header: [u8; 4],
+}
+
+impl IcmpLayer {
+    pub fn new(icmp_type: u8, code: u8) -> Self {
+        IcmpLayer {
+            icmp_type,
+            code,
+            rest_of_header: [0; 4],
+        }
+    }
+}
+
+/// IPv4 之上的传输层
+#[derive(Debug, Clone)]
+pub enum TransportLayer {
+    Udp(UdpLayer),
+    Tcp(TcpLayer),
+    Icmp(IcmpLayer),
+}
+
+impl TransportLayer {
+    fn protocol(&self) -> IpNextHeaderProtocol {
+        match self {
+            TransportLayer::Udp(_) => IpNextHeaderProtocols::Udp,
+            TransportLayer::Tcp(_) => IpNextHeaderProtocols::Tcp,
+            TransportLayer::Icmp(_) => IpNextHeaderProtocols::Icmp,
+        }
+    }
+}
+
+/// 分层数据包构造器
+///
+/// ```no_run
+/// use ip_header::{PacketBuilder, UdpLayer};
+///
+/// let packet = PacketBuilder::ipv4("10.0.0.1".parse().unwrap(), "10.0.0.2".parse().unwrap())
+///     .ttl(64)
+///     .udp(UdpLayer::new(54321, 8001))
+///     .payload(b"Hello, Biaoshi!".to_vec())
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct PacketBuilder {
+    ip: Ipv4Layer,
+    transport: Option<TransportLayer>,
+    payload: Vec<u8>,
+}
+
+impl PacketBuilder {
+    pub fn new(ip: Ipv4Layer) -> Self {
+        PacketBuilder {
+            ip,
+            transport: None,
+            payload: Vec::new(),
+        }
+    }
+
+    pub fn ipv4(source: Ipv4Addr, destination: Ipv4Addr) -> Self {
+        Self::new(Ipv4Layer::new(source, destination))
+    }
+
+    /// 只指定目的地址，源地址在 `build` 时按路由表选择
+    pub fn to(destination: Ipv4Addr) -> Self {
+        Self::ipv4(Ipv4Addr::UNSPECIFIED, destination)
+    }
+
+    /// 显式指定源地址，0.0.0.0 表示按路由表选择
+    pub fn source(mut self, source: Ipv4Addr) -> Self {
+        self.ip.source = source;
+        self
+    }
+
+    pub fn tos(mut self, tos: u8) -> Self {
+        self.ip.tos = tos;
+        self
+    }
+
+    pub fn identification(mut self, identification: u16) -> Self {
+        self.ip.identification = identification;
+        self
+    }
+
+    pub fn dont_fragment(mut self, dont_fragment: bool) -> Self {
+        self.ip.dont_fragment = dont_fragment;
+        self
+    }
+
+    pub fn ttl(mut self, ttl: u8) -> Self {
+        self.ip.ttl = ttl;
+        self
+    }
+
+    /// 显式指定 IP 协议号；未指定时由传输层推断，没有传输层时为 0
+    pub fn protocol(mut self, protocol: IpNextHeaderProtocol) -> Self {
+        self.ip.protocol = Some(protocol);
+        self
+    }
+
+    pub fn ip_options(mut self, options: Vec<u8>) -> Self {
+        self.ip.options = options;
+        self
+    }
+
+    pub fn udp(mut self, udp: UdpLayer) -> Self {
+        self.transport = Some(TransportLayer::Udp(udp));
+        self
+    }
+
+    pub fn tcp(mut self, tcp: TcpLayer) -> Self {
+        self.transport = Some(TransportLayer::Tcp(tcp));
+        self
+    }
+
+    pub fn icmp(mut self, icmp: IcmpLayer) -> Self {
+        self.transport = Some(TransportLayer::Icmp(icmp));
+        self
+    }
+
+    pub fn payload(mut self, payload: Vec<u8>) -> Self {
+        self.payload = payload;
+        self
+    }
+
+    pub fn ip_layer(&self) -> &Ipv4Layer {
+        &self.ip
+    }
+
+    pub fn ip_layer_mut(&mut self) -> &mut Ipv4Layer {
+        &mut self.ip
+    }
+
+    pub fn transport_layer(&self) -> Option<&TransportLayer> {
+        self.transport.as_ref()
+    }
+
+    pub fn transport_layer_mut(&mut self) -> Option<&mut TransportLayer> {
+        self.transport.as_mut()
+    }
+
+    pub fn payload_bytes(&self) -> &[u8] {
+        &self.payload
+    }
+
+    /// 生成完整的 IPv4 数据报
+    ///
+    /// # 返回
+    /// 已填好 IHL、总长度、UDP 长度 / TCP data offset 及所有校验和的字节序列
+    pub fn build(&self) -> Result<Vec<u8>, BuildError> {
+        let source = self.resolve_source()?;
+        let segment = self.build_transport(source)?;
+
+        let ip_options = pad_to_words(&self.ip.options);
+        if ip_options.len() > IPV4_MAX_OPTIONS_LEN {
+            return Err(BuildError::Ipv4OptionsTooLong(ip_options.len()));
+        }
+        let ip_header_len = IPV4_HEADER_LEN + ip_options.len();
+        let total_len = ip_header_len + segment.len();
+        if total_len > u16::MAX as usize {
+            return Err(BuildError::PacketTooLarge(total_len));
+        }
+
+        let protocol = self
+            .ip
+            .protocol
+            .or_else(|| self.transport.as_ref().map(TransportLayer::protocol))
+            .unwrap_or(IpNextHeaderProtocol(0));
+
+        let mut buffer = vec![0u8; total_len];
+        let mut ip_packet = MutableIpv4Packet::new(&mut buffer).unwrap();
+        ip_packet.set_version(4);
+        ip_packet.set_header_length((ip_header_len / 4) as u8);
+        ip_packet.set_dscp(self.ip.tos >> 2);
+        ip_packet.set_ecn(self.ip.tos & 0x03);
+        ip_packet.set_total_length(total_len as u16);
+        ip_packet.set_identification(self.ip.identification);
+        ip_packet.set_flags(((self.ip.dont_fragment as u8) << 1) | (self.ip.more_fragments as u8));
+        ip_packet.set_fragment_offset(self.ip.fragment_offset);
+        ip_packet.set_ttl(self.ip.ttl);
+        ip_packet.set_next_level_protocol(protocol);
+        ip_packet.set_source(source);
+        ip_packet.set_destination(self.ip.destination);
+        ip_packet.get_options_raw_mut().copy_from_slice(&ip_options);
+        ip_packet.set_payload(&segment);
+
+        let check = checksum::checksum(&ip_packet.packet()[..ip_header_len]);
+        ip_packet.set_checksum(check);
+
+        Ok(buffer)
+    }
+
+    /// 实际使用的源地址：未指定时查询路由表
+    fn resolve_source(&self) -> Result<Ipv4Addr, BuildError> {
+        if !self.ip.source.is_unspecified() {
+            return Ok(self.ip.source);
+        }
+        route::lookup(self.ip.destination)
+            .map(|egress| egress.source)
+            .map_err(BuildError::NoSource)
+    }
+
+    /// 生成传输层报文段 (头部 + 负载)
+    fn build_transport(&self, source: Ipv4Addr) -> Result<Vec<u8>, BuildError> {
+        let destination = self.ip.destination;
+
+        let segment = match &self.transport {
+            None => self.payload.clone(),
+            Some(TransportLayer::Udp(layer)) => {
+                let len = UDP_HEADER_LEN + self.payload.len();
+                if IPV4_HEADER_LEN + len > u16::MAX as usize {
+                    return Err(BuildError::PacketTooLarge(IPV4_HEADER_LEN + len));
+                }
+                let mut buffer = vec![0u8; len];
+                let mut udp_packet = MutableUdpPacket::new(&mut buffer).unwrap();
+                udp_packet.set_source(layer.source);
+                udp_packet.set_destination(layer.destination);
+                udp_packet.set_length(len as u16);
+                udp_packet.set_payload(&self.payload);
+
+                let check = checksum::udp_checksum(source, destination, udp_packet.packet());
+                udp_packet.set_checksum(check);
+                buffer
+            }
+            Some(TransportLayer::Tcp(layer)) => {
+                let options = pad_to_words(&layer.options);
+                if options.len() > TCP_MAX_OPTIONS_LEN {
+                    return Err(BuildError::TcpOptionsTooLong(options.len()));
+                }
+                let header_len = TCP_HEADER_LEN + options.len();
+                let mut buffer = vec![0u8; header_len + self.payload.len()];
+                let mut tcp_packet = MutableTcpPacket::new(&mut buffer).unwrap();
+                tcp_packet.set_source(layer.source);
+                tcp_packet.set_destination(layer.destination);
+                tcp_packet.set_sequence(layer.sequence);
+                tcp_packet.set_acknowledgement(layer.acknowledgement);
+                tcp_packet.set_data_offset((header_len / 4) as u8);
+                tcp_packet.set_flags(layer.flags);
+                tcp_packet.set_window(layer.window);
+                tcp_packet.set_urgent_ptr(layer.urgent_ptr);
+                tcp_packet.get_options_raw_mut().copy_from_slice(&options);
+                tcp_packet.set_payload(&self.payload);
+
+                let check = checksum::tcp_checksum(source, destination, tcp_packet.packet());
+                tcp_packet.set_checksum(check);
+                buffer
+            }
+            Some(TransportLayer::Icmp(layer)) => {
+                let mut buffer = vec![0u8; ICMP_HEADER_LEN + self.payload.len()];
+                let mut icmp_packet = MutableIcmpPacket::new(&mut buffer).unwrap();
+                icmp_packet.set_icmp_type(IcmpType(layer.icmp_type));
+                icmp_packet.set_icmp_code(IcmpCode(layer.code));
+                // pnet 的 ICMP payload 从第 4 字节开始, 包含 rest of header
+                let mut body = layer.rest_of_header.to_vec();
+                body.extend_from_slice(&self.payload);
+                icmp_packet.set_payload(&body);
+
+                let check = checksum::checksum(icmp_packet.packet());
+                icmp_packet.set_checksum(check);
+                buffer
+            }
+        };
+
+        Ok(segment)
+    }
+}
+
+/// 将选项字节以 EOL(0) 补齐到 4 字节的倍数
+fn pad_to_words(options: &[u8]) -> Vec<u8> {
+    let mut padded = options.to_vec();
+    while !padded.len().is_multiple_of(4) {
+        padded.push(0);
+    }
+    padded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 1);
+    const DESTINATION: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 2);
+    const PAYLOAD: &[u8] = b"Hello, Biaoshi!";
+
+    fn builder() -> PacketBuilder {
+        PacketBuilder::ipv4(SOURCE, DESTINATION).payload(PAYLOAD.to_vec())
+    }
+
+    fn read_u16(bytes: &[u8], at: usize) -> u16 {
+        u16::from_be_bytes([bytes[at], bytes[at + 1]])
+    }
+
+    fn header_len(packet: &[u8]) -> usize {
+        (packet[0] & 0x0f) as usize * 4
+    }
+
+    /// 伪首部加报文段的校验和，正确时为 0
+    fn transport_sum(packet: &[u8]) -> u16 {
+        let segment = &packet[header_len(packet)..];
+        let mut bytes = packet[12..20].to_vec();
+        bytes.extend_from_slice(&[0, packet[9]]);
+        bytes.extend_from_slice(&(segment.len() as u16).to_be_bytes());
+        bytes.extend_from_slice(segment);
+        checksum::checksum(&bytes)
+    }
+
+    fn assert_ip_header(packet: &[u8], ihl: u8, protocol: u8) {
+        assert_eq!(packet[0], 0x40 | ihl);
+        assert_eq!(read_u16(packet, 2) as usize, packet.len());
+        assert_eq!(packet[9], protocol);
+        assert_eq!(packet[12..16], SOURCE.octets());
+        assert_eq!(packet[16..20], DESTINATION.octets());
+        assert_eq!(checksum::checksum(&packet[..header_len(packet)]), 0);
+    }
+
+    #[test]
+    fn ip_options_are_padded_and_counted_in_ihl() {
+        let packet = builder()
+            .tos(0xb8)
+            .identification(0x4242)
+            .dont_fragment(true)
+            .ttl(9)
+            .ip_options(vec![0x94, 4, 0])
+            .udp(UdpLayer::new(40000, 8001))
+            .build()
+            .unwrap();
+        assert_ip_header(&packet, 6, 17);
+        assert_eq!(packet.len(), 24 + 8 + PAYLOAD.len());
+        assert_eq!(packet[1], 0xb8);
+        assert_eq!(read_u16(&packet, 4), 0x4242);
+        assert_eq!(read_u16(&packet, 6), 0x4000);
+        assert_eq!(packet[8], 9);
+        assert_eq!(packet[20..24], [0x94, 4, 0, 0]);
+
+        // 恰好 40 字节的选项
+        let packet = builder().ip_options(vec![1; 37]).build().unwrap();
+        assert_ip_header(&packet, 15, 0);
+        assert_eq!(packet[60..], *PAYLOAD);
+    }
+
+    #[test]
+    fn udp_length_and_checksum() {
+        let packet = builder().udp(UdpLayer::new(40000, 8001)).build().unwrap();
+        assert_ip_header(&packet, 5, 17);
+        assert_eq!(read_u16(&packet, 20), 40000);
+        assert_eq!(read_u16(&packet, 22), 8001);
+        assert_eq!(read_u16(&packet, 24) as usize, 8 + PAYLOAD.len());
+        assert_ne!(read_u16(&packet, 26), 0);
+        assert_eq!(transport_sum(&packet), 0);
+        assert_eq!(packet[28..], *PAYLOAD);
+    }
+
+    #[test]
+    fn tcp_data_offset_and_checksum() {
+        let mut tcp = TcpLayer::new(40000, 8001);
+        tcp.sequence = 7;
+        tcp.flags = 0x02;
+        // MSS 加一个 NOP，补齐到 8 字节
+        tcp.options = vec![2, 4, 0x05, 0xb4, 1];
+        let packet = builder().tcp(tcp).build().unwrap();
+        assert_ip_header(&packet, 5, 6);
+        assert_eq!(packet.len(), 20 + 28 + PAYLOAD.len());
+        assert_eq!(packet[20 + 12] >> 4, 7);
+        assert_eq!(packet[20 + 13], 0x02);
+        assert_eq!(packet[40..48], [2, 4, 0x05, 0xb4, 1, 0, 0, 0]);
+        assert_eq!(transport_sum(&packet), 0);
+        assert_eq!(packet[48..], *PAYLOAD);
+
+        let packet = builder().tcp(TcpLayer::new(40000, 8001)).build().unwrap();
+        assert_eq!(packet[20 + 12] >> 4, 5);
+        assert_eq!(transport_sum(&packet), 0);
+    }
+
+    #[test]
+    fn icmp_checksum() {
+        let mut icmp = IcmpLayer::new(8, 0);
+        icmp.rest_of_header = [0x12, 0x34, 0, 1];
+        let packet = builder().icmp(icmp).build().unwrap();
+        assert_ip_header(&packet, 5, 1);
+        assert_eq!(packet[20..22], [8, 0]);
+        assert_eq!(packet[24..28], [0x12, 0x34, 0, 1]);
+        assert_eq!(checksum::checksum(&packet[20..]), 0);
+        assert_eq!(packet[28..], *PAYLOAD);
+    }
+
+    #[test]
+    fn explicit_protocol_overrides_transport() {
+        let packet = builder()
+            .protocol(IpNextHeaderProtocol(253))
+            .udp(UdpLayer::new(1, 2))
+            .build()
+            .unwrap();
+        assert_ip_header(&packet, 5, 253);
+    }
+
+    #[test]
+    fn length_errors() {
+        assert_eq!(
+            builder().ip_options(vec![1; 41]).build(),
+            Err(BuildError::Ipv4OptionsTooLong(44))
+        );
+        let mut tcp = TcpLayer::new(1, 2);
+        tcp.options = vec![1; 41];
+        assert_eq!(
+            builder().tcp(tcp).build(),
+            Err(BuildError::TcpOptionsTooLong(44))
+        );
+        let udp = || PacketBuilder::ipv4(SOURCE, DESTINATION).udp(UdpLayer::new(1, 2));
+        assert_eq!(udp().payload(vec![0; 65507]).build().unwrap().len(), 65535);
+        assert_eq!(
+            udp().payload(vec![0; 65508]).build(),
+            Err(BuildError::PacketTooLarge(65536))
+        );
+        assert_eq!(
+            PacketBuilder::ipv4(SOURCE, DESTINATION)
+                .payload(vec![0; 65516])
+                .build(),
+            Err(BuildError::PacketTooLarge(65536))
+        );
+    }
+
+    #[test]
+    fn explicit_source_skips_route_lookup() {
+        // 显式源地址原样使用，不经路由表选择 (测试环境里可能根本没有路由)
+        let destination = Ipv4Addr::new(240, 0, 0, 1);
+        let builder = PacketBuilder::ipv4(SOURCE, destination).udp(UdpLayer::new(1, 2));
+        assert_eq!(builder.resolve_source(), Ok(SOURCE));
+        let packet = builder.build().unwrap();
+        assert_eq!(packet[12..16], SOURCE.octets());
+        assert_eq!(packet[16..20], destination.octets());
+    }
+}
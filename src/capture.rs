@@ -0,0 +1,195 @@
+//! 抓包套接字
+//!
+//! [`Capture`] 使用 `AF_PACKET` + `SOCK_DGRAM` 套接字，内核去掉链路层首部后交来
+//! 完整的 IPv4 帧 (含未重组的分片)，可绑定到某个接口 (包括 `lo`)，也可以用
+//! `any` 同时抓所有接口。接收时附带内核时间戳、接口序号和方向。
+//!
+//! 本机发出的帧只会交给 `ETH_P_ALL` 套接字，因此按所有协议抓包，再丢弃非 IPv4 帧。
+//! 可以附加 [`crate::bpf`] 编译出的过滤程序，让内核先丢弃无关的帧。
+
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::io;
+use std::mem;
+use std::os::fd::RawFd;
+use std::time::Duration;
+
+use crate::bpf::Instruction;
+use crate::timestamp::{enable_kernel_timestamps, recv_timestamped};
+
+/// 表示所有接口的名称
+pub const ANY_INTERFACE: &str = "any";
+
+/// 以太网类型 IPv4
+const ETH_P_IP: u16 = 0x0800;
+/// 所有协议
+const ETH_P_ALL: u16 = 0x0003;
+
+/// 收到的一帧的元数据
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameInfo {
+    /// 收到的字节数；IPv4 数据报不超过 65535 字节，足够大的缓冲区不会截断
+    pub len: usize,
+    pub interface_index: u32,
+    /// 本机发出的帧
+    pub outgoing: bool,
+    /// 内核接收时间 (Unix 微秒)，内核没有附带时为 `None`
+    pub kernel_time: Option<u64>,
+}
+
+/// AF_PACKET 抓包套接字
+#[derive(Debug)]
+pub struct Capture {
+    fd: RawFd,
+    /// 接口序号到名称
+    names: HashMap<u32, String>,
+    loopbacks: Vec<u32>,
+}
+
+impl Capture {
+    /// 在指定接口上抓 IPv4 帧
+    ///
+    /// # 参数
+    /// - `interface`: 接口名，[`ANY_INTERFACE`] 表示所有接口
+    /// - `filter`: 附加到套接字的 BPF 程序，`None` 时不过滤
+    /// - `timeout`: 每次接收最多等待的时间，便于调用方定期检查是否该退出
+    pub fn open(
+        interface: &str,
+        filter: Option<&[Instruction]>,
+        timeout: Duration,
+    ) -> io::Result<Self> {
+        // 协议为 0 的套接字在 bind 之前收不到任何帧，过滤程序生效前不会有漏网的帧
+        let fd = unsafe { libc::socket(libc::AF_PACKET, libc::SOCK_DGRAM, 0) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut capture = Capture {
+            fd,
+            names: HashMap::new(),
+            loopbacks: Vec::new(),
+        };
+        for iface in pnet::datalink::interfaces() {
+            if iface.is_loopback() {
+                capture.loopbacks.push(iface.index);
+            }
+            capture.names.insert(iface.index, iface.name);
+        }
+
+        if let Some(program) = filter {
+            let fprog = libc::sock_fprog {
+                len: program.len() as libc::c_ushort,
+                filter: program.as_ptr() as *mut libc::sock_filter,
+            };
+            let ret = unsafe {
+                libc::setsockopt(
+                    fd,
+                    libc::SOL_SOCKET,
+                    libc::SO_ATTACH_FILTER,
+                    &fprog as *const _ as *const libc::c_void,
+                    mem::size_of_val(&fprog) as libc::socklen_t,
+                )
+            };
+            if ret < 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+
+        let index = if interface == ANY_INTERFACE {
+            0
+        } else {
+            let name = CString::new(interface)
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "接口名含 NUL"))?;
+            let index = unsafe { libc::if_nametoindex(name.as_ptr()) };
+            if index == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("接口不存在: {}", interface),
+                ));
+            }
+            index
+        };
+        let mut addr: libc::sockaddr_ll = unsafe { mem::zeroed() };
+        addr.sll_family = libc::AF_PACKET as libc::c_ushort;
+        addr.sll_protocol = ETH_P_ALL.to_be();
+        addr.sll_ifindex = index as libc::c_int;
+        let ret = unsafe {
+            libc::bind(
+                fd,
+                &addr as *const _ as *const libc::sockaddr,
+                mem::size_of_val(&addr) as libc::socklen_t,
+            )
+        };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let timeout = libc::timeval {
+            tv_sec: timeout.as_secs() as libc::time_t,
+            tv_usec: timeout.subsec_micros() as libc::suseconds_t,
+        };
+        let ret = unsafe {
+            libc::setsockopt(
+                fd,
+                libc::SOL_SOCKET,
+                libc::SO_RCVTIMEO,
+                &timeout as *const _ as *const libc::c_void,
+                mem::size_of_val(&timeout) as libc::socklen_t,
+            )
+        };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        enable_kernel_timestamps(fd)?;
+        Ok(capture)
+    }
+
+    /// 接口序号对应的名称，不认识的序号返回 `None`
+    pub fn interface_name(&self, index: u32) -> Option<&str> {
+        self.names.get(&index).map(String::as_str)
+    }
+
+    /// 接收一帧
+    ///
+    /// 跳过非 IPv4 帧；回环接口上发出的帧会被内核原样再收一次，这里跳过发出方向
+    /// 的副本，每个包只交出一次。
+    ///
+    /// # 返回
+    /// 帧的元数据；超时时返回 `None`
+    pub fn recv(&self, buf: &mut [u8]) -> io::Result<Option<FrameInfo>> {
+        loop {
+            let mut addr: libc::sockaddr_ll = unsafe { mem::zeroed() };
+            let (len, kernel_time) = match recv_timestamped(self.fd, buf, &mut addr) {
+                Ok(result) => result,
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted
+                    ) =>
+                {
+                    return Ok(None);
+                }
+                Err(e) => return Err(e),
+            };
+            if u16::from_be(addr.sll_protocol) != ETH_P_IP {
+                continue;
+            }
+            let interface_index = addr.sll_ifindex as u32;
+            let outgoing = addr.sll_pkttype == libc::PACKET_OUTGOING;
+            if outgoing && self.loopbacks.contains(&interface_index) {
+                continue;
+            }
+            return Ok(Some(FrameInfo {
+                len,
+                interface_index,
+                outgoing,
+                kernel_time,
+            }));
+        }
+    }
+}
+
+impl Drop for Capture {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.fd) };
+    }
+}
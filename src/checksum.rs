@@ -0,0 +1,260 @@
+//! Internet 校验和 (RFC 1071)
+//!
+//! 提供反码求和、IPv4 / IPv6 伪首部校验和，以及 RFC 1624 增量更新。
+//! 所有发送、接收和代理程序都应使用这里的实现。
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use pnet::packet::ip::{IpNextHeaderProtocol, IpNextHeaderProtocols};
+
+/// 对 `data` 做 16 位反码累加，结果未折叠
+///
+/// # 参数
+/// - `data`: 待累加的字节，奇数长度时末尾补零
+/// - `initial`: 初始累加值 (例如伪首部的累加结果)
+pub fn sum(data: &[u8], initial: u32) -> u32 {
+    let mut sum = initial as u64;
+    let mut chunks = data.chunks_exact(2);
+    for chunk in &mut chunks {
+        sum += u16::from_be_bytes([chunk[0], chunk[1]]) as u64;
+    }
+    if let [last] = chunks.remainder() {
+        sum += (*last as u64) << 8;
+    }
+    fold64(sum) as u32
+}
+
+/// 将累加值的高 16 位折叠到低 16 位，直到没有进位
+pub fn fold(sum: u32) -> u16 {
+    fold64(sum as u64)
+}
+
+fn fold64(mut sum: u64) -> u16 {
+    while sum >> 16 != 0 {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+    sum as u16
+}
+
+/// 计算 Internet 校验和 (IPv4 头部、ICMP 等)
+pub fn checksum(data: &[u8]) -> u16 {
+    !fold(sum(data, 0))
+}
+
+/// IPv4 伪首部累加值
+///
+/// # 参数
+/// - `length`: 上层报文段长度 (头部 + 负载)
+pub fn ipv4_pseudo_header_sum(
+    source: Ipv4Addr,
+    destination: Ipv4Addr,
+    protocol: IpNextHeaderProtocol,
+    length: u16,
+) -> u32 {
+    let mut pseudo = [0u8; 12];
+    pseudo[..4].copy_from_slice(&source.octets());
+    pseudo[4..8].copy_from_slice(&destination.octets());
+    pseudo[9] = protocol.0;
+    pseudo[10..].copy_from_slice(&length.to_be_bytes());
+    sum(&pseudo, 0)
+}
+
+/// IPv6 伪首部累加值 (RFC 8200 8.1)
+pub fn ipv6_pseudo_header_sum(
+    source: Ipv6Addr,
+    destination: Ipv6Addr,
+    next_header: IpNextHeaderProtocol,
+    length: u32,
+) -> u32 {
+    let mut pseudo = [0u8; 40];
+    pseudo[..16].copy_from_slice(&source.octets());
+    pseudo[16..32].copy_from_slice(&destination.octets());
+    pseudo[32..36].copy_from_slice(&length.to_be_bytes());
+    pseudo[39] = next_header.0;
+    sum(&pseudo, 0)
+}
+
+/// 带伪首部的传输层校验和，支持 IPv4 和 IPv6
+///
+/// # 参数
+/// - `source` / `destination`: 源、目的地址，必须同属一个地址族
+/// - `protocol`: 上层协议号 (UDP / TCP / ICMPv6 ...)
+/// - `segment`: 校验和字段已置零的报文段
+///
+/// # 返回
+/// 计算得到的 16 位校验和，UDP 结果为 0 时按 RFC 768 返回 0xFFFF；
+/// 地址族不一致时返回 `None`
+pub fn transport_checksum(
+    source: IpAddr,
+    destination: IpAddr,
+    protocol: IpNextHeaderProtocol,
+    segment: &[u8],
+) -> Option<u16> {
+    let pseudo = match (source, destination) {
+        (IpAddr::V4(source), IpAddr::V4(destination)) => {
+            ipv4_pseudo_header_sum(source, destination, protocol, segment.len() as u16)
+        }
+        (IpAddr::V6(source), IpAddr::V6(destination)) => {
+            ipv6_pseudo_header_sum(source, destination, protocol, segment.len() as u32)
+        }
+        _ => return None,
+    };
+    match !fold(sum(segment, pseudo)) {
+        0 if protocol == IpNextHeaderProtocols::Udp => Some(0xFFFF),
+        check => Some(check),
+    }
+}
+
+/// 计算 TCP 校验和 (IPv4)
+///
+/// # 参数
+/// - `source_ip`: 源 IP 地址
+/// - `dest_ip`: 目标 IP 地址
+/// - `tcp_segment`: TCP 段数据 (包括头部和负载)，校验和字段需为 0
+///
+/// # 返回
+/// 计算得到的 16 位校验和
+pub fn tcp_checksum(source_ip: Ipv4Addr, dest_ip: Ipv4Addr, tcp_segment: &[u8]) -> u16 {
+    let pseudo = ipv4_pseudo_header_sum(
+        source_ip,
+        dest_ip,
+        IpNextHeaderProtocols::Tcp,
+        tcp_segment.len() as u16,
+    );
+    !fold(sum(tcp_segment, pseudo))
+}
+
+/// 计算 UDP 校验和 (IPv4)
+///
+/// 结果为 0 时按 RFC 768 返回 0xFFFF，因为 0 表示"未计算校验和"。
+pub fn udp_checksum(source_ip: Ipv4Addr, dest_ip: Ipv4Addr, udp_datagram: &[u8]) -> u16 {
+    let pseudo = ipv4_pseudo_header_sum(
+        source_ip,
+        dest_ip,
+        IpNextHeaderProtocols::Udp,
+        udp_datagram.len() as u16,
+    );
+    match !fold(sum(udp_datagram, pseudo)) {
+        0 => 0xFFFF,
+        check => check,
+    }
+}
+
+/// 计算 ICMPv6 校验和 (包含 IPv6 伪首部)
+pub fn icmpv6_checksum(source: Ipv6Addr, destination: Ipv6Addr, message: &[u8]) -> u16 {
+    let pseudo = ipv6_pseudo_header_sum(
+        source,
+        destination,
+        IpNextHeaderProtocols::Icmpv6,
+        message.len() as u32,
+    );
+    !fold(sum(message, pseudo))
+}
+
+/// RFC 1624 增量更新校验和
+///
+/// `HC' = ~(~HC + ~m + m')`，其中 `m` 为被移除内容的累加值，`m'` 为新增内容的累加值。
+/// 被移除和新增的内容都必须从报文中的偶数偏移开始，否则字节序会错位。
+///
+/// # 参数
+/// - `old_checksum`: 原校验和
+/// - `removed`: 被移除 (或修改前) 的字节
+/// - `added`: 新增 (或修改后) 的字节
+///
+/// # 返回
+/// 更新后的校验和
+pub fn update(old_checksum: u16, removed: &[u8], added: &[u8]) -> u16 {
+    let removed = fold(sum(removed, 0));
+    let added = fold(sum(added, 0));
+    let total = (!old_checksum) as u32 + (!removed) as u32 + added as u32;
+    !fold(total)
+}
+
+/// RFC 1624 增量更新单个 16 位字
+pub fn update_word(old_checksum: u16, old_word: u16, new_word: u16) -> u16 {
+    update(
+        old_checksum,
+        &old_word.to_be_bytes(),
+        &new_word.to_be_bytes(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const V6_SOURCE: Ipv6Addr = Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1);
+    const V6_DESTINATION: Ipv6Addr = Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 2);
+
+    /// 端口 1234 -> 8001、长度 12、校验和为 0 的 UDP 数据报
+    fn udp(payload: [u8; 4]) -> Vec<u8> {
+        let mut datagram = vec![0x04, 0xd2, 0x1f, 0x41, 0x00, 0x0c, 0x00, 0x00];
+        datagram.extend_from_slice(&payload);
+        datagram
+    }
+
+    #[test]
+    fn rfc1071_example() {
+        let data = [0x00, 0x01, 0xf2, 0x03, 0xf4, 0xf5, 0xf6, 0xf7];
+        assert_eq!(fold(sum(&data, 0)), 0xddf2);
+        assert_eq!(checksum(&data), 0x220d);
+        // 奇数长度末尾补零
+        assert_eq!(sum(&[0x12, 0x34, 0x56], 0), 0x6834);
+    }
+
+    #[test]
+    fn rfc1624_examples() {
+        // RFC 1624 第 4 节：HC = 0xDD2F，m = 0x5555 改为 m' = 0x3285，正确结果为 0x0000
+        assert_eq!(update_word(0xdd2f, 0x5555, 0x3285), 0x0000);
+        // 增量更新与重新计算一致
+        let mut data = [0x45, 0x00, 0x00, 0x54, 0x12, 0x34, 0x40, 0x00];
+        let old = checksum(&data);
+        data[4..6].copy_from_slice(&0xbeefu16.to_be_bytes());
+        assert_eq!(update_word(old, 0x1234, 0xbeef), checksum(&data));
+        assert_eq!(update(old, &[0xbe, 0xef], &[0xbe, 0xef]), old);
+    }
+
+    #[test]
+    fn pseudo_header_sums() {
+        let v4 = ipv4_pseudo_header_sum(
+            Ipv4Addr::new(192, 0, 2, 1),
+            Ipv4Addr::new(198, 51, 100, 2),
+            IpNextHeaderProtocols::Udp,
+            12,
+        );
+        assert_eq!(v4, 0xec54);
+        let v6 = ipv6_pseudo_header_sum(V6_SOURCE, V6_DESTINATION, IpNextHeaderProtocols::Udp, 12);
+        assert_eq!(v6, 0x5b92);
+    }
+
+    #[test]
+    fn udp_over_ipv6() {
+        let (source, destination) = (V6_SOURCE.into(), V6_DESTINATION.into());
+        let datagram = udp(*b"abcd");
+        assert_eq!(
+            transport_checksum(source, destination, IpNextHeaderProtocols::Udp, &datagram),
+            Some(0xbb87)
+        );
+        // 整段累加为 0xFFFF，算出的 0 对 UDP 改为 0xFFFF，其他协议保持 0
+        let datagram = udp([0x80, 0x4e, 0x00, 0x00]);
+        assert_eq!(
+            transport_checksum(source, destination, IpNextHeaderProtocols::Udp, &datagram),
+            Some(0xffff)
+        );
+        // 协议号 6 的伪首部少 11，负载相应加 11 后同样累加为 0xFFFF
+        let segment = udp([0x80, 0x59, 0x00, 0x00]);
+        assert_eq!(
+            transport_checksum(source, destination, IpNextHeaderProtocols::Tcp, &segment),
+            Some(0x0000)
+        );
+        assert_eq!(
+            transport_checksum(
+                source,
+                Ipv4Addr::LOCALHOST.into(),
+                IpNextHeaderProtocols::Udp,
+                &datagram
+            ),
+            None
+        );
+    }
+}
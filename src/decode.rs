@@ -0,0 +1,427 @@
+//! 接收端通用解码
+//!
+//! 把一个完整的 IPv4 数据报解码为首部字段、IP 选项、UDP / TCP / ICMP 首部、
+//! TCP 选项、标记 (含认证结论) 和校验和验证结果。抓包工具只负责取得数据报，
+//! 解码和输出格式各处共用。
+
+use std::fmt;
+use std::net::Ipv4Addr;
+
+use pnet::packet::tcp::TcpFlags;
+
+use crate::auth::{AuthContext, AuthStatus, MarkerCodec};
+use crate::ipv4_option::{self, Ipv4Option, Ipv4OptionError};
+use crate::marker::{Ipv4OptionCarrier, Marker, MarkerCarrier, MarkerError, TcpOptionCarrier};
+use crate::tcp_option::{self, ParsedTcpOptions};
+use crate::validate::{ChecksumReport, validate_ipv4};
+
+pub const PROTOCOL_ICMP: u8 = 1;
+pub const PROTOCOL_TCP: u8 = 6;
+pub const PROTOCOL_UDP: u8 = 17;
+
+const TCP_FLAG_NAMES: [(u8, &str); 8] = [
+    (TcpFlags::FIN, "FIN"),
+    (TcpFlags::SYN, "SYN"),
+    (TcpFlags::RST, "RST"),
+    (TcpFlags::PSH, "PSH"),
+    (TcpFlags::ACK, "ACK"),
+    (TcpFlags::URG, "URG"),
+    (TcpFlags::ECE, "ECE"),
+    (TcpFlags::CWR, "CWR"),
+];
+
+/// 解码错误
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// 短于 20 字节的 IPv4 首部
+    Truncated(usize),
+    /// 版本号不是 4
+    NotIpv4(u8),
+    /// IHL 小于 5 或超出数据长度
+    BadHeaderLength(usize),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::Truncated(len) => write!(f, "数据包只有 {} 字节, 不足 IPv4 首部", len),
+            DecodeError::NotIpv4(version) => write!(f, "IP 版本号为 {}, 不是 IPv4", version),
+            DecodeError::BadHeaderLength(len) => write!(f, "首部长度 {} 字节非法", len),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// IPv4 首部字段
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ipv4Header {
+    pub header_len: usize,
+    pub tos: u8,
+    pub total_len: u16,
+    pub identification: u16,
+    pub dont_fragment: bool,
+    pub more_fragments: bool,
+    /// 单位字节
+    pub fragment_offset: u16,
+    pub ttl: u8,
+    pub protocol: u8,
+    pub checksum: u16,
+    pub source: Ipv4Addr,
+    pub destination: Ipv4Addr,
+    pub options: Result<Vec<Ipv4Option>, Ipv4OptionError>,
+}
+
+/// 传输层首部
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransportHeader {
+    Udp {
+        source_port: u16,
+        destination_port: u16,
+        length: u16,
+        checksum: u16,
+    },
+    Tcp {
+        source_port: u16,
+        destination_port: u16,
+        sequence: u32,
+        acknowledgement: u32,
+        /// 首部长度，单位字节
+        header_len: usize,
+        flags: u8,
+        window: u16,
+        checksum: u16,
+        urgent: u16,
+        options: ParsedTcpOptions,
+    },
+    Icmp {
+        icmp_type: u8,
+        code: u8,
+        checksum: u16,
+        /// 类型相关的 4 字节 (回显请求 / 应答中为标识符和序号)
+        rest: u32,
+    },
+}
+
+impl TransportHeader {
+    /// (源端口, 目的端口)；ICMP 返回 `None`
+    pub fn ports(&self) -> Option<(u16, u16)> {
+        match self {
+            TransportHeader::Udp {
+                source_port,
+                destination_port,
+                ..
+            }
+            | TransportHeader::Tcp {
+                source_port,
+                destination_port,
+                ..
+            } => Some((*source_port, *destination_port)),
+            TransportHeader::Icmp { .. } => None,
+        }
+    }
+}
+
+/// 标记所在的位置
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MarkerLocation {
+    Ipv4Option,
+    TcpOption,
+}
+
+impl MarkerLocation {
+    pub fn name(&self) -> &'static str {
+        match self {
+            MarkerLocation::Ipv4Option => "ip",
+            MarkerLocation::TcpOption => "tcp",
+        }
+    }
+}
+
+/// 数据报中找到的标记
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MarkerInfo {
+    pub location: MarkerLocation,
+    pub result: Result<(Marker, AuthStatus), MarkerError>,
+}
+
+/// 解码后的数据报
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodedPacket {
+    pub ip: Ipv4Header,
+    /// 非首个分片、不认识的协议或首部不完整时为 `None`
+    pub transport: Option<TransportHeader>,
+    /// 传输层负载；没有解码传输层时为 IP 负载
+    pub payload: Vec<u8>,
+    pub marker: Option<MarkerInfo>,
+    pub checksums: Option<ChecksumReport>,
+}
+
+impl DecodedPacket {
+    /// 是否为分片 (设置了 MF 或偏移不为 0)
+    pub fn is_fragment(&self) -> bool {
+        self.ip.more_fragments || self.ip.fragment_offset != 0
+    }
+
+    /// (源端口, 目的端口)；没有端口时返回 `None`
+    pub fn ports(&self) -> Option<(u16, u16)> {
+        self.transport.as_ref().and_then(TransportHeader::ports)
+    }
+}
+
+fn read_u16(bytes: &[u8], at: usize) -> u16 {
+    u16::from_be_bytes([bytes[at], bytes[at + 1]])
+}
+
+fn read_u32(bytes: &[u8], at: usize) -> u32 {
+    u32::from_be_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+}
+
+/// 解码一个 IPv4 数据报
+///
+/// # 参数
+/// - `packet`: 从 IP 首部开始的字节，超出总长度的部分 (如以太网填充) 被忽略
+/// - `codec`: 验证 / 解密标记用的密钥
+///
+/// # 返回
+/// 解码结果；首部本身不合法时返回错误
+pub fn decode(packet: &[u8], codec: &MarkerCodec) -> Result<DecodedPacket, DecodeError> {
+    if packet.len() < 20 {
+        return Err(DecodeError::Truncated(packet.len()));
+    }
+    if packet[0] >> 4 != 4 {
+        return Err(DecodeError::NotIpv4(packet[0] >> 4));
+    }
+    let header_len = (packet[0] & 0x0f) as usize * 4;
+    if header_len < 20 || header_len > packet.len() {
+        return Err(DecodeError::BadHeaderLength(header_len));
+    }
+    let total_len = read_u16(packet, 2);
+    let end = (total_len as usize).clamp(header_len, packet.len());
+    let packet = &packet[..end];
+    let flags = read_u16(packet, 6);
+    let ip = Ipv4Header {
+        header_len,
+        tos: packet[1],
+        total_len,
+        identification: read_u16(packet, 4),
+        dont_fragment: flags & 0x4000 != 0,
+        more_fragments: flags & 0x2000 != 0,
+        fragment_offset: (flags & 0x1fff) * 8,
+        ttl: packet[8],
+        protocol: packet[9],
+        checksum: read_u16(packet, 10),
+        source: Ipv4Addr::new(packet[12], packet[13], packet[14], packet[15]),
+        destination: Ipv4Addr::new(packet[16], packet[17], packet[18], packet[19]),
+        options: ipv4_option::parse_options(&packet[20..header_len]),
+    };
+
+    let body = &packet[header_len..];
+    let first = ip.fragment_offset == 0;
+    let (transport, payload_start) = match ip.protocol {
+        PROTOCOL_UDP if first && body.len() >= 8 => (
+            Some(TransportHeader::Udp {
+                source_port: read_u16(body, 0),
+                destination_port: read_u16(body, 2),
+                length: read_u16(body, 4),
+                checksum: read_u16(body, 6),
+            }),
+            8,
+        ),
+        PROTOCOL_TCP if first && body.len() >= 20 => {
+            let tcp_header_len = ((body[12] >> 4) as usize * 4).clamp(20, body.len());
+            (
+                Some(TransportHeader::Tcp {
+                    source_port: read_u16(body, 0),
+                    destination_port: read_u16(body, 2),
+                    sequence: read_u32(body, 4),
+                    acknowledgement: read_u32(body, 8),
+                    header_len: tcp_header_len,
+                    flags: body[13],
+                    window: read_u16(body, 14),
+                    checksum: read_u16(body, 16),
+                    urgent: read_u16(body, 18),
+                    options: tcp_option::parse_options(&body[20..tcp_header_len]),
+                }),
+                tcp_header_len,
+            )
+        }
+        PROTOCOL_ICMP if first && body.len() >= 8 => (
+            Some(TransportHeader::Icmp {
+                icmp_type: body[0],
+                code: body[1],
+                checksum: read_u16(body, 2),
+                rest: read_u32(body, 4),
+            }),
+            8,
+        ),
+        _ => (None, 0),
+    };
+
+    let marker = find_marker(packet, &ip, transport.as_ref(), codec);
+    Ok(DecodedPacket {
+        transport,
+        payload: body[payload_start..].to_vec(),
+        marker,
+        checksums: validate_ipv4(packet),
+        ip,
+    })
+}
+
+/// 先找 IP 选项中的标记，再找 TCP 实验选项中的标记
+fn find_marker(
+    packet: &[u8],
+    ip: &Ipv4Header,
+    transport: Option<&TransportHeader>,
+    codec: &MarkerCodec,
+) -> Option<MarkerInfo> {
+    let ctx = AuthContext::from_ipv4(packet)?;
+    let in_ip = ip
+        .options
+        .as_ref()
+        .ok()
+        .and_then(|options| Ipv4OptionCarrier::default().payload(options))
+        .map(|payload| (MarkerLocation::Ipv4Option, payload));
+    let in_tcp = || match transport {
+        Some(TransportHeader::Tcp { options, .. }) => TcpOptionCarrier::default()
+            .payload(&options.options)
+            .map(|payload| (MarkerLocation::TcpOption, payload)),
+        _ => None,
+    };
+    in_ip.or_else(in_tcp).map(|(location, payload)| MarkerInfo {
+        location,
+        result: codec.verify(payload, &ctx),
+    })
+}
+
+/// TCP 标志的名称，如 `SYN|ACK`；没有标志时为 `none`
+pub fn tcp_flags_name(flags: u8) -> String {
+    let names: Vec<&str> = TCP_FLAG_NAMES
+        .iter()
+        .filter(|(bit, _)| flags & bit != 0)
+        .map(|(_, name)| *name)
+        .collect();
+    if names.is_empty() {
+        "none".to_string()
+    } else {
+        names.join("|")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::{PacketBuilder, TcpLayer, UdpLayer};
+    use crate::fragment::Fragmenter;
+
+    fn builder(payload_len: usize) -> PacketBuilder {
+        PacketBuilder::ipv4(Ipv4Addr::new(10, 0, 0, 1), Ipv4Addr::new(10, 0, 0, 2))
+            .payload((0..payload_len).map(|i| i as u8).collect())
+    }
+
+    fn decode_plain(packet: &[u8]) -> Result<DecodedPacket, DecodeError> {
+        decode(packet, &MarkerCodec::default())
+    }
+
+    /// 改写 TCP data offset 为 `words` 个 32 位字；解码不依赖校验和，不重算
+    fn tcp_with_data_offset(payload_len: usize, words: u8) -> Vec<u8> {
+        let mut packet = builder(payload_len)
+            .tcp(TcpLayer::new(40000, 8001))
+            .build()
+            .unwrap();
+        packet[20 + 12] = words << 4;
+        packet
+    }
+
+    #[test]
+    fn udp_and_ethernet_padding() {
+        let packet = builder(10).udp(UdpLayer::new(40000, 8001)).build().unwrap();
+        let mut padded = packet.clone();
+        padded.extend_from_slice(&[0xee; 16]);
+        let decoded = decode_plain(&padded).unwrap();
+        assert_eq!(decoded, decode_plain(&packet).unwrap());
+        assert_eq!(decoded.ip.total_len, 38);
+        assert_eq!(decoded.ports(), Some((40000, 8001)));
+        assert_eq!(decoded.payload, (0..10).collect::<Vec<u8>>());
+        assert!(decoded.checksums.unwrap().all_valid());
+        assert!(decoded.marker.is_none());
+    }
+
+    #[test]
+    fn non_first_fragment_has_no_transport_header() {
+        let packet = builder(100)
+            .udp(UdpLayer::new(40000, 8001))
+            .build()
+            .unwrap();
+        let fragments = Fragmenter::new(68).fragment(&packet).unwrap();
+
+        let first = decode_plain(&fragments[0]).unwrap();
+        assert!(first.is_fragment() && first.ip.more_fragments);
+        assert_eq!(first.ports(), Some((40000, 8001)));
+
+        let second = decode_plain(&fragments[1]).unwrap();
+        assert!(second.is_fragment());
+        assert_eq!(second.ip.fragment_offset, 48);
+        assert_eq!(second.transport, None);
+        assert_eq!(second.ports(), None);
+        // 没有传输层时负载就是 IP 负载
+        assert_eq!(second.payload, &packet[20 + 48..20 + 96]);
+    }
+
+    #[test]
+    fn tcp_data_offset_is_clamped() {
+        let decoded = decode_plain(&tcp_with_data_offset(6, 5)).unwrap();
+        assert!(matches!(
+            decoded.transport,
+            Some(TransportHeader::Tcp { header_len: 20, .. })
+        ));
+        assert_eq!(decoded.payload.len(), 6);
+
+        // 小于 5 个字按 20 字节首部解码
+        let decoded = decode_plain(&tcp_with_data_offset(6, 3)).unwrap();
+        match decoded.transport {
+            Some(TransportHeader::Tcp {
+                header_len,
+                options,
+                ..
+            }) => {
+                assert_eq!(header_len, 20);
+                assert!(options.options.is_empty() && options.is_ok());
+            }
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(decoded.payload, (0..6).collect::<Vec<u8>>());
+
+        // 超出报文时截到报文末尾，负载为空
+        let decoded = decode_plain(&tcp_with_data_offset(6, 15)).unwrap();
+        assert!(matches!(
+            decoded.transport,
+            Some(TransportHeader::Tcp { header_len: 26, .. })
+        ));
+        assert!(decoded.payload.is_empty());
+    }
+
+    #[test]
+    fn header_errors() {
+        let packet = builder(4).udp(UdpLayer::new(40000, 8001)).build().unwrap();
+        assert_eq!(decode_plain(&packet[..19]), Err(DecodeError::Truncated(19)));
+
+        let mut ipv6 = packet.clone();
+        ipv6[0] = 0x65;
+        assert_eq!(decode_plain(&ipv6), Err(DecodeError::NotIpv4(6)));
+
+        let mut short_ihl = packet.clone();
+        short_ihl[0] = 0x44;
+        assert_eq!(
+            decode_plain(&short_ihl),
+            Err(DecodeError::BadHeaderLength(16))
+        );
+
+        let mut long_ihl = packet.clone();
+        long_ihl[0] = 0x4f;
+        assert_eq!(
+            decode_plain(&long_ihl),
+            Err(DecodeError::BadHeaderLength(60))
+        );
+    }
+}
@@ -0,0 +1,211 @@
+//! ICMP echo 应答的接收与匹配
+//!
+//! [`IcmpListener`] 在 ICMP 原始套接字上接收带内核时间戳的应答，
+//! [`EchoTracker`] 按 identifier / sequence 把应答和已发送的请求配对并统计 RTT。
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io;
+use std::mem;
+use std::net::Ipv4Addr;
+use std::os::fd::RawFd;
+use std::time::Duration;
+
+use crate::checksum;
+use crate::latency::LatencyStats;
+use crate::timestamp::{enable_kernel_timestamps, recv_timestamped, unix_micros};
+
+/// ICMP echo reply 类型
+pub const ECHO_REPLY: u8 = 0;
+/// ICMP echo request 类型
+pub const ECHO_REQUEST: u8 = 8;
+
+/// 解析出的 echo 应答
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EchoReply {
+    pub source: Ipv4Addr,
+    pub identifier: u16,
+    pub sequence: u16,
+    pub ttl: u8,
+    /// 应答 IP 首部中的选项原始字节 (如回显的 Record Route)
+    pub ip_options: Vec<u8>,
+    /// IP 包总长度
+    pub len: usize,
+    /// ICMP 校验和是否正确
+    pub checksum_ok: bool,
+}
+
+/// 从完整的 IPv4 包中解析 echo 应答，其他报文返回 `None`
+pub fn parse_echo_reply(packet: &[u8]) -> Option<EchoReply> {
+    if packet.len() < 20 || packet[0] >> 4 != 4 || packet[9] != 1 {
+        return None;
+    }
+    let header_len = (packet[0] & 0x0f) as usize * 4;
+    let icmp = packet.get(header_len..)?;
+    if icmp.len() < 8 || icmp[0] != ECHO_REPLY {
+        return None;
+    }
+    Some(EchoReply {
+        source: Ipv4Addr::new(packet[12], packet[13], packet[14], packet[15]),
+        identifier: u16::from_be_bytes([icmp[4], icmp[5]]),
+        sequence: u16::from_be_bytes([icmp[6], icmp[7]]),
+        ttl: packet[8],
+        ip_options: packet[20..header_len.max(20)].to_vec(),
+        len: packet.len(),
+        checksum_ok: checksum::checksum(icmp) == 0,
+    })
+}
+
+/// 接收 ICMP 报文的原始套接字，收到的数据含 IP 首部
+#[derive(Debug)]
+pub struct IcmpListener {
+    fd: RawFd,
+}
+
+impl IcmpListener {
+    pub fn new() -> io::Result<Self> {
+        let fd = unsafe { libc::socket(libc::AF_INET, libc::SOCK_RAW, libc::IPPROTO_ICMP) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let listener = IcmpListener { fd };
+        // 内核时间戳不可用时退回到用户态时间
+        let _ = enable_kernel_timestamps(fd);
+        Ok(listener)
+    }
+
+    /// 在 `timeout` 内接收一个报文
+    ///
+    /// # 返回
+    /// (报文长度, 接收时间 (Unix 微秒))；超时返回 `None`
+    pub fn recv(&self, buf: &mut [u8], timeout: Duration) -> io::Result<Option<(usize, u64)>> {
+        let mut pollfd = libc::pollfd {
+            fd: self.fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let millis = timeout.as_millis().min(i32::MAX as u128) as libc::c_int;
+        let ret = unsafe { libc::poll(&mut pollfd, 1, millis) };
+        if ret < 0 {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::Interrupted {
+                return Ok(None);
+            }
+            return Err(err);
+        }
+        if ret == 0 {
+            return Ok(None);
+        }
+        let mut src: libc::sockaddr_in = unsafe { mem::zeroed() };
+        let (amt, kernel_time) = recv_timestamped(self.fd, buf, &mut src)?;
+        Ok(Some((amt, kernel_time.unwrap_or_else(unix_micros))))
+    }
+}
+
+impl Drop for IcmpListener {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.fd) };
+    }
+}
+
+/// 应答的配对结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EchoMatch {
+    /// 首次收到，附 RTT (微秒)
+    Reply(i64),
+    /// 同一序号的重复应答
+    Duplicate,
+    /// identifier 或来源不符，不是本次发出的请求的应答
+    Unrelated,
+}
+
+/// 按 identifier / sequence 匹配 echo 请求与应答
+#[derive(Debug, Clone)]
+pub struct EchoTracker {
+    identifier: u16,
+    destination: Ipv4Addr,
+    /// 已发送未应答的请求: sequence -> 发送时间 (Unix 微秒)
+    outstanding: HashMap<u16, u64>,
+    answered: HashSet<u16>,
+    sent: u64,
+    received: u64,
+    duplicates: u64,
+    rtt: LatencyStats,
+}
+
+impl EchoTracker {
+    pub fn new(identifier: u16, destination: Ipv4Addr) -> Self {
+        EchoTracker {
+            identifier,
+            destination,
+            outstanding: HashMap::new(),
+            answered: HashSet::new(),
+            sent: 0,
+            received: 0,
+            duplicates: 0,
+            rtt: LatencyStats::default(),
+        }
+    }
+
+    /// 记录发出了序号为 `sequence` 的请求
+    pub fn sent(&mut self, sequence: u16, sent_us: u64) {
+        self.sent += 1;
+        self.answered.remove(&sequence);
+        self.outstanding.insert(sequence, sent_us);
+    }
+
+    /// 配对一个应答；目的地址为广播或组播时不检查来源
+    pub fn reply(&mut self, reply: &EchoReply, received_us: u64) -> EchoMatch {
+        let any_source = self.destination.is_broadcast() || self.destination.is_multicast();
+        if reply.identifier != self.identifier || (!any_source && reply.source != self.destination)
+        {
+            return EchoMatch::Unrelated;
+        }
+        if let Some(sent_us) = self.outstanding.remove(&reply.sequence) {
+            let rtt = received_us as i64 - sent_us as i64;
+            self.received += 1;
+            self.answered.insert(reply.sequence);
+            self.rtt.record(rtt);
+            EchoMatch::Reply(rtt)
+        } else if self.answered.contains(&reply.sequence) {
+            self.duplicates += 1;
+            EchoMatch::Duplicate
+        } else {
+            EchoMatch::Unrelated
+        }
+    }
+
+    pub fn transmitted(&self) -> u64 {
+        self.sent
+    }
+
+    pub fn received(&self) -> u64 {
+        self.received
+    }
+
+    /// 丢包率 (0.0 ~ 1.0)
+    pub fn loss(&self) -> f64 {
+        if self.sent == 0 {
+            return 0.0;
+        }
+        1.0 - self.received as f64 / self.sent as f64
+    }
+
+    pub fn rtt(&self) -> &LatencyStats {
+        &self.rtt
+    }
+}
+
+impl fmt::Display for EchoTracker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "发送 {} 个请求, 收到 {} 个应答",
+            self.sent, self.received
+        )?;
+        if self.duplicates > 0 {
+            write!(f, " (+{} 重复)", self.duplicates)?;
+        }
+        write!(f, ", 丢失 {:.1}%\nRTT: {}", self.loss() * 100.0, self.rtt)
+    }
+}
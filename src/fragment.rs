@@ -0,0 +1,543 @@
+//! IPv4 分片
+//!
+//! 按 MTU 把完整的 IPv4 数据报切成分片：第一个分片保留全部选项，其余分片只带
+//! copied 位为 1 的选项 (RFC 791)，例如标记选项 0x79 的 copied 位为 0，只出现在
+//! 第一个分片中。为了测试接收端，还可以打乱分片顺序、让分片互相重叠，或者把第一个
+//! 分片切得很小。
+
+use std::fmt;
+
+use crate::builder::IPV4_HEADER_LEN;
+use crate::checksum;
+use crate::ip_id::fill_random;
+use crate::ipv4_option::{Ipv4OptionError, KIND_EOL, KIND_NOP, OptionType};
+use crate::timestamp::unix_micros;
+
+/// Don't Fragment 标志
+const FLAG_DF: u8 = 0x02;
+/// More Fragments 标志
+const FLAG_MF: u8 = 0x01;
+/// 13 位偏移字段的最大值 (以 8 字节为单位)
+const MAX_OFFSET: u16 = 0x1fff;
+
+/// 分片错误
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FragmentError {
+    /// 数据报短于首部或总长度字段
+    Truncated,
+    /// 设置了 DF
+    DontFragment,
+    /// 选项无法解析
+    Options(Ipv4OptionError),
+    /// MTU 放不下首部和 8 字节数据
+    MtuTooSmall(usize),
+    /// 分片数据长度或重叠长度不是 8 的倍数
+    Unaligned(usize),
+    /// 重叠长度不小于分片数据长度，分片无法向前推进
+    OverlapTooLarge(usize),
+    /// 分片的字节偏移超出 13 位偏移字段能表示的 65528
+    OffsetOverflow(usize),
+}
+
+impl fmt::Display for FragmentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FragmentError::Truncated => write!(f, "数据报被截断"),
+            FragmentError::DontFragment => write!(f, "数据报设置了 DF, 不能分片"),
+            FragmentError::Options(e) => write!(f, "IP 选项无法解析: {}", e),
+            FragmentError::MtuTooSmall(mtu) => write!(f, "MTU {} 放不下首部和 8 字节数据", mtu),
+            FragmentError::Unaligned(len) => write!(f, "长度 {} 不是 8 的倍数", len),
+            FragmentError::OverlapTooLarge(len) => {
+                write!(f, "重叠 {} 字节不小于分片数据长度", len)
+            }
+            FragmentError::OffsetOverflow(offset) => {
+                write!(f, "分片偏移 {} 字节超出 {}", offset, MAX_OFFSET * 8)
+            }
+        }
+    }
+}
+
+impl std::error::Error for FragmentError {}
+
+/// 分片的发送顺序
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FragmentOrder {
+    #[default]
+    InOrder,
+    Reverse,
+    Random,
+}
+
+impl FragmentOrder {
+    /// 按名称解析 (`in-order` / `reverse` / `random`)
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "in-order" => Some(FragmentOrder::InOrder),
+            "reverse" => Some(FragmentOrder::Reverse),
+            "random" => Some(FragmentOrder::Random),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            FragmentOrder::InOrder => "in-order",
+            FragmentOrder::Reverse => "reverse",
+            FragmentOrder::Random => "random",
+        }
+    }
+}
+
+/// IPv4 分片器
+#[derive(Debug, Clone)]
+pub struct Fragmenter {
+    /// 每个分片的最大总长度 (含首部)
+    pub mtu: usize,
+    /// 第一个分片的数据长度 (8 的倍数)，用于构造极小分片；`None` 时按 MTU 切
+    pub first_size: Option<usize>,
+    /// 第二个及以后的分片向前重叠的字节数 (8 的倍数)
+    pub overlap: usize,
+    /// 重叠部分改写为该字节，用来区分接收端保留先到还是后到的数据
+    pub overlap_fill: Option<u8>,
+    pub order: FragmentOrder,
+}
+
+impl Fragmenter {
+    pub fn new(mtu: usize) -> Self {
+        Fragmenter {
+            mtu,
+            first_size: None,
+            overlap: 0,
+            overlap_fill: None,
+            order: FragmentOrder::InOrder,
+        }
+    }
+
+    /// 检查与数据报无关的参数：长度对齐以及 MTU 至少能放下固定首部和 8 字节数据
+    pub fn validate(&self) -> Result<(), FragmentError> {
+        if self.mtu < IPV4_HEADER_LEN + 8 {
+            return Err(FragmentError::MtuTooSmall(self.mtu));
+        }
+        if !self.overlap.is_multiple_of(8) {
+            return Err(FragmentError::Unaligned(self.overlap));
+        }
+        match self.first_size {
+            Some(size) if size == 0 || !size.is_multiple_of(8) => {
+                Err(FragmentError::Unaligned(size))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// 把 `packet` 切成分片，按 `order` 排列
+    ///
+    /// 不超过 MTU 且未指定 `first_size` 时原样返回一个包。`packet` 本身是分片时，
+    /// 偏移在原偏移上累加，最后一个分片保留原来的 MF。
+    pub fn fragment(&self, packet: &[u8]) -> Result<Vec<Vec<u8>>, FragmentError> {
+        if packet.len() < IPV4_HEADER_LEN {
+            return Err(FragmentError::Truncated);
+        }
+        let header_len = (packet[0] & 0x0f) as usize * 4;
+        let total_len = u16::from_be_bytes([packet[2], packet[3]]) as usize;
+        if header_len < IPV4_HEADER_LEN || total_len < header_len || total_len > packet.len() {
+            return Err(FragmentError::Truncated);
+        }
+        self.validate()?;
+        if total_len <= self.mtu && self.first_size.is_none() {
+            return Ok(vec![packet[..total_len].to_vec()]);
+        }
+        let flags = packet[6] >> 5;
+        if flags & FLAG_DF != 0 {
+            return Err(FragmentError::DontFragment);
+        }
+
+        let first_header = &packet[..header_len];
+        let mut rest_header = packet[..IPV4_HEADER_LEN].to_vec();
+        rest_header.extend(copied_options(&packet[IPV4_HEADER_LEN..header_len])?);
+        rest_header[0] = 0x40 | (rest_header.len() / 4) as u8;
+
+        let first_chunk = match self.first_size {
+            Some(size) => size,
+            None => chunk_len(self.mtu, header_len)?,
+        };
+        let chunk = chunk_len(self.mtu, rest_header.len())?;
+        if self.overlap >= chunk {
+            return Err(FragmentError::OverlapTooLarge(self.overlap));
+        }
+
+        let data = &packet[header_len..total_len];
+        let base_offset = u16::from_be_bytes([packet[6], packet[7]]) & MAX_OFFSET;
+        let last_mf = flags & FLAG_MF != 0;
+
+        let mut fragments = Vec::new();
+        let mut end = 0;
+        while end < data.len() || fragments.is_empty() {
+            let (header, start, len) = if fragments.is_empty() {
+                (first_header, 0, first_chunk)
+            } else {
+                (rest_header.as_slice(), end - self.overlap.min(end), chunk)
+            };
+            let stop = (start + len).min(data.len());
+            let mut body = data[start..stop].to_vec();
+            if let Some(fill) = self.overlap_fill {
+                body[..end - start].fill(fill);
+            }
+            let more = stop < data.len() || last_mf;
+            let offset = base_offset as usize + start / 8;
+            if offset > MAX_OFFSET as usize {
+                return Err(FragmentError::OffsetOverflow(offset * 8));
+            }
+            let offset = offset as u16;
+            fragments.push(build_fragment(header, &body, more, offset));
+            end = stop;
+        }
+
+        match self.order {
+            FragmentOrder::InOrder => {}
+            FragmentOrder::Reverse => fragments.reverse(),
+            FragmentOrder::Random => shuffle(&mut fragments),
+        }
+        Ok(fragments)
+    }
+}
+
+/// MTU 下每个分片能携带的数据长度 (向下取 8 的倍数)
+fn chunk_len(mtu: usize, header_len: usize) -> Result<usize, FragmentError> {
+    let len = mtu.saturating_sub(header_len) & !7;
+    if len == 0 {
+        return Err(FragmentError::MtuTooSmall(mtu));
+    }
+    Ok(len)
+}
+
+/// 从原始选项字节中取出 copied 位为 1 的选项，补齐到 4 字节的倍数
+pub fn copied_options(options: &[u8]) -> Result<Vec<u8>, FragmentError> {
+    let mut copied = Vec::new();
+    let mut offset = 0;
+    while offset < options.len() {
+        let kind = options[offset];
+        match kind {
+            KIND_EOL => break,
+            KIND_NOP => {
+                offset += 1;
+                continue;
+            }
+            _ => {}
+        }
+        let length =
+            *options
+                .get(offset + 1)
+                .ok_or(FragmentError::Options(Ipv4OptionError::Truncated {
+                    offset,
+                    kind,
+                }))?;
+        if length < 2 || offset + length as usize > options.len() {
+            return Err(FragmentError::Options(Ipv4OptionError::BadLength {
+                offset,
+                kind,
+                length,
+            }));
+        }
+        if OptionType::from(kind).copied {
+            copied.extend_from_slice(&options[offset..offset + length as usize]);
+        }
+        offset += length as usize;
+    }
+    while !copied.len().is_multiple_of(4) {
+        copied.push(KIND_EOL);
+    }
+    Ok(copied)
+}
+
+/// 用 `header` 和分片数据组成一个分片，重写总长度、MF、偏移和校验和
+fn build_fragment(header: &[u8], body: &[u8], more: bool, offset: u16) -> Vec<u8> {
+    let mut fragment = header.to_vec();
+    fragment.extend_from_slice(body);
+    let total_len = fragment.len() as u16;
+    fragment[2..4].copy_from_slice(&total_len.to_be_bytes());
+    let flags = if more { FLAG_MF } else { 0 };
+    let flags_offset = ((flags as u16) << 13) | offset;
+    fragment[6..8].copy_from_slice(&flags_offset.to_be_bytes());
+    fragment[10..12].fill(0);
+    let check = checksum::checksum(&fragment[..header.len()]);
+    fragment[10..12].copy_from_slice(&check.to_be_bytes());
+    fragment
+}
+
+/// Fisher-Yates 洗牌
+fn shuffle<T>(items: &mut [T]) {
+    let mut seed = [0u8; 8];
+    let mut state = match fill_random(&mut seed) {
+        Ok(()) => u64::from_ne_bytes(seed),
+        Err(_) => unix_micros(),
+    } | 1;
+    for i in (1..items.len()).rev() {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        items.swap(i, (state % (i as u64 + 1)) as usize);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::{PacketBuilder, UdpLayer};
+    use crate::ipv4_option::{self, Ipv4Option};
+    use crate::marker::{self, Marker};
+    use crate::reassembly::{Reassembler, ReassemblyConfig};
+    use std::net::Ipv4Addr;
+    use std::time::Instant;
+
+    const LSRR: u8 = 0x83;
+
+    /// 带标记选项 (copied=0) 和 LSRR (copied=1) 的 UDP 数据报：首部 52 字节，
+    /// 数据 208 字节。MTU 100 时第一个分片带 48 字节数据，其余分片首部只剩 LSRR
+    /// (32 字节)，各带 64 字节
+    fn datagram() -> Vec<u8> {
+        datagram_with(PacketBuilder::ipv4(
+            Ipv4Addr::new(10, 0, 0, 1),
+            Ipv4Addr::new(10, 0, 0, 2),
+        ))
+    }
+
+    fn datagram_with(builder: PacketBuilder) -> Vec<u8> {
+        let options = ipv4_option::serialize_options(&[
+            Ipv4Option::Unknown {
+                kind: marker::IPV4_OPTION_KIND,
+                data: Marker::new(7, 1).encode(),
+            },
+            Ipv4Option::LooseSourceRoute {
+                pointer: 4,
+                route: vec![Ipv4Addr::new(192, 0, 2, 1), Ipv4Addr::new(10, 0, 0, 2)],
+            },
+        ])
+        .unwrap();
+        builder
+            .identification(0x1234)
+            .ip_options(options)
+            .udp(UdpLayer::new(40000, 8001))
+            .payload((0..200).map(|i| i as u8).collect())
+            .build()
+            .unwrap()
+    }
+
+    fn header_len(fragment: &[u8]) -> usize {
+        (fragment[0] & 0x0f) as usize * 4
+    }
+
+    fn data(fragment: &[u8]) -> &[u8] {
+        &fragment[header_len(fragment)..]
+    }
+
+    /// 分片中的选项 kind，不含 EOL / NOP 填充
+    fn option_kinds(fragment: &[u8]) -> Vec<u8> {
+        ipv4_option::parse_options(&fragment[IPV4_HEADER_LEN..header_len(fragment)])
+            .unwrap()
+            .iter()
+            .map(Ipv4Option::kind)
+            .filter(|kind| *kind != KIND_EOL && *kind != KIND_NOP)
+            .collect()
+    }
+
+    /// (字节偏移, MF)
+    fn placement(fragment: &[u8]) -> (usize, bool) {
+        let field = u16::from_be_bytes([fragment[6], fragment[7]]);
+        ((field & MAX_OFFSET) as usize * 8, field >> 13 & 1 == 1)
+    }
+
+    fn reassemble(fragments: &[Vec<u8>]) -> Vec<u8> {
+        let mut reassembler = Reassembler::new(ReassemblyConfig::default());
+        let now = Instant::now();
+        fragments
+            .iter()
+            .find_map(|fragment| reassembler.push(fragment, now))
+            .unwrap()
+            .data
+    }
+
+    #[test]
+    fn options_follow_copy_semantics() {
+        let packet = datagram();
+        assert_eq!(header_len(&packet), 52);
+        let fragments = Fragmenter::new(100).fragment(&packet).unwrap();
+        assert_eq!(
+            fragments.iter().map(|f| placement(f)).collect::<Vec<_>>(),
+            vec![(0, true), (48, true), (112, true), (176, false)]
+        );
+        assert_eq!(
+            option_kinds(&fragments[0]),
+            vec![marker::IPV4_OPTION_KIND, LSRR]
+        );
+        assert_eq!(fragments[0][20..52], packet[20..52]);
+        for fragment in &fragments {
+            assert!(fragment.len() <= 100);
+            let len = header_len(fragment);
+            assert_eq!(fragment[0] >> 4, 4);
+            assert_eq!(
+                u16::from_be_bytes([fragment[2], fragment[3]]) as usize,
+                fragment.len()
+            );
+            assert_eq!(fragment[4..6], [0x12, 0x34]);
+            assert_eq!(checksum::checksum(&fragment[..len]), 0);
+        }
+        for fragment in &fragments[1..] {
+            assert_eq!(header_len(fragment), 32);
+            assert_eq!(option_kinds(fragment), vec![LSRR]);
+            assert!(data(fragment).len() <= 64);
+        }
+
+        let mut joined = Vec::new();
+        for fragment in &fragments {
+            assert_eq!(placement(fragment).0, joined.len());
+            joined.extend_from_slice(data(fragment));
+        }
+        assert_eq!(joined, packet[52..]);
+        assert_eq!(reassemble(&fragments), packet);
+    }
+
+    #[test]
+    fn small_datagram_is_returned_unchanged() {
+        let packet = datagram();
+        assert_eq!(
+            Fragmenter::new(packet.len()).fragment(&packet),
+            Ok(vec![packet])
+        );
+    }
+
+    #[test]
+    fn tiny_first_fragment() {
+        let packet = datagram();
+        let fragmenter = Fragmenter {
+            first_size: Some(8),
+            ..Fragmenter::new(100)
+        };
+        let fragments = fragmenter.fragment(&packet).unwrap();
+        assert_eq!(data(&fragments[0]), &packet[52..60]);
+        assert_eq!(
+            fragments.iter().map(|f| placement(f).0).collect::<Vec<_>>(),
+            vec![0, 8, 72, 136, 200]
+        );
+        assert_eq!(reassemble(&fragments), packet);
+    }
+
+    #[test]
+    fn overlap_is_filled() {
+        let packet = datagram();
+        let fragmenter = Fragmenter {
+            overlap: 8,
+            overlap_fill: Some(0xee),
+            ..Fragmenter::new(100)
+        };
+        let fragments = fragmenter.fragment(&packet).unwrap();
+        assert_eq!(
+            fragments.iter().map(|f| placement(f)).collect::<Vec<_>>(),
+            vec![(0, true), (40, true), (96, true), (152, false)]
+        );
+        assert_eq!(data(&fragments[0]), &packet[52..100]);
+        for fragment in &fragments[1..] {
+            let (offset, _) = placement(fragment);
+            let body = data(fragment);
+            assert_eq!(body[..8], [0xee; 8]);
+            assert_eq!(body[8..], packet[52 + offset + 8..52 + offset + body.len()]);
+        }
+    }
+
+    #[test]
+    fn reverse_order() {
+        let packet = datagram();
+        let in_order = Fragmenter::new(100).fragment(&packet).unwrap();
+        let fragmenter = Fragmenter {
+            order: FragmentOrder::Reverse,
+            ..Fragmenter::new(100)
+        };
+        let mut reversed = fragmenter.fragment(&packet).unwrap();
+        assert_eq!(placement(&reversed[0]), (176, false));
+        reversed.reverse();
+        assert_eq!(reversed, in_order);
+    }
+
+    #[test]
+    fn errors() {
+        let packet = datagram();
+        let fragment = |fragmenter: Fragmenter, packet: &[u8]| fragmenter.fragment(packet);
+        let df = datagram_with(
+            PacketBuilder::ipv4(Ipv4Addr::new(10, 0, 0, 1), Ipv4Addr::new(10, 0, 0, 2))
+                .dont_fragment(true),
+        );
+        assert_eq!(
+            fragment(Fragmenter::new(100), &df),
+            Err(FragmentError::DontFragment)
+        );
+        assert_eq!(
+            fragment(Fragmenter::new(100), &packet[..19]),
+            Err(FragmentError::Truncated)
+        );
+        assert_eq!(
+            fragment(Fragmenter::new(100), &packet[..packet.len() - 1]),
+            Err(FragmentError::Truncated)
+        );
+        assert_eq!(
+            fragment(Fragmenter::new(27), &packet),
+            Err(FragmentError::MtuTooSmall(27))
+        );
+        // 放得下固定首部，但放不下带选项的第一个分片首部和 8 字节数据
+        assert_eq!(
+            fragment(Fragmenter::new(56), &packet),
+            Err(FragmentError::MtuTooSmall(56))
+        );
+        let overlapping = |overlap| Fragmenter {
+            overlap,
+            ..Fragmenter::new(100)
+        };
+        assert_eq!(
+            fragment(overlapping(64), &packet),
+            Err(FragmentError::OverlapTooLarge(64))
+        );
+        assert_eq!(
+            fragment(overlapping(4), &packet),
+            Err(FragmentError::Unaligned(4))
+        );
+        let first = Fragmenter {
+            first_size: Some(12),
+            ..Fragmenter::new(100)
+        };
+        assert_eq!(fragment(first, &packet), Err(FragmentError::Unaligned(12)));
+    }
+
+    /// 偏移为 `offset` (8 字节单位)、带 MF 的分片，数据为 `len` 字节
+    fn fragment_at(offset: u16, len: usize) -> Vec<u8> {
+        let mut packet =
+            PacketBuilder::ipv4(Ipv4Addr::new(10, 0, 0, 1), Ipv4Addr::new(10, 0, 0, 2))
+                .udp(UdpLayer::new(40000, 8001))
+                .payload(vec![0; len - 8])
+                .build()
+                .unwrap();
+        packet[6..8].copy_from_slice(&((u16::from(FLAG_MF) << 13) | offset).to_be_bytes());
+        packet
+    }
+
+    #[test]
+    fn refragmenting_adds_to_the_original_offset() {
+        let fragments = Fragmenter::new(68)
+            .fragment(&fragment_at(100, 144))
+            .unwrap();
+        let offsets: Vec<u16> = fragments
+            .iter()
+            .map(|f| u16::from_be_bytes([f[6], f[7]]))
+            .collect();
+        let mf = u16::from(FLAG_MF) << 13;
+        assert_eq!(offsets, vec![mf | 100, mf | 106, mf | 112]);
+    }
+
+    #[test]
+    fn offset_past_the_field_is_rejected() {
+        // 最后一个分片恰好落在 0x1fff
+        let fragments = Fragmenter::new(68).fragment(&fragment_at(MAX_OFFSET - 12, 144));
+        assert_eq!(fragments.unwrap().len(), 3);
+        assert_eq!(
+            Fragmenter::new(68).fragment(&fragment_at(MAX_OFFSET - 11, 144)),
+            Err(FragmentError::OffsetOverflow(0x2000 * 8))
+        );
+    }
+}
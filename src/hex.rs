@@ -0,0 +1,48 @@
+//! 十六进制文本与字节的转换
+
+/// 解析十六进制串，允许 `0x` 前缀以及空白、`:`、`-` 分隔符
+///
+/// 空串、奇数个数字或非法字符返回 `None`。
+pub fn decode(text: &str) -> Option<Vec<u8>> {
+    let text = text.strip_prefix("0x").unwrap_or(text);
+    let digits: Vec<u8> = text
+        .bytes()
+        .filter(|b| !b.is_ascii_whitespace() && *b != b':' && *b != b'-')
+        .collect();
+    if digits.is_empty() || !digits.len().is_multiple_of(2) {
+        return None;
+    }
+    digits
+        .chunks(2)
+        .map(|pair| Some(nibble(pair[0])? << 4 | nibble(pair[1])?))
+        .collect()
+}
+
+/// 单个十六进制数字的值；`u8::from_str_radix` 会接受 `+` 号，所以逐字符解析
+fn nibble(digit: u8) -> Option<u8> {
+    (digit as char).to_digit(16).map(|value| value as u8)
+}
+
+/// 编码为连续的小写十六进制串
+pub fn encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_separators_and_prefix() {
+        assert_eq!(decode("0x00ff"), Some(vec![0x00, 0xff]));
+        assert_eq!(decode("aa:BB-cc dd"), Some(vec![0xaa, 0xbb, 0xcc, 0xdd]));
+        assert_eq!(encode(&decode("0102fe").unwrap()), "0102fe");
+    }
+
+    #[test]
+    fn decode_rejects_invalid_digits() {
+        for text in ["", "0x", "abc", "0g", "+f", "+f00", "f+", "é"] {
+            assert_eq!(decode(text), None, "{}", text);
+        }
+    }
+}
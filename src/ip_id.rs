@@ -0,0 +1,410 @@
+//! IP Identification 字段的编码策略
+//!
+//! 发送端通过 [`IdStrategy`] 生成 ID，接收端用同一策略的 `decode` 解释 ID。
+//! 时间戳类策略只保留低 16 位，解码时借助接收时间消除回绕的歧义。
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::net::Ipv4Addr;
+
+use crate::timestamp::unix_micros;
+
+/// 生成 / 解释 ID 时用到的流信息
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FlowTuple {
+    pub source: Ipv4Addr,
+    pub destination: Ipv4Addr,
+    pub protocol: u8,
+    pub source_port: u16,
+    pub destination_port: u16,
+}
+
+/// 接收端对 ID 的解释
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodedId {
+    /// 还原出的发送时间，Unix 微秒
+    Timestamp(u64),
+    Counter(u16),
+    /// ID 是否与本流的哈希一致
+    FlowHash {
+        expected: u16,
+        matches: bool,
+    },
+    /// 没有可解释的含义 (随机 / 固定值)
+    Opaque(u16),
+}
+
+impl fmt::Display for DecodedId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodedId::Timestamp(us) => {
+                write!(f, "发送时间 {}.{:06}", us / 1_000_000, us % 1_000_000)
+            }
+            DecodedId::Counter(value) => write!(f, "计数 {}", value),
+            DecodedId::FlowHash { expected, matches } => {
+                if *matches {
+                    write!(f, "流哈希匹配")
+                } else {
+                    write!(f, "流哈希不匹配 (期望 0x{:04x})", expected)
+                }
+            }
+            DecodedId::Opaque(value) => write!(f, "0x{:04x}", value),
+        }
+    }
+}
+
+/// ID 编码策略
+pub trait IdStrategy {
+    /// 策略名称，与 [`strategy_from_name`] 接受的名称一致
+    fn name(&self) -> &'static str;
+
+    /// 为下一个发往 `flow` 的数据包生成 ID
+    fn next_id(&mut self, flow: &FlowTuple) -> u16;
+
+    /// 解释收到的 ID
+    ///
+    /// # 参数
+    /// - `received_us`: 接收时间 (Unix 微秒)，用于时间戳类策略的回绕消歧
+    fn decode(&self, id: u16, flow: &FlowTuple, received_us: u64) -> DecodedId;
+}
+
+/// Unix 秒的低 16 位，约 18 小时回绕一次
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TimestampSeconds;
+
+impl IdStrategy for TimestampSeconds {
+    fn name(&self) -> &'static str {
+        "seconds"
+    }
+
+    fn next_id(&mut self, _flow: &FlowTuple) -> u16 {
+        (unix_micros() / 1_000_000) as u16
+    }
+
+    fn decode(&self, id: u16, _flow: &FlowTuple, received_us: u64) -> DecodedId {
+        DecodedId::Timestamp(unwrap_nearest(id, received_us / 1_000_000) * 1_000_000)
+    }
+}
+
+/// Unix 毫秒对 65536 取模，约 65 秒回绕一次
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TimestampMillis;
+
+impl IdStrategy for TimestampMillis {
+    fn name(&self) -> &'static str {
+        "millis"
+    }
+
+    fn next_id(&mut self, _flow: &FlowTuple) -> u16 {
+        (unix_micros() / 1_000) as u16
+    }
+
+    fn decode(&self, id: u16, _flow: &FlowTuple, received_us: u64) -> DecodedId {
+        DecodedId::Timestamp(unwrap_nearest(id, received_us / 1_000) * 1_000)
+    }
+}
+
+/// 每个目的地址独立递增的计数器
+#[derive(Debug, Clone, Default)]
+pub struct PerDestinationCounter {
+    counters: HashMap<Ipv4Addr, u16>,
+}
+
+impl IdStrategy for PerDestinationCounter {
+    fn name(&self) -> &'static str {
+        "counter"
+    }
+
+    fn next_id(&mut self, flow: &FlowTuple) -> u16 {
+        let counter = self.counters.entry(flow.destination).or_default();
+        let id = *counter;
+        *counter = counter.wrapping_add(1);
+        id
+    }
+
+    fn decode(&self, id: u16, _flow: &FlowTuple, _received_us: u64) -> DecodedId {
+        DecodedId::Counter(id)
+    }
+}
+
+/// 随机 ID
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Random;
+
+impl IdStrategy for Random {
+    fn name(&self) -> &'static str {
+        "random"
+    }
+
+    fn next_id(&mut self, _flow: &FlowTuple) -> u16 {
+        let mut bytes = [0u8; 2];
+        match fill_random(&mut bytes) {
+            Ok(()) => u16::from_be_bytes(bytes),
+            // 内核随机数不可用时退回到时间的低位
+            Err(_) => unix_micros() as u16,
+        }
+    }
+
+    fn decode(&self, id: u16, _flow: &FlowTuple, _received_us: u64) -> DecodedId {
+        DecodedId::Opaque(id)
+    }
+}
+
+/// 流五元组的哈希 (FNV-1a 折叠到 16 位)，同一流的 ID 恒定
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FlowHash;
+
+impl FlowHash {
+    pub fn hash(flow: &FlowTuple) -> u16 {
+        let mut hash: u32 = 0x811c_9dc5;
+        let mut feed = |bytes: &[u8]| {
+            for b in bytes {
+                hash ^= *b as u32;
+                hash = hash.wrapping_mul(0x0100_0193);
+            }
+        };
+        feed(&flow.source.octets());
+        feed(&flow.destination.octets());
+        feed(&[flow.protocol]);
+        feed(&flow.source_port.to_be_bytes());
+        feed(&flow.destination_port.to_be_bytes());
+        ((hash >> 16) ^ (hash & 0xffff)) as u16
+    }
+}
+
+impl IdStrategy for FlowHash {
+    fn name(&self) -> &'static str {
+        "flow-hash"
+    }
+
+    fn next_id(&mut self, flow: &FlowTuple) -> u16 {
+        Self::hash(flow)
+    }
+
+    fn decode(&self, id: u16, flow: &FlowTuple, _received_us: u64) -> DecodedId {
+        let expected = Self::hash(flow);
+        DecodedId::FlowHash {
+            expected,
+            matches: id == expected,
+        }
+    }
+}
+
+/// 固定 ID
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Fixed(pub u16);
+
+impl IdStrategy for Fixed {
+    fn name(&self) -> &'static str {
+        "fixed"
+    }
+
+    fn next_id(&mut self, _flow: &FlowTuple) -> u16 {
+        self.0
+    }
+
+    fn decode(&self, id: u16, _flow: &FlowTuple, _received_us: u64) -> DecodedId {
+        DecodedId::Opaque(id)
+    }
+}
+
+/// 按名称创建策略
+///
+/// 支持 `seconds`、`millis`、`counter`、`random`、`flow-hash`、`fixed` 和 `fixed:<值>`。
+pub fn strategy_from_name(name: &str) -> Option<Box<dyn IdStrategy>> {
+    let strategy: Box<dyn IdStrategy> = match name {
+        "seconds" => Box::new(TimestampSeconds),
+        "millis" => Box::new(TimestampMillis),
+        "counter" => Box::new(PerDestinationCounter::default()),
+        "random" => Box::new(Random),
+        "flow-hash" => Box::new(FlowHash),
+        "fixed" => Box::new(Fixed(0)),
+        _ => {
+            let value = name.strip_prefix("fixed:")?;
+            let value = match value.strip_prefix("0x") {
+                Some(hex) => u16::from_str_radix(hex, 16).ok()?,
+                None => value.parse().ok()?,
+            };
+            Box::new(Fixed(value))
+        }
+    };
+    Some(strategy)
+}
+
+/// 找出与 `reference` 最接近、且低 16 位等于 `low` 的值
+///
+/// 允许发送时间略晚于参考时间 (两端时钟偏差)，差值在半个回绕周期内都能正确还原；
+/// 恰好相差半个周期时取较早的值。结果不小于 0：参考值不足一个周期时不会回绕。
+pub fn unwrap_nearest(low: u16, reference: u64) -> u64 {
+    let back = reference.wrapping_sub(low as u64) & 0xffff;
+    if back > 0x8000 || back > reference {
+        reference.wrapping_add(0x1_0000 - back)
+    } else {
+        reference - back
+    }
+}
+
+/// 用内核随机数填充缓冲区
+pub fn fill_random(buf: &mut [u8]) -> io::Result<()> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        let ret = unsafe {
+            libc::getrandom(
+                buf[filled..].as_mut_ptr() as *mut libc::c_void,
+                buf.len() - filled,
+                0,
+            )
+        };
+        if ret < 0 {
+            let err = io::Error::last_os_error();
+            if err.kind() != io::ErrorKind::Interrupted {
+                return Err(err);
+            }
+        } else {
+            filled += ret as usize;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flow(destination_port: u16) -> FlowTuple {
+        FlowTuple {
+            source: Ipv4Addr::new(10, 0, 0, 1),
+            destination: Ipv4Addr::new(10, 0, 0, 2),
+            protocol: 17,
+            source_port: 40000,
+            destination_port,
+        }
+    }
+
+    #[test]
+    fn unwrap_nearest_disambiguates_rollover() {
+        for (low, reference, expected) in [
+            (0x1234, 0x5_1234, 0x5_1234),
+            // 发送于回绕之前，接收于回绕之后
+            (0xfffe, 0x5_0002, 0x4_fffe),
+            // 发送端时钟略快，发送时间在回绕之后
+            (0x0003, 0x4_fffe, 0x5_0003),
+            (0x1000, 0x5_0ff0, 0x5_1000),
+            // 恰好相差半个周期时取较早的值
+            (0x0000, 0x5_8000, 0x5_0000),
+            (0xffff, 0x5_7fff, 0x4_ffff),
+            (0x0001, 0x5_8000, 0x5_0001),
+            // 参考值不足一个周期时不向下回绕到 u64::MAX 附近
+            (0xfff0, 5, 0xfff0),
+            (0x8000, 0, 0x8000),
+            (0x0000, 0, 0),
+        ] {
+            assert_eq!(
+                unwrap_nearest(low, reference),
+                expected,
+                "low 0x{:04x} reference 0x{:x}",
+                low,
+                reference
+            );
+        }
+    }
+
+    #[test]
+    fn timestamp_strategies_round_trip() {
+        let mut seconds = TimestampSeconds;
+        let before = unix_micros();
+        let id = seconds.next_id(&flow(8001));
+        let after = unix_micros();
+        // 接收时间晚 30 秒，远小于半个回绕周期
+        let DecodedId::Timestamp(sent) = seconds.decode(id, &flow(8001), after + 30_000_000) else {
+            panic!("seconds 应解码为时间戳");
+        };
+        assert!(before / 1_000_000 * 1_000_000 <= sent && sent <= after);
+
+        let mut millis = TimestampMillis;
+        let before = unix_micros();
+        let id = millis.next_id(&flow(8001));
+        let after = unix_micros();
+        let DecodedId::Timestamp(sent) = millis.decode(id, &flow(8001), after + 10_000_000) else {
+            panic!("millis 应解码为时间戳");
+        };
+        assert!(before / 1_000 * 1_000 <= sent && sent <= after);
+        // 接收端时钟慢 2 秒也能还原
+        assert_eq!(
+            millis.decode(id, &flow(8001), after.saturating_sub(2_000_000)),
+            DecodedId::Timestamp(sent)
+        );
+    }
+
+    #[test]
+    fn other_strategies_round_trip() {
+        let mut counter = PerDestinationCounter::default();
+        let mut other = flow(8001);
+        other.destination = Ipv4Addr::new(10, 0, 0, 3);
+        let ids: Vec<u16> = [flow(8001), flow(8002), other]
+            .iter()
+            .map(|flow| counter.next_id(flow))
+            .collect();
+        assert_eq!(ids, vec![0, 1, 0]);
+        assert_eq!(counter.decode(1, &flow(8001), 0), DecodedId::Counter(1));
+
+        let id = Random.next_id(&flow(8001));
+        assert_eq!(Random.decode(id, &flow(8001), 0), DecodedId::Opaque(id));
+
+        let id = FlowHash.next_id(&flow(8001));
+        assert_eq!(id, FlowHash.next_id(&flow(8001)));
+        assert_ne!(id, FlowHash::hash(&flow(8002)));
+        assert_eq!(
+            FlowHash.decode(id, &flow(8001), 0),
+            DecodedId::FlowHash {
+                expected: id,
+                matches: true
+            }
+        );
+        assert_eq!(
+            FlowHash.decode(id, &flow(8002), 0),
+            DecodedId::FlowHash {
+                expected: FlowHash::hash(&flow(8002)),
+                matches: false
+            }
+        );
+
+        let mut fixed = Fixed(0x4242);
+        assert_eq!(fixed.next_id(&flow(8001)), 0x4242);
+        assert_eq!(
+            fixed.decode(0x4242, &flow(8001), 0),
+            DecodedId::Opaque(0x4242)
+        );
+    }
+
+    #[test]
+    fn strategies_by_name() {
+        for name in [
+            "seconds",
+            "millis",
+            "counter",
+            "random",
+            "flow-hash",
+            "fixed",
+        ] {
+            assert_eq!(strategy_from_name(name).unwrap().name(), name);
+        }
+        for (name, id) in [("fixed:17", 17), ("fixed:0x1f41", 0x1f41)] {
+            let mut strategy = strategy_from_name(name).unwrap();
+            assert_eq!(strategy.name(), "fixed");
+            assert_eq!(strategy.next_id(&flow(8001)), id);
+        }
+        for name in [
+            "",
+            "sec",
+            "Seconds",
+            "fixed:",
+            "fixed:70000",
+            "fixed:0xzz",
+            "fixed:-1",
+        ] {
+            assert!(strategy_from_name(name).is_none(), "{}", name);
+        }
+    }
+}
@@ -0,0 +1,682 @@
+//! IPv4 选项编解码 (RFC 791 / 1108 / 1393 / 2113)
+//!
+//! 选项类型字节的构成: copied(1 位) + class(2 位) + number(5 位)。
+
+use std::fmt;
+use std::net::Ipv4Addr;
+
+use crate::builder::IPV4_MAX_OPTIONS_LEN;
+use crate::hex;
+
+pub const KIND_EOL: u8 = 0;
+pub const KIND_NOP: u8 = 1;
+pub const KIND_RECORD_ROUTE: u8 = 7;
+pub const KIND_TIMESTAMP: u8 = 68;
+pub const KIND_SECURITY: u8 = 130;
+pub const KIND_LSRR: u8 = 131;
+pub const KIND_STREAM_ID: u8 = 136;
+pub const KIND_SSRR: u8 = 137;
+pub const KIND_ROUTER_ALERT: u8 = 148;
+
+/// 单个选项数据部分的最大长度 (长度字节最大 255，包括类型和长度两个字节)
+const MAX_BODY_LEN: usize = 253;
+
+/// 选项类型字节解码后的三个字段
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OptionType {
+    /// 分片时是否复制到每个分片
+    pub copied: bool,
+    /// 0 = control, 2 = debugging and measurement, 1 / 3 保留
+    pub class: u8,
+    pub number: u8,
+}
+
+impl From<u8> for OptionType {
+    fn from(kind: u8) -> Self {
+        OptionType {
+            copied: kind & 0x80 != 0,
+            class: (kind >> 5) & 0x03,
+            number: kind & 0x1f,
+        }
+    }
+}
+
+impl fmt::Display for OptionType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "copied={} class={} number={}",
+            self.copied as u8, self.class, self.number
+        )
+    }
+}
+
+/// Timestamp 选项的 flag 字段
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimestampFlag {
+    /// 仅时间戳
+    TimestampOnly,
+    /// 地址 + 时间戳
+    AddressAndTimestamp,
+    /// 预先指定地址
+    Prespecified,
+    Other(u8),
+}
+
+impl From<u8> for TimestampFlag {
+    fn from(flag: u8) -> Self {
+        match flag {
+            0 => TimestampFlag::TimestampOnly,
+            1 => TimestampFlag::AddressAndTimestamp,
+            3 => TimestampFlag::Prespecified,
+            other => TimestampFlag::Other(other),
+        }
+    }
+}
+
+impl From<TimestampFlag> for u8 {
+    fn from(flag: TimestampFlag) -> Self {
+        match flag {
+            TimestampFlag::TimestampOnly => 0,
+            TimestampFlag::AddressAndTimestamp => 1,
+            TimestampFlag::Prespecified => 3,
+            TimestampFlag::Other(other) => other & 0x0f,
+        }
+    }
+}
+
+/// Timestamp 选项中的一项，`TimestampOnly` 模式下没有地址
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimestampEntry {
+    pub address: Option<Ipv4Addr>,
+    pub timestamp: u32,
+}
+
+/// IPv4 选项
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Ipv4Option {
+    EndOfList,
+    Nop,
+    RecordRoute {
+        pointer: u8,
+        route: Vec<Ipv4Addr>,
+    },
+    Timestamp {
+        pointer: u8,
+        overflow: u8,
+        flag: TimestampFlag,
+        entries: Vec<TimestampEntry>,
+    },
+    LooseSourceRoute {
+        pointer: u8,
+        route: Vec<Ipv4Addr>,
+    },
+    StrictSourceRoute {
+        pointer: u8,
+        route: Vec<Ipv4Addr>,
+    },
+    /// RFC 2113，值 0 表示路由器应检查该数据报
+    RouterAlert(u16),
+    /// RFC 1108 基本安全选项
+    Security {
+        classification: u8,
+        protection_authority: Vec<u8>,
+    },
+    StreamId(u16),
+    /// 未识别的选项 (包括我们自定义的 0x79)
+    Unknown {
+        kind: u8,
+        data: Vec<u8>,
+    },
+}
+
+/// 选项编解码错误
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Ipv4OptionError {
+    /// 选项在 `offset` 处被截断
+    Truncated { offset: usize, kind: u8 },
+    /// 长度字段非法 (小于 2、超出剩余字节或与选项类型不符)
+    BadLength { offset: usize, kind: u8, length: u8 },
+    /// 序列化后 (补齐前) 超过 40 字节，或单个选项超出长度字节的范围
+    TooLong(usize),
+    /// `Unknown` 使用了 EOL / NOP 的类型号，这两种选项只有一个字节，无法带数据编码
+    SingleByteKind(u8),
+    /// Timestamp 的溢出计数只有 4 位，不能超过 15
+    TimestampOverflow(u8),
+}
+
+impl fmt::Display for Ipv4OptionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Ipv4OptionError::Truncated { offset, kind } => {
+                write!(f, "偏移 {} 处的选项 0x{:02x} 被截断", offset, kind)
+            }
+            Ipv4OptionError::BadLength {
+                offset,
+                kind,
+                length,
+            } => write!(
+                f,
+                "偏移 {} 处的选项 0x{:02x} 长度字段非法: {}",
+                offset, kind, length
+            ),
+            Ipv4OptionError::TooLong(len) => write!(
+                f,
+                "IP 选项长度 {} 字节, 超过上限 {} 字节",
+                len, IPV4_MAX_OPTIONS_LEN
+            ),
+            Ipv4OptionError::SingleByteKind(kind) => {
+                write!(f, "选项类型 {} 只有一个字节, 不能带数据编码", kind)
+            }
+            Ipv4OptionError::TimestampOverflow(overflow) => {
+                write!(f, "Timestamp 溢出计数 {} 超过 4 位上限 15", overflow)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Ipv4OptionError {}
+
+impl Ipv4Option {
+    /// 选项类型字节
+    pub fn kind(&self) -> u8 {
+        match self {
+            Ipv4Option::EndOfList => KIND_EOL,
+            Ipv4Option::Nop => KIND_NOP,
+            Ipv4Option::RecordRoute { .. } => KIND_RECORD_ROUTE,
+            Ipv4Option::Timestamp { .. } => KIND_TIMESTAMP,
+            Ipv4Option::LooseSourceRoute { .. } => KIND_LSRR,
+            Ipv4Option::StrictSourceRoute { .. } => KIND_SSRR,
+            Ipv4Option::RouterAlert(_) => KIND_ROUTER_ALERT,
+            Ipv4Option::Security { .. } => KIND_SECURITY,
+            Ipv4Option::StreamId(_) => KIND_STREAM_ID,
+            Ipv4Option::Unknown { kind, .. } => *kind,
+        }
+    }
+
+    pub fn option_type(&self) -> OptionType {
+        OptionType::from(self.kind())
+    }
+
+    /// 将单个选项追加到 `out`
+    ///
+    /// # 返回
+    /// 数据部分超过 253 字节、长度字节放不下时返回 `TooLong`；
+    /// `Unknown` 的类型为 EOL / NOP 时返回 `SingleByteKind`；
+    /// Timestamp 溢出计数超过 15 时返回 `TimestampOverflow`。出错时 `out` 不变
+    pub fn write_to(&self, out: &mut Vec<u8>) -> Result<(), Ipv4OptionError> {
+        let kind = self.kind();
+        match self {
+            Ipv4Option::EndOfList | Ipv4Option::Nop => {
+                out.push(kind);
+                return Ok(());
+            }
+            _ => {}
+        }
+
+        let mut body = Vec::new();
+        match self {
+            Ipv4Option::RecordRoute { pointer, route }
+            | Ipv4Option::LooseSourceRoute { pointer, route }
+            | Ipv4Option::StrictSourceRoute { pointer, route } => {
+                body.push(*pointer);
+                for addr in route {
+                    body.extend_from_slice(&addr.octets());
+                }
+            }
+            Ipv4Option::Timestamp {
+                pointer,
+                overflow,
+                flag,
+                entries,
+            } => {
+                if *overflow > 0x0f {
+                    return Err(Ipv4OptionError::TimestampOverflow(*overflow));
+                }
+                body.push(*pointer);
+                body.push((overflow << 4) | u8::from(*flag));
+                for entry in entries {
+                    if let Some(addr) = entry.address {
+                        body.extend_from_slice(&addr.octets());
+                    }
+                    body.extend_from_slice(&entry.timestamp.to_be_bytes());
+                }
+            }
+            Ipv4Option::RouterAlert(value) | Ipv4Option::StreamId(value) => {
+                body.extend_from_slice(&value.to_be_bytes());
+            }
+            Ipv4Option::Security {
+                classification,
+                protection_authority,
+            } => {
+                body.push(*classification);
+                body.extend_from_slice(protection_authority);
+            }
+            Ipv4Option::Unknown { kind, data } => {
+                if *kind == KIND_EOL || *kind == KIND_NOP {
+                    return Err(Ipv4OptionError::SingleByteKind(*kind));
+                }
+                body.extend_from_slice(data);
+            }
+            Ipv4Option::EndOfList | Ipv4Option::Nop => unreachable!(),
+        }
+        if body.len() > MAX_BODY_LEN {
+            return Err(Ipv4OptionError::TooLong(body.len() + 2));
+        }
+
+        out.push(kind);
+        out.push((body.len() + 2) as u8);
+        out.extend_from_slice(&body);
+        Ok(())
+    }
+}
+
+impl fmt::Display for Ipv4Option {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Ipv4Option::EndOfList => write!(f, "EOL"),
+            Ipv4Option::Nop => write!(f, "NOP"),
+            Ipv4Option::RecordRoute { pointer, route } => {
+                write!(f, "RR(ptr={}, {})", pointer, join_addrs(route))
+            }
+            Ipv4Option::LooseSourceRoute { pointer, route } => {
+                write!(f, "LSRR(ptr={}, {})", pointer, join_addrs(route))
+            }
+            Ipv4Option::StrictSourceRoute { pointer, route } => {
+                write!(f, "SSRR(ptr={}, {})", pointer, join_addrs(route))
+            }
+            Ipv4Option::Timestamp {
+                pointer,
+                overflow,
+                flag,
+                entries,
+            } => {
+                write!(f, "TS(ptr={}, oflw={}, {:?}, [", pointer, overflow, flag)?;
+                for (i, entry) in entries.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    match entry.address {
+                        Some(addr) => write!(f, "{}@{}", addr, entry.timestamp)?,
+                        None => write!(f, "{}", entry.timestamp)?,
+                    }
+                }
+                write!(f, "])")
+            }
+            Ipv4Option::RouterAlert(value) => write!(f, "RA({})", value),
+            Ipv4Option::Security {
+                classification,
+                protection_authority,
+            } => write!(
+                f,
+                "SEC(class=0x{:02x}, auth={})",
+                classification,
+                hex::encode(protection_authority)
+            ),
+            Ipv4Option::StreamId(id) => write!(f, "SID({})", id),
+            Ipv4Option::Unknown { kind, data } => write!(
+                f,
+                "Unknown(0x{:02x} {}, data={})",
+                kind,
+                OptionType::from(*kind),
+                hex::encode(data)
+            ),
+        }
+    }
+}
+
+/// 序列化选项列表
+///
+/// # 返回
+/// 以 EOL 补齐到 4 字节倍数的选项字节；补齐前超过 40 字节时返回错误
+pub fn serialize_options(options: &[Ipv4Option]) -> Result<Vec<u8>, Ipv4OptionError> {
+    let mut out = Vec::new();
+    for option in options {
+        option.write_to(&mut out)?;
+    }
+    if out.len() > IPV4_MAX_OPTIONS_LEN {
+        return Err(Ipv4OptionError::TooLong(out.len()));
+    }
+    while !out.len().is_multiple_of(4) {
+        out.push(KIND_EOL);
+    }
+    Ok(out)
+}
+
+/// 解析 IP 头部中的选项字节
+///
+/// 遇到 EOL 后停止解析，其后的字节视为填充。
+pub fn parse_options(bytes: &[u8]) -> Result<Vec<Ipv4Option>, Ipv4OptionError> {
+    let mut options = Vec::new();
+    let mut offset = 0;
+
+    while offset < bytes.len() {
+        let kind = bytes[offset];
+        match kind {
+            KIND_EOL => {
+                options.push(Ipv4Option::EndOfList);
+                break;
+            }
+            KIND_NOP => {
+                options.push(Ipv4Option::Nop);
+                offset += 1;
+                continue;
+            }
+            _ => {}
+        }
+
+        if offset + 1 >= bytes.len() {
+            return Err(Ipv4OptionError::Truncated { offset, kind });
+        }
+        let length = bytes[offset + 1];
+        if length < 2 || offset + length as usize > bytes.len() {
+            return Err(Ipv4OptionError::BadLength {
+                offset,
+                kind,
+                length,
+            });
+        }
+        let body = &bytes[offset + 2..offset + length as usize];
+        let bad_length = Ipv4OptionError::BadLength {
+            offset,
+            kind,
+            length,
+        };
+
+        let option = match kind {
+            KIND_RECORD_ROUTE | KIND_LSRR | KIND_SSRR => {
+                if body.is_empty() || !(body.len() - 1).is_multiple_of(4) {
+                    return Err(bad_length);
+                }
+                let pointer = body[0];
+                let route = body[1..].chunks(4).map(read_addr).collect();
+                match kind {
+                    KIND_RECORD_ROUTE => Ipv4Option::RecordRoute { pointer, route },
+                    KIND_LSRR => Ipv4Option::LooseSourceRoute { pointer, route },
+                    _ => Ipv4Option::StrictSourceRoute { pointer, route },
+                }
+            }
+            KIND_TIMESTAMP => {
+                if body.len() < 2 {
+                    return Err(bad_length);
+                }
+                let flag = TimestampFlag::from(body[1] & 0x0f);
+                let entry_len = match flag {
+                    TimestampFlag::TimestampOnly => 4,
+                    _ => 8,
+                };
+                let data = &body[2..];
+                if !data.len().is_multiple_of(entry_len) {
+                    return Err(bad_length);
+                }
+                let entries = data
+                    .chunks(entry_len)
+                    .map(|chunk| {
+                        if entry_len == 4 {
+                            TimestampEntry {
+                                address: None,
+                                timestamp: read_u32(chunk),
+                            }
+                        } else {
+                            TimestampEntry {
+                                address: Some(read_addr(&chunk[..4])),
+                                timestamp: read_u32(&chunk[4..]),
+                            }
+                        }
+                    })
+                    .collect();
+                Ipv4Option::Timestamp {
+                    pointer: body[0],
+                    overflow: body[1] >> 4,
+                    flag,
+                    entries,
+                }
+            }
+            KIND_ROUTER_ALERT | KIND_STREAM_ID => {
+                if body.len() != 2 {
+                    return Err(bad_length);
+                }
+                let value = u16::from_be_bytes([body[0], body[1]]);
+                if kind == KIND_ROUTER_ALERT {
+                    Ipv4Option::RouterAlert(value)
+                } else {
+                    Ipv4Option::StreamId(value)
+                }
+            }
+            KIND_SECURITY => {
+                if body.is_empty() {
+                    return Err(bad_length);
+                }
+                Ipv4Option::Security {
+                    classification: body[0],
+                    protection_authority: body[1..].to_vec(),
+                }
+            }
+            _ => Ipv4Option::Unknown {
+                kind,
+                data: body.to_vec(),
+            },
+        };
+        options.push(option);
+        offset += length as usize;
+    }
+
+    Ok(options)
+}
+
+/// 将选项列表格式化为一行，便于接收端打印
+pub fn format_options(options: &[Ipv4Option]) -> String {
+    options
+        .iter()
+        .map(|option| option.to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn read_addr(bytes: &[u8]) -> Ipv4Addr {
+    Ipv4Addr::new(bytes[0], bytes[1], bytes[2], bytes[3])
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+fn join_addrs(route: &[Ipv4Addr]) -> String {
+    let addrs: Vec<String> = route.iter().map(|addr| addr.to_string()).collect();
+    format!("[{}]", addrs.join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(last: u8) -> Ipv4Addr {
+        Ipv4Addr::new(192, 0, 2, last)
+    }
+
+    #[test]
+    fn serialize_parse_round_trip() {
+        let options = vec![
+            Ipv4Option::Nop,
+            Ipv4Option::RecordRoute {
+                pointer: 8,
+                route: vec![addr(1), addr(2)],
+            },
+            Ipv4Option::RouterAlert(0),
+            Ipv4Option::StreamId(0x1234),
+            Ipv4Option::Unknown {
+                kind: 0x79,
+                data: vec![1, 2, 3],
+            },
+        ];
+        let bytes = serialize_options(&options).unwrap();
+        assert_eq!(bytes.len(), 28);
+        assert_eq!(&bytes[..4], &[KIND_NOP, KIND_RECORD_ROUTE, 11, 8]);
+        // 补齐用的 EOL 解析为一个 EndOfList
+        let mut expected = options.clone();
+        expected.push(Ipv4Option::EndOfList);
+        assert_eq!(parse_options(&bytes).unwrap(), expected);
+
+        let others = vec![
+            Ipv4Option::Timestamp {
+                pointer: 5,
+                overflow: 3,
+                flag: TimestampFlag::TimestampOnly,
+                entries: vec![TimestampEntry {
+                    address: None,
+                    timestamp: 1000,
+                }],
+            },
+            Ipv4Option::Timestamp {
+                pointer: 13,
+                overflow: 0,
+                flag: TimestampFlag::AddressAndTimestamp,
+                entries: vec![TimestampEntry {
+                    address: Some(addr(3)),
+                    timestamp: 2000,
+                }],
+            },
+            Ipv4Option::LooseSourceRoute {
+                pointer: 4,
+                route: vec![addr(4)],
+            },
+            Ipv4Option::StrictSourceRoute {
+                pointer: 4,
+                route: Vec::new(),
+            },
+            Ipv4Option::Security {
+                classification: 0xab,
+                protection_authority: vec![0x80],
+            },
+        ];
+        for option in others {
+            let bytes = serialize_options(std::slice::from_ref(&option)).unwrap();
+            assert_eq!(parse_options(&bytes).unwrap()[0], option, "{}", option);
+        }
+    }
+
+    #[test]
+    fn too_long_options_are_rejected() {
+        let route = |count| Ipv4Option::RecordRoute {
+            pointer: 4,
+            route: vec![addr(1); count],
+        };
+        // 3 + 4 * 9 = 39 字节，补齐到 40
+        assert_eq!(serialize_options(&[route(9)]).unwrap().len(), 40);
+        assert_eq!(
+            serialize_options(&[route(9), Ipv4Option::Nop, Ipv4Option::Nop]),
+            Err(Ipv4OptionError::TooLong(41))
+        );
+
+        // 长度字节放不下时不能回绕成一个短选项
+        let huge = Ipv4Option::Unknown {
+            kind: 0x79,
+            data: vec![0; 254],
+        };
+        let mut out = Vec::new();
+        assert_eq!(huge.write_to(&mut out), Err(Ipv4OptionError::TooLong(256)));
+        assert!(out.is_empty());
+        let largest = Ipv4Option::Unknown {
+            kind: 0x79,
+            data: vec![0; 253],
+        };
+        largest.write_to(&mut out).unwrap();
+        assert_eq!(&out[..2], &[0x79, 255]);
+        assert_eq!(
+            serialize_options(&[huge]),
+            Err(Ipv4OptionError::TooLong(256))
+        );
+    }
+
+    #[test]
+    fn unencodable_options_are_rejected() {
+        // EOL / NOP 没有长度字节，写成 TLV 会被重新解析成单字节选项
+        for kind in [KIND_EOL, KIND_NOP] {
+            let option = Ipv4Option::Unknown {
+                kind,
+                data: vec![0x79, 2],
+            };
+            let mut out = Vec::new();
+            assert_eq!(
+                option.write_to(&mut out),
+                Err(Ipv4OptionError::SingleByteKind(kind))
+            );
+            assert!(out.is_empty());
+            assert_eq!(
+                serialize_options(&[option]),
+                Err(Ipv4OptionError::SingleByteKind(kind))
+            );
+        }
+
+        // 溢出计数只有 4 位，16 不能截断成 0 混进 flag
+        let timestamp = |overflow| Ipv4Option::Timestamp {
+            pointer: 5,
+            overflow,
+            flag: TimestampFlag::TimestampOnly,
+            entries: Vec::new(),
+        };
+        let mut out = Vec::new();
+        timestamp(15).write_to(&mut out).unwrap();
+        assert_eq!(out, [KIND_TIMESTAMP, 4, 5, 0xf0]);
+        out.clear();
+        assert_eq!(
+            timestamp(16).write_to(&mut out),
+            Err(Ipv4OptionError::TimestampOverflow(16))
+        );
+        assert!(out.is_empty());
+        assert_eq!(
+            serialize_options(&[Ipv4Option::Nop, timestamp(255)]),
+            Err(Ipv4OptionError::TimestampOverflow(255))
+        );
+    }
+
+    #[test]
+    fn malformed_options_are_rejected() {
+        let truncated = |offset, kind| Ipv4OptionError::Truncated { offset, kind };
+        let bad_length = |offset, kind, length| Ipv4OptionError::BadLength {
+            offset,
+            kind,
+            length,
+        };
+        let cases: &[(&[u8], Ipv4OptionError)] = &[
+            // 只有类型字节
+            (
+                &[KIND_NOP, KIND_ROUTER_ALERT],
+                truncated(1, KIND_ROUTER_ALERT),
+            ),
+            // 长度小于 2 或超出剩余字节
+            (&[0x79, 1, 0, 0], bad_length(0, 0x79, 1)),
+            (&[KIND_NOP, 0x79, 5, 0], bad_length(1, 0x79, 5)),
+            // 路由表不是整数个地址
+            (
+                &[KIND_RECORD_ROUTE, 5, 4, 0, 0],
+                bad_length(0, KIND_RECORD_ROUTE, 5),
+            ),
+            (&[KIND_LSRR, 2], bad_length(0, KIND_LSRR, 2)),
+            // Timestamp 数据不是整数个条目
+            (&[KIND_TIMESTAMP, 3, 5], bad_length(0, KIND_TIMESTAMP, 3)),
+            (
+                &[KIND_TIMESTAMP, 8, 5, 0x01, 0, 0, 0, 0],
+                bad_length(0, KIND_TIMESTAMP, 8),
+            ),
+            // Router Alert / Stream ID 固定 4 字节
+            (
+                &[KIND_ROUTER_ALERT, 3, 0],
+                bad_length(0, KIND_ROUTER_ALERT, 3),
+            ),
+            (
+                &[KIND_STREAM_ID, 6, 0, 0, 0, 0],
+                bad_length(0, KIND_STREAM_ID, 6),
+            ),
+            (&[KIND_SECURITY, 2], bad_length(0, KIND_SECURITY, 2)),
+        ];
+        for (bytes, error) in cases {
+            assert_eq!(parse_options(bytes).as_ref(), Err(error), "{:02x?}", bytes);
+        }
+        // EOL 之后的字节是填充，不再解析
+        assert_eq!(
+            parse_options(&[KIND_EOL, 0x79, 0]).unwrap(),
+            vec![Ipv4Option::EndOfList]
+        );
+    }
+}
@@ -0,0 +1,257 @@
+//! IPv6 目的选项头编解码 (RFC 8200 4.6)
+//!
+//! 只实现携带标记所需的部分：Pad1 / PadN 和按类型保存的其他选项。
+
+use std::fmt;
+
+pub const TYPE_PAD1: u8 = 0;
+pub const TYPE_PADN: u8 = 1;
+/// RFC 4727 实验用选项类型 (动作位 00 = 跳过, 路由中不改变)
+pub const TYPE_EXPERIMENTAL: u8 = 0x1e;
+
+/// IPv6 目的选项 / 逐跳选项中的一项
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Ipv6Option {
+    Pad1,
+    PadN(usize),
+    Other { option_type: u8, data: Vec<u8> },
+}
+
+/// 扩展头最长 (hdr ext len 为 255 时) 的字节数
+pub const MAX_HEADER_LEN: usize = 2048;
+
+/// 选项头编解码错误
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Ipv6OptionError {
+    /// 扩展头或其中的选项被截断
+    Truncated { offset: usize },
+    /// 选项数据超过 255 字节 (PadN 超过 257 字节)
+    OptionTooLong(usize),
+    /// 扩展头超过 `MAX_HEADER_LEN` 字节
+    HeaderTooLong(usize),
+}
+
+impl fmt::Display for Ipv6OptionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Ipv6OptionError::Truncated { offset } => {
+                write!(f, "IPv6 选项头在偏移 {} 处被截断", offset)
+            }
+            Ipv6OptionError::OptionTooLong(len) => {
+                write!(f, "IPv6 选项数据 {} 字节, 超过 255 字节", len)
+            }
+            Ipv6OptionError::HeaderTooLong(len) => {
+                write!(f, "IPv6 选项头 {} 字节, 超过 {} 字节", len, MAX_HEADER_LEN)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Ipv6OptionError {}
+
+/// 序列化目的选项扩展头
+///
+/// # 参数
+/// - `next_header`: 扩展头之后的协议号
+/// - `options`: 选项列表，以 PadN / Pad1 补齐到 8 字节的倍数
+///
+/// # 返回
+/// 完整的扩展头字节 (next header + hdr ext len + 选项)；选项数据超过 255 字节或
+/// 扩展头超过 2048 字节时返回错误
+pub fn serialize_destination_options(
+    next_header: u8,
+    options: &[Ipv6Option],
+) -> Result<Vec<u8>, Ipv6OptionError> {
+    let mut out = vec![next_header, 0];
+    for option in options {
+        write_option(option, &mut out)?;
+    }
+    let padding = (8 - out.len() % 8) % 8;
+    write_option(&Ipv6Option::PadN(padding), &mut out)?;
+    if out.len() > MAX_HEADER_LEN {
+        return Err(Ipv6OptionError::HeaderTooLong(out.len()));
+    }
+    out[1] = (out.len() / 8 - 1) as u8;
+    Ok(out)
+}
+
+/// 写入一个选项；`PadN(1)` 写为 Pad1，`PadN(0)` 不写
+fn write_option(option: &Ipv6Option, out: &mut Vec<u8>) -> Result<(), Ipv6OptionError> {
+    match option {
+        Ipv6Option::Pad1 | Ipv6Option::PadN(1) => out.push(TYPE_PAD1),
+        Ipv6Option::PadN(0) => {}
+        Ipv6Option::PadN(n) => {
+            let len = u8::try_from(n - 2).map_err(|_| Ipv6OptionError::OptionTooLong(n - 2))?;
+            out.push(TYPE_PADN);
+            out.push(len);
+            out.extend(std::iter::repeat_n(0, n - 2));
+        }
+        Ipv6Option::Other { option_type, data } => {
+            let len =
+                u8::try_from(data.len()).map_err(|_| Ipv6OptionError::OptionTooLong(data.len()))?;
+            out.push(*option_type);
+            out.push(len);
+            out.extend_from_slice(data);
+        }
+    }
+    Ok(())
+}
+
+/// 解析目的选项扩展头
+///
+/// # 返回
+/// (next header, 选项列表)
+pub fn parse_destination_options(bytes: &[u8]) -> Result<(u8, Vec<Ipv6Option>), Ipv6OptionError> {
+    if bytes.len() < 2 {
+        return Err(Ipv6OptionError::Truncated { offset: 0 });
+    }
+    let total = (bytes[1] as usize + 1) * 8;
+    if bytes.len() < total {
+        return Err(Ipv6OptionError::Truncated { offset: 0 });
+    }
+
+    let mut options = Vec::new();
+    let mut offset = 2;
+    while offset < total {
+        let option_type = bytes[offset];
+        if option_type == TYPE_PAD1 {
+            options.push(Ipv6Option::Pad1);
+            offset += 1;
+            continue;
+        }
+        if offset + 2 > total {
+            return Err(Ipv6OptionError::Truncated { offset });
+        }
+        let len = bytes[offset + 1] as usize;
+        if offset + 2 + len > total {
+            return Err(Ipv6OptionError::Truncated { offset });
+        }
+        if option_type == TYPE_PADN {
+            options.push(Ipv6Option::PadN(len + 2));
+        } else {
+            options.push(Ipv6Option::Other {
+                option_type,
+                data: bytes[offset + 2..offset + 2 + len].to_vec(),
+            });
+        }
+        offset += 2 + len;
+    }
+
+    Ok((bytes[0], options))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn marker_option() -> Ipv6Option {
+        Ipv6Option::Other {
+            option_type: TYPE_EXPERIMENTAL,
+            data: (1..=16).collect(),
+        }
+    }
+
+    #[test]
+    fn round_trip_pads_to_eight_bytes() {
+        // 2 + 18 字节，补 PadN(4)
+        let bytes = serialize_destination_options(17, &[marker_option()]).unwrap();
+        assert_eq!(bytes.len(), 24);
+        assert_eq!(bytes[..4], [17, 2, TYPE_EXPERIMENTAL, 16]);
+        assert_eq!(bytes[20..], [TYPE_PADN, 2, 0, 0]);
+        assert_eq!(
+            parse_destination_options(&bytes),
+            Ok((17, vec![marker_option(), Ipv6Option::PadN(4)]))
+        );
+
+        // 刚好 8 字节时不补齐
+        let option = Ipv6Option::Other {
+            option_type: 0x3e,
+            data: vec![0xaa; 4],
+        };
+        let bytes = serialize_destination_options(6, std::slice::from_ref(&option)).unwrap();
+        assert_eq!(bytes, [6, 0, 0x3e, 4, 0xaa, 0xaa, 0xaa, 0xaa]);
+        assert_eq!(parse_destination_options(&bytes), Ok((6, vec![option])));
+    }
+
+    #[test]
+    fn short_padding() {
+        // 2 + 2 + 3 字节，差 1 字节时补 Pad1
+        let option = Ipv6Option::Other {
+            option_type: 0x3e,
+            data: vec![0xaa],
+        };
+        let bytes =
+            serialize_destination_options(59, &[Ipv6Option::PadN(2), option.clone()]).unwrap();
+        assert_eq!(bytes, [59, 0, TYPE_PADN, 0, 0x3e, 1, 0xaa, TYPE_PAD1]);
+        assert_eq!(
+            parse_destination_options(&bytes),
+            Ok((59, vec![Ipv6Option::PadN(2), option, Ipv6Option::Pad1]))
+        );
+
+        // PadN(1) 写为 Pad1，PadN(0) 不写
+        let bytes = serialize_destination_options(
+            59,
+            &[
+                Ipv6Option::PadN(0),
+                Ipv6Option::PadN(1),
+                Ipv6Option::PadN(5),
+            ],
+        )
+        .unwrap();
+        assert_eq!(bytes, [59, 0, TYPE_PAD1, TYPE_PADN, 3, 0, 0, 0]);
+        assert_eq!(
+            parse_destination_options(&bytes),
+            Ok((59, vec![Ipv6Option::Pad1, Ipv6Option::PadN(5)]))
+        );
+    }
+
+    #[test]
+    fn oversized_options_and_headers() {
+        let long = Ipv6Option::Other {
+            option_type: 0x3e,
+            data: vec![0; 256],
+        };
+        assert_eq!(
+            serialize_destination_options(59, &[long]),
+            Err(Ipv6OptionError::OptionTooLong(256))
+        );
+        assert_eq!(
+            serialize_destination_options(59, &[Ipv6Option::PadN(258)]),
+            Err(Ipv6OptionError::OptionTooLong(256))
+        );
+        let full = Ipv6Option::Other {
+            option_type: 0x3e,
+            data: vec![0; 255],
+        };
+        let options = vec![full; 8];
+        assert_eq!(
+            serialize_destination_options(59, &options),
+            Err(Ipv6OptionError::HeaderTooLong(2064))
+        );
+        let bytes = serialize_destination_options(59, &options[..7]).unwrap();
+        assert_eq!(bytes.len(), 1808);
+        assert_eq!(parse_destination_options(&bytes).unwrap().1.len(), 8);
+    }
+
+    #[test]
+    fn truncated_headers() {
+        assert_eq!(
+            parse_destination_options(&[59]),
+            Err(Ipv6OptionError::Truncated { offset: 0 })
+        );
+        assert_eq!(
+            parse_destination_options(&[59, 1, 0, 0, 0, 0, 0, 0]),
+            Err(Ipv6OptionError::Truncated { offset: 0 })
+        );
+        // 选项长度超出扩展头
+        assert_eq!(
+            parse_destination_options(&[59, 0, 0x3e, 5, 0, 0, 0, 0]),
+            Err(Ipv6OptionError::Truncated { offset: 2 })
+        );
+        // 最后一个字节只有类型没有长度
+        assert_eq!(
+            parse_destination_options(&[59, 0, TYPE_PADN, 2, 0, 0, TYPE_PAD1, 0x3e]),
+            Err(Ipv6OptionError::Truncated { offset: 7 })
+        );
+    }
+}
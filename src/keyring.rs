@@ -0,0 +1,227 @@
+//! 标记密钥环
+//!
+//! 每个密钥由 1 字节的 key ID 标识。发送端使用当前的活动密钥，接收端接受密钥环
+//! 中的任意 key ID。轮换密钥时先把新密钥加入所有接收端，再切换发送端的活动密钥，
+//! 最后删除旧密钥，期间新旧标记都能通过验证。
+//!
+//! 密钥从环境变量 `BIAOSHI_KEYS` 读取，未设置时读取 `BIAOSHI_KEY_FILE` 指向的文件。
+//! 格式为 `<id>:<十六进制密钥>`，条目之间用逗号或换行分隔，`#` 之后为注释。
+//! 以 `*` 开头的条目为活动密钥，没有时第一个条目为活动密钥。
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::hex;
+
+/// 保存密钥的环境变量名
+pub const KEYS_ENV: &str = "BIAOSHI_KEYS";
+/// 保存密钥文件路径的环境变量名
+pub const KEY_FILE_ENV: &str = "BIAOSHI_KEY_FILE";
+
+/// 密钥 / 标记保护配置错误
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyError {
+    /// 条目不是 `<id>:<hex>` 格式
+    BadEntry(String),
+    /// key ID 不是 0 ~ 255 的整数
+    BadKeyId(String),
+    /// 密钥不是合法的十六进制串或为空
+    BadSecret(u8),
+    /// 同一 key ID 出现多次
+    DuplicateKeyId(u8),
+    /// 多个条目标记为活动密钥
+    MultipleActive,
+    /// 读取密钥文件失败
+    Io(String),
+    /// 不认识的标记保护方式
+    BadProtection(String),
+    /// MAC 截断长度不是 4 ~ 16 的整数
+    BadMacLen(String),
+    /// 保护方式需要活动密钥，但密钥环中没有
+    NoActiveKey(String),
+}
+
+impl fmt::Display for KeyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyError::BadEntry(entry) => write!(f, "密钥条目格式错误: {}", entry),
+            KeyError::BadKeyId(id) => write!(f, "无效的 key ID: {}", id),
+            KeyError::BadSecret(id) => write!(f, "key ID {} 的密钥不是合法的十六进制串", id),
+            KeyError::DuplicateKeyId(id) => write!(f, "重复的 key ID: {}", id),
+            KeyError::MultipleActive => write!(f, "只能有一个活动密钥"),
+            KeyError::Io(e) => write!(f, "读取密钥文件失败: {}", e),
+            KeyError::BadProtection(name) => write!(f, "未知的标记保护方式: {}", name),
+            KeyError::BadMacLen(value) => write!(f, "无效的 MAC 长度: {} (应为 4 ~ 16)", value),
+            KeyError::NoActiveKey(protection) => write!(
+                f,
+                "标记保护方式 {} 需要活动密钥, 请在 BIAOSHI_KEYS 中配置",
+                protection
+            ),
+        }
+    }
+}
+
+impl std::error::Error for KeyError {}
+
+/// 按 key ID 索引的密钥集合
+#[derive(Debug, Clone, Default)]
+pub struct KeyRing {
+    keys: BTreeMap<u8, Vec<u8>>,
+    active: Option<u8>,
+}
+
+impl KeyRing {
+    /// 添加或替换密钥；第一个添加的密钥成为活动密钥
+    pub fn insert(&mut self, key_id: u8, secret: Vec<u8>) {
+        self.keys.insert(key_id, secret);
+        self.active.get_or_insert(key_id);
+    }
+
+    /// 切换发送端使用的活动密钥，key ID 不存在时返回 `false`
+    pub fn set_active(&mut self, key_id: u8) -> bool {
+        if self.keys.contains_key(&key_id) {
+            self.active = Some(key_id);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// 活动密钥 (key ID, 密钥)
+    pub fn active(&self) -> Option<(u8, &[u8])> {
+        let key_id = self.active?;
+        self.get(key_id).map(|secret| (key_id, secret))
+    }
+
+    /// 删除退役的密钥；删除活动密钥后须用 [`KeyRing::set_active`] 另选一个，否则
+    /// 要求保护的发送端无法编码标记
+    pub fn remove(&mut self, key_id: u8) -> Option<Vec<u8>> {
+        if self.active == Some(key_id) {
+            self.active = None;
+        }
+        self.keys.remove(&key_id)
+    }
+
+    pub fn get(&self, key_id: u8) -> Option<&[u8]> {
+        self.keys.get(&key_id).map(Vec::as_slice)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// 所有 key ID，升序
+    pub fn key_ids(&self) -> impl Iterator<Item = u8> + '_ {
+        self.keys.keys().copied()
+    }
+
+    /// 解析密钥配置，空串得到空密钥环
+    pub fn parse(spec: &str) -> Result<Self, KeyError> {
+        let mut ring = KeyRing::default();
+        let mut active = None;
+        let entries = spec
+            .lines()
+            .map(|line| line.split_once('#').map_or(line, |(entry, _)| entry))
+            .flat_map(|line| line.split(','))
+            .map(str::trim)
+            .filter(|e| !e.is_empty());
+        for entry in entries {
+            let (is_active, entry) = match entry.strip_prefix('*') {
+                Some(rest) => (true, rest.trim_start()),
+                None => (false, entry),
+            };
+            let (id, hex) = entry
+                .split_once(':')
+                .ok_or_else(|| KeyError::BadEntry(entry.to_string()))?;
+            let key_id: u8 = id
+                .trim()
+                .parse()
+                .map_err(|_| KeyError::BadKeyId(id.to_string()))?;
+            let secret = hex::decode(hex.trim()).ok_or(KeyError::BadSecret(key_id))?;
+            if ring.keys.contains_key(&key_id) {
+                return Err(KeyError::DuplicateKeyId(key_id));
+            }
+            ring.insert(key_id, secret);
+            if is_active && active.replace(key_id).is_some() {
+                return Err(KeyError::MultipleActive);
+            }
+        }
+        if let Some(key_id) = active {
+            ring.set_active(key_id);
+        }
+        Ok(ring)
+    }
+
+    /// 从 `BIAOSHI_KEYS` 或 `BIAOSHI_KEY_FILE` 读取；都未设置时返回空密钥环
+    pub fn from_env() -> Result<Self, KeyError> {
+        Self::from_lookup(|name| std::env::var(name).ok())
+    }
+
+    /// 同 [`KeyRing::from_env`]，变量值由 `lookup` 提供
+    pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Result<Self, KeyError> {
+        if let Some(spec) = lookup(KEYS_ENV) {
+            return Self::parse(&spec);
+        }
+        match lookup(KEY_FILE_ENV) {
+            Some(path) => {
+                let spec = std::fs::read_to_string(&path)
+                    .map_err(|e| KeyError::Io(format!("{}: {}", path, e)))?;
+                Self::parse(&spec)
+            }
+            None => Ok(KeyRing::default()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_entries_comments_and_active_key() {
+        let ring = KeyRing::parse("1:00ff, *2:0x0102 # 当前\n\n3: aa-bb # 退役\n").unwrap();
+        assert_eq!(ring.key_ids().collect::<Vec<_>>(), vec![1, 2, 3]);
+        assert_eq!(ring.active(), Some((2, &[0x01, 0x02][..])));
+        assert_eq!(ring.get(1), Some(&[0x00, 0xff][..]));
+        assert_eq!(ring.get(3), Some(&[0xaa, 0xbb][..]));
+        assert_eq!(ring.get(4), None);
+
+        // 没有 `*` 时第一个条目为活动密钥
+        let ring = KeyRing::parse("9:01,4:02").unwrap();
+        assert_eq!(ring.active().map(|(id, _)| id), Some(9));
+
+        let ring = KeyRing::parse(" # 只有注释\n").unwrap();
+        assert!(ring.is_empty());
+        assert_eq!(ring.active(), None);
+    }
+
+    #[test]
+    fn parse_errors() {
+        for (spec, error) in [
+            ("1=00", KeyError::BadEntry("1=00".into())),
+            ("256:00", KeyError::BadKeyId("256".into())),
+            ("x:00", KeyError::BadKeyId("x".into())),
+            ("1:0g", KeyError::BadSecret(1)),
+            ("1:", KeyError::BadSecret(1)),
+            ("1:00,1:01", KeyError::DuplicateKeyId(1)),
+            ("*1:00,*2:01", KeyError::MultipleActive),
+        ] {
+            assert_eq!(KeyRing::parse(spec).unwrap_err(), error, "{}", spec);
+        }
+    }
+
+    #[test]
+    fn rotation_keeps_old_keys_until_removed() {
+        let mut ring = KeyRing::default();
+        ring.insert(1, vec![1]);
+        ring.insert(2, vec![2]);
+        assert_eq!(ring.active().map(|(id, _)| id), Some(1));
+        assert!(!ring.set_active(3));
+        assert!(ring.set_active(2));
+        assert_eq!(ring.remove(1), Some(vec![1]));
+        assert_eq!(ring.active(), Some((2, &[2][..])));
+        assert_eq!(ring.remove(2), Some(vec![2]));
+        assert_eq!(ring.active(), None);
+        assert!(ring.is_empty());
+    }
+}
@@ -0,0 +1,200 @@
+//! 单向时延统计
+//!
+//! 用标记中的发送时间和接收端的接收时间计算单向时延，按流汇总
+//! 最小 / 平均 / 最大值和分位数。两端时钟不同步时时延可能为负。
+//! 标记中的时间戳由发送端任意填写，时延绝对值超过
+//! `MAX_PLAUSIBLE_DELAY_US` 的样本直接丢弃，不计入统计。
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::net::IpAddr;
+
+/// 计算分位数时保留的最近样本数
+pub const PERCENTILE_WINDOW: usize = 10_000;
+
+/// 可信时延的绝对值上限 (一天，微秒)，超出的样本视为伪造或时钟错误
+pub const MAX_PLAUSIBLE_DELAY_US: i64 = 86_400 * 1_000_000;
+
+/// 流标识：源地址、目的地址和标记中的 tag
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FlowKey {
+    pub source: IpAddr,
+    pub destination: IpAddr,
+    pub tag: u16,
+}
+
+impl fmt::Display for FlowKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} -> {} tag={}",
+            self.source, self.destination, self.tag
+        )
+    }
+}
+
+/// 单个流的时延统计，单位微秒
+#[derive(Debug, Clone, Default)]
+pub struct LatencyStats {
+    count: u64,
+    sum: i128,
+    min: i64,
+    max: i64,
+    /// 最近 `PERCENTILE_WINDOW` 个样本，用于计算分位数
+    recent: VecDeque<i64>,
+}
+
+impl LatencyStats {
+    pub fn record(&mut self, delay_us: i64) {
+        if self.count == 0 {
+            self.min = delay_us;
+            self.max = delay_us;
+        } else {
+            self.min = self.min.min(delay_us);
+            self.max = self.max.max(delay_us);
+        }
+        self.count += 1;
+        self.sum += delay_us as i128;
+        if self.recent.len() == PERCENTILE_WINDOW {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(delay_us);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn min(&self) -> Option<i64> {
+        (self.count > 0).then_some(self.min)
+    }
+
+    pub fn max(&self) -> Option<i64> {
+        (self.count > 0).then_some(self.max)
+    }
+
+    pub fn mean(&self) -> Option<f64> {
+        (self.count > 0).then(|| self.sum as f64 / self.count as f64)
+    }
+
+    /// 最近窗口内的分位数 (`p` 取 0.0 ~ 1.0，最近邻取值)
+    pub fn percentile(&self, p: f64) -> Option<i64> {
+        if self.recent.is_empty() {
+            return None;
+        }
+        let mut sorted: Vec<i64> = self.recent.iter().copied().collect();
+        sorted.sort_unstable();
+        let rank = (p.clamp(0.0, 1.0) * (sorted.len() - 1) as f64).round() as usize;
+        Some(sorted[rank])
+    }
+}
+
+impl fmt::Display for LatencyStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.count == 0 {
+            return write!(f, "无样本");
+        }
+        write!(
+            f,
+            "n={} min={}us avg={:.1}us max={}us p50={}us p90={}us p99={}us",
+            self.count,
+            self.min,
+            self.mean().unwrap_or_default(),
+            self.max,
+            self.percentile(0.5).unwrap_or_default(),
+            self.percentile(0.9).unwrap_or_default(),
+            self.percentile(0.99).unwrap_or_default(),
+        )
+    }
+}
+
+/// 按流汇总的时延统计
+#[derive(Debug, Clone, Default)]
+pub struct LatencyTracker {
+    flows: HashMap<FlowKey, LatencyStats>,
+}
+
+impl LatencyTracker {
+    /// 记录一个样本
+    ///
+    /// # 参数
+    /// - `sent_us`: 标记中的发送时间 (Unix 微秒)
+    /// - `received_us`: 接收时间 (Unix 微秒)
+    ///
+    /// # 返回
+    /// 本包的单向时延 (微秒)；超出 `MAX_PLAUSIBLE_DELAY_US` 时返回
+    /// `None`，样本不计入统计
+    pub fn record(&mut self, flow: FlowKey, sent_us: u64, received_us: u64) -> Option<i64> {
+        let delay = i64::try_from(received_us as i128 - sent_us as i128)
+            .ok()
+            .filter(|delay| delay.abs() <= MAX_PLAUSIBLE_DELAY_US)?;
+        self.flows.entry(flow).or_default().record(delay);
+        Some(delay)
+    }
+
+    pub fn get(&self, flow: &FlowKey) -> Option<&LatencyStats> {
+        self.flows.get(flow)
+    }
+
+    pub fn flows(&self) -> impl Iterator<Item = (&FlowKey, &LatencyStats)> {
+        self.flows.iter()
+    }
+}
+
+impl fmt::Display for LatencyTracker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (flow, stats) in &self.flows {
+            writeln!(f, "{}: {}", flow, stats)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    fn flow() -> FlowKey {
+        FlowKey {
+            source: Ipv4Addr::new(10, 0, 0, 1).into(),
+            destination: Ipv4Addr::new(10, 0, 0, 2).into(),
+            tag: 7,
+        }
+    }
+
+    #[test]
+    fn records_plausible_delays() {
+        let mut tracker = LatencyTracker::default();
+        assert_eq!(tracker.record(flow(), 1_000, 1_250), Some(250));
+        // 时钟不同步时允许为负
+        assert_eq!(tracker.record(flow(), 1_250, 1_000), Some(-250));
+        let stats = tracker.get(&flow()).unwrap();
+        assert_eq!(
+            (stats.count(), stats.min(), stats.max()),
+            (2, Some(-250), Some(250))
+        );
+    }
+
+    #[test]
+    fn drops_implausible_timestamps() {
+        let mut tracker = LatencyTracker::default();
+        let now = 1_700_000_000_000_000;
+        for sent in [
+            u64::MAX,
+            1 << 63,
+            0,
+            now + MAX_PLAUSIBLE_DELAY_US as u64 + 1,
+        ] {
+            assert_eq!(tracker.record(flow(), sent, now), None, "sent = {}", sent);
+        }
+        assert_eq!(tracker.record(flow(), now, u64::MAX), None);
+        assert!(tracker.get(&flow()).is_none());
+
+        let limit = now - MAX_PLAUSIBLE_DELAY_US as u64;
+        assert_eq!(
+            tracker.record(flow(), limit, now),
+            Some(MAX_PLAUSIBLE_DELAY_US)
+        );
+    }
+}
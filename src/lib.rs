@@ -0,0 +1,39 @@
+//! ip_header 公共库
+//!
+//! 各个发送 / 接收程序共享的数据包构造与解析逻辑。
+
+pub mod auth;
+pub mod base64;
+pub mod bpf;
+pub mod builder;
+pub mod capture;
+pub mod checksum;
+pub mod decode;
+pub mod echo;
+pub mod fragment;
+pub mod hex;
+pub mod ip_id;
+pub mod ipv4_option;
+pub mod ipv6_option;
+pub mod keyring;
+pub mod latency;
+pub mod marker;
+pub mod neighbor;
+pub mod pacing;
+pub mod pcap;
+pub mod reassembly;
+pub mod rewrite;
+pub mod route;
+pub mod sender;
+pub mod sequence;
+pub mod tcp_option;
+pub mod timestamp;
+pub mod validate;
+
+pub use builder::{
+    BuildError, IcmpLayer, Ipv4Layer, PacketBuilder, TcpLayer, TransportLayer, UdpLayer,
+};
+pub use checksum::tcp_checksum;
+pub use ipv4_option::{Ipv4Option, Ipv4OptionError, OptionType};
+pub use marker::{Marker, MarkerCarrier, MarkerError};
+pub use tcp_option::{TcpOption, TcpOptionError};
@@ -0,0 +1,410 @@
+//! "Biaoshi" 标记格式
+//!
+//! 发送端在 IPv4 选项 0x79、TCP 实验选项 253 或 IPv6 目的选项中携带同一份标记，
+//! 接收端用同一套代码解码。线上格式 (网络字节序):
+//!
+//! ```text
+//! v1: version(1) flags(1) tag(2) sequence(4) timestamp(4, Unix 秒)
+//! v2: version(1) flags(1) tag(2) sequence(4) timestamp(8, Unix 微秒)
+//! ```
+//!
+//! 发送端默认发送最新版本，接收端兼容所有旧版本。标记之后可以附带认证数据
+//! (见 [`crate::auth`])，解码时忽略。
+
+use std::fmt;
+
+use crate::ipv4_option::Ipv4Option;
+use crate::ipv6_option::{self, Ipv6Option};
+use crate::tcp_option::{self, TcpOption};
+use crate::timestamp::unix_micros;
+
+/// 当前发送的标记版本
+pub const MARKER_VERSION: u8 = 2;
+/// v1 标记的编码长度
+pub const MARKER_V1_LEN: usize = 12;
+/// v2 标记的编码长度
+pub const MARKER_V2_LEN: usize = 16;
+
+/// flags: 标记后附带 key ID 和截断的 HMAC
+pub const FLAG_AUTHENTICATED: u8 = 0x01;
+/// flags: 标记后附带 key ID 和加密的 tag，明文 tag 字段为 0
+pub const FLAG_ENCRYPTED: u8 = 0x02;
+
+/// 携带标记的 IPv4 选项类型
+pub const IPV4_OPTION_KIND: u8 = 0x79;
+/// 携带标记的 TCP 实验选项类型 (RFC 6994)
+pub const TCP_OPTION_KIND: u8 = tcp_option::KIND_EXPERIMENTAL_1;
+/// 携带标记的 TCP 实验选项 ExID ("BS")
+pub const TCP_OPTION_EXID: u16 = 0x4253;
+/// 携带标记的 IPv6 目的选项类型
+pub const IPV6_OPTION_TYPE: u8 = ipv6_option::TYPE_EXPERIMENTAL;
+
+/// 标记解码错误
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MarkerError {
+    /// 长度不足以容纳该版本的标记
+    Truncated { version: u8, len: usize },
+    /// 不认识的版本号
+    UnsupportedVersion(u8),
+}
+
+impl fmt::Display for MarkerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MarkerError::Truncated { version, len } => {
+                write!(f, "v{} 标记长度不足: {} 字节", version, len)
+            }
+            MarkerError::UnsupportedVersion(version) => write!(f, "不支持的标记版本: {}", version),
+        }
+    }
+}
+
+impl std::error::Error for MarkerError {}
+
+/// Biaoshi 标记
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Marker {
+    pub version: u8,
+    pub flags: u8,
+    /// 发送端 / 租户标识
+    pub tag: u16,
+    pub sequence: u32,
+    /// 发送时间，Unix 微秒 (v1 标记解码时由秒换算)
+    pub timestamp: u64,
+}
+
+impl Marker {
+    /// 以当前时间创建标记
+    pub fn new(tag: u16, sequence: u32) -> Self {
+        Marker {
+            version: MARKER_VERSION,
+            flags: 0,
+            tag,
+            sequence,
+            timestamp: unix_micros(),
+        }
+    }
+
+    /// 按 `version` 编码为线上格式
+    ///
+    /// v1 的时间戳截为秒 (超出 32 位时取最大值)；不认识的版本按当前版本编码，
+    /// 版本字段写 [`MARKER_VERSION`]。
+    pub fn encode(&self) -> Vec<u8> {
+        let version = if self.version == 1 { 1 } else { MARKER_VERSION };
+        let mut out = Vec::with_capacity(MARKER_V2_LEN);
+        out.push(version);
+        out.push(self.flags);
+        out.extend_from_slice(&self.tag.to_be_bytes());
+        out.extend_from_slice(&self.sequence.to_be_bytes());
+        if version == 1 {
+            let seconds = u32::try_from(self.timestamp / 1_000_000).unwrap_or(u32::MAX);
+            out.extend_from_slice(&seconds.to_be_bytes());
+        } else {
+            out.extend_from_slice(&self.timestamp.to_be_bytes());
+        }
+        out
+    }
+
+    /// 从线上格式解码，多余的字节被忽略
+    pub fn decode(bytes: &[u8]) -> Result<Self, MarkerError> {
+        let version = *bytes
+            .first()
+            .ok_or(MarkerError::Truncated { version: 0, len: 0 })?;
+        let len = wire_len(version).ok_or(MarkerError::UnsupportedVersion(version))?;
+        if bytes.len() < len {
+            return Err(MarkerError::Truncated {
+                version,
+                len: bytes.len(),
+            });
+        }
+
+        let timestamp = if version == 1 {
+            u32::from_be_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]) as u64 * 1_000_000
+        } else {
+            u64::from_be_bytes(bytes[8..16].try_into().unwrap())
+        };
+        Ok(Marker {
+            version,
+            flags: bytes[1],
+            tag: u16::from_be_bytes([bytes[2], bytes[3]]),
+            sequence: u32::from_be_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+            timestamp,
+        })
+    }
+}
+
+/// 指定版本标记的编码长度，不认识的版本返回 `None`
+pub fn wire_len(version: u8) -> Option<usize> {
+    match version {
+        1 => Some(MARKER_V1_LEN),
+        2 => Some(MARKER_V2_LEN),
+        _ => None,
+    }
+}
+
+impl fmt::Display for Marker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "v{} tag={} seq={} ts={} flags=0x{:02x}",
+            self.version, self.tag, self.sequence, self.timestamp, self.flags
+        )
+    }
+}
+
+/// 标记的载体：把标记包进某种协议的选项，或从选项列表中取出标记
+pub trait MarkerCarrier {
+    type Option;
+
+    /// 将编码后的标记包装为一个选项
+    fn wrap(&self, encoded: Vec<u8>) -> Self::Option;
+
+    /// 如果该选项携带标记，返回其中的标记字节
+    fn unwrap<'a>(&self, option: &'a Self::Option) -> Option<&'a [u8]>;
+
+    /// 生成携带 `marker` 的选项
+    fn encode(&self, marker: &Marker) -> Self::Option {
+        self.wrap(marker.encode())
+    }
+
+    /// 在选项列表中查找标记选项，返回其中的原始字节 (含认证数据)
+    fn payload<'a>(&self, options: &'a [Self::Option]) -> Option<&'a [u8]> {
+        options.iter().find_map(|option| self.unwrap(option))
+    }
+
+    /// 在选项列表中查找并解码标记；没有标记选项时返回 `None`
+    fn find(&self, options: &[Self::Option]) -> Option<Result<Marker, MarkerError>> {
+        self.payload(options).map(Marker::decode)
+    }
+}
+
+/// 通过 IPv4 选项携带标记
+#[derive(Debug, Clone, Copy)]
+pub struct Ipv4OptionCarrier {
+    pub kind: u8,
+}
+
+impl Default for Ipv4OptionCarrier {
+    fn default() -> Self {
+        Ipv4OptionCarrier {
+            kind: IPV4_OPTION_KIND,
+        }
+    }
+}
+
+impl MarkerCarrier for Ipv4OptionCarrier {
+    type Option = Ipv4Option;
+
+    fn wrap(&self, encoded: Vec<u8>) -> Ipv4Option {
+        Ipv4Option::Unknown {
+            kind: self.kind,
+            data: encoded,
+        }
+    }
+
+    fn unwrap<'a>(&self, option: &'a Ipv4Option) -> Option<&'a [u8]> {
+        match option {
+            Ipv4Option::Unknown { kind, data } if *kind == self.kind => Some(data),
+            _ => None,
+        }
+    }
+}
+
+/// 通过 TCP 实验选项 (kind + ExID) 携带标记
+#[derive(Debug, Clone, Copy)]
+pub struct TcpOptionCarrier {
+    pub kind: u8,
+    pub exid: u16,
+}
+
+impl Default for TcpOptionCarrier {
+    fn default() -> Self {
+        TcpOptionCarrier {
+            kind: TCP_OPTION_KIND,
+            exid: TCP_OPTION_EXID,
+        }
+    }
+}
+
+impl MarkerCarrier for TcpOptionCarrier {
+    type Option = TcpOption;
+
+    fn wrap(&self, encoded: Vec<u8>) -> TcpOption {
+        TcpOption::Experimental {
+            kind: self.kind,
+            exid: self.exid,
+            data: encoded,
+        }
+    }
+
+    fn unwrap<'a>(&self, option: &'a TcpOption) -> Option<&'a [u8]> {
+        match option {
+            TcpOption::Experimental { kind, exid, data }
+                if *kind == self.kind && *exid == self.exid =>
+            {
+                Some(data)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// 通过 IPv6 目的选项携带标记
+#[derive(Debug, Clone, Copy)]
+pub struct Ipv6DestOptionCarrier {
+    pub option_type: u8,
+}
+
+impl Default for Ipv6DestOptionCarrier {
+    fn default() -> Self {
+        Ipv6DestOptionCarrier {
+            option_type: IPV6_OPTION_TYPE,
+        }
+    }
+}
+
+impl MarkerCarrier for Ipv6DestOptionCarrier {
+    type Option = Ipv6Option;
+
+    fn wrap(&self, encoded: Vec<u8>) -> Ipv6Option {
+        Ipv6Option::Other {
+            option_type: self.option_type,
+            data: encoded,
+        }
+    }
+
+    fn unwrap<'a>(&self, option: &'a Ipv6Option) -> Option<&'a [u8]> {
+        match option {
+            Ipv6Option::Other { option_type, data } if *option_type == self.option_type => {
+                Some(data)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v2() -> Marker {
+        Marker {
+            version: 2,
+            flags: FLAG_AUTHENTICATED,
+            tag: 0x0102,
+            sequence: 0x0304_0506,
+            timestamp: 0x0000_0656_1234_5678,
+        }
+    }
+
+    #[test]
+    fn decode_v1_and_v2() {
+        let v1 = [1, 0, 0x00, 0x07, 0, 0, 0, 42, 0x65, 0x00, 0x00, 0x01];
+        assert_eq!(
+            Marker::decode(&v1),
+            Ok(Marker {
+                version: 1,
+                flags: 0,
+                tag: 7,
+                sequence: 42,
+                timestamp: 0x6500_0001 * 1_000_000,
+            })
+        );
+
+        let bytes = [
+            2, 0x01, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0, 0, 0x06, 0x56, 0x12, 0x34, 0x56, 0x78,
+        ];
+        assert_eq!(Marker::decode(&bytes), Ok(v2()));
+        // 认证数据等多余的字节被忽略
+        let mut trailer = bytes.to_vec();
+        trailer.extend_from_slice(&[9; 9]);
+        assert_eq!(Marker::decode(&trailer), Ok(v2()));
+    }
+
+    #[test]
+    fn round_trip_keeps_version() {
+        let marker = v2();
+        let encoded = marker.encode();
+        assert_eq!(encoded.len(), MARKER_V2_LEN);
+        assert_eq!(Marker::decode(&encoded), Ok(marker));
+
+        let v1 = Marker {
+            version: 1,
+            timestamp: 1_700_000_000 * 1_000_000,
+            ..marker
+        };
+        let encoded = v1.encode();
+        assert_eq!(encoded.len(), MARKER_V1_LEN);
+        assert_eq!(encoded[0], 1);
+        assert_eq!(Marker::decode(&encoded), Ok(v1));
+
+        // v1 只能表示整秒
+        let partial = Marker {
+            timestamp: v1.timestamp + 999_999,
+            ..v1
+        };
+        assert_eq!(Marker::decode(&partial.encode()), Ok(v1));
+
+        // 不认识的版本按当前版本编码
+        let unknown = Marker {
+            version: 9,
+            ..marker
+        };
+        assert_eq!(unknown.encode(), marker.encode());
+
+        let fresh = Marker::new(7, 1);
+        assert_eq!(fresh.version, MARKER_VERSION);
+        assert_eq!(Marker::decode(&fresh.encode()), Ok(fresh));
+    }
+
+    #[test]
+    fn decode_errors() {
+        assert_eq!(
+            Marker::decode(&[]),
+            Err(MarkerError::Truncated { version: 0, len: 0 })
+        );
+        for version in [0, 3, 0xff] {
+            assert_eq!(
+                Marker::decode(&[version; 16]),
+                Err(MarkerError::UnsupportedVersion(version))
+            );
+        }
+        let encoded = v2().encode();
+        assert_eq!(
+            Marker::decode(&encoded[..15]),
+            Err(MarkerError::Truncated {
+                version: 2,
+                len: 15
+            })
+        );
+        assert_eq!(
+            Marker::decode(&[1; 11]),
+            Err(MarkerError::Truncated {
+                version: 1,
+                len: 11
+            })
+        );
+    }
+
+    #[test]
+    fn carriers_find_their_option() {
+        let marker = v2();
+        let ip = Ipv4OptionCarrier::default();
+        let options = vec![Ipv4Option::Nop, ip.encode(&marker)];
+        assert_eq!(ip.find(&options), Some(Ok(marker)));
+        assert_eq!(Ipv4OptionCarrier { kind: 0x7a }.find(&options), None);
+
+        let tcp = TcpOptionCarrier::default();
+        let options = vec![TcpOption::Nop, tcp.encode(&marker)];
+        assert_eq!(tcp.find(&options), Some(Ok(marker)));
+        let other_exid = TcpOptionCarrier {
+            exid: 0x1234,
+            ..tcp
+        };
+        assert_eq!(other_exid.find(&options), None);
+
+        let ipv6 = Ipv6DestOptionCarrier::default();
+        let options = vec![Ipv6Option::Pad1, ipv6.encode(&marker)];
+        assert_eq!(ipv6.find(&options), Some(Ok(marker)));
+    }
+}
@@ -0,0 +1,176 @@
+//! 邻居解析
+//!
+//! 二层发送需要下一跳的 MAC 地址：先查内核邻居表 `/proc/net/arp`，查不到时由
+//! [`crate::sender::LinkSender`] 自己发 ARP 请求。这里提供邻居表解析和 ARP 帧的
+//! 构造与解析；广播、组播地址按固定规则映射，不需要解析。
+
+use std::net::Ipv4Addr;
+
+use pnet::packet::Packet;
+use pnet::packet::arp::{ArpHardwareTypes, ArpOperations, ArpPacket, MutableArpPacket};
+use pnet::packet::ethernet::{EtherTypes, EthernetPacket, MutableEthernetPacket};
+use pnet::util::MacAddr;
+
+/// 内核 IPv4 邻居表
+pub const NEIGHBOR_TABLE_PATH: &str = "/proc/net/arp";
+
+/// 邻居表项已解析完成
+const ATF_COM: u32 = 0x02;
+
+const ETHERNET_HEADER_LEN: usize = 14;
+const ARP_PACKET_LEN: usize = 28;
+
+/// 邻居表中已解析的一项
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Neighbor {
+    pub address: Ipv4Addr,
+    pub mac: MacAddr,
+    pub interface: String,
+}
+
+/// 解析 `/proc/net/arp` 的内容，只保留已解析完成的表项
+pub fn parse_neighbor_table(text: &str) -> Vec<Neighbor> {
+    text.lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 6 {
+                return None;
+            }
+            let flags = u32::from_str_radix(fields[2].trim_start_matches("0x"), 16).ok()?;
+            if flags & ATF_COM == 0 {
+                return None;
+            }
+            Some(Neighbor {
+                address: fields[0].parse().ok()?,
+                mac: fields[3].parse().ok()?,
+                interface: fields[5].to_string(),
+            })
+        })
+        .collect()
+}
+
+/// 在内核邻居表中查找 `interface` 上 `address` 的 MAC 地址
+pub fn lookup(address: Ipv4Addr, interface: &str) -> Option<MacAddr> {
+    let text = std::fs::read_to_string(NEIGHBOR_TABLE_PATH).ok()?;
+    parse_neighbor_table(&text)
+        .into_iter()
+        .find(|neighbor| neighbor.address == address && neighbor.interface == interface)
+        .map(|neighbor| neighbor.mac)
+}
+
+/// IPv4 组播地址对应的 MAC 地址 (01:00:5e + 低 23 位)
+pub fn multicast_mac(address: Ipv4Addr) -> MacAddr {
+    let octets = address.octets();
+    MacAddr::new(0x01, 0x00, 0x5e, octets[1] & 0x7f, octets[2], octets[3])
+}
+
+/// 构造询问 `target` 的广播 ARP 请求帧
+pub fn arp_request(source_mac: MacAddr, source: Ipv4Addr, target: Ipv4Addr) -> Vec<u8> {
+    let mut frame = vec![0u8; ETHERNET_HEADER_LEN + ARP_PACKET_LEN];
+    let mut ethernet = MutableEthernetPacket::new(&mut frame).unwrap();
+    ethernet.set_destination(MacAddr::broadcast());
+    ethernet.set_source(source_mac);
+    ethernet.set_ethertype(EtherTypes::Arp);
+
+    let mut arp = MutableArpPacket::new(&mut frame[ETHERNET_HEADER_LEN..]).unwrap();
+    arp.set_hardware_type(ArpHardwareTypes::Ethernet);
+    arp.set_protocol_type(EtherTypes::Ipv4);
+    arp.set_hw_addr_len(6);
+    arp.set_proto_addr_len(4);
+    arp.set_operation(ArpOperations::Request);
+    arp.set_sender_hw_addr(source_mac);
+    arp.set_sender_proto_addr(source);
+    arp.set_target_hw_addr(MacAddr::zero());
+    arp.set_target_proto_addr(target);
+    frame
+}
+
+/// 如果 `frame` 是 `target` 发出的 ARP 应答，返回其 MAC 地址
+pub fn parse_arp_reply(frame: &[u8], target: Ipv4Addr) -> Option<MacAddr> {
+    let ethernet = EthernetPacket::new(frame)?;
+    if ethernet.get_ethertype() != EtherTypes::Arp {
+        return None;
+    }
+    let arp = ArpPacket::new(ethernet.payload())?;
+    (arp.get_operation() == ArpOperations::Reply && arp.get_sender_proto_addr() == target)
+        .then(|| arp.get_sender_hw_addr())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROUTER_MAC: MacAddr = MacAddr(0x52, 0x54, 0x00, 0x12, 0x34, 0x56);
+    const LOCAL_MAC: MacAddr = MacAddr(0x02, 0x00, 0x00, 0x00, 0x00, 0x01);
+    const LOCAL: Ipv4Addr = Ipv4Addr::new(192, 168, 1, 20);
+    const ROUTER: Ipv4Addr = Ipv4Addr::new(192, 168, 1, 1);
+
+    /// `sender` 回给本机的 ARP 应答
+    fn reply(sender_mac: MacAddr, sender: Ipv4Addr) -> Vec<u8> {
+        let mut frame = arp_request(sender_mac, sender, LOCAL);
+        let mut arp = MutableArpPacket::new(&mut frame[ETHERNET_HEADER_LEN..]).unwrap();
+        arp.set_operation(ArpOperations::Reply);
+        arp.set_target_hw_addr(LOCAL_MAC);
+        frame
+    }
+
+    #[test]
+    fn neighbor_table_keeps_complete_entries() {
+        let text = "IP address       HW type     Flags       HW address            Mask     Device\n\
+                    192.168.1.1      0x1         0x2         52:54:00:12:34:56     *        eth0\n\
+                    192.168.1.7      0x1         0x0         00:00:00:00:00:00     *        eth0\n\
+                    10.0.0.9         0x1         0x6         02:00:00:aa:bb:cc     *        eth1\n\
+                    192.168.1.8      0x1         0x2         not-a-mac             *        eth0\n\
+                    192.168.1.9      0x1         0x2\n";
+        assert_eq!(
+            parse_neighbor_table(text),
+            vec![
+                Neighbor {
+                    address: ROUTER,
+                    mac: ROUTER_MAC,
+                    interface: "eth0".to_string(),
+                },
+                Neighbor {
+                    address: Ipv4Addr::new(10, 0, 0, 9),
+                    mac: MacAddr::new(0x02, 0, 0, 0xaa, 0xbb, 0xcc),
+                    interface: "eth1".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn arp_reply_must_come_from_target() {
+        assert_eq!(
+            parse_arp_reply(&reply(ROUTER_MAC, ROUTER), ROUTER),
+            Some(ROUTER_MAC)
+        );
+        let other = Ipv4Addr::new(192, 168, 1, 66);
+        assert_eq!(parse_arp_reply(&reply(ROUTER_MAC, other), ROUTER), None);
+        // 请求不是应答
+        assert_eq!(
+            parse_arp_reply(&arp_request(ROUTER_MAC, ROUTER, LOCAL), ROUTER),
+            None
+        );
+        let mut ipv4 = reply(ROUTER_MAC, ROUTER);
+        ipv4[12..14].copy_from_slice(&[0x08, 0x00]);
+        assert_eq!(parse_arp_reply(&ipv4, ROUTER), None);
+        assert_eq!(parse_arp_reply(&ipv4[..10], ROUTER), None);
+    }
+
+    #[test]
+    fn request_asks_for_target() {
+        let frame = arp_request(LOCAL_MAC, LOCAL, ROUTER);
+        let ethernet = EthernetPacket::new(&frame).unwrap();
+        assert_eq!(ethernet.get_destination(), MacAddr::broadcast());
+        let arp = ArpPacket::new(ethernet.payload()).unwrap();
+        assert_eq!(arp.get_operation(), ArpOperations::Request);
+        assert_eq!(arp.get_target_proto_addr(), ROUTER);
+        assert_eq!(arp.get_sender_hw_addr(), LOCAL_MAC);
+        assert_eq!(
+            multicast_mac(Ipv4Addr::new(239, 129, 2, 3)),
+            MacAddr::new(0x01, 0x00, 0x5e, 0x01, 0x02, 0x03)
+        );
+    }
+}
@@ -0,0 +1,274 @@
+//! 发送速率控制
+//!
+//! [`Pacer`] 按目标包速率或比特速率安排每个突发的发送时刻，时刻从起点累计计算，
+//! 单次睡眠误差不会累积。[`SendStats`] 汇总实际达到的速率和发送错误。
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::ip_id::fill_random;
+use crate::timestamp::unix_micros;
+
+/// 剩余时间小于该值时改为忙等，避免睡眠唤醒延迟
+const SPIN_THRESHOLD: Duration = Duration::from_micros(200);
+
+/// 单个包允许的最长发送间隔，也是抖动范围的上限
+pub const MAX_INTERVAL: Duration = Duration::from_secs(24 * 3600);
+
+/// 目标速率
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Rate {
+    /// 每秒包数
+    Packets(f64),
+    /// 每秒比特数 (按 IP 包长计算)
+    Bits(f64),
+}
+
+impl Rate {
+    /// 发送一个 `len` 字节的包占用的时间，超出 `Duration` 范围时取最大值
+    fn cost(&self, len: usize) -> Duration {
+        let seconds = match *self {
+            Rate::Packets(pps) => 1.0 / pps,
+            Rate::Bits(bps) => (len * 8) as f64 / bps,
+        };
+        Duration::try_from_secs_f64(seconds).unwrap_or(Duration::MAX)
+    }
+
+    /// 发送最大的 IPv4 包 (65535 字节) 的间隔是否不超过 `MAX_INTERVAL`
+    pub fn is_plausible(&self) -> bool {
+        self.cost(u16::MAX as usize) <= MAX_INTERVAL
+    }
+}
+
+impl fmt::Display for Rate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rate::Packets(pps) => write!(f, "{} pps", pps),
+            Rate::Bits(bps) => write!(f, "{} bps", bps),
+        }
+    }
+}
+
+/// 发送节奏控制
+///
+/// 每个突发开始前调用 [`Pacer::wait`]，每发出一个包后调用 [`Pacer::sent`]。
+/// 突发内的包连续发送，突发的总时长计入下一个突发的等待。
+#[derive(Debug, Clone)]
+pub struct Pacer {
+    rate: Option<Rate>,
+    burst: u32,
+    jitter: Duration,
+    start: Instant,
+    /// 下一个突发相对起点的计划时刻
+    due: Duration,
+    /// 当前突发已发送的包数
+    in_burst: u32,
+    rng: u64,
+}
+
+impl Pacer {
+    /// # 参数
+    /// - `rate`: 目标速率，`None` 表示不限速
+    /// - `burst`: 每个突发的包数，0 按 1 处理
+    /// - `jitter`: 每个突发的发送时刻在 ±`jitter` 内随机偏移
+    pub fn new(rate: Option<Rate>, burst: u32, jitter: Duration) -> Self {
+        let mut seed = [0u8; 8];
+        // 随机数只用于抖动，取不到时用时间作种子即可
+        let rng = match fill_random(&mut seed) {
+            Ok(()) => u64::from_ne_bytes(seed),
+            Err(_) => unix_micros(),
+        };
+        Pacer {
+            rate,
+            burst: burst.max(1),
+            jitter,
+            start: Instant::now(),
+            due: Duration::ZERO,
+            in_burst: 0,
+            rng: rng | 1,
+        }
+    }
+
+    /// 起点到现在的时间
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    /// 已发送的包按目标速率应占的时间与实际用时中的较大者
+    ///
+    /// 最后一个包发出后还要占满它的时间片，用它计算实际速率和判断时长才准确。
+    pub fn span(&self) -> Duration {
+        self.elapsed().max(self.due)
+    }
+
+    /// 新突发开始前等待到计划时刻，突发中途直接返回
+    pub fn wait(&mut self) {
+        if self.rate.is_none() || self.in_burst > 0 {
+            return;
+        }
+        let offset = self.jitter_offset();
+        let target = if offset >= 0 {
+            self.due + Duration::from_nanos(offset as u64)
+        } else {
+            self.due
+                .saturating_sub(Duration::from_nanos(offset.unsigned_abs()))
+        };
+        sleep_until(self.start + target);
+    }
+
+    /// 记录发出了一个 `len` 字节的包
+    pub fn sent(&mut self, len: usize) {
+        if let Some(rate) = self.rate {
+            self.due = self.due.saturating_add(rate.cost(len));
+        }
+        self.in_burst += 1;
+        if self.in_burst >= self.burst {
+            self.in_burst = 0;
+        }
+    }
+
+    /// ±jitter 内均匀分布的偏移 (纳秒)
+    fn jitter_offset(&mut self) -> i64 {
+        // 截到 i64 一半以内，下面的 2 * span + 1 和取负都不会溢出
+        let span = self.jitter.as_nanos().min(i64::MAX as u128 / 2) as u64;
+        if span == 0 {
+            return 0;
+        }
+        // xorshift64
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        (self.rng % (2 * span + 1)) as i64 - span as i64
+    }
+}
+
+/// 睡眠到 `deadline`，最后一小段忙等以减小唤醒延迟
+pub fn sleep_until(deadline: Instant) {
+    loop {
+        let now = Instant::now();
+        if now >= deadline {
+            break;
+        }
+        let remaining = deadline - now;
+        if remaining > SPIN_THRESHOLD {
+            thread::sleep(remaining - SPIN_THRESHOLD);
+        } else {
+            std::hint::spin_loop();
+        }
+    }
+}
+
+/// 发送统计
+#[derive(Debug, Clone, Default)]
+pub struct SendStats {
+    pub packets: u64,
+    pub bytes: u64,
+    /// 按错误信息计数的发送失败
+    pub errors: BTreeMap<String, u64>,
+    pub elapsed: Duration,
+}
+
+impl SendStats {
+    pub fn record(&mut self, result: &io::Result<usize>) {
+        match result {
+            Ok(len) => {
+                self.packets += 1;
+                self.bytes += *len as u64;
+            }
+            Err(e) => *self.errors.entry(e.to_string()).or_default() += 1,
+        }
+    }
+
+    pub fn error_count(&self) -> u64 {
+        self.errors.values().sum()
+    }
+
+    /// 实际包速率
+    pub fn pps(&self) -> f64 {
+        per_second(self.packets as f64, self.elapsed)
+    }
+
+    /// 实际比特速率
+    pub fn bps(&self) -> f64 {
+        per_second((self.bytes * 8) as f64, self.elapsed)
+    }
+}
+
+fn per_second(amount: f64, elapsed: Duration) -> f64 {
+    if elapsed.is_zero() {
+        return 0.0;
+    }
+    amount / elapsed.as_secs_f64()
+}
+
+impl fmt::Display for SendStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "发送 {} 包 / {} 字节, 失败 {} 次, 用时 {:.3}s, 实际 {:.1} pps / {:.0} bps",
+            self.packets,
+            self.bytes,
+            self.error_count(),
+            self.elapsed.as_secs_f64(),
+            self.pps(),
+            self.bps(),
+        )?;
+        for (error, count) in &self.errors {
+            write!(f, "\n  {} x{}", error, count)?;
+        }
+        Ok(())
+    }
+}
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_interrupt(_signal: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::Relaxed);
+}
+
+/// 捕获 SIGINT，之后用 [`interrupted`] 查询是否收到，便于循环结束后输出汇总
+pub fn catch_interrupt() {
+    let handler = on_interrupt as extern "C" fn(libc::c_int);
+    unsafe { libc::signal(libc::SIGINT, handler as libc::sighandler_t) };
+}
+
+/// 是否收到过 SIGINT
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::Relaxed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tiny_rates_are_implausible() {
+        assert!(Rate::Packets(1.0).is_plausible());
+        assert!(Rate::Bits(1e6).is_plausible());
+        for rate in [
+            Rate::Packets(1e-320),
+            Rate::Packets(1e-6),
+            Rate::Bits(1e-12),
+        ] {
+            assert!(!rate.is_plausible(), "{}", rate);
+        }
+        assert_eq!(Rate::Packets(1e-320).cost(0), Duration::MAX);
+    }
+
+    #[test]
+    fn jitter_stays_in_range() {
+        let jitter = Duration::from_micros(50);
+        let mut pacer = Pacer::new(Some(Rate::Packets(1.0)), 1, jitter);
+        for _ in 0..1000 {
+            assert!(pacer.jitter_offset().unsigned_abs() <= jitter.as_nanos() as u64);
+        }
+        let mut pacer = Pacer::new(Some(Rate::Packets(1.0)), 1, Duration::MAX);
+        for _ in 0..1000 {
+            pacer.jitter_offset();
+        }
+    }
+}
@@ -0,0 +1,995 @@
+//! pcap / pcapng 抓包文件读写
+//!
+//! [`PcapReader`] 按文件开头的魔数识别经典 pcap (微秒或纳秒时间戳、任意字节序)
+//! 和 pcapng，逐条返回 [`PcapRecord`]。pcapng 中每个接口可以有不同的链路类型和
+//! 时间戳精度，记录中带上各自的链路类型，[`ipv4_offset`] 据此找到 IPv4 首部。
+//!
+//! [`PcapWriter`] 以本机字节序、微秒精度写出 pcap 或 pcapng；pcapng 为每个接口名
+//! 写一个接口描述块，数据包可以带注释。[`RotatingWriter`] 在其上按大小或时间切换
+//! 文件。
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// BSD loopback，4 字节主机字节序的地址族
+pub const LINKTYPE_NULL: u16 = 0;
+pub const LINKTYPE_ETHERNET: u16 = 1;
+/// 没有链路层首部，直接从 IP 首部开始
+pub const LINKTYPE_RAW: u16 = 101;
+/// OpenBSD loopback，4 字节网络字节序的地址族
+pub const LINKTYPE_LOOP: u16 = 108;
+/// Linux cooked capture v1 (`any` 接口)
+pub const LINKTYPE_LINUX_SLL: u16 = 113;
+pub const LINKTYPE_IPV4: u16 = 228;
+/// Linux cooked capture v2
+pub const LINKTYPE_LINUX_SLL2: u16 = 276;
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_VLAN: u16 = 0x8100;
+const ETHERTYPE_QINQ: u16 = 0x88a8;
+
+const PCAP_MAGIC_MICROS: u32 = 0xa1b2_c3d4;
+const PCAP_MAGIC_NANOS: u32 = 0xa1b2_3c4d;
+const PCAPNG_SECTION_HEADER: u32 = 0x0a0d_0d0a;
+const PCAPNG_BYTE_ORDER_MAGIC: u32 = 0x1a2b_3c4d;
+const PCAPNG_INTERFACE_DESCRIPTION: u32 = 1;
+const PCAPNG_PACKET: u32 = 2;
+const PCAPNG_SIMPLE_PACKET: u32 = 3;
+const PCAPNG_ENHANCED_PACKET: u32 = 6;
+/// 数据包块的注释选项
+const PCAPNG_OPTION_COMMENT: u16 = 1;
+/// 接口描述块中的接口名选项
+const PCAPNG_OPTION_IF_NAME: u16 = 2;
+/// 接口描述块中的时间戳精度选项
+const PCAPNG_OPTION_TSRESOL: u16 = 9;
+
+/// 写出文件的抓包长度上限
+const SNAPLEN: u32 = 65535;
+
+/// 单条记录或块的长度上限，防止损坏的文件导致巨大的内存分配
+const MAX_RECORD_LEN: usize = 256 * 1024;
+
+/// 抓包文件读取错误
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PcapError {
+    Io(String),
+    /// 开头既不是 pcap 也不是 pcapng 的魔数
+    UnknownFormat(u32),
+    /// 文件在记录中间结束
+    Truncated,
+    /// 记录或块的长度字段不合法
+    BadLength(usize),
+    /// pcapng 的数据包引用了未声明的接口
+    UnknownInterface(u32),
+    /// pcapng 接口的时间戳精度不合法或细于 1 纳秒
+    BadResolution(u8),
+}
+
+impl fmt::Display for PcapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PcapError::Io(e) => write!(f, "读取抓包文件失败: {}", e),
+            PcapError::UnknownFormat(magic) => {
+                write!(f, "不是 pcap / pcapng 文件 (魔数 0x{:08x})", magic)
+            }
+            PcapError::Truncated => write!(f, "抓包文件被截断"),
+            PcapError::BadLength(len) => write!(f, "记录长度 {} 不合法", len),
+            PcapError::UnknownInterface(id) => write!(f, "未声明的接口 {}", id),
+            PcapError::BadResolution(value) => {
+                write!(f, "不支持的时间戳精度 0x{:02x}", value)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PcapError {}
+
+impl From<io::Error> for PcapError {
+    fn from(e: io::Error) -> Self {
+        if e.kind() == io::ErrorKind::UnexpectedEof {
+            PcapError::Truncated
+        } else {
+            PcapError::Io(e.to_string())
+        }
+    }
+}
+
+/// 一条抓包记录
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PcapRecord {
+    pub link_type: u16,
+    /// 抓包时间 (自 Unix 纪元)
+    pub timestamp: Duration,
+    /// 链路上的原始长度，大于 `data.len()` 表示抓包时被截断
+    pub original_len: usize,
+    pub data: Vec<u8>,
+}
+
+impl PcapRecord {
+    /// 抓包时是否被截断
+    pub fn is_truncated(&self) -> bool {
+        self.original_len > self.data.len()
+    }
+}
+
+/// pcapng 时间戳精度：每秒 `per_second` 个单位
+#[derive(Debug, Clone, Copy)]
+struct Resolution {
+    per_second: u64,
+}
+
+impl Resolution {
+    const MICROS: Resolution = Resolution {
+        per_second: 1_000_000,
+    };
+
+    /// 解析 `if_tsresol` 选项：最高位为 1 时是 2 的负幂，否则是 10 的负幂；
+    /// 细于 1 纳秒的精度返回 `None`
+    fn from_option(value: u8) -> Option<Self> {
+        let base: u64 = if value & 0x80 != 0 { 2 } else { 10 };
+        base.checked_pow((value & 0x7f) as u32)
+            .filter(|&per_second| per_second <= 1_000_000_000)
+            .map(|per_second| Resolution { per_second })
+    }
+
+    fn to_duration(self, units: u64) -> Duration {
+        let per_second = self.per_second as u128;
+        let units = units as u128;
+        let secs = units / per_second;
+        let nanos = (units % per_second) * 1_000_000_000 / per_second;
+        Duration::new(secs as u64, nanos as u32)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Interface {
+    link_type: u16,
+    resolution: Resolution,
+}
+
+#[derive(Debug)]
+enum Format {
+    Pcap {
+        big_endian: bool,
+        nanos: bool,
+        link_type: u16,
+    },
+    Pcapng {
+        big_endian: bool,
+        interfaces: Vec<Interface>,
+    },
+}
+
+/// pcap / pcapng 读取器
+#[derive(Debug)]
+pub struct PcapReader<R> {
+    reader: R,
+    format: Format,
+}
+
+impl PcapReader<BufReader<File>> {
+    /// 打开抓包文件
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, PcapError> {
+        let file = File::open(path).map_err(|e| PcapError::Io(e.to_string()))?;
+        PcapReader::new(BufReader::new(file))
+    }
+}
+
+impl<R: Read> PcapReader<R> {
+    /// 读取文件头并识别格式
+    pub fn new(mut reader: R) -> Result<Self, PcapError> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        let format = match (u32::from_le_bytes(magic), u32::from_be_bytes(magic)) {
+            (PCAPNG_SECTION_HEADER, _) => {
+                let big_endian = read_section_header(&mut reader)?;
+                Format::Pcapng {
+                    big_endian,
+                    interfaces: Vec::new(),
+                }
+            }
+            (le, be)
+                if [le, be].contains(&PCAP_MAGIC_MICROS)
+                    || [le, be].contains(&PCAP_MAGIC_NANOS) =>
+            {
+                let big_endian = be == PCAP_MAGIC_MICROS || be == PCAP_MAGIC_NANOS;
+                let magic = if big_endian { be } else { le };
+                let mut header = [0u8; 20];
+                reader.read_exact(&mut header)?;
+                Format::Pcap {
+                    big_endian,
+                    nanos: magic == PCAP_MAGIC_NANOS,
+                    // 高位可能带有 FCS 信息
+                    link_type: read_u32(&header, 16, big_endian) as u16,
+                }
+            }
+            (le, _) => return Err(PcapError::UnknownFormat(le)),
+        };
+        Ok(PcapReader { reader, format })
+    }
+
+    /// 经典 pcap 文件的链路类型；pcapng 的链路类型随接口而定，返回 `None`
+    pub fn link_type(&self) -> Option<u16> {
+        match self.format {
+            Format::Pcap { link_type, .. } => Some(link_type),
+            Format::Pcapng { .. } => None,
+        }
+    }
+
+    /// 读取下一条记录，文件结束时返回 `None`
+    pub fn next_record(&mut self) -> Result<Option<PcapRecord>, PcapError> {
+        match self.format {
+            Format::Pcap {
+                big_endian,
+                nanos,
+                link_type,
+            } => {
+                let mut header = [0u8; 16];
+                if !read_or_eof(&mut self.reader, &mut header)? {
+                    return Ok(None);
+                }
+                let secs = read_u32(&header, 0, big_endian) as u64;
+                let fraction = read_u32(&header, 4, big_endian) as u64;
+                let captured = read_u32(&header, 8, big_endian) as usize;
+                let original_len = read_u32(&header, 12, big_endian) as usize;
+                if captured > MAX_RECORD_LEN {
+                    return Err(PcapError::BadLength(captured));
+                }
+                let mut data = vec![0u8; captured];
+                self.reader.read_exact(&mut data)?;
+                let nanos = if nanos { fraction } else { fraction * 1000 };
+                Ok(Some(PcapRecord {
+                    link_type,
+                    timestamp: Duration::new(secs, 0) + Duration::from_nanos(nanos),
+                    original_len,
+                    data,
+                }))
+            }
+            Format::Pcapng { .. } => self.next_pcapng_record(),
+        }
+    }
+
+    /// 逐块读取 pcapng，跳过不含数据包的块
+    fn next_pcapng_record(&mut self) -> Result<Option<PcapRecord>, PcapError> {
+        loop {
+            let mut head = [0u8; 8];
+            if !read_or_eof(&mut self.reader, &mut head)? {
+                return Ok(None);
+            }
+            let Format::Pcapng {
+                big_endian,
+                interfaces,
+            } = &mut self.format
+            else {
+                unreachable!("只在 pcapng 格式下调用");
+            };
+
+            // 新的节重新确定字节序，接口编号从 0 开始
+            if u32::from_le_bytes(head[..4].try_into().unwrap()) == PCAPNG_SECTION_HEADER {
+                let mut chained = io::Cursor::new(head[4..].to_vec()).chain(&mut self.reader);
+                *big_endian = read_section_header(&mut chained)?;
+                interfaces.clear();
+                continue;
+            }
+
+            let block_type = read_u32(&head, 0, *big_endian);
+            let block_len = read_u32(&head, 4, *big_endian) as usize;
+            if block_len < 12 || !block_len.is_multiple_of(4) || block_len > MAX_RECORD_LEN {
+                return Err(PcapError::BadLength(block_len));
+            }
+            let mut body = vec![0u8; block_len - 8];
+            self.reader.read_exact(&mut body)?;
+            let body = &body[..body.len() - 4];
+            let be = *big_endian;
+
+            match block_type {
+                PCAPNG_INTERFACE_DESCRIPTION => {
+                    if body.len() < 8 {
+                        return Err(PcapError::BadLength(block_len));
+                    }
+                    let mut resolution = Resolution::MICROS;
+                    for (code, value) in options(&body[8..], be) {
+                        if code == PCAPNG_OPTION_TSRESOL && !value.is_empty() {
+                            resolution = Resolution::from_option(value[0])
+                                .ok_or(PcapError::BadResolution(value[0]))?;
+                        }
+                    }
+                    interfaces.push(Interface {
+                        link_type: read_u16(body, 0, be),
+                        resolution,
+                    });
+                }
+                PCAPNG_ENHANCED_PACKET | PCAPNG_PACKET => {
+                    if body.len() < 20 {
+                        return Err(PcapError::BadLength(block_len));
+                    }
+                    let interface_id = if block_type == PCAPNG_PACKET {
+                        read_u16(body, 0, be) as u32
+                    } else {
+                        read_u32(body, 0, be)
+                    };
+                    let interface = *interfaces
+                        .get(interface_id as usize)
+                        .ok_or(PcapError::UnknownInterface(interface_id))?;
+                    let units =
+                        ((read_u32(body, 4, be) as u64) << 32) | read_u32(body, 8, be) as u64;
+                    let captured = read_u32(body, 12, be) as usize;
+                    let original_len = read_u32(body, 16, be) as usize;
+                    let data = body
+                        .get(20..20 + captured)
+                        .ok_or(PcapError::BadLength(captured))?;
+                    return Ok(Some(PcapRecord {
+                        link_type: interface.link_type,
+                        timestamp: interface.resolution.to_duration(units),
+                        original_len,
+                        data: data.to_vec(),
+                    }));
+                }
+                PCAPNG_SIMPLE_PACKET => {
+                    if body.len() < 4 {
+                        return Err(PcapError::BadLength(block_len));
+                    }
+                    let interface = *interfaces.first().ok_or(PcapError::UnknownInterface(0))?;
+                    let original_len = read_u32(body, 0, be) as usize;
+                    let data = &body[4..(4 + original_len).min(body.len())];
+                    // 简单数据包块没有时间戳
+                    return Ok(Some(PcapRecord {
+                        link_type: interface.link_type,
+                        timestamp: Duration::ZERO,
+                        original_len,
+                        data: data.to_vec(),
+                    }));
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+impl<R: Read> Iterator for PcapReader<R> {
+    type Item = Result<PcapRecord, PcapError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_record().transpose()
+    }
+}
+
+/// 写出的文件格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PcapFormat {
+    Pcap,
+    /// 支持每包注释和多个接口
+    Pcapng,
+}
+
+impl PcapFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "pcap" => Some(PcapFormat::Pcap),
+            "pcapng" => Some(PcapFormat::Pcapng),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PcapFormat::Pcap => "pcap",
+            PcapFormat::Pcapng => "pcapng",
+        }
+    }
+
+    /// 按扩展名选择格式：`.pcap` 为 pcap，其他为 pcapng
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("pcap") => PcapFormat::Pcap,
+            _ => PcapFormat::Pcapng,
+        }
+    }
+}
+
+/// pcap / pcapng 写入器
+///
+/// 所有数据包使用同一链路类型，时间戳为微秒。经典 pcap 没有接口和注释，写入时
+/// 忽略这两项。
+#[derive(Debug)]
+pub struct PcapWriter<W: Write> {
+    writer: W,
+    format: PcapFormat,
+    link_type: u16,
+    /// pcapng 中已写出接口描述块的接口名，下标即接口编号
+    interfaces: Vec<String>,
+    bytes: u64,
+}
+
+impl PcapWriter<BufWriter<File>> {
+    /// 创建 (或截断) 抓包文件并写入文件头
+    pub fn create(path: impl AsRef<Path>, format: PcapFormat, link_type: u16) -> io::Result<Self> {
+        PcapWriter::new(BufWriter::new(File::create(path)?), format, link_type)
+    }
+}
+
+impl<W: Write> PcapWriter<W> {
+    /// 写入文件头 (pcap) 或节头块 (pcapng)
+    pub fn new(writer: W, format: PcapFormat, link_type: u16) -> io::Result<Self> {
+        let mut pcap = PcapWriter {
+            writer,
+            format,
+            link_type,
+            interfaces: Vec::new(),
+            bytes: 0,
+        };
+        let mut header = Vec::with_capacity(24);
+        match format {
+            PcapFormat::Pcap => {
+                header.extend_from_slice(&PCAP_MAGIC_MICROS.to_ne_bytes());
+                header.extend_from_slice(&2u16.to_ne_bytes());
+                header.extend_from_slice(&4u16.to_ne_bytes());
+                // 时区和时间戳精度字段，总是 0
+                header.extend_from_slice(&[0; 8]);
+                header.extend_from_slice(&SNAPLEN.to_ne_bytes());
+                header.extend_from_slice(&(link_type as u32).to_ne_bytes());
+            }
+            PcapFormat::Pcapng => {
+                header.extend_from_slice(&PCAPNG_BYTE_ORDER_MAGIC.to_ne_bytes());
+                header.extend_from_slice(&1u16.to_ne_bytes());
+                header.extend_from_slice(&0u16.to_ne_bytes());
+                // 节长度未知
+                header.extend_from_slice(&(-1i64).to_ne_bytes());
+                header = block(PCAPNG_SECTION_HEADER, &header);
+            }
+        }
+        pcap.emit(&header)?;
+        Ok(pcap)
+    }
+
+    pub fn format(&self) -> PcapFormat {
+        self.format
+    }
+
+    /// 已写出的字节数，含文件头
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes
+    }
+
+    /// 写入一个数据包
+    ///
+    /// # 参数
+    /// - `timestamp`: 抓包时间 (自 Unix 纪元)
+    /// - `interface`: 收到数据包的接口名，pcapng 首次出现时写出接口描述块
+    /// - `data`: 链路层帧，超过 65535 字节的部分被截断
+    /// - `comment`: pcapng 数据包注释
+    pub fn write_packet(
+        &mut self,
+        timestamp: Duration,
+        interface: &str,
+        data: &[u8],
+        comment: Option<&str>,
+    ) -> io::Result<()> {
+        let captured = &data[..data.len().min(SNAPLEN as usize)];
+        let micros = timestamp.as_micros() as u64;
+        let mut record = Vec::with_capacity(captured.len() + 64);
+        match self.format {
+            PcapFormat::Pcap => {
+                record.extend_from_slice(&(timestamp.as_secs() as u32).to_ne_bytes());
+                record.extend_from_slice(&timestamp.subsec_micros().to_ne_bytes());
+                record.extend_from_slice(&(captured.len() as u32).to_ne_bytes());
+                record.extend_from_slice(&(data.len() as u32).to_ne_bytes());
+                record.extend_from_slice(captured);
+            }
+            PcapFormat::Pcapng => {
+                let interface_id = self.interface_id(interface)?;
+                record.extend_from_slice(&interface_id.to_ne_bytes());
+                record.extend_from_slice(&((micros >> 32) as u32).to_ne_bytes());
+                record.extend_from_slice(&(micros as u32).to_ne_bytes());
+                record.extend_from_slice(&(captured.len() as u32).to_ne_bytes());
+                record.extend_from_slice(&(data.len() as u32).to_ne_bytes());
+                record.extend_from_slice(captured);
+                record.resize(record.len().next_multiple_of(4), 0);
+                if let Some(comment) = comment {
+                    push_option(&mut record, PCAPNG_OPTION_COMMENT, comment.as_bytes());
+                    push_option(&mut record, 0, &[]);
+                }
+                record = block(PCAPNG_ENHANCED_PACKET, &record);
+            }
+        }
+        self.emit(&record)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    /// 接口编号；新接口先写出接口描述块
+    fn interface_id(&mut self, name: &str) -> io::Result<u32> {
+        if let Some(id) = self.interfaces.iter().position(|known| known == name) {
+            return Ok(id as u32);
+        }
+        let mut body = Vec::new();
+        body.extend_from_slice(&self.link_type.to_ne_bytes());
+        body.extend_from_slice(&0u16.to_ne_bytes());
+        body.extend_from_slice(&SNAPLEN.to_ne_bytes());
+        push_option(&mut body, PCAPNG_OPTION_IF_NAME, name.as_bytes());
+        push_option(&mut body, 0, &[]);
+        self.emit(&block(PCAPNG_INTERFACE_DESCRIPTION, &body))?;
+        self.interfaces.push(name.to_string());
+        Ok(self.interfaces.len() as u32 - 1)
+    }
+
+    fn emit(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.writer.write_all(bytes)?;
+        self.bytes += bytes.len() as u64;
+        Ok(())
+    }
+}
+
+/// 把块体包装为 pcapng 块：类型、总长度、块体 (已按 4 字节对齐)、总长度
+fn block(block_type: u32, body: &[u8]) -> Vec<u8> {
+    let len = (body.len() + 12) as u32;
+    let mut block = Vec::with_capacity(len as usize);
+    block.extend_from_slice(&block_type.to_ne_bytes());
+    block.extend_from_slice(&len.to_ne_bytes());
+    block.extend_from_slice(body);
+    block.extend_from_slice(&len.to_ne_bytes());
+    block
+}
+
+/// 追加一个 pcapng 选项，值补齐到 4 字节
+fn push_option(buf: &mut Vec<u8>, code: u16, value: &[u8]) {
+    buf.extend_from_slice(&code.to_ne_bytes());
+    buf.extend_from_slice(&(value.len() as u16).to_ne_bytes());
+    buf.extend_from_slice(value);
+    buf.resize(buf.len().next_multiple_of(4), 0);
+}
+
+/// 切换文件的条件，都为 `None` 时只写一个文件
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Rotation {
+    /// 文件达到这么多字节后切换
+    pub max_bytes: Option<u64>,
+    /// 文件中第一个包之后经过这么久 (按抓包时间) 切换
+    pub interval: Option<Duration>,
+}
+
+impl Rotation {
+    pub fn is_enabled(&self) -> bool {
+        self.max_bytes.is_some() || self.interval.is_some()
+    }
+}
+
+/// 按大小或时间切换文件的写入器
+///
+/// 启用切换时文件名带上序号，如 `capture.pcapng` 依次写为 `capture-0000.pcapng`、
+/// `capture-0001.pcapng` ……；条件在写下一个包之前检查，单个文件可能略超出上限。
+#[derive(Debug)]
+pub struct RotatingWriter {
+    base: PathBuf,
+    format: PcapFormat,
+    link_type: u16,
+    rotation: Rotation,
+    index: u32,
+    path: PathBuf,
+    writer: PcapWriter<BufWriter<File>>,
+    /// 当前文件中第一个包的时间
+    started: Option<Duration>,
+}
+
+impl RotatingWriter {
+    /// 创建第一个文件
+    pub fn create(
+        path: impl AsRef<Path>,
+        format: PcapFormat,
+        link_type: u16,
+        rotation: Rotation,
+    ) -> io::Result<Self> {
+        let base = path.as_ref().to_path_buf();
+        let path = if rotation.is_enabled() {
+            numbered(&base, 0)
+        } else {
+            base.clone()
+        };
+        let writer = PcapWriter::create(&path, format, link_type)?;
+        Ok(RotatingWriter {
+            base,
+            format,
+            link_type,
+            rotation,
+            index: 0,
+            path,
+            writer,
+            started: None,
+        })
+    }
+
+    /// 当前正在写的文件
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// 写入一个数据包，参数同 [`PcapWriter::write_packet`]
+    ///
+    /// # 返回
+    /// 写入前是否切换到了新文件
+    pub fn write_packet(
+        &mut self,
+        timestamp: Duration,
+        interface: &str,
+        data: &[u8],
+        comment: Option<&str>,
+    ) -> io::Result<bool> {
+        let full = self
+            .rotation
+            .max_bytes
+            .is_some_and(|max| self.writer.bytes_written() >= max);
+        let expired = match (self.rotation.interval, self.started) {
+            (Some(interval), Some(started)) => timestamp.saturating_sub(started) >= interval,
+            _ => false,
+        };
+        let rotated = full || expired;
+        if rotated {
+            self.writer.flush()?;
+            self.index += 1;
+            self.path = numbered(&self.base, self.index);
+            self.writer = PcapWriter::create(&self.path, self.format, self.link_type)?;
+            self.started = None;
+        }
+        self.started.get_or_insert(timestamp);
+        self.writer
+            .write_packet(timestamp, interface, data, comment)?;
+        Ok(rotated)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// 在扩展名前插入序号：`dir/capture.pcapng` -> `dir/capture-0003.pcapng`
+fn numbered(base: &Path, index: u32) -> PathBuf {
+    let stem = base
+        .file_stem()
+        .map_or_else(String::new, |stem| stem.to_string_lossy().into_owned());
+    let name = match base.extension() {
+        Some(ext) => format!("{}-{:04}.{}", stem, index, ext.to_string_lossy()),
+        None => format!("{}-{:04}", stem, index),
+    };
+    base.with_file_name(name)
+}
+
+/// 读取节头块魔数之后的部分，返回该节是否为大端
+fn read_section_header(reader: &mut impl Read) -> Result<bool, PcapError> {
+    let mut head = [0u8; 8];
+    reader.read_exact(&mut head)?;
+    let big_endian = match u32::from_le_bytes(head[4..].try_into().unwrap()) {
+        PCAPNG_BYTE_ORDER_MAGIC => false,
+        magic if magic.swap_bytes() == PCAPNG_BYTE_ORDER_MAGIC => true,
+        magic => return Err(PcapError::UnknownFormat(magic)),
+    };
+    let block_len = read_u32(&head, 0, big_endian) as usize;
+    if block_len < 28 || !block_len.is_multiple_of(4) || block_len > MAX_RECORD_LEN {
+        return Err(PcapError::BadLength(block_len));
+    }
+    // 版本号、节长度和选项都用不到
+    let mut rest = vec![0u8; block_len - 12];
+    reader.read_exact(&mut rest)?;
+    Ok(big_endian)
+}
+
+/// 读满 `buf`；一个字节都没有读到时表示文件正常结束
+fn read_or_eof(reader: &mut impl Read, buf: &mut [u8]) -> Result<bool, PcapError> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) if filled == 0 => return Ok(false),
+            Ok(0) => return Err(PcapError::Truncated),
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(true)
+}
+
+/// 遍历 pcapng 选项 (code, value)，遇到 opt_endofopt 或数据不足时结束
+fn options(mut bytes: &[u8], big_endian: bool) -> impl Iterator<Item = (u16, &[u8])> {
+    std::iter::from_fn(move || {
+        if bytes.len() < 4 {
+            return None;
+        }
+        let code = read_u16(bytes, 0, big_endian);
+        let len = read_u16(bytes, 2, big_endian) as usize;
+        if code == 0 || bytes.len() < 4 + len {
+            return None;
+        }
+        let value = &bytes[4..4 + len];
+        bytes = &bytes[(4 + len.next_multiple_of(4)).min(bytes.len())..];
+        Some((code, value))
+    })
+}
+
+fn read_u16(bytes: &[u8], offset: usize, big_endian: bool) -> u16 {
+    let raw = [bytes[offset], bytes[offset + 1]];
+    if big_endian {
+        u16::from_be_bytes(raw)
+    } else {
+        u16::from_le_bytes(raw)
+    }
+}
+
+fn read_u32(bytes: &[u8], offset: usize, big_endian: bool) -> u32 {
+    let raw = bytes[offset..offset + 4].try_into().unwrap();
+    if big_endian {
+        u32::from_be_bytes(raw)
+    } else {
+        u32::from_le_bytes(raw)
+    }
+}
+
+/// 链路层帧中 IPv4 首部的偏移；不是 IPv4 或链路类型不支持时返回 `None`
+pub fn ipv4_offset(link_type: u16, frame: &[u8]) -> Option<usize> {
+    let offset = match link_type {
+        LINKTYPE_RAW | LINKTYPE_IPV4 => 0,
+        LINKTYPE_NULL | LINKTYPE_LOOP => {
+            let family = frame.get(..4)?;
+            // 地址族 2 (AF_INET) 的字节序取决于抓包的主机
+            let family_le = u32::from_le_bytes(family.try_into().unwrap());
+            let family_be = u32::from_be_bytes(family.try_into().unwrap());
+            if family_le != 2 && family_be != 2 {
+                return None;
+            }
+            4
+        }
+        LINKTYPE_ETHERNET => {
+            let mut offset = 12;
+            let mut ethertype = u16::from_be_bytes(frame.get(offset..offset + 2)?.try_into().ok()?);
+            while ethertype == ETHERTYPE_VLAN || ethertype == ETHERTYPE_QINQ {
+                offset += 4;
+                ethertype = u16::from_be_bytes(frame.get(offset..offset + 2)?.try_into().ok()?);
+            }
+            if ethertype != ETHERTYPE_IPV4 {
+                return None;
+            }
+            offset + 2
+        }
+        LINKTYPE_LINUX_SLL => {
+            if u16::from_be_bytes(frame.get(14..16)?.try_into().ok()?) != ETHERTYPE_IPV4 {
+                return None;
+            }
+            16
+        }
+        LINKTYPE_LINUX_SLL2 => {
+            if u16::from_be_bytes(frame.get(0..2)?.try_into().ok()?) != ETHERTYPE_IPV4 {
+                return None;
+            }
+            20
+        }
+        _ => return None,
+    };
+    let version = *frame.get(offset)? >> 4;
+    (version == 4).then_some(offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ipv4(fill: u8, len: usize) -> Vec<u8> {
+        let mut packet = vec![fill; len];
+        packet[0] = 0x45;
+        packet
+    }
+
+    /// 写入三个包后读回：两个接口、一个带注释、一个超过抓包长度
+    fn round_trip(format: PcapFormat) {
+        let mut bytes = Vec::new();
+        let mut writer = PcapWriter::new(&mut bytes, format, LINKTYPE_RAW).unwrap();
+        let packets = [
+            (Duration::new(1_700_000_000, 123_456_000), "lo", ipv4(1, 41)),
+            (
+                Duration::new(1_700_000_001, 999_999_000),
+                "eth0",
+                ipv4(2, 20),
+            ),
+            (Duration::new(1_700_000_002, 0), "lo", ipv4(3, 70_000)),
+        ];
+        for (i, (timestamp, interface, data)) in packets.iter().enumerate() {
+            let comment = (i == 0).then_some("标记 tag=7");
+            writer
+                .write_packet(*timestamp, interface, data, comment)
+                .unwrap();
+        }
+        writer.flush().unwrap();
+        let written = writer.bytes_written();
+        assert_eq!(written, bytes.len() as u64);
+        let comment = "标记 tag=7".as_bytes();
+        let has_comment = bytes.windows(comment.len()).any(|w| w == comment);
+        assert_eq!(has_comment, format == PcapFormat::Pcapng);
+
+        let mut reader = PcapReader::new(&bytes[..]).unwrap();
+        let expected_link = (format == PcapFormat::Pcap).then_some(LINKTYPE_RAW);
+        assert_eq!(reader.link_type(), expected_link);
+        let records: Vec<_> = reader.by_ref().collect::<Result<_, _>>().unwrap();
+        assert_eq!(records.len(), packets.len());
+        for (record, (timestamp, _, data)) in records.iter().zip(&packets) {
+            assert_eq!(record.link_type, LINKTYPE_RAW);
+            assert_eq!(record.timestamp, *timestamp);
+            assert_eq!(record.original_len, data.len());
+            assert_eq!(record.data[..], data[..record.data.len()]);
+            assert_eq!(ipv4_offset(record.link_type, &record.data), Some(0));
+        }
+        assert!(!records[0].is_truncated());
+        assert!(records[2].is_truncated());
+        assert_eq!(records[2].data.len(), SNAPLEN as usize);
+    }
+
+    /// 按本机字节序拼出接口描述块，`tsresol` 为时间戳精度选项
+    fn interface_block(tsresol: Option<u8>) -> Vec<u8> {
+        let mut body = Vec::new();
+        body.extend_from_slice(&LINKTYPE_ETHERNET.to_ne_bytes());
+        body.extend_from_slice(&0u16.to_ne_bytes());
+        body.extend_from_slice(&SNAPLEN.to_ne_bytes());
+        if let Some(value) = tsresol {
+            push_option(&mut body, PCAPNG_OPTION_TSRESOL, &[value]);
+        }
+        push_option(&mut body, 0, &[]);
+        block(PCAPNG_INTERFACE_DESCRIPTION, &body)
+    }
+
+    fn pcapng_with_interface(tsresol: Option<u8>, units: u64) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        PcapWriter::new(&mut bytes, PcapFormat::Pcapng, LINKTYPE_RAW).unwrap();
+        bytes.extend(interface_block(tsresol));
+        let mut body = Vec::new();
+        body.extend_from_slice(&0u32.to_ne_bytes());
+        body.extend_from_slice(&((units >> 32) as u32).to_ne_bytes());
+        body.extend_from_slice(&(units as u32).to_ne_bytes());
+        body.extend_from_slice(&4u32.to_ne_bytes());
+        body.extend_from_slice(&4u32.to_ne_bytes());
+        body.extend_from_slice(&[0; 4]);
+        bytes.extend(block(PCAPNG_ENHANCED_PACKET, &body));
+        bytes
+    }
+
+    fn first_record(bytes: &[u8]) -> Result<Option<PcapRecord>, PcapError> {
+        PcapReader::new(bytes).unwrap().next_record()
+    }
+
+    #[test]
+    fn pcap_round_trip() {
+        round_trip(PcapFormat::Pcap);
+    }
+
+    #[test]
+    fn pcapng_round_trip() {
+        round_trip(PcapFormat::Pcapng);
+    }
+
+    #[test]
+    fn pcapng_timestamp_resolutions() {
+        let nanos = pcapng_with_interface(Some(9), 1_700_000_000_123_456_789);
+        let record = first_record(&nanos).unwrap().unwrap();
+        assert_eq!(record.link_type, LINKTYPE_ETHERNET);
+        assert_eq!(record.timestamp, Duration::new(1_700_000_000, 123_456_789));
+
+        // 2^-10 秒
+        let binary = pcapng_with_interface(Some(0x8a), 3 * 1024 + 512);
+        let record = first_record(&binary).unwrap().unwrap();
+        assert_eq!(record.timestamp, Duration::from_millis(3500));
+
+        let micros = pcapng_with_interface(None, 2_500_000);
+        let record = first_record(&micros).unwrap().unwrap();
+        assert_eq!(record.timestamp, Duration::from_millis(2500));
+
+        for value in [10, 0x7f, 0x9e, 0xff] {
+            let bytes = pcapng_with_interface(Some(value), 1);
+            assert_eq!(first_record(&bytes), Err(PcapError::BadResolution(value)));
+        }
+    }
+
+    #[test]
+    fn reader_errors() {
+        assert_eq!(
+            PcapReader::new(&[1u8, 2, 3, 4, 0, 0][..]).unwrap_err(),
+            PcapError::UnknownFormat(0x0403_0201)
+        );
+        assert_eq!(
+            PcapReader::new(&[0xd4u8, 0xc3][..]).unwrap_err(),
+            PcapError::Truncated
+        );
+
+        let mut bytes = Vec::new();
+        let mut writer = PcapWriter::new(&mut bytes, PcapFormat::Pcap, LINKTYPE_RAW).unwrap();
+        writer
+            .write_packet(Duration::ZERO, "lo", &ipv4(0, 20), None)
+            .unwrap();
+        bytes.pop();
+        assert_eq!(first_record(&bytes), Err(PcapError::Truncated));
+
+        // 数据包块引用了未声明的接口
+        let mut bytes = pcapng_with_interface(None, 0);
+        let interface_len = interface_block(None).len();
+        let header_len = bytes.len() - interface_len - 36;
+        bytes.drain(header_len..header_len + interface_len);
+        assert_eq!(first_record(&bytes), Err(PcapError::UnknownInterface(0)));
+    }
+
+    #[test]
+    fn rotating_writer_splits_files() {
+        let dir = std::env::temp_dir().join(format!("pcap-rotation-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let rotation = Rotation {
+            max_bytes: Some(200),
+            interval: Some(Duration::from_secs(10)),
+        };
+        let mut writer = RotatingWriter::create(
+            dir.join("capture.pcap"),
+            PcapFormat::Pcap,
+            LINKTYPE_RAW,
+            rotation,
+        )
+        .unwrap();
+        assert_eq!(writer.path(), dir.join("capture-0000.pcap"));
+        let packet = ipv4(0, 100);
+        let mut rotated = Vec::new();
+        // 第二个包之后文件超过 200 字节；第四个包距第三个包 10 秒
+        for secs in [0, 1, 2, 12, 13] {
+            rotated.push(
+                writer
+                    .write_packet(Duration::from_secs(secs), "lo", &packet, None)
+                    .unwrap(),
+            );
+        }
+        writer.flush().unwrap();
+        assert_eq!(rotated, [false, false, true, true, false]);
+        assert_eq!(writer.path(), dir.join("capture-0002.pcap"));
+        let counts: Vec<usize> = (0..3)
+            .map(|i| {
+                let path = dir.join(format!("capture-{:04}.pcap", i));
+                PcapReader::open(path).unwrap().count()
+            })
+            .collect();
+        assert_eq!(counts, [2, 1, 2]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn format_names() {
+        assert_eq!(PcapFormat::from_name("PCAPNG"), Some(PcapFormat::Pcapng));
+        assert_eq!(PcapFormat::from_name("erf"), None);
+        assert_eq!(PcapFormat::from_path(Path::new("a.PCAP")), PcapFormat::Pcap);
+        assert_eq!(PcapFormat::from_path(Path::new("a")), PcapFormat::Pcapng);
+        assert_eq!(
+            numbered(Path::new("dir/capture"), 12),
+            Path::new("dir/capture-0012")
+        );
+    }
+
+    #[test]
+    fn ipv4_offsets() {
+        // 链路层首部之后跟一个 IPv4 版本字节
+        let frame = |link: &[u8]| [link, &[0x45]].concat();
+        let mut ethernet = vec![0; 14];
+        ethernet[12..14].copy_from_slice(&ETHERTYPE_IPV4.to_be_bytes());
+        assert_eq!(ipv4_offset(LINKTYPE_ETHERNET, &frame(&ethernet)), Some(14));
+        assert_eq!(ipv4_offset(LINKTYPE_ETHERNET, &ethernet), None);
+        let mut vlan = vec![0; 18];
+        vlan[12..14].copy_from_slice(&ETHERTYPE_VLAN.to_be_bytes());
+        vlan[16..18].copy_from_slice(&ETHERTYPE_IPV4.to_be_bytes());
+        assert_eq!(ipv4_offset(LINKTYPE_ETHERNET, &frame(&vlan)), Some(18));
+        vlan[16..18].copy_from_slice(&0x86ddu16.to_be_bytes());
+        assert_eq!(ipv4_offset(LINKTYPE_ETHERNET, &frame(&vlan)), None);
+        let null = frame(&2u32.to_le_bytes());
+        assert_eq!(ipv4_offset(LINKTYPE_NULL, &null), Some(4));
+        assert_eq!(
+            ipv4_offset(LINKTYPE_LOOP, &frame(&2u32.to_be_bytes())),
+            Some(4)
+        );
+        assert_eq!(
+            ipv4_offset(LINKTYPE_NULL, &frame(&24u32.to_le_bytes())),
+            None
+        );
+        assert_eq!(ipv4_offset(LINKTYPE_RAW, &[0x60]), None);
+    }
+}
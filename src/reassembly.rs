@@ -0,0 +1,938 @@
+//! IPv4 分片重组
+//!
+//! [`Reassembler`] 按 (源地址, 目的地址, 协议, ID) 缓存分片，数据齐全后拼出完整的
+//! 数据报，标记和负载都从重组后的数据报中解析。缓存的数据报有超时和内存上限，
+//! 重叠分片按 [`OverlapPolicy`] 处理。重叠、极小分片、长度不一致等异常记录为
+//! [`FragmentAnomaly`]，由调用方取出报告。
+
+use std::collections::HashMap;
+use std::fmt;
+use std::mem;
+use std::net::Ipv4Addr;
+use std::time::{Duration, Instant};
+
+use crate::builder::IPV4_HEADER_LEN;
+use crate::checksum;
+
+/// 重叠策略 (`first` / `last` / `reject`)
+pub const OVERLAP_POLICY_ENV: &str = "BIAOSHI_OVERLAP_POLICY";
+/// 重组超时秒数
+pub const REASSEMBLY_TIMEOUT_ENV: &str = "BIAOSHI_REASSEMBLY_TIMEOUT";
+/// 重组缓存的字节上限
+pub const REASSEMBLY_MEMORY_ENV: &str = "BIAOSHI_REASSEMBLY_MEMORY";
+
+/// More Fragments 标志 (flags / offset 字段中的位置)
+const FLAG_MF: u16 = 0x2000;
+/// Don't Fragment 标志
+const FLAG_DF: u16 = 0x4000;
+/// 首部之后的数据最多能到的位置 (按不带选项的首部计算，首部的实际长度
+/// 要等收到第一个分片后再检查)
+const MAX_DATA_END: usize = 65535 - IPV4_HEADER_LEN;
+
+/// 分片所属数据报的标识
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FragmentKey {
+    pub source: Ipv4Addr,
+    pub destination: Ipv4Addr,
+    pub protocol: u8,
+    pub identification: u16,
+}
+
+impl FragmentKey {
+    /// 取 IPv4 包的源地址、目的地址、协议和 ID；不足 20 字节时返回 `None`
+    pub fn from_ipv4(packet: &[u8]) -> Option<Self> {
+        if packet.len() < IPV4_HEADER_LEN {
+            return None;
+        }
+        Some(FragmentKey {
+            source: Ipv4Addr::new(packet[12], packet[13], packet[14], packet[15]),
+            destination: Ipv4Addr::new(packet[16], packet[17], packet[18], packet[19]),
+            protocol: packet[9],
+            identification: u16::from_be_bytes([packet[4], packet[5]]),
+        })
+    }
+}
+
+impl fmt::Display for FragmentKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} -> {} 协议 {} ID 0x{:04x}",
+            self.source, self.destination, self.protocol, self.identification
+        )
+    }
+}
+
+/// 重叠部分内容不同时的处理方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OverlapPolicy {
+    /// 保留先到的数据
+    #[default]
+    First,
+    /// 用后到的数据覆盖
+    Last,
+    /// 丢弃整个数据报
+    Reject,
+}
+
+impl OverlapPolicy {
+    /// 按名称解析 (`first` / `last` / `reject`)
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "first" => Some(OverlapPolicy::First),
+            "last" => Some(OverlapPolicy::Last),
+            "reject" => Some(OverlapPolicy::Reject),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            OverlapPolicy::First => "first",
+            OverlapPolicy::Last => "last",
+            OverlapPolicy::Reject => "reject",
+        }
+    }
+}
+
+/// 重组配置错误
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReassemblyError {
+    BadPolicy(String),
+    BadTimeout(String),
+    BadMemory(String),
+}
+
+impl fmt::Display for ReassemblyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReassemblyError::BadPolicy(name) => write!(f, "未知的重叠策略: {}", name),
+            ReassemblyError::BadTimeout(value) => write!(f, "无效的重组超时: {}", value),
+            ReassemblyError::BadMemory(value) => write!(f, "无效的重组内存上限: {}", value),
+        }
+    }
+}
+
+impl std::error::Error for ReassemblyError {}
+
+/// 重组参数
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReassemblyConfig {
+    /// 从收到第一个分片算起的超时
+    pub timeout: Duration,
+    /// 所有未完成数据报占用的字节上限
+    pub max_bytes: usize,
+    /// 同时缓存的数据报个数上限
+    pub max_datagrams: usize,
+    pub policy: OverlapPolicy,
+}
+
+impl Default for ReassemblyConfig {
+    fn default() -> Self {
+        ReassemblyConfig {
+            timeout: Duration::from_secs(30),
+            max_bytes: 4 * 1024 * 1024,
+            max_datagrams: 1024,
+            policy: OverlapPolicy::default(),
+        }
+    }
+}
+
+impl ReassemblyConfig {
+    /// 从环境变量读取重叠策略、超时和内存上限，未设置的项取默认值
+    pub fn from_env() -> Result<Self, ReassemblyError> {
+        Self::from_lookup(|name| std::env::var(name).ok())
+    }
+
+    /// 同 [`ReassemblyConfig::from_env`]，变量值由 `lookup` 提供
+    pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Result<Self, ReassemblyError> {
+        let mut config = ReassemblyConfig::default();
+        if let Some(name) = lookup(OVERLAP_POLICY_ENV) {
+            config.policy =
+                OverlapPolicy::from_name(&name).ok_or(ReassemblyError::BadPolicy(name))?;
+        }
+        if let Some(value) = lookup(REASSEMBLY_TIMEOUT_ENV) {
+            config.timeout = value
+                .parse::<f64>()
+                .ok()
+                .filter(|secs| *secs > 0.0)
+                .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+                .ok_or(ReassemblyError::BadTimeout(value))?;
+        }
+        if let Some(value) = lookup(REASSEMBLY_MEMORY_ENV) {
+            config.max_bytes = value
+                .parse()
+                .ok()
+                .filter(|bytes| *bytes > 0)
+                .ok_or(ReassemblyError::BadMemory(value))?;
+        }
+        Ok(config)
+    }
+}
+
+/// 重组过程中发现的异常
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FragmentAnomaly {
+    /// 首部长度或总长度字段不合法，无法作为分片处理
+    Malformed,
+    /// 不是最后一个分片，数据长度却不是 8 的倍数，分片被丢弃
+    Unaligned {
+        key: FragmentKey,
+        offset: usize,
+        len: usize,
+    },
+    /// 分片结尾超出 IPv4 数据报的最大长度，分片被丢弃；加上第一个分片的
+    /// 首部才超出时丢弃整个数据报
+    TooLong { key: FragmentKey, end: usize },
+    /// 第一个分片放不下传输层首部 (RFC 1858)
+    Tiny { key: FragmentKey, len: usize },
+    /// 与已收到的数据重叠；`conflicting` 表示重叠部分内容不同
+    Overlap {
+        key: FragmentKey,
+        offset: usize,
+        len: usize,
+        conflicting: bool,
+    },
+    /// 与已收到的数据完全相同的分片
+    Duplicate { key: FragmentKey, offset: usize },
+    /// 最后一个分片给出的总长度与其他分片矛盾，数据报被丢弃
+    LengthMismatch { key: FragmentKey },
+    /// 重叠内容冲突，按 `reject` 策略丢弃数据报
+    Rejected { key: FragmentKey },
+    /// 超时仍未收齐
+    Timeout {
+        key: FragmentKey,
+        received: usize,
+        fragments: usize,
+    },
+    /// 超出内存或个数上限，丢弃最早的未完成数据报
+    Evicted { key: FragmentKey },
+}
+
+impl fmt::Display for FragmentAnomaly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FragmentAnomaly::Malformed => write!(f, "首部长度或总长度不合法"),
+            FragmentAnomaly::Unaligned { key, offset, len } => write!(
+                f,
+                "{}: 偏移 {} 的分片长度 {} 不是 8 的倍数",
+                key, offset, len
+            ),
+            FragmentAnomaly::TooLong { key, end } => {
+                write!(f, "{}: 分片结尾 {} 超出最大长度", key, end)
+            }
+            FragmentAnomaly::Tiny { key, len } => {
+                write!(f, "{}: 第一个分片只有 {} 字节数据", key, len)
+            }
+            FragmentAnomaly::Overlap {
+                key,
+                offset,
+                len,
+                conflicting,
+            } => {
+                write!(f, "{}: 偏移 {} 的分片重叠 {} 字节", key, offset, len)?;
+                if *conflicting {
+                    write!(f, " (内容不同)")?;
+                }
+                Ok(())
+            }
+            FragmentAnomaly::Duplicate { key, offset } => {
+                write!(f, "{}: 偏移 {} 的分片重复", key, offset)
+            }
+            FragmentAnomaly::LengthMismatch { key } => {
+                write!(f, "{}: 分片给出的总长度不一致, 已丢弃", key)
+            }
+            FragmentAnomaly::Rejected { key } => {
+                write!(f, "{}: 重叠内容冲突, 已丢弃", key)
+            }
+            FragmentAnomaly::Timeout {
+                key,
+                received,
+                fragments,
+            } => write!(
+                f,
+                "{}: 重组超时, 已收到 {} 个分片 {} 字节",
+                key, fragments, received
+            ),
+            FragmentAnomaly::Evicted { key } => {
+                write!(f, "{}: 超出重组缓存上限, 已丢弃", key)
+            }
+        }
+    }
+}
+
+/// 重组得到的完整数据报
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Datagram {
+    /// 完整的 IPv4 数据报，首部取自第一个分片，总长度、分片字段和校验和已重写
+    pub data: Vec<u8>,
+    /// 组成它的分片个数，未分片的数据报为 1
+    pub fragments: usize,
+}
+
+/// 未收齐的数据报
+#[derive(Debug)]
+struct Partial {
+    /// 偏移为 0 的分片的首部
+    header: Option<Vec<u8>>,
+    data: Vec<u8>,
+    /// 每个字节是否已收到
+    filled: Vec<bool>,
+    /// 最后一个分片给出的数据长度
+    total: Option<usize>,
+    fragments: usize,
+    first_seen: Instant,
+    /// 已判定丢弃，只等超时释放，其后的分片不再处理
+    dropped: bool,
+}
+
+impl Partial {
+    fn new(now: Instant) -> Self {
+        Partial {
+            header: None,
+            data: Vec::new(),
+            filled: Vec::new(),
+            total: None,
+            fragments: 0,
+            first_seen: now,
+            dropped: false,
+        }
+    }
+
+    /// 占用的缓存字节数
+    fn size(&self) -> usize {
+        self.data.len() + self.filled.len() + self.header.as_ref().map_or(0, Vec::len)
+    }
+
+    fn received(&self) -> usize {
+        self.filled.iter().filter(|filled| **filled).count()
+    }
+
+    /// 丢弃数据，只保留标识直到超时
+    fn drop_data(&mut self) {
+        self.dropped = true;
+        self.header = None;
+        self.data = Vec::new();
+        self.filled = Vec::new();
+    }
+
+    /// 数据齐全时拼出完整的数据报
+    fn assemble(&self) -> Option<Vec<u8>> {
+        let header = self.header.as_ref()?;
+        let total = self.total?;
+        if self.filled.len() < total || !self.filled[..total].iter().all(|filled| *filled) {
+            return None;
+        }
+        let mut datagram = header.clone();
+        datagram.extend_from_slice(&self.data[..total]);
+        let total_len = u16::try_from(datagram.len()).expect("push 已检查总长度");
+        datagram[2..4].copy_from_slice(&total_len.to_be_bytes());
+        let flags_offset = u16::from_be_bytes([datagram[6], datagram[7]]) & FLAG_DF;
+        datagram[6..8].copy_from_slice(&flags_offset.to_be_bytes());
+        datagram[10..12].fill(0);
+        let check = checksum::checksum(&datagram[..header.len()]);
+        datagram[10..12].copy_from_slice(&check.to_be_bytes());
+        Some(datagram)
+    }
+}
+
+/// IPv4 分片重组器
+#[derive(Debug)]
+pub struct Reassembler {
+    config: ReassemblyConfig,
+    partials: HashMap<FragmentKey, Partial>,
+    /// 所有未完成数据报占用的字节数
+    used: usize,
+    anomalies: Vec<FragmentAnomaly>,
+}
+
+impl Reassembler {
+    pub fn new(config: ReassemblyConfig) -> Self {
+        Reassembler {
+            config,
+            partials: HashMap::new(),
+            used: 0,
+            anomalies: Vec::new(),
+        }
+    }
+
+    pub fn config(&self) -> &ReassemblyConfig {
+        &self.config
+    }
+
+    /// 正在重组的数据报个数
+    pub fn pending(&self) -> usize {
+        self.partials.values().filter(|p| !p.dropped).count()
+    }
+
+    /// `key` 的数据报是否正在重组 (已被丢弃的不算)
+    pub fn is_pending(&self, key: &FragmentKey) -> bool {
+        self.partials.get(key).is_some_and(|p| !p.dropped)
+    }
+
+    /// 取出目前记录的异常
+    pub fn take_anomalies(&mut self) -> Vec<FragmentAnomaly> {
+        mem::take(&mut self.anomalies)
+    }
+
+    /// 处理收到的一个 IPv4 包
+    ///
+    /// # 参数
+    /// - `packet`: 从 IP 首部开始的数据，总长度字段之后的填充会被忽略
+    /// - `now`: 收到的时间，用于超时判断
+    ///
+    /// # 返回
+    /// 未分片的包或刚收齐的数据报；分片尚未收齐或被丢弃时返回 `None`
+    pub fn push(&mut self, packet: &[u8], now: Instant) -> Option<Datagram> {
+        self.expire(now);
+        if packet.len() < IPV4_HEADER_LEN || packet[0] >> 4 != 4 {
+            self.anomalies.push(FragmentAnomaly::Malformed);
+            return None;
+        }
+        let header_len = (packet[0] & 0x0f) as usize * 4;
+        let total_len = u16::from_be_bytes([packet[2], packet[3]]) as usize;
+        if header_len < IPV4_HEADER_LEN || total_len < header_len || total_len > packet.len() {
+            self.anomalies.push(FragmentAnomaly::Malformed);
+            return None;
+        }
+        let packet = &packet[..total_len];
+        let flags_offset = u16::from_be_bytes([packet[6], packet[7]]);
+        let more = flags_offset & FLAG_MF != 0;
+        let start = (flags_offset & 0x1fff) as usize * 8;
+        if !more && start == 0 {
+            return Some(Datagram {
+                data: packet.to_vec(),
+                fragments: 1,
+            });
+        }
+
+        let key = FragmentKey::from_ipv4(packet).expect("已检查首部长度");
+        let body = &packet[header_len..];
+        let end = start + body.len();
+        if more && !body.len().is_multiple_of(8) {
+            self.anomalies.push(FragmentAnomaly::Unaligned {
+                key,
+                offset: start,
+                len: body.len(),
+            });
+            return None;
+        }
+        if end > MAX_DATA_END {
+            self.anomalies.push(FragmentAnomaly::TooLong { key, end });
+            return None;
+        }
+        if start == 0 && body.len() < transport_header_len(key.protocol) {
+            self.anomalies.push(FragmentAnomaly::Tiny {
+                key,
+                len: body.len(),
+            });
+        }
+
+        if !self.partials.contains_key(&key) {
+            while self.partials.len() >= self.config.max_datagrams.max(1) {
+                self.evict_oldest(None);
+            }
+            self.partials.insert(key, Partial::new(now));
+        }
+        let partial = &self.partials[&key];
+        if partial.dropped {
+            return None;
+        }
+
+        // 最后一个分片确定总长度，其他分片都不能超出它
+        let received_end = partial
+            .filled
+            .iter()
+            .rposition(|filled| *filled)
+            .map_or(0, |i| i + 1);
+        let mismatch = match (more, partial.total) {
+            (false, Some(total)) => total != end,
+            (false, None) => received_end > end,
+            (true, Some(total)) => end > total,
+            (true, None) => false,
+        };
+        if mismatch {
+            self.discard(&key, FragmentAnomaly::LengthMismatch { key });
+            return None;
+        }
+
+        // 为新数据腾出缓存
+        let grow = end.saturating_sub(partial.data.len()) * 2
+            + if start == 0 && partial.header.is_none() {
+                header_len
+            } else {
+                0
+            };
+        while self.used + grow > self.config.max_bytes {
+            if !self.evict_oldest(Some(&key)) {
+                self.discard(&key, FragmentAnomaly::Evicted { key });
+                return None;
+            }
+        }
+
+        let policy = self.config.policy;
+        let partial = self.partials.get_mut(&key).unwrap();
+        self.used -= partial.size();
+        if partial.data.len() < end {
+            partial.data.resize(end, 0);
+            partial.filled.resize(end, false);
+        }
+
+        let mut overlap = 0;
+        let mut conflicting = false;
+        for (i, byte) in body.iter().enumerate() {
+            if partial.filled[start + i] {
+                overlap += 1;
+                conflicting |= partial.data[start + i] != *byte;
+            }
+        }
+        if overlap > 0 && overlap == body.len() && !conflicting {
+            self.used += partial.size();
+            self.anomalies
+                .push(FragmentAnomaly::Duplicate { key, offset: start });
+            return None;
+        }
+        if overlap > 0 {
+            self.anomalies.push(FragmentAnomaly::Overlap {
+                key,
+                offset: start,
+                len: overlap,
+                conflicting,
+            });
+            if conflicting && policy == OverlapPolicy::Reject {
+                self.used += partial.size();
+                self.discard(&key, FragmentAnomaly::Rejected { key });
+                return None;
+            }
+        }
+        for (i, byte) in body.iter().enumerate() {
+            if !partial.filled[start + i] || policy == OverlapPolicy::Last {
+                partial.data[start + i] = *byte;
+                partial.filled[start + i] = true;
+            }
+        }
+        if start == 0 && (partial.header.is_none() || policy == OverlapPolicy::Last) {
+            partial.header = Some(packet[..header_len].to_vec());
+        }
+        if !more {
+            partial.total = Some(end);
+        }
+        partial.fragments += 1;
+        self.used += partial.size();
+
+        // 第一个分片的首部可能带选项，加上数据后不能超出总长度字段的范围
+        let data_end = partial.data.len();
+        if partial
+            .header
+            .as_ref()
+            .is_some_and(|header| header.len() + data_end > u16::MAX as usize)
+        {
+            self.discard(&key, FragmentAnomaly::TooLong { key, end: data_end });
+            return None;
+        }
+
+        let data = partial.assemble()?;
+        let fragments = partial.fragments;
+        self.used -= partial.size();
+        self.partials.remove(&key);
+        Some(Datagram { data, fragments })
+    }
+
+    /// 丢弃超时的数据报，未被丢弃过的记录超时异常
+    pub fn expire(&mut self, now: Instant) {
+        let timeout = self.config.timeout;
+        let expired: Vec<FragmentKey> = self
+            .partials
+            .iter()
+            .filter(|(_, p)| now.saturating_duration_since(p.first_seen) >= timeout)
+            .map(|(key, _)| *key)
+            .collect();
+        for key in expired {
+            let partial = self.partials.remove(&key).unwrap();
+            self.used -= partial.size();
+            if !partial.dropped {
+                self.anomalies.push(FragmentAnomaly::Timeout {
+                    key,
+                    received: partial.received(),
+                    fragments: partial.fragments,
+                });
+            }
+        }
+    }
+
+    /// 丢弃 `key` 的数据并记录异常，标识保留到超时以忽略它后续的分片
+    fn discard(&mut self, key: &FragmentKey, anomaly: FragmentAnomaly) {
+        if let Some(partial) = self.partials.get_mut(key) {
+            self.used -= partial.size();
+            partial.drop_data();
+        }
+        self.anomalies.push(anomaly);
+    }
+
+    /// 移除除 `keep` 以外最早的数据报，没有可移除的返回 `false`
+    fn evict_oldest(&mut self, keep: Option<&FragmentKey>) -> bool {
+        let oldest = self
+            .partials
+            .iter()
+            .filter(|(key, _)| Some(*key) != keep)
+            .min_by_key(|(_, p)| p.first_seen)
+            .map(|(key, _)| *key);
+        let Some(key) = oldest else {
+            return false;
+        };
+        let partial = self.partials.remove(&key).unwrap();
+        self.used -= partial.size();
+        if !partial.dropped {
+            self.anomalies.push(FragmentAnomaly::Evicted { key });
+        }
+        true
+    }
+}
+
+/// 第一个分片至少要带上的传输层首部长度，其他协议不检查
+fn transport_header_len(protocol: u8) -> usize {
+    match protocol {
+        6 => 20,
+        1 | 17 => 8,
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::{PacketBuilder, TcpLayer, UdpLayer};
+    use crate::fragment::{FragmentOrder, Fragmenter};
+
+    const SOURCE: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 1);
+    const DESTINATION: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 2);
+
+    fn datagram(identification: u16, options: &[u8]) -> Vec<u8> {
+        PacketBuilder::ipv4(SOURCE, DESTINATION)
+            .identification(identification)
+            .ip_options(options.to_vec())
+            .udp(UdpLayer::new(40000, 8001))
+            .payload((0..200).map(|i| i as u8).collect())
+            .build()
+            .unwrap()
+    }
+
+    fn fragments(packet: &[u8], fragmenter: &Fragmenter) -> Vec<Vec<u8>> {
+        let fragments = fragmenter.fragment(packet).unwrap();
+        assert!(fragments.len() > 2);
+        fragments
+    }
+
+    /// 依次送入，只有最后一个分片返回数据报
+    fn push_all(
+        reassembler: &mut Reassembler,
+        fragments: &[Vec<u8>],
+        now: Instant,
+    ) -> Option<Datagram> {
+        let (last, rest) = fragments.split_last().unwrap();
+        for fragment in rest {
+            assert_eq!(reassembler.push(fragment, now), None);
+        }
+        reassembler.push(last, now)
+    }
+
+    fn config(policy: OverlapPolicy) -> ReassemblyConfig {
+        ReassemblyConfig {
+            policy,
+            ..ReassemblyConfig::default()
+        }
+    }
+
+    #[test]
+    fn unfragmented_packet_passes_through() {
+        let packet = datagram(1, &[]);
+        let mut reassembler = Reassembler::new(ReassemblyConfig::default());
+        let result = reassembler.push(&packet, Instant::now()).unwrap();
+        assert_eq!(result.data, packet);
+        assert_eq!(result.fragments, 1);
+        assert_eq!(reassembler.pending(), 0);
+    }
+
+    #[test]
+    fn reassembles_in_any_order() {
+        // 30 号选项的 copied 位为 0，只在第一个分片中，重组后的首部仍然带着它
+        let packet = datagram(2, &[0x94, 4, 0, 0, 0x1e, 4, 0, 0]);
+        for order in [
+            FragmentOrder::InOrder,
+            FragmentOrder::Reverse,
+            FragmentOrder::Random,
+        ] {
+            let fragmenter = Fragmenter {
+                order,
+                ..Fragmenter::new(68)
+            };
+            let fragments = fragments(&packet, &fragmenter);
+            let mut reassembler = Reassembler::new(ReassemblyConfig::default());
+            let key = FragmentKey::from_ipv4(&packet).unwrap();
+            assert!(reassembler.push(&fragments[0], Instant::now()).is_none());
+            assert!(reassembler.is_pending(&key));
+            let result = push_all(&mut reassembler, &fragments[1..], Instant::now());
+            let result = result.unwrap_or_else(|| panic!("{} 未重组", order.name()));
+            assert_eq!(result.data, packet, "{}", order.name());
+            assert_eq!(result.fragments, fragments.len());
+            assert_eq!(reassembler.pending(), 0);
+            assert!(!reassembler.is_pending(&key));
+            assert_eq!(reassembler.take_anomalies(), Vec::new());
+        }
+    }
+
+    #[test]
+    fn interleaved_datagrams_are_kept_apart() {
+        let (a, b) = (datagram(3, &[]), datagram(4, &[]));
+        let fragmenter = Fragmenter::new(68);
+        let (a_fragments, b_fragments) = (fragments(&a, &fragmenter), fragments(&b, &fragmenter));
+        let mut reassembler = Reassembler::new(ReassemblyConfig::default());
+        let now = Instant::now();
+        let mut done = Vec::new();
+        for (x, y) in a_fragments.iter().zip(&b_fragments) {
+            done.extend(reassembler.push(x, now));
+            done.extend(reassembler.push(y, now));
+        }
+        let data: Vec<_> = done.into_iter().map(|d| d.data).collect();
+        assert_eq!(data, vec![a, b]);
+    }
+
+    #[test]
+    fn duplicate_fragment_is_reported_once() {
+        let packet = datagram(5, &[]);
+        let fragments = fragments(&packet, &Fragmenter::new(68));
+        let mut reassembler = Reassembler::new(ReassemblyConfig::default());
+        let now = Instant::now();
+        assert!(reassembler.push(&fragments[0], now).is_none());
+        assert!(reassembler.push(&fragments[0], now).is_none());
+        let key = FragmentKey::from_ipv4(&packet).unwrap();
+        assert_eq!(
+            reassembler.take_anomalies(),
+            vec![FragmentAnomaly::Duplicate { key, offset: 0 }]
+        );
+        let result = push_all(&mut reassembler, &fragments[1..], now).unwrap();
+        assert_eq!(result.data, packet);
+        assert_eq!(result.fragments, fragments.len());
+    }
+
+    #[test]
+    fn identical_overlap_is_reported_and_reassembled() {
+        let packet = datagram(6, &[]);
+        let fragmenter = Fragmenter {
+            overlap: 8,
+            ..Fragmenter::new(68)
+        };
+        let fragments = fragments(&packet, &fragmenter);
+        let mut reassembler = Reassembler::new(config(OverlapPolicy::Reject));
+        let result = push_all(&mut reassembler, &fragments, Instant::now()).unwrap();
+        assert_eq!(result.data, packet);
+        let anomalies = reassembler.take_anomalies();
+        assert_eq!(anomalies.len(), fragments.len() - 1);
+        assert!(anomalies.iter().all(|anomaly| matches!(
+            anomaly,
+            FragmentAnomaly::Overlap {
+                len: 8,
+                conflicting: false,
+                ..
+            }
+        )));
+    }
+
+    #[test]
+    fn conflicting_overlap_follows_policy() {
+        let packet = datagram(7, &[]);
+        let fragmenter = Fragmenter {
+            overlap: 8,
+            overlap_fill: Some(0xee),
+            ..Fragmenter::new(68)
+        };
+        let fragments = fragments(&packet, &fragmenter);
+        let overlaps = fragments.len() - 1;
+        let now = Instant::now();
+
+        let mut first = Reassembler::new(config(OverlapPolicy::First));
+        assert_eq!(push_all(&mut first, &fragments, now).unwrap().data, packet);
+
+        let mut last = Reassembler::new(config(OverlapPolicy::Last));
+        let result = push_all(&mut last, &fragments, now).unwrap();
+        assert_eq!(result.data.len(), packet.len());
+        let filled = result.data.iter().filter(|byte| **byte == 0xee).count();
+        assert_eq!(filled, 8 * overlaps);
+
+        let mut reject = Reassembler::new(config(OverlapPolicy::Reject));
+        let key = FragmentKey::from_ipv4(&packet).unwrap();
+        for fragment in &fragments {
+            assert!(reject.push(fragment, now).is_none());
+        }
+        assert!(!reject.is_pending(&key));
+        let anomalies = reject.take_anomalies();
+        assert!(anomalies.contains(&FragmentAnomaly::Rejected { key }));
+        // 丢弃后的分片不再处理，也不再报告
+        assert_eq!(anomalies.len(), 2);
+    }
+
+    #[test]
+    fn incomplete_datagram_times_out() {
+        let packet = datagram(8, &[]);
+        let fragments = fragments(&packet, &Fragmenter::new(68));
+        let mut reassembler = Reassembler::new(ReassemblyConfig::default());
+        let timeout = reassembler.config().timeout;
+        let start = Instant::now();
+        let (last, rest) = fragments.split_last().unwrap();
+        for fragment in rest {
+            assert!(reassembler.push(fragment, start).is_none());
+        }
+        reassembler.expire(start + timeout - Duration::from_millis(1));
+        assert_eq!(reassembler.pending(), 1);
+
+        // 超时之后的最后一个分片开始新的一轮重组
+        assert!(reassembler.push(last, start + timeout).is_none());
+        let key = FragmentKey::from_ipv4(&packet).unwrap();
+        assert_eq!(
+            reassembler.take_anomalies(),
+            vec![FragmentAnomaly::Timeout {
+                key,
+                received: rest.iter().map(|f| f.len() - IPV4_HEADER_LEN).sum(),
+                fragments: rest.len(),
+            }]
+        );
+        assert!(reassembler.is_pending(&key));
+        reassembler.expire(start + timeout * 2);
+        assert_eq!(reassembler.pending(), 0);
+    }
+
+    #[test]
+    fn oldest_datagram_is_evicted_over_the_limit() {
+        let (a, b) = (datagram(9, &[]), datagram(10, &[]));
+        let fragmenter = Fragmenter::new(68);
+        let mut reassembler = Reassembler::new(ReassemblyConfig {
+            max_datagrams: 1,
+            ..ReassemblyConfig::default()
+        });
+        let start = Instant::now();
+        assert!(
+            reassembler
+                .push(&fragments(&a, &fragmenter)[0], start)
+                .is_none()
+        );
+        let b_fragments = fragments(&b, &fragmenter);
+        let later = start + Duration::from_millis(1);
+        assert_eq!(
+            push_all(&mut reassembler, &b_fragments, later)
+                .unwrap()
+                .data,
+            b
+        );
+        let key = FragmentKey::from_ipv4(&a).unwrap();
+        assert_eq!(
+            reassembler.take_anomalies(),
+            vec![FragmentAnomaly::Evicted { key }]
+        );
+    }
+
+    #[test]
+    fn malformed_and_unaligned_fragments_are_dropped() {
+        let packet = datagram(11, &[]);
+        let fragments = fragments(&packet, &Fragmenter::new(68));
+        let mut reassembler = Reassembler::new(ReassemblyConfig::default());
+        let now = Instant::now();
+        assert!(reassembler.push(&packet[..19], now).is_none());
+
+        // 去掉第一个分片末尾的 4 字节，数据长度不再是 8 的倍数
+        let mut short = fragments[0].clone();
+        short.truncate(short.len() - 4);
+        let total_len = short.len() as u16;
+        short[2..4].copy_from_slice(&total_len.to_be_bytes());
+        assert!(reassembler.push(&short, now).is_none());
+
+        let key = FragmentKey::from_ipv4(&packet).unwrap();
+        assert_eq!(
+            reassembler.take_anomalies(),
+            vec![
+                FragmentAnomaly::Malformed,
+                FragmentAnomaly::Unaligned {
+                    key,
+                    offset: 0,
+                    len: short.len() - IPV4_HEADER_LEN,
+                },
+            ]
+        );
+        assert_eq!(reassembler.pending(), 0);
+    }
+
+    #[test]
+    fn tiny_first_fragment_is_reported() {
+        let packet = PacketBuilder::ipv4(SOURCE, DESTINATION)
+            .identification(12)
+            .tcp(TcpLayer::new(40000, 8001))
+            .payload(vec![0x5a; 64])
+            .build()
+            .unwrap();
+        let fragmenter = Fragmenter {
+            first_size: Some(8),
+            ..Fragmenter::new(68)
+        };
+        let mut reassembler = Reassembler::new(ReassemblyConfig::default());
+        let fragments = fragments(&packet, &fragmenter);
+        let result = push_all(&mut reassembler, &fragments, Instant::now()).unwrap();
+        assert_eq!(result.data, packet);
+        let key = FragmentKey::from_ipv4(&packet).unwrap();
+        assert_eq!(
+            reassembler.take_anomalies(),
+            vec![FragmentAnomaly::Tiny { key, len: 8 }]
+        );
+    }
+
+    #[test]
+    fn oversized_datagram_with_options_is_dropped() {
+        // 最后一个分片按 20 字节首部算恰好不超长，加上第一个分片 60 字节的首部就超出了
+        let packet = datagram(13, &[1; 40]);
+        let key = FragmentKey::from_ipv4(&packet).unwrap();
+        let first = fragments(&packet, &Fragmenter::new(68)).remove(0);
+        assert_eq!(first[0] & 0x0f, 15);
+
+        let mut last = packet[..IPV4_HEADER_LEN].to_vec();
+        last[0] = 0x45;
+        last.extend_from_slice(&[0x5a; 11]);
+        let total_len = last.len() as u16;
+        last[2..4].copy_from_slice(&total_len.to_be_bytes());
+        let offset = ((MAX_DATA_END - 11) / 8) as u16;
+        last[6..8].copy_from_slice(&offset.to_be_bytes());
+
+        let mut reassembler = Reassembler::new(ReassemblyConfig::default());
+        let now = Instant::now();
+        assert!(reassembler.push(&last, now).is_none());
+        assert!(reassembler.take_anomalies().is_empty());
+        assert!(reassembler.push(&first, now).is_none());
+        assert_eq!(
+            reassembler.take_anomalies(),
+            vec![FragmentAnomaly::TooLong {
+                key,
+                end: MAX_DATA_END
+            }]
+        );
+        assert_eq!(reassembler.pending(), 0);
+    }
+
+    #[test]
+    fn config_from_lookup() {
+        let load = |value: Option<&str>| {
+            ReassemblyConfig::from_lookup(|name| {
+                value
+                    .filter(|_| name == REASSEMBLY_TIMEOUT_ENV)
+                    .map(str::to_string)
+            })
+        };
+        assert_eq!(
+            load(Some("1.5")).unwrap().timeout,
+            Duration::from_millis(1500)
+        );
+        for value in ["0", "-1", "nan", "inf", "1e300", "soon"] {
+            assert_eq!(
+                load(Some(value)),
+                Err(ReassemblyError::BadTimeout(value.to_string()))
+            );
+        }
+        assert_eq!(load(None).unwrap(), ReassemblyConfig::default());
+    }
+}
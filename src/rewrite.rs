@@ -0,0 +1,513 @@
+//! 改写已有的 IPv4 数据包
+//!
+//! 回放抓包时用来改写地址和端口、插入标记选项并重新计算校验和。未分片的数据报
+//! 重新计算全部校验和；分片缺少完整的传输层数据，只在第一个分片中按 RFC 1624
+//! 增量更新传输层校验和，也不能插入 TCP 选项。
+
+use std::fmt;
+use std::net::Ipv4Addr;
+
+use crate::builder::{IPV4_HEADER_LEN, TCP_HEADER_LEN};
+use crate::checksum;
+use crate::ipv4_option::{self, Ipv4Option, Ipv4OptionError};
+use crate::tcp_option::{self, TcpOption, TcpOptionError};
+
+const PROTOCOL_ICMP: u8 = 1;
+const PROTOCOL_TCP: u8 = 6;
+const PROTOCOL_UDP: u8 = 17;
+
+/// 首部 (含选项) 的最大长度，IPv4 与 TCP 相同
+const MAX_HEADER_LEN: usize = 60;
+
+/// 改写错误
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RewriteError {
+    /// 不是完整的 IPv4 数据包
+    Truncated,
+    /// 分片无法插入 TCP 选项
+    Fragmented,
+    /// 不是 TCP 报文段
+    NotTcp,
+    /// 插入选项后首部超过 60 字节
+    OptionsFull(usize),
+    /// 要插入的 IP 选项本身无法编码
+    BadOption(Ipv4OptionError),
+}
+
+impl fmt::Display for RewriteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RewriteError::Truncated => write!(f, "数据包不完整"),
+            RewriteError::Fragmented => write!(f, "分片无法插入 TCP 选项"),
+            RewriteError::NotTcp => write!(f, "不是 TCP 报文段"),
+            RewriteError::OptionsFull(len) => {
+                write!(f, "插入选项后首部长 {} 字节, 超过 60 字节", len)
+            }
+            RewriteError::BadOption(e) => write!(f, "IP 选项无法编码: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for RewriteError {}
+
+/// 一条改写规则：`from` 为 `None` 时改写所有值
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mapping<T> {
+    pub from: Option<T>,
+    pub to: T,
+}
+
+impl<T: Copy + PartialEq> Mapping<T> {
+    /// 按第一条匹配的规则改写 `value`，没有匹配时原样返回
+    pub fn apply(mappings: &[Mapping<T>], value: T) -> T {
+        mappings
+            .iter()
+            .find(|mapping| mapping.from.is_none_or(|from| from == value))
+            .map_or(value, |mapping| mapping.to)
+    }
+}
+
+/// 地址和端口的改写规则
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RewriteRules {
+    pub source: Vec<Mapping<Ipv4Addr>>,
+    pub destination: Vec<Mapping<Ipv4Addr>>,
+    /// 只作用于 TCP / UDP
+    pub source_port: Vec<Mapping<u16>>,
+    pub destination_port: Vec<Mapping<u16>>,
+}
+
+impl RewriteRules {
+    pub fn is_empty(&self) -> bool {
+        self.source.is_empty()
+            && self.destination.is_empty()
+            && self.source_port.is_empty()
+            && self.destination_port.is_empty()
+    }
+}
+
+/// 检查首部并返回 (首部长度, 总长度)
+fn lengths(packet: &[u8]) -> Result<(usize, usize), RewriteError> {
+    if packet.len() < IPV4_HEADER_LEN || packet[0] >> 4 != 4 {
+        return Err(RewriteError::Truncated);
+    }
+    let header_len = (packet[0] & 0x0f) as usize * 4;
+    let total_len = u16::from_be_bytes([packet[2], packet[3]]) as usize;
+    if header_len < IPV4_HEADER_LEN || total_len < header_len || total_len > packet.len() {
+        return Err(RewriteError::Truncated);
+    }
+    Ok((header_len, total_len))
+}
+
+/// 是否为分片 (设置了 MF 或偏移不为 0)
+pub fn is_fragment(packet: &[u8]) -> bool {
+    u16::from_be_bytes([packet[6], packet[7]]) & 0x3fff != 0
+}
+
+/// 是否为第一个分片或未分片的数据报
+fn is_first(packet: &[u8]) -> bool {
+    u16::from_be_bytes([packet[6], packet[7]]) & 0x1fff == 0
+}
+
+/// 按规则改写地址和端口，并更新校验和
+///
+/// 未分片时重新计算全部校验和，原来就错误的校验和也会被改正；分片时只重算
+/// IP 首部校验和，第一个分片中的传输层校验和按改动增量更新。
+pub fn rewrite(packet: &mut [u8], rules: &RewriteRules) -> Result<(), RewriteError> {
+    let (header_len, total_len) = lengths(packet)?;
+    let old_addresses: [u8; 8] = packet[12..20].try_into().unwrap();
+    let source = Ipv4Addr::new(packet[12], packet[13], packet[14], packet[15]);
+    let destination = Ipv4Addr::new(packet[16], packet[17], packet[18], packet[19]);
+    let source = Mapping::apply(&rules.source, source);
+    let destination = Mapping::apply(&rules.destination, destination);
+    packet[12..16].copy_from_slice(&source.octets());
+    packet[16..20].copy_from_slice(&destination.octets());
+
+    let protocol = packet[9];
+    let has_ports = matches!(protocol, PROTOCOL_TCP | PROTOCOL_UDP)
+        && is_first(packet)
+        && total_len >= header_len + 4;
+    let mut old_ports = [0u8; 4];
+    if has_ports {
+        let ports = &mut packet[header_len..header_len + 4];
+        old_ports.copy_from_slice(ports);
+        let sport = Mapping::apply(&rules.source_port, u16::from_be_bytes([ports[0], ports[1]]));
+        let dport = Mapping::apply(
+            &rules.destination_port,
+            u16::from_be_bytes([ports[2], ports[3]]),
+        );
+        ports[..2].copy_from_slice(&sport.to_be_bytes());
+        ports[2..].copy_from_slice(&dport.to_be_bytes());
+    }
+
+    if !is_fragment(packet) {
+        return recompute_checksums(packet);
+    }
+    let offset = match protocol {
+        PROTOCOL_TCP => 16,
+        PROTOCOL_UDP => 6,
+        _ => 0,
+    };
+    if has_ports && total_len >= header_len + offset + 2 {
+        let field = header_len + offset;
+        let old = u16::from_be_bytes([packet[field], packet[field + 1]]);
+        // UDP 校验和为 0 表示未计算，保持不变
+        if !(protocol == PROTOCOL_UDP && old == 0) {
+            let mut removed = old_addresses.to_vec();
+            removed.extend_from_slice(&old_ports);
+            let mut added = packet[12..20].to_vec();
+            added.extend_from_slice(&packet[header_len..header_len + 4]);
+            let mut check = checksum::update(old, &removed, &added);
+            if protocol == PROTOCOL_UDP && check == 0 {
+                check = 0xFFFF;
+            }
+            packet[field..field + 2].copy_from_slice(&check.to_be_bytes());
+        }
+    }
+    write_header_checksum(packet, header_len);
+    Ok(())
+}
+
+/// 重新计算 IP 首部校验和，以及未分片时的 TCP / UDP / ICMP 校验和
+///
+/// 原来 UDP 校验和为 0 (未计算) 时保持为 0。
+pub fn recompute_checksums(packet: &mut [u8]) -> Result<(), RewriteError> {
+    let (header_len, total_len) = lengths(packet)?;
+    write_header_checksum(packet, header_len);
+    if is_fragment(packet) {
+        return Ok(());
+    }
+    let source = Ipv4Addr::new(packet[12], packet[13], packet[14], packet[15]);
+    let destination = Ipv4Addr::new(packet[16], packet[17], packet[18], packet[19]);
+    let protocol = packet[9];
+    let segment = &mut packet[header_len..total_len];
+    let offset = match protocol {
+        PROTOCOL_TCP => 16,
+        PROTOCOL_UDP => 6,
+        PROTOCOL_ICMP => 2,
+        _ => return Ok(()),
+    };
+    if segment.len() < offset + 2 {
+        return Ok(());
+    }
+    if protocol == PROTOCOL_UDP && segment[offset..offset + 2] == [0, 0] {
+        return Ok(());
+    }
+    segment[offset..offset + 2].fill(0);
+    let check = match protocol {
+        PROTOCOL_TCP => checksum::tcp_checksum(source, destination, segment),
+        PROTOCOL_UDP => checksum::udp_checksum(source, destination, segment),
+        _ => checksum::checksum(segment),
+    };
+    segment[offset..offset + 2].copy_from_slice(&check.to_be_bytes());
+    Ok(())
+}
+
+fn write_header_checksum(packet: &mut [u8], header_len: usize) {
+    packet[10..12].fill(0);
+    let check = checksum::checksum(&packet[..header_len]);
+    packet[10..12].copy_from_slice(&check.to_be_bytes());
+}
+
+/// 把选项插入到 IP 首部已有选项之前，以 NOP 补齐并更新首部长度和总长度
+///
+/// 不重算校验和，改写完成后调用 [`recompute_checksums`]。
+pub fn insert_ip_option(packet: &mut Vec<u8>, option: &Ipv4Option) -> Result<(), RewriteError> {
+    let (header_len, total_len) = lengths(packet)?;
+    packet.truncate(total_len);
+    let mut bytes = Vec::new();
+    match option.write_to(&mut bytes) {
+        Ok(()) => {}
+        Err(Ipv4OptionError::TooLong(len)) => {
+            return Err(RewriteError::OptionsFull(header_len + len));
+        }
+        Err(e) => return Err(RewriteError::BadOption(e)),
+    }
+    while !bytes.len().is_multiple_of(4) {
+        bytes.push(ipv4_option::KIND_NOP);
+    }
+    let new_header_len = header_len + bytes.len();
+    if new_header_len > MAX_HEADER_LEN {
+        return Err(RewriteError::OptionsFull(new_header_len));
+    }
+    packet.splice(IPV4_HEADER_LEN..IPV4_HEADER_LEN, bytes);
+    packet[0] = 0x40 | (new_header_len / 4) as u8;
+    set_total_len(packet)
+}
+
+/// 把选项插入到 TCP 首部已有选项之前，以 NOP 补齐并更新 data offset 和 IP 总长度
+///
+/// 不重算校验和，改写完成后调用 [`recompute_checksums`]。
+pub fn insert_tcp_option(packet: &mut Vec<u8>, option: &TcpOption) -> Result<(), RewriteError> {
+    let (header_len, total_len) = lengths(packet)?;
+    if packet[9] != PROTOCOL_TCP {
+        return Err(RewriteError::NotTcp);
+    }
+    if is_fragment(packet) {
+        return Err(RewriteError::Fragmented);
+    }
+    if total_len < header_len + TCP_HEADER_LEN {
+        return Err(RewriteError::Truncated);
+    }
+    packet.truncate(total_len);
+    let tcp_header_len = (packet[header_len + 12] >> 4) as usize * 4;
+    if tcp_header_len < TCP_HEADER_LEN || total_len < header_len + tcp_header_len {
+        return Err(RewriteError::Truncated);
+    }
+    let mut bytes = Vec::new();
+    // write_to 只会因选项过长返回 TooLong
+    if let Err(TcpOptionError::TooLong(len)) = option.write_to(&mut bytes) {
+        return Err(RewriteError::OptionsFull(tcp_header_len + len));
+    }
+    while !bytes.len().is_multiple_of(4) {
+        bytes.push(tcp_option::KIND_NOP);
+    }
+    let new_tcp_header_len = tcp_header_len + bytes.len();
+    if new_tcp_header_len > MAX_HEADER_LEN {
+        return Err(RewriteError::OptionsFull(new_tcp_header_len));
+    }
+    let at = header_len + TCP_HEADER_LEN;
+    packet.splice(at..at, bytes);
+    let offset = &mut packet[header_len + 12];
+    *offset = (*offset & 0x0f) | (((new_tcp_header_len / 4) as u8) << 4);
+    set_total_len(packet)
+}
+
+fn set_total_len(packet: &mut [u8]) -> Result<(), RewriteError> {
+    let total_len = u16::try_from(packet.len()).map_err(|_| RewriteError::Truncated)?;
+    packet[2..4].copy_from_slice(&total_len.to_be_bytes());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::{IcmpLayer, PacketBuilder, TcpLayer, UdpLayer};
+    use crate::fragment::Fragmenter;
+    use crate::marker::{self, Marker};
+    use crate::reassembly::{Reassembler, ReassemblyConfig};
+    use crate::validate::{ChecksumStatus, validate_ipv4};
+    use std::time::Instant;
+
+    const SOURCE: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 1);
+    const DESTINATION: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 2);
+
+    fn builder() -> PacketBuilder {
+        PacketBuilder::ipv4(SOURCE, DESTINATION)
+            .identification(0x4242)
+            .payload((0..120).map(|i| i as u8).collect())
+    }
+
+    fn udp() -> Vec<u8> {
+        builder().udp(UdpLayer::new(40000, 8001)).build().unwrap()
+    }
+
+    fn tcp() -> Vec<u8> {
+        builder().tcp(TcpLayer::new(40000, 8001)).build().unwrap()
+    }
+
+    fn icmp() -> Vec<u8> {
+        builder().icmp(IcmpLayer::new(8, 0)).build().unwrap()
+    }
+
+    fn rules() -> RewriteRules {
+        RewriteRules {
+            source: vec![Mapping {
+                from: Some(SOURCE),
+                to: Ipv4Addr::new(192, 0, 2, 1),
+            }],
+            destination: vec![Mapping {
+                from: None,
+                to: Ipv4Addr::new(198, 51, 100, 7),
+            }],
+            source_port: vec![Mapping {
+                from: Some(1),
+                to: 2,
+            }],
+            destination_port: vec![Mapping {
+                from: Some(8001),
+                to: 9001,
+            }],
+        }
+    }
+
+    fn marker_option() -> Ipv4Option {
+        Ipv4Option::Unknown {
+            kind: marker::IPV4_OPTION_KIND,
+            data: Marker::new(7, 1).encode(),
+        }
+    }
+
+    fn assert_valid(packet: &[u8]) {
+        let report = validate_ipv4(packet).unwrap();
+        assert!(report.all_valid(), "{}", report);
+    }
+
+    #[test]
+    fn mapping_uses_first_match() {
+        let mappings = [
+            Mapping {
+                from: Some(1),
+                to: 10,
+            },
+            Mapping { from: None, to: 99 },
+            Mapping {
+                from: Some(2),
+                to: 20,
+            },
+        ];
+        assert_eq!(Mapping::apply(&mappings, 1), 10);
+        assert_eq!(Mapping::apply(&mappings, 2), 99);
+        assert_eq!(Mapping::apply(&mappings[2..], 3), 3);
+        assert!(RewriteRules::default().is_empty());
+        assert!(!rules().is_empty());
+    }
+
+    #[test]
+    fn rewrite_keeps_checksums_valid() {
+        for mut packet in [udp(), tcp(), icmp()] {
+            rewrite(&mut packet, &rules()).unwrap();
+            assert_eq!(packet[12..16], [192, 0, 2, 1]);
+            assert_eq!(packet[16..20], [198, 51, 100, 7]);
+            assert_valid(&packet);
+        }
+        let mut packet = udp();
+        rewrite(&mut packet, &rules()).unwrap();
+        assert_eq!(packet[20..24], [0x9c, 0x40, 0x23, 0x29]);
+    }
+
+    #[test]
+    fn rewrite_fixes_wrong_checksums_but_keeps_absent_udp_checksum() {
+        let mut packet = tcp();
+        packet[10] ^= 0xff;
+        packet[36] ^= 0xff;
+        rewrite(&mut packet, &RewriteRules::default()).unwrap();
+        assert_valid(&packet);
+
+        let mut packet = udp();
+        packet[26..28].fill(0);
+        rewrite(&mut packet, &rules()).unwrap();
+        assert_eq!(packet[26..28], [0, 0]);
+        let report = validate_ipv4(&packet).unwrap();
+        assert_eq!(report.transport.unwrap().1, ChecksumStatus::Absent);
+        assert!(report.all_valid());
+    }
+
+    #[test]
+    fn rewritten_fragments_reassemble_with_valid_checksums() {
+        for packet in [udp(), tcp()] {
+            let mut fragments = Fragmenter::new(68).fragment(&packet).unwrap();
+            assert!(fragments.len() > 1);
+            let mut reassembler = Reassembler::new(ReassemblyConfig::default());
+            let mut datagram = None;
+            for fragment in &mut fragments {
+                rewrite(fragment, &rules()).unwrap();
+                let header = validate_ipv4(fragment).unwrap();
+                assert!(header.ip.is_valid());
+                datagram = reassembler.push(fragment, Instant::now());
+            }
+            let datagram = datagram.unwrap();
+            assert_eq!(datagram.data[16..20], [198, 51, 100, 7]);
+            assert_valid(&datagram.data);
+        }
+    }
+
+    #[test]
+    fn inserted_options_keep_checksums_valid() {
+        let mut packet = tcp();
+        insert_ip_option(&mut packet, &marker_option()).unwrap();
+        insert_tcp_option(&mut packet, &TcpOption::Mss(1400)).unwrap();
+        recompute_checksums(&mut packet).unwrap();
+        assert_eq!(packet[0], 0x45 + 5);
+        assert_eq!(
+            u16::from_be_bytes([packet[2], packet[3]]) as usize,
+            packet.len()
+        );
+        assert_eq!(packet[20], marker::IPV4_OPTION_KIND);
+        assert_eq!(packet[40 + 12] >> 4, 6);
+        assert_eq!(packet[60..64], [2, 4, 0x05, 0x78]);
+        assert_valid(&packet);
+
+        // 原有选项排在插入的选项之后，总长度字段之后的填充被去掉
+        let mut packet = builder()
+            .ip_options(vec![0x94, 4, 0, 0])
+            .udp(UdpLayer::new(1, 2))
+            .build()
+            .unwrap();
+        packet.extend_from_slice(&[0; 6]);
+        insert_ip_option(&mut packet, &Ipv4Option::RouterAlert(0)).unwrap();
+        recompute_checksums(&mut packet).unwrap();
+        assert_eq!(packet[20..28], [0x94, 4, 0, 0, 0x94, 4, 0, 0]);
+        assert_eq!(
+            u16::from_be_bytes([packet[2], packet[3]]) as usize,
+            packet.len()
+        );
+        assert_valid(&packet);
+    }
+
+    #[test]
+    fn options_full_at_sixty_bytes() {
+        // 已有 36 字节 IP 选项，再插入 4 字节恰好到 60 字节
+        let mut packet = builder()
+            .ip_options([1; 36].to_vec())
+            .udp(UdpLayer::new(1, 2))
+            .build()
+            .unwrap();
+        insert_ip_option(&mut packet, &Ipv4Option::RouterAlert(0)).unwrap();
+        assert_eq!(packet[0], 0x4f);
+        let before = packet.clone();
+        assert_eq!(
+            insert_ip_option(&mut packet, &Ipv4Option::Nop),
+            Err(RewriteError::OptionsFull(64))
+        );
+        assert_eq!(packet, before);
+        let nop_tlv = Ipv4Option::Unknown {
+            kind: 1,
+            data: vec![0; 2],
+        };
+        assert_eq!(
+            insert_ip_option(&mut packet, &nop_tlv),
+            Err(RewriteError::BadOption(Ipv4OptionError::SingleByteKind(1)))
+        );
+        assert_eq!(packet, before);
+        recompute_checksums(&mut packet).unwrap();
+        assert_valid(&packet);
+
+        let mut layer = TcpLayer::new(1, 2);
+        layer.options = [1; 36].to_vec();
+        let mut packet = builder().tcp(layer).build().unwrap();
+        insert_tcp_option(&mut packet, &TcpOption::Mss(1400)).unwrap();
+        assert_eq!(packet[20 + 12] >> 4, 15);
+        assert_eq!(
+            insert_tcp_option(&mut packet, &TcpOption::SackPermitted),
+            Err(RewriteError::OptionsFull(64))
+        );
+        recompute_checksums(&mut packet).unwrap();
+        assert_valid(&packet);
+    }
+
+    #[test]
+    fn tcp_option_errors() {
+        let option = TcpOption::Mss(1400);
+        assert_eq!(
+            insert_tcp_option(&mut udp(), &option),
+            Err(RewriteError::NotTcp)
+        );
+        let mut fragment = Fragmenter::new(68).fragment(&tcp()).unwrap().remove(0);
+        assert!(is_fragment(&fragment));
+        assert_eq!(
+            insert_tcp_option(&mut fragment, &option),
+            Err(RewriteError::Fragmented)
+        );
+        let mut short = tcp();
+        short.truncate(30);
+        assert_eq!(
+            insert_tcp_option(&mut short, &option),
+            Err(RewriteError::Truncated)
+        );
+        assert_eq!(
+            rewrite(&mut [0x45; 19], &rules()),
+            Err(RewriteError::Truncated)
+        );
+    }
+}
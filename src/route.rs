@@ -0,0 +1,338 @@
+//! 出接口与源地址选择
+//!
+//! 读取内核路由表 `/proc/net/route` (main 表) 做最长前缀匹配，再从出接口的地址中
+//! 挑选源地址：下一跳在接口某个子网内时用该子网的地址，否则用接口的第一个 IPv4 地址。
+//! 本机地址和 127.0.0.0/8 的路由在 local 表中，`/proc/net/route` 里没有，单独处理。
+
+use std::fmt;
+use std::net::Ipv4Addr;
+
+use pnet::datalink::{self, NetworkInterface};
+use pnet::ipnetwork::IpNetwork;
+
+/// 内核 IPv4 路由表
+pub const ROUTE_TABLE_PATH: &str = "/proc/net/route";
+
+const RTF_UP: u32 = 0x0001;
+const RTF_GATEWAY: u32 = 0x0002;
+const RTF_REJECT: u32 = 0x0200;
+
+/// 路由查询错误
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RouteError {
+    /// 无法读取路由表
+    ReadTable(String),
+    /// 没有到目的地址的路由
+    NoRoute(Ipv4Addr),
+    /// 出接口不存在
+    UnknownInterface(String),
+    /// 出接口没有 IPv4 地址
+    NoSourceAddress(String),
+}
+
+impl fmt::Display for RouteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RouteError::ReadTable(e) => write!(f, "无法读取路由表 {}: {}", ROUTE_TABLE_PATH, e),
+            RouteError::NoRoute(addr) => write!(f, "没有到 {} 的路由", addr),
+            RouteError::UnknownInterface(name) => write!(f, "接口不存在: {}", name),
+            RouteError::NoSourceAddress(name) => write!(f, "接口 {} 没有 IPv4 地址", name),
+        }
+    }
+}
+
+impl std::error::Error for RouteError {}
+
+/// 路由表中的一条路由
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Route {
+    pub interface: String,
+    pub destination: Ipv4Addr,
+    pub prefix_len: u8,
+    /// 直连路由为 `None`
+    pub gateway: Option<Ipv4Addr>,
+    pub metric: u32,
+    /// reject / unreachable 路由：匹配到它的目的地址内核拒绝发送
+    pub reject: bool,
+}
+
+impl Route {
+    pub fn contains(&self, addr: Ipv4Addr) -> bool {
+        let mask = prefix_mask(self.prefix_len);
+        u32::from(addr) & mask == u32::from(self.destination) & mask
+    }
+}
+
+/// 路由查询结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Egress {
+    pub interface: String,
+    pub source: Ipv4Addr,
+    /// 经网关转发时的网关地址，直连时为 `None`
+    pub gateway: Option<Ipv4Addr>,
+}
+
+impl Egress {
+    /// 二层下一跳：有网关时为网关，否则为目的地址本身
+    pub fn next_hop(&self, destination: Ipv4Addr) -> Ipv4Addr {
+        self.gateway.unwrap_or(destination)
+    }
+}
+
+impl fmt::Display for Egress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "dev {} src {}", self.interface, self.source)?;
+        if let Some(gateway) = self.gateway {
+            write!(f, " via {}", gateway)?;
+        }
+        Ok(())
+    }
+}
+
+fn prefix_mask(prefix_len: u8) -> u32 {
+    u32::MAX
+        .checked_shl(32 - prefix_len.min(32) as u32)
+        .unwrap_or(0)
+}
+
+/// `/proc/net/route` 中的地址是按主机字节序打印的网络序整数
+fn parse_proc_addr(hex: &str) -> Option<Ipv4Addr> {
+    u32::from_str_radix(hex, 16)
+        .ok()
+        .map(|value| Ipv4Addr::from(value.to_ne_bytes()))
+}
+
+/// 解析 `/proc/net/route` 的内容，跳过未启用的路由
+pub fn parse_route_table(text: &str) -> Vec<Route> {
+    text.lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 8 {
+                return None;
+            }
+            let flags = u32::from_str_radix(fields[3], 16).ok()?;
+            if flags & RTF_UP == 0 {
+                return None;
+            }
+            let mask = u32::from(parse_proc_addr(fields[7])?);
+            Some(Route {
+                interface: fields[0].to_string(),
+                destination: parse_proc_addr(fields[1])?,
+                prefix_len: mask.count_ones() as u8,
+                gateway: (flags & RTF_GATEWAY != 0)
+                    .then(|| parse_proc_addr(fields[2]))
+                    .flatten(),
+                metric: fields[6].parse().ok()?,
+                reject: flags & RTF_REJECT != 0,
+            })
+        })
+        .collect()
+}
+
+pub fn read_route_table() -> Result<Vec<Route>, RouteError> {
+    std::fs::read_to_string(ROUTE_TABLE_PATH)
+        .map(|text| parse_route_table(&text))
+        .map_err(|e| RouteError::ReadTable(e.to_string()))
+}
+
+/// 在路由中选出到 `destination` 的最佳路由：最长前缀优先，其次 metric 最小
+///
+/// 最佳匹配是 reject 路由时内核不会发送，返回 `None`。
+pub fn best_route(routes: &[Route], destination: Ipv4Addr) -> Option<&Route> {
+    longest_match(routes, destination).filter(|route| !route.reject)
+}
+
+/// 最长前缀匹配，含 reject 路由
+fn longest_match(routes: &[Route], destination: Ipv4Addr) -> Option<&Route> {
+    routes
+        .iter()
+        .filter(|route| route.contains(destination))
+        .max_by(|a, b| {
+            a.prefix_len
+                .cmp(&b.prefix_len)
+                .then(b.metric.cmp(&a.metric))
+        })
+}
+
+/// 从接口地址中选源地址：优先与 `next_hop` 同一子网的地址
+fn pick_source(interface: &NetworkInterface, next_hop: Ipv4Addr) -> Option<Ipv4Addr> {
+    let v4: Vec<_> = interface
+        .ips
+        .iter()
+        .filter_map(|ip| match ip {
+            IpNetwork::V4(net) => Some(*net),
+            IpNetwork::V6(_) => None,
+        })
+        .collect();
+    v4.iter()
+        .find(|net| net.contains(next_hop))
+        .or(v4.first())
+        .map(|net| net.ip())
+}
+
+/// 目的地址是本机地址时走回环接口，源地址即目的地址
+fn local_egress(interfaces: &[NetworkInterface], destination: Ipv4Addr) -> Option<Egress> {
+    let is_local = destination.is_loopback()
+        || interfaces
+            .iter()
+            .flat_map(|iface| &iface.ips)
+            .any(|ip| ip.ip() == destination);
+    if !is_local {
+        return None;
+    }
+    let interface = interfaces
+        .iter()
+        .find(|iface| iface.is_loopback())
+        .map_or_else(|| "lo".to_string(), |iface| iface.name.clone());
+    Some(Egress {
+        interface,
+        source: destination,
+        gateway: None,
+    })
+}
+
+fn egress_on(
+    interfaces: &[NetworkInterface],
+    name: &str,
+    destination: Ipv4Addr,
+    gateway: Option<Ipv4Addr>,
+) -> Result<Egress, RouteError> {
+    let interface = interfaces
+        .iter()
+        .find(|iface| iface.name == name)
+        .ok_or_else(|| RouteError::UnknownInterface(name.to_string()))?;
+    let source = pick_source(interface, gateway.unwrap_or(destination))
+        .ok_or_else(|| RouteError::NoSourceAddress(name.to_string()))?;
+    Ok(Egress {
+        interface: name.to_string(),
+        source,
+        gateway,
+    })
+}
+
+/// 按内核路由表查询到 `destination` 的出接口、源地址和网关
+pub fn lookup(destination: Ipv4Addr) -> Result<Egress, RouteError> {
+    let interfaces = datalink::interfaces();
+    if let Some(egress) = local_egress(&interfaces, destination) {
+        return Ok(egress);
+    }
+    let routes = read_route_table()?;
+    let route = best_route(&routes, destination).ok_or(RouteError::NoRoute(destination))?;
+    egress_on(&interfaces, &route.interface, destination, route.gateway)
+}
+
+/// 指定出接口查询：只考虑该接口上的路由和 reject 路由，没有路由时视为直连
+pub fn lookup_via(destination: Ipv4Addr, interface: &str) -> Result<Egress, RouteError> {
+    let interfaces = datalink::interfaces();
+    let routes: Vec<Route> = read_route_table()?
+        .into_iter()
+        .filter(|route| route.interface == interface || route.reject)
+        .collect();
+    let gateway = match longest_match(&routes, destination) {
+        Some(route) if route.reject => return Err(RouteError::NoRoute(destination)),
+        route => route.and_then(|route| route.gateway),
+    };
+    egress_on(&interfaces, interface, destination, gateway)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 按 `/proc/net/route` 的格式打印地址
+    fn proc_addr(addr: Ipv4Addr) -> String {
+        format!("{:08X}", u32::from_ne_bytes(addr.octets()))
+    }
+
+    /// (接口, 目的地址, 网关, 标志, metric, 前缀长度)
+    type Row<'a> = (&'a str, [u8; 4], [u8; 4], u32, u32, u8);
+
+    fn table(rows: &[Row]) -> String {
+        let mut text =
+            "Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\t\tMTU\tWindow\tIRTT\n"
+                .to_string();
+        for (iface, destination, gateway, flags, metric, prefix_len) in rows {
+            text += &format!(
+                "{}\t{}\t{}\t{:04X}\t0\t0\t{}\t{}\t0\t0\t0\n",
+                iface,
+                proc_addr(Ipv4Addr::from(*destination)),
+                proc_addr(Ipv4Addr::from(*gateway)),
+                flags,
+                metric,
+                proc_addr(Ipv4Addr::from(prefix_mask(*prefix_len)))
+            );
+        }
+        text
+    }
+
+    fn routes() -> Vec<Route> {
+        parse_route_table(&table(&[
+            ("eth0", [0, 0, 0, 0], [192, 168, 1, 1], 0x0003, 100, 0),
+            ("wlan0", [0, 0, 0, 0], [10, 0, 0, 1], 0x0003, 600, 0),
+            ("eth0", [192, 168, 1, 0], [0, 0, 0, 0], 0x0001, 100, 24),
+            ("eth1", [10, 8, 0, 0], [0, 0, 0, 0], 0x0001, 50, 16),
+            ("tun0", [10, 8, 1, 0], [0, 0, 0, 0], 0x0001, 500, 24),
+            ("lo", [10, 9, 0, 0], [0, 0, 0, 0], 0x0201, 0, 16),
+            ("eth1", [10, 9, 5, 0], [0, 0, 0, 0], 0x0001, 0, 24),
+            ("eth2", [172, 16, 0, 0], [0, 0, 0, 0], 0x0000, 0, 12),
+        ]))
+    }
+
+    #[test]
+    #[cfg(target_endian = "little")]
+    fn addresses_are_in_host_order() {
+        let text = "Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\t\tMTU\tWindow\tIRTT\n\
+                    eth0\t0001A8C0\t0101A8C0\t0003\t0\t0\t100\t00FFFFFF\t0\t0\t0\n";
+        assert_eq!(
+            parse_route_table(text),
+            vec![Route {
+                interface: "eth0".to_string(),
+                destination: Ipv4Addr::new(192, 168, 1, 0),
+                prefix_len: 24,
+                gateway: Some(Ipv4Addr::new(192, 168, 1, 1)),
+                metric: 100,
+                reject: false,
+            }]
+        );
+    }
+
+    #[test]
+    fn parse_flags_reject_and_skips_down_and_malformed_routes() {
+        let routes = routes();
+        assert_eq!(routes.len(), 7);
+        assert!(routes.iter().all(|route| route.interface != "eth2"));
+        let rejects: Vec<&str> = routes
+            .iter()
+            .filter(|route| route.reject)
+            .map(|route| route.interface.as_str())
+            .collect();
+        assert_eq!(rejects, vec!["lo"]);
+        assert_eq!(routes[0].gateway, Some(Ipv4Addr::new(192, 168, 1, 1)));
+        assert_eq!(routes[0].prefix_len, 0);
+        // 没有 RTF_GATEWAY 时忽略网关字段
+        assert_eq!(routes[2].gateway, None);
+        assert_eq!(routes[2].destination, Ipv4Addr::new(192, 168, 1, 0));
+
+        assert!(parse_route_table("Iface\tDestination\neth0\tzz\n").is_empty());
+    }
+
+    #[test]
+    fn longest_prefix_before_metric() {
+        let routes = routes();
+        let best = |addr: [u8; 4]| {
+            best_route(&routes, Ipv4Addr::from(addr)).map(|route| route.interface.as_str())
+        };
+        // /24 胜过 metric 更小的 /16
+        assert_eq!(best([10, 8, 1, 5]), Some("tun0"));
+        assert_eq!(best([10, 8, 2, 5]), Some("eth1"));
+        // 同为默认路由时 metric 小的胜出
+        assert_eq!(best([8, 8, 8, 8]), Some("eth0"));
+        assert_eq!(best([192, 168, 1, 20]), Some("eth0"));
+        // 最佳匹配是 reject 路由时没有路由，不退回默认路由
+        assert_eq!(best([10, 9, 0, 1]), None);
+        // reject 前缀内更长的前缀照常使用
+        assert_eq!(best([10, 9, 5, 1]), Some("eth1"));
+        assert_eq!(best_route(&[], Ipv4Addr::new(8, 8, 8, 8)), None);
+    }
+}
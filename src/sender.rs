@@ -0,0 +1,284 @@
+//! 原始数据包发送
+//!
+//! [`RawSender`] 使用 `IPPROTO_RAW` 套接字 (隐含 IP_HDRINCL) 发送构造好的完整
+//! IPv4 数据包，内核不会改写首部，只在源地址为 0 时填入出接口地址、ID 为 0 时
+//! 另选 ID，并总是重新计算首部校验和。
+//!
+//! [`LinkSender`] 在指定接口上直接写以太网帧，IP 首部按构造的原样发出；下一跳的
+//! MAC 地址查内核邻居表，查不到时自己发 ARP 请求。
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::mem;
+use std::net::Ipv4Addr;
+use std::os::fd::RawFd;
+use std::time::{Duration, Instant};
+
+use pnet::datalink::{self, Channel, DataLinkReceiver, DataLinkSender, NetworkInterface};
+use pnet::ipnetwork::IpNetwork;
+use pnet::packet::ethernet::{EtherTypes, MutableEthernetPacket};
+use pnet::util::MacAddr;
+
+use crate::neighbor;
+
+/// 以太网首部长度
+const ETHERNET_HEADER_LEN: usize = 14;
+/// 每次 ARP 请求等待应答的时间
+const ARP_REPLY_TIMEOUT: Duration = Duration::from_millis(500);
+/// ARP 请求的次数
+const ARP_ATTEMPTS: u32 = 3;
+
+/// IPv4 原始套接字
+#[derive(Debug)]
+pub struct RawSender {
+    fd: RawFd,
+}
+
+impl RawSender {
+    pub fn new() -> io::Result<Self> {
+        let fd = unsafe { libc::socket(libc::AF_INET, libc::SOCK_RAW, libc::IPPROTO_RAW) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let sender = RawSender { fd };
+
+        // 告诉内核不要自动添加IP头部
+        let on: libc::c_int = 1;
+        let ret = unsafe {
+            libc::setsockopt(
+                fd,
+                libc::IPPROTO_IP,
+                libc::IP_HDRINCL,
+                &on as *const _ as *const libc::c_void,
+                mem::size_of_val(&on) as libc::socklen_t,
+            )
+        };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(sender)
+    }
+
+    /// 只从指定接口发送 (SO_BINDTODEVICE)
+    pub fn bind_to_device(&self, interface: &str) -> io::Result<()> {
+        let ret = unsafe {
+            libc::setsockopt(
+                self.fd,
+                libc::SOL_SOCKET,
+                libc::SO_BINDTODEVICE,
+                interface.as_ptr() as *const libc::c_void,
+                interface.len() as libc::socklen_t,
+            )
+        };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// 发送完整的 IPv4 数据包，目的地址取自首部
+    ///
+    /// # 返回
+    /// 发送的字节数
+    pub fn send(&self, packet: &[u8]) -> io::Result<usize> {
+        if packet.len() < 20 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "数据包短于 IPv4 首部",
+            ));
+        }
+        let destination = Ipv4Addr::new(packet[16], packet[17], packet[18], packet[19]);
+        self.send_to(packet, destination)
+    }
+
+    /// 把完整的 IPv4 数据包发往 `destination`
+    pub fn send_to(&self, packet: &[u8], destination: Ipv4Addr) -> io::Result<usize> {
+        let addr = libc::sockaddr_in {
+            sin_family: libc::AF_INET as libc::sa_family_t,
+            sin_port: 0, // 端口对IP层不重要
+            sin_addr: libc::in_addr {
+                s_addr: u32::from_ne_bytes(destination.octets()), // 网络字节序
+            },
+            sin_zero: [0; 8],
+        };
+        let ret = unsafe {
+            libc::sendto(
+                self.fd,
+                packet.as_ptr() as *const libc::c_void,
+                packet.len(),
+                0,
+                &addr as *const _ as *const libc::sockaddr,
+                mem::size_of_val(&addr) as libc::socklen_t,
+            )
+        };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(ret as usize)
+    }
+}
+
+impl Drop for RawSender {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.fd) };
+    }
+}
+
+/// 二层发送错误
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinkError {
+    UnknownInterface(String),
+    /// 接口没有 MAC 地址 (如 tun 等三层接口)
+    NoMacAddress(String),
+    /// 无法创建数据链路层通道
+    Channel(String),
+    /// 下一跳不应答 ARP
+    Unresolved(Ipv4Addr),
+}
+
+impl fmt::Display for LinkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LinkError::UnknownInterface(name) => write!(f, "接口不存在: {}", name),
+            LinkError::NoMacAddress(name) => write!(f, "接口 {} 没有 MAC 地址", name),
+            LinkError::Channel(e) => write!(f, "无法创建数据链路层通道: {}", e),
+            LinkError::Unresolved(addr) => write!(f, "无法解析 {} 的 MAC 地址", addr),
+        }
+    }
+}
+
+impl std::error::Error for LinkError {}
+
+/// 以太网二层发送
+pub struct LinkSender {
+    interface: NetworkInterface,
+    source_mac: MacAddr,
+    tx: Box<dyn DataLinkSender>,
+    rx: Box<dyn DataLinkReceiver>,
+    /// 本进程解析过的邻居
+    neighbors: HashMap<Ipv4Addr, MacAddr>,
+}
+
+impl fmt::Debug for LinkSender {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LinkSender")
+            .field("interface", &self.interface.name)
+            .field("source_mac", &self.source_mac)
+            .finish()
+    }
+}
+
+impl LinkSender {
+    /// 在名为 `name` 的接口上打开以太网通道
+    pub fn new(name: &str) -> Result<Self, LinkError> {
+        let interface = datalink::interfaces()
+            .into_iter()
+            .find(|iface| iface.name == name)
+            .ok_or_else(|| LinkError::UnknownInterface(name.to_string()))?;
+        // 回环接口的 MAC 为全 0，同样按以太网帧收发
+        let source_mac = match interface.mac {
+            Some(mac) => mac,
+            None if interface.is_loopback() => MacAddr::zero(),
+            None => return Err(LinkError::NoMacAddress(name.to_string())),
+        };
+        let config = datalink::Config {
+            read_timeout: Some(Duration::from_millis(50)),
+            ..Default::default()
+        };
+        let (tx, rx) = match datalink::channel(&interface, config) {
+            Ok(Channel::Ethernet(tx, rx)) => (tx, rx),
+            Ok(_) => return Err(LinkError::Channel("不是以太网通道".to_string())),
+            Err(e) => return Err(LinkError::Channel(e.to_string())),
+        };
+        Ok(LinkSender {
+            interface,
+            source_mac,
+            tx,
+            rx,
+            neighbors: HashMap::new(),
+        })
+    }
+
+    pub fn interface(&self) -> &str {
+        &self.interface.name
+    }
+
+    pub fn source_mac(&self) -> MacAddr {
+        self.source_mac
+    }
+
+    /// 解析下一跳的 MAC 地址
+    ///
+    /// 回环接口、广播和组播地址不需要解析；其他地址依次查本进程缓存、内核邻居表，
+    /// 最后以 `source` 为发送方地址发 ARP 请求。
+    pub fn resolve(&mut self, next_hop: Ipv4Addr, source: Ipv4Addr) -> Result<MacAddr, LinkError> {
+        if self.interface.is_loopback() {
+            return Ok(MacAddr::zero());
+        }
+        let subnet_broadcast = self.interface.ips.iter().any(|ip| match ip {
+            IpNetwork::V4(net) => net.prefix() < 31 && net.broadcast() == next_hop,
+            IpNetwork::V6(_) => false,
+        });
+        if next_hop.is_broadcast() || subnet_broadcast {
+            return Ok(MacAddr::broadcast());
+        }
+        if next_hop.is_multicast() {
+            return Ok(neighbor::multicast_mac(next_hop));
+        }
+        if let Some(mac) = self.neighbors.get(&next_hop) {
+            return Ok(*mac);
+        }
+        let mac = match neighbor::lookup(next_hop, &self.interface.name) {
+            Some(mac) => mac,
+            None => self.arp(next_hop, source)?,
+        };
+        self.neighbors.insert(next_hop, mac);
+        Ok(mac)
+    }
+
+    /// 发 ARP 请求并等待应答，最多请求 [`ARP_ATTEMPTS`] 次
+    fn arp(&mut self, target: Ipv4Addr, source: Ipv4Addr) -> Result<MacAddr, LinkError> {
+        let request = neighbor::arp_request(self.source_mac, source, target);
+        for _ in 0..ARP_ATTEMPTS {
+            // 发送失败和超时一样重试
+            let _ = self.send_frame(&request);
+            let deadline = Instant::now() + ARP_REPLY_TIMEOUT;
+            while Instant::now() < deadline {
+                match self.rx.next() {
+                    Ok(frame) => {
+                        if let Some(mac) = neighbor::parse_arp_reply(frame, target) {
+                            return Ok(mac);
+                        }
+                    }
+                    // 读超时，继续等到截止时间
+                    Err(_) => continue,
+                }
+            }
+        }
+        Err(LinkError::Unresolved(target))
+    }
+
+    /// 把 IPv4 数据包封装成以太网帧发往 `destination_mac`
+    ///
+    /// # 返回
+    /// 发送的 IP 包字节数
+    pub fn send(&mut self, packet: &[u8], destination_mac: MacAddr) -> io::Result<usize> {
+        let mut frame = vec![0u8; ETHERNET_HEADER_LEN + packet.len()];
+        let mut ethernet = MutableEthernetPacket::new(&mut frame).unwrap();
+        ethernet.set_destination(destination_mac);
+        ethernet.set_source(self.source_mac);
+        ethernet.set_ethertype(EtherTypes::Ipv4);
+        frame[ETHERNET_HEADER_LEN..].copy_from_slice(packet);
+        self.send_frame(&frame)?;
+        Ok(packet.len())
+    }
+
+    /// 原样发送一个完整的以太网帧
+    pub fn send_frame(&mut self, frame: &[u8]) -> io::Result<usize> {
+        match self.tx.send_to(frame, None) {
+            Some(result) => result.map(|()| frame.len()),
+            None => Err(io::Error::other("数据链路层发送缓冲区不足")),
+        }
+    }
+}
@@ -0,0 +1,328 @@
+//! 标记序号的丢包、重复和乱序检测
+//!
+//! 按流 (源地址、目的地址、tag) 记录收到的最大序号和其后空缺的序号：序号跳跃
+//! 时空缺计为丢失，空缺的序号迟到时改计为乱序，已收到过的序号计为重复。只记住
+//! 最大序号之前 [`REORDER_WINDOW`] 个序号内的空缺，窗口外迟到的包只在还有
+//! 窗口外的空缺未补上时计为乱序，否则计为重复。
+//! 迟到和重复的包发送时间都不晚于最大序号的包，序号回退而发送时间更晚的视为
+//! 发送端重启，从新序号重新开始。序号按 32 位回绕比较。
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::latency::FlowKey;
+
+/// 等待迟到包的序号窗口
+pub const REORDER_WINDOW: u32 = 4096;
+
+/// 一个序号相对于此前收到的序号的情况
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SequenceEvent {
+    /// 流的第一个包
+    First,
+    /// 紧接着最大序号
+    InOrder,
+    /// 跳过了 `missing` 个序号
+    Gap {
+        expected: u32,
+        missing: u32,
+    },
+    /// 补上了之前的空缺，比最大序号落后 `behind`
+    Late {
+        behind: u32,
+    },
+    Duplicate,
+    /// 序号回退但发送时间更晚，此前的最大序号为 `previous`
+    Restart {
+        previous: u32,
+    },
+}
+
+impl SequenceEvent {
+    /// 机器可读格式中的取值
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SequenceEvent::First => "first",
+            SequenceEvent::InOrder => "in_order",
+            SequenceEvent::Gap { .. } => "gap",
+            SequenceEvent::Late { .. } => "late",
+            SequenceEvent::Duplicate => "duplicate",
+            SequenceEvent::Restart { .. } => "restart",
+        }
+    }
+}
+
+impl fmt::Display for SequenceEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SequenceEvent::First => write!(f, "首个"),
+            SequenceEvent::InOrder => write!(f, "按序"),
+            SequenceEvent::Gap { expected, missing } => {
+                write!(f, "丢失 {} 个 (期望 {})", missing, expected)
+            }
+            SequenceEvent::Late { behind } => write!(f, "乱序, 落后 {}", behind),
+            SequenceEvent::Duplicate => write!(f, "重复"),
+            SequenceEvent::Restart { previous } => {
+                write!(f, "发送端重启 (此前最大序号 {})", previous)
+            }
+        }
+    }
+}
+
+/// 单个流的序号统计
+#[derive(Debug, Clone, Default)]
+pub struct SequenceStats {
+    /// 收到的包数，含重复
+    pub received: u64,
+    pub duplicates: u64,
+    /// 迟到后补上空缺的包数
+    pub reordered: u64,
+    /// 仍然空缺的序号数
+    pub lost: u64,
+    /// 序号跳跃的次数
+    pub gaps: u64,
+    /// 单次跳跃最多空缺的序号数
+    pub max_gap: u32,
+    pub restarts: u64,
+    /// 最大序号及其发送时间
+    highest: Option<(u32, u64)>,
+    /// 窗口内空缺的序号
+    missing: HashSet<u32>,
+    /// 已移出窗口、仍计在 `lost` 中的空缺数
+    outside: u64,
+}
+
+impl SequenceStats {
+    /// 记录一个序号
+    ///
+    /// # 参数
+    /// - `timestamp`: 标记中的发送时间，用于区分发送端重启和迟到的包
+    pub fn record(&mut self, sequence: u32, timestamp: u64) -> SequenceEvent {
+        self.received += 1;
+        let Some((highest, highest_time)) = self.highest else {
+            self.highest = Some((sequence, timestamp));
+            return SequenceEvent::First;
+        };
+        let ahead = sequence.wrapping_sub(highest) as i32;
+        if ahead > 0 {
+            let missing = ahead as u32 - 1;
+            self.highest = Some((sequence, timestamp));
+            if missing == 0 {
+                return SequenceEvent::InOrder;
+            }
+            self.lost += missing as u64;
+            self.gaps += 1;
+            self.max_gap = self.max_gap.max(missing);
+            for behind in 1..=missing.min(REORDER_WINDOW) {
+                self.missing.insert(sequence.wrapping_sub(behind));
+            }
+            self.outside += missing.saturating_sub(REORDER_WINDOW) as u64;
+            let before = self.missing.len();
+            self.missing
+                .retain(|&m| sequence.wrapping_sub(m) <= REORDER_WINDOW);
+            self.outside += (before - self.missing.len()) as u64;
+            return SequenceEvent::Gap {
+                expected: highest.wrapping_add(1),
+                missing,
+            };
+        }
+        let behind = ahead.unsigned_abs();
+        if timestamp > highest_time {
+            self.restarts += 1;
+            self.missing.clear();
+            self.outside = 0;
+            self.highest = Some((sequence, timestamp));
+            return SequenceEvent::Restart { previous: highest };
+        }
+        // 窗口外的空缺已不再逐个记录，只要还有未补上的就按补上空缺处理
+        let filled = if behind > REORDER_WINDOW {
+            let filled = self.outside > 0;
+            self.outside = self.outside.saturating_sub(1);
+            filled
+        } else {
+            self.missing.remove(&sequence)
+        };
+        if filled {
+            self.lost = self.lost.saturating_sub(1);
+            self.reordered += 1;
+            SequenceEvent::Late { behind }
+        } else {
+            self.duplicates += 1;
+            SequenceEvent::Duplicate
+        }
+    }
+
+    /// 丢失率：空缺的序号占应收序号 (不重复的包加空缺) 的比例
+    pub fn loss_ratio(&self) -> f64 {
+        let unique = self.received - self.duplicates;
+        let expected = unique + self.lost;
+        if expected == 0 {
+            0.0
+        } else {
+            self.lost as f64 / expected as f64
+        }
+    }
+}
+
+impl fmt::Display for SequenceStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "收到 {} 丢失 {} ({:.2}%, {} 次跳跃, 最多 {}) 乱序 {} 重复 {}",
+            self.received,
+            self.lost,
+            self.loss_ratio() * 100.0,
+            self.gaps,
+            self.max_gap,
+            self.reordered,
+            self.duplicates
+        )?;
+        if self.restarts > 0 {
+            write!(f, " 重启 {}", self.restarts)?;
+        }
+        Ok(())
+    }
+}
+
+/// 按流汇总的序号统计
+#[derive(Debug, Clone, Default)]
+pub struct SequenceTracker {
+    flows: HashMap<FlowKey, SequenceStats>,
+}
+
+impl SequenceTracker {
+    /// 记录一个流的序号
+    pub fn record(&mut self, flow: FlowKey, sequence: u32, timestamp: u64) -> SequenceEvent {
+        self.flows
+            .entry(flow)
+            .or_default()
+            .record(sequence, timestamp)
+    }
+
+    pub fn get(&self, flow: &FlowKey) -> Option<&SequenceStats> {
+        self.flows.get(flow)
+    }
+
+    pub fn flows(&self) -> impl Iterator<Item = (&FlowKey, &SequenceStats)> {
+        self.flows.iter()
+    }
+}
+
+impl fmt::Display for SequenceTracker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (flow, stats) in &self.flows {
+            writeln!(f, "{}: {}", flow, stats)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record_all(stats: &mut SequenceStats, sequences: &[u32]) -> Vec<SequenceEvent> {
+        sequences
+            .iter()
+            .map(|&sequence| stats.record(sequence, 1000))
+            .collect()
+    }
+
+    #[test]
+    fn sequence_wraps_after_u32_max() {
+        let mut stats = SequenceStats::default();
+        assert_eq!(
+            record_all(&mut stats, &[u32::MAX - 1, u32::MAX, 0, 2]),
+            vec![
+                SequenceEvent::First,
+                SequenceEvent::InOrder,
+                SequenceEvent::InOrder,
+                SequenceEvent::Gap {
+                    expected: 1,
+                    missing: 1
+                },
+            ]
+        );
+        assert_eq!(stats.record(1, 1000), SequenceEvent::Late { behind: 1 });
+        assert_eq!(stats.record(u32::MAX, 1000), SequenceEvent::Duplicate);
+        assert_eq!((stats.lost, stats.reordered, stats.duplicates), (0, 1, 1));
+    }
+
+    #[test]
+    fn late_arrival_fills_gap() {
+        let mut stats = SequenceStats::default();
+        record_all(&mut stats, &[1, 2, 5]);
+        assert_eq!((stats.lost, stats.gaps, stats.max_gap), (2, 1, 2));
+        assert_eq!(stats.record(3, 1000), SequenceEvent::Late { behind: 2 });
+        assert_eq!((stats.lost, stats.reordered), (1, 1));
+        assert_eq!(stats.record(3, 1000), SequenceEvent::Duplicate);
+        assert_eq!(stats.record(5, 1000), SequenceEvent::Duplicate);
+        assert_eq!((stats.lost, stats.reordered, stats.duplicates), (1, 1, 2));
+    }
+
+    #[test]
+    fn gap_larger_than_window() {
+        let mut stats = SequenceStats::default();
+        let highest = REORDER_WINDOW + 11;
+        record_all(&mut stats, &[0, highest]);
+        assert_eq!(stats.lost, highest as u64 - 1);
+        assert_eq!(stats.max_gap, highest - 1);
+
+        // 窗口内的空缺逐个记录
+        assert_eq!(
+            stats.record(11, 1000),
+            SequenceEvent::Late {
+                behind: REORDER_WINDOW
+            }
+        );
+        assert_eq!(stats.record(11, 1000), SequenceEvent::Duplicate);
+        // 窗口外只记录数量，10 个空缺补完后再来的按重复计
+        for sequence in 1..=10 {
+            assert!(matches!(
+                stats.record(sequence, 1000),
+                SequenceEvent::Late { .. }
+            ));
+        }
+        assert_eq!(stats.record(0, 1000), SequenceEvent::Duplicate);
+        assert_eq!(stats.lost, highest as u64 - 12);
+        assert_eq!((stats.reordered, stats.duplicates), (11, 2));
+    }
+
+    #[test]
+    fn stale_replay_does_not_hide_loss() {
+        let mut stats = SequenceStats::default();
+        let sequences: Vec<u32> = (0..=REORDER_WINDOW + 10).collect();
+        record_all(&mut stats, &sequences);
+        record_all(&mut stats, &[REORDER_WINDOW + 12]);
+        assert_eq!(stats.lost, 1);
+        assert_eq!(stats.record(0, 1000), SequenceEvent::Duplicate);
+        assert_eq!((stats.lost, stats.reordered, stats.duplicates), (1, 0, 1));
+    }
+
+    #[test]
+    fn restart_when_sequence_goes_back_with_later_timestamp() {
+        let mut stats = SequenceStats::default();
+        stats.record(100, 1000);
+        stats.record(103, 1001);
+        // 发送时间不晚于最大序号的是迟到包
+        assert_eq!(stats.record(101, 1000), SequenceEvent::Late { behind: 2 });
+        assert_eq!(
+            stats.record(5, 2000),
+            SequenceEvent::Restart { previous: 103 }
+        );
+        assert_eq!(stats.record(6, 2001), SequenceEvent::InOrder);
+        // 重启前的空缺仍计为丢失
+        assert_eq!((stats.restarts, stats.lost, stats.reordered), (1, 1, 1));
+    }
+
+    #[test]
+    fn loss_ratio() {
+        let mut stats = SequenceStats::default();
+        assert_eq!(stats.loss_ratio(), 0.0);
+        record_all(&mut stats, &[1, 2, 2, 5]);
+        // 不重复的 3 个加空缺 2 个
+        assert_eq!(stats.loss_ratio(), 0.4);
+        stats.record(4, 1000);
+        assert_eq!(stats.loss_ratio(), 0.2);
+    }
+}
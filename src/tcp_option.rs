@@ -0,0 +1,457 @@
+//! TCP 选项编解码 (RFC 793 / 7323 / 2018 / 7413 / 8684 / 6994)
+//!
+//! 解析是宽松的：遇到长度非法的选项时记录错误并尽量继续，而不是 panic。
+
+use std::fmt;
+
+use crate::builder::{TCP_HEADER_LEN, TCP_MAX_OPTIONS_LEN};
+use crate::hex;
+
+pub const KIND_EOL: u8 = 0;
+pub const KIND_NOP: u8 = 1;
+pub const KIND_MSS: u8 = 2;
+pub const KIND_WINDOW_SCALE: u8 = 3;
+pub const KIND_SACK_PERMITTED: u8 = 4;
+pub const KIND_SACK: u8 = 5;
+pub const KIND_TIMESTAMPS: u8 = 8;
+pub const KIND_MPTCP: u8 = 30;
+pub const KIND_FAST_OPEN: u8 = 34;
+/// RFC 6994 实验选项
+pub const KIND_EXPERIMENTAL_1: u8 = 253;
+pub const KIND_EXPERIMENTAL_2: u8 = 254;
+
+/// 单个选项数据部分的最大长度 (长度字节最大 255，包括类型和长度两个字节)
+const MAX_BODY_LEN: usize = 253;
+
+/// TCP 选项
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TcpOption {
+    EndOfList,
+    Nop,
+    Mss(u16),
+    WindowScale(u8),
+    SackPermitted,
+    /// SACK 块 (左边界, 右边界)
+    Sack(Vec<(u32, u32)>),
+    Timestamps {
+        value: u32,
+        echo_reply: u32,
+    },
+    /// TFO cookie，空 cookie 表示请求
+    FastOpen(Vec<u8>),
+    /// MPTCP 选项，不解析子类型
+    Mptcp(Vec<u8>),
+    /// RFC 6994 实验选项 (kind 253 / 254)，带 16 位 ExID
+    Experimental {
+        kind: u8,
+        exid: u16,
+        data: Vec<u8>,
+    },
+    Unknown {
+        kind: u8,
+        data: Vec<u8>,
+    },
+}
+
+/// 选项编解码错误
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TcpOptionError {
+    /// 选项在 `offset` 处被截断
+    Truncated { offset: usize, kind: u8 },
+    /// 长度字段非法 (小于 2、超出剩余字节或与选项类型不符)
+    BadLength { offset: usize, kind: u8, length: u8 },
+    /// 序列化后 (补齐前) 超过 40 字节，或单个选项超出长度字节的范围
+    TooLong(usize),
+}
+
+impl fmt::Display for TcpOptionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TcpOptionError::Truncated { offset, kind } => {
+                write!(f, "偏移 {} 处的 TCP 选项 {} 被截断", offset, kind)
+            }
+            TcpOptionError::BadLength {
+                offset,
+                kind,
+                length,
+            } => write!(
+                f,
+                "偏移 {} 处的 TCP 选项 {} 长度字段非法: {}",
+                offset, kind, length
+            ),
+            TcpOptionError::TooLong(len) => write!(
+                f,
+                "TCP 选项长度 {} 字节, 超过上限 {} 字节",
+                len, TCP_MAX_OPTIONS_LEN
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TcpOptionError {}
+
+impl TcpOption {
+    pub fn kind(&self) -> u8 {
+        match self {
+            TcpOption::EndOfList => KIND_EOL,
+            TcpOption::Nop => KIND_NOP,
+            TcpOption::Mss(_) => KIND_MSS,
+            TcpOption::WindowScale(_) => KIND_WINDOW_SCALE,
+            TcpOption::SackPermitted => KIND_SACK_PERMITTED,
+            TcpOption::Sack(_) => KIND_SACK,
+            TcpOption::Timestamps { .. } => KIND_TIMESTAMPS,
+            TcpOption::FastOpen(_) => KIND_FAST_OPEN,
+            TcpOption::Mptcp(_) => KIND_MPTCP,
+            TcpOption::Experimental { kind, .. } | TcpOption::Unknown { kind, .. } => *kind,
+        }
+    }
+
+    /// 将单个选项追加到 `out`
+    ///
+    /// # 返回
+    /// 数据部分超过 253 字节、长度字节放不下时返回 `TooLong`，`out` 不变
+    pub fn write_to(&self, out: &mut Vec<u8>) -> Result<(), TcpOptionError> {
+        let kind = self.kind();
+        let mut body = Vec::new();
+        match self {
+            TcpOption::EndOfList | TcpOption::Nop => {
+                out.push(kind);
+                return Ok(());
+            }
+            TcpOption::Mss(mss) => body.extend_from_slice(&mss.to_be_bytes()),
+            TcpOption::WindowScale(shift) => body.push(*shift),
+            TcpOption::SackPermitted => {}
+            TcpOption::Sack(blocks) => {
+                for (left, right) in blocks {
+                    body.extend_from_slice(&left.to_be_bytes());
+                    body.extend_from_slice(&right.to_be_bytes());
+                }
+            }
+            TcpOption::Timestamps { value, echo_reply } => {
+                body.extend_from_slice(&value.to_be_bytes());
+                body.extend_from_slice(&echo_reply.to_be_bytes());
+            }
+            TcpOption::FastOpen(data) | TcpOption::Mptcp(data) => body.extend_from_slice(data),
+            TcpOption::Experimental { exid, data, .. } => {
+                body.extend_from_slice(&exid.to_be_bytes());
+                body.extend_from_slice(data);
+            }
+            TcpOption::Unknown { data, .. } => body.extend_from_slice(data),
+        }
+        if body.len() > MAX_BODY_LEN {
+            return Err(TcpOptionError::TooLong(body.len() + 2));
+        }
+
+        out.push(kind);
+        out.push((body.len() + 2) as u8);
+        out.extend_from_slice(&body);
+        Ok(())
+    }
+}
+
+impl fmt::Display for TcpOption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TcpOption::EndOfList => write!(f, "EOL"),
+            TcpOption::Nop => write!(f, "NOP"),
+            TcpOption::Mss(mss) => write!(f, "MSS({})", mss),
+            TcpOption::WindowScale(shift) => write!(f, "WS({})", shift),
+            TcpOption::SackPermitted => write!(f, "SACK_PERM"),
+            TcpOption::Sack(blocks) => {
+                let blocks: Vec<String> = blocks
+                    .iter()
+                    .map(|(left, right)| format!("{}-{}", left, right))
+                    .collect();
+                write!(f, "SACK([{}])", blocks.join(", "))
+            }
+            TcpOption::Timestamps { value, echo_reply } => {
+                write!(f, "TS(val={}, ecr={})", value, echo_reply)
+            }
+            TcpOption::FastOpen(cookie) => write!(f, "TFO({})", hex::encode(cookie)),
+            TcpOption::Mptcp(data) => write!(f, "MPTCP({})", hex::encode(data)),
+            TcpOption::Experimental { kind, exid, data } => {
+                write!(
+                    f,
+                    "EXP{}(exid=0x{:04x}, data={})",
+                    kind,
+                    exid,
+                    hex::encode(data)
+                )
+            }
+            TcpOption::Unknown { kind, data } => {
+                write!(f, "Unknown({}, data={})", kind, hex::encode(data))
+            }
+        }
+    }
+}
+
+/// 宽松解析的结果：成功解析的选项以及遇到的错误
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ParsedTcpOptions {
+    pub options: Vec<TcpOption>,
+    pub errors: Vec<TcpOptionError>,
+}
+
+impl ParsedTcpOptions {
+    pub fn is_ok(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+impl fmt::Display for ParsedTcpOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let options: Vec<String> = self.options.iter().map(|o| o.to_string()).collect();
+        write!(f, "{}", options.join(" "))?;
+        for error in &self.errors {
+            write!(f, " <{}>", error)?;
+        }
+        Ok(())
+    }
+}
+
+/// 序列化选项列表
+///
+/// # 返回
+/// 以 NOP 补齐到 4 字节倍数的选项字节；补齐前超过 40 字节时返回错误
+pub fn serialize_options(options: &[TcpOption]) -> Result<Vec<u8>, TcpOptionError> {
+    let mut out = Vec::new();
+    for option in options {
+        option.write_to(&mut out)?;
+    }
+    if out.len() > TCP_MAX_OPTIONS_LEN {
+        return Err(TcpOptionError::TooLong(out.len()));
+    }
+    while !out.len().is_multiple_of(4) {
+        out.push(KIND_NOP);
+    }
+    Ok(out)
+}
+
+/// 根据 (已补齐的) 选项长度计算 data offset，单位为 32 位字
+pub fn data_offset(options_len: usize) -> u8 {
+    (TCP_HEADER_LEN + options_len).div_ceil(4) as u8
+}
+
+/// 宽松解析 TCP 头部中的选项字节
+///
+/// 长度字段越界时停止解析；长度在界内但与选项类型不符时记录错误并跳过该选项。
+pub fn parse_options(bytes: &[u8]) -> ParsedTcpOptions {
+    let mut parsed = ParsedTcpOptions::default();
+    let mut offset = 0;
+
+    while offset < bytes.len() {
+        let kind = bytes[offset];
+        match kind {
+            KIND_EOL => {
+                parsed.options.push(TcpOption::EndOfList);
+                break;
+            }
+            KIND_NOP => {
+                parsed.options.push(TcpOption::Nop);
+                offset += 1;
+                continue;
+            }
+            _ => {}
+        }
+
+        if offset + 1 >= bytes.len() {
+            parsed
+                .errors
+                .push(TcpOptionError::Truncated { offset, kind });
+            break;
+        }
+        let length = bytes[offset + 1];
+        let bad_length = TcpOptionError::BadLength {
+            offset,
+            kind,
+            length,
+        };
+        if length < 2 || offset + length as usize > bytes.len() {
+            parsed.errors.push(bad_length);
+            break;
+        }
+        let body = &bytes[offset + 2..offset + length as usize];
+        offset += length as usize;
+
+        let option = match kind {
+            KIND_MSS if body.len() == 2 => TcpOption::Mss(u16::from_be_bytes([body[0], body[1]])),
+            KIND_WINDOW_SCALE if body.len() == 1 => TcpOption::WindowScale(body[0]),
+            KIND_SACK_PERMITTED if body.is_empty() => TcpOption::SackPermitted,
+            KIND_SACK if !body.is_empty() && body.len().is_multiple_of(8) => TcpOption::Sack(
+                body.chunks(8)
+                    .map(|chunk| (read_u32(&chunk[..4]), read_u32(&chunk[4..])))
+                    .collect(),
+            ),
+            KIND_TIMESTAMPS if body.len() == 8 => TcpOption::Timestamps {
+                value: read_u32(&body[..4]),
+                echo_reply: read_u32(&body[4..]),
+            },
+            KIND_FAST_OPEN => TcpOption::FastOpen(body.to_vec()),
+            KIND_MPTCP => TcpOption::Mptcp(body.to_vec()),
+            KIND_EXPERIMENTAL_1 | KIND_EXPERIMENTAL_2 if body.len() >= 2 => {
+                TcpOption::Experimental {
+                    kind,
+                    exid: u16::from_be_bytes([body[0], body[1]]),
+                    data: body[2..].to_vec(),
+                }
+            }
+            KIND_MSS | KIND_WINDOW_SCALE | KIND_SACK_PERMITTED | KIND_SACK | KIND_TIMESTAMPS
+            | KIND_EXPERIMENTAL_1 | KIND_EXPERIMENTAL_2 => {
+                parsed.errors.push(bad_length);
+                continue;
+            }
+            _ => TcpOption::Unknown {
+                kind,
+                data: body.to_vec(),
+            },
+        };
+        parsed.options.push(option);
+    }
+
+    parsed
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serialize_parse_round_trip() {
+        let options = vec![
+            TcpOption::Mss(1460),
+            TcpOption::SackPermitted,
+            TcpOption::Timestamps {
+                value: 1,
+                echo_reply: 2,
+            },
+            TcpOption::Nop,
+            TcpOption::WindowScale(7),
+            TcpOption::Experimental {
+                kind: KIND_EXPERIMENTAL_1,
+                exid: 0xbeef,
+                data: vec![0xaa],
+            },
+        ];
+        // 4 + 2 + 10 + 1 + 3 + 5 = 25 字节，补齐到 28
+        let bytes = serialize_options(&options).unwrap();
+        assert_eq!(bytes.len(), 28);
+        assert_eq!(&bytes[..4], &[KIND_MSS, 4, 0x05, 0xb4]);
+        assert_eq!(data_offset(bytes.len()), 12);
+        let parsed = parse_options(&bytes);
+        assert!(parsed.is_ok(), "{}", parsed);
+        let mut expected = options.clone();
+        expected.extend(vec![TcpOption::Nop; 3]);
+        assert_eq!(parsed.options, expected);
+
+        let others = vec![
+            TcpOption::Sack(vec![(1, 2), (3, 4)]),
+            TcpOption::FastOpen(Vec::new()),
+            TcpOption::Mptcp(vec![0x00, 0x81]),
+            TcpOption::Unknown {
+                kind: 99,
+                data: vec![1, 2, 3],
+            },
+        ];
+        for option in others {
+            let bytes = serialize_options(std::slice::from_ref(&option)).unwrap();
+            assert_eq!(parse_options(&bytes).options[0], option, "{}", option);
+        }
+    }
+
+    #[test]
+    fn too_long_options_are_rejected() {
+        let sack = |blocks| TcpOption::Sack(vec![(0, 0); blocks]);
+        // 2 + 8 * 4 = 34 字节，补齐到 36
+        assert_eq!(serialize_options(&[sack(4)]).unwrap().len(), 36);
+        // 与 IPv4 选项一样按补齐前的长度判断和报告
+        assert_eq!(
+            serialize_options(&[sack(4), TcpOption::Mss(1460), TcpOption::WindowScale(1)]),
+            Err(TcpOptionError::TooLong(41))
+        );
+
+        // 长度字节放不下时不能回绕成一个短选项
+        let huge = TcpOption::Experimental {
+            kind: KIND_EXPERIMENTAL_2,
+            exid: 0,
+            data: vec![0; 252],
+        };
+        let mut out = Vec::new();
+        assert_eq!(huge.write_to(&mut out), Err(TcpOptionError::TooLong(256)));
+        assert!(out.is_empty());
+        TcpOption::Unknown {
+            kind: 99,
+            data: vec![0; 253],
+        }
+        .write_to(&mut out)
+        .unwrap();
+        assert_eq!(&out[..2], &[99, 255]);
+    }
+
+    #[test]
+    fn malformed_options_are_reported() {
+        // 长度与类型不符的选项跳过，继续解析后面的选项
+        let parsed = parse_options(&[KIND_MSS, 3, 0, KIND_WINDOW_SCALE, 3, 2]);
+        assert_eq!(parsed.options, vec![TcpOption::WindowScale(2)]);
+        assert_eq!(
+            parsed.errors,
+            vec![TcpOptionError::BadLength {
+                offset: 0,
+                kind: KIND_MSS,
+                length: 3,
+            }]
+        );
+
+        // ExID 不完整的实验选项
+        let parsed = parse_options(&[KIND_EXPERIMENTAL_1, 3, 0xbe, KIND_NOP]);
+        assert_eq!(parsed.options, vec![TcpOption::Nop]);
+        assert_eq!(
+            parsed.errors,
+            vec![TcpOptionError::BadLength {
+                offset: 0,
+                kind: KIND_EXPERIMENTAL_1,
+                length: 3,
+            }]
+        );
+
+        // 长度越界或小于 2 时停止解析
+        let cases: &[(&[u8], TcpOptionError)] = &[
+            (
+                &[KIND_NOP, KIND_TIMESTAMPS, 10, 0, 0],
+                TcpOptionError::BadLength {
+                    offset: 1,
+                    kind: KIND_TIMESTAMPS,
+                    length: 10,
+                },
+            ),
+            (
+                &[99, 1, KIND_NOP],
+                TcpOptionError::BadLength {
+                    offset: 0,
+                    kind: 99,
+                    length: 1,
+                },
+            ),
+            (
+                &[KIND_NOP, KIND_SACK],
+                TcpOptionError::Truncated {
+                    offset: 1,
+                    kind: KIND_SACK,
+                },
+            ),
+        ];
+        for (bytes, error) in cases {
+            let parsed = parse_options(bytes);
+            assert!(!parsed.is_ok());
+            assert_eq!(
+                &parsed.errors,
+                std::slice::from_ref(error),
+                "{:02x?}",
+                bytes
+            );
+        }
+    }
+}
@@ -0,0 +1,137 @@
+//! 发送 / 接收时间戳
+//!
+//! 发送端用微秒级的 Unix 时间填写标记；接收端优先使用内核接收时间戳
+//! (SO_TIMESTAMPNS)，拿不到时退回到用户态的当前时间。
+
+use std::io;
+use std::mem;
+use std::os::fd::RawFd;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// 当前 Unix 时间，单位微秒
+pub fn unix_micros() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_micros() as u64)
+        .unwrap_or_default()
+}
+
+/// 在套接字上开启内核接收时间戳 (SO_TIMESTAMPNS)
+pub fn enable_kernel_timestamps(fd: RawFd) -> io::Result<()> {
+    let on: libc::c_int = 1;
+    let ret = unsafe {
+        libc::setsockopt(
+            fd,
+            libc::SOL_SOCKET,
+            libc::SO_TIMESTAMPNS,
+            &on as *const _ as *const libc::c_void,
+            mem::size_of_val(&on) as libc::socklen_t,
+        )
+    };
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// 带内核时间戳的 recvmsg
+///
+/// # 参数
+/// - `fd`: 已调用 [`enable_kernel_timestamps`] 的套接字
+/// - `buf`: 接收缓冲区
+/// - `addr`: 接收对端地址的结构体，必须是 libc 的 sockaddr_* 类型
+///
+/// # 返回
+/// (收到的字节数, 内核接收时间 (Unix 微秒))；内核没有附带时间戳时为 `None`
+pub fn recv_timestamped<A>(
+    fd: RawFd,
+    buf: &mut [u8],
+    addr: &mut A,
+) -> io::Result<(usize, Option<u64>)> {
+    let mut iov = libc::iovec {
+        iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+        iov_len: buf.len(),
+    };
+    // 足够容纳一个 timespec 控制消息
+    let mut control = [0u64; 8];
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    msg.msg_name = addr as *mut A as *mut libc::c_void;
+    msg.msg_namelen = mem::size_of::<A>() as libc::socklen_t;
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+    msg.msg_controllen = mem::size_of_val(&control) as _;
+
+    let amt = unsafe { libc::recvmsg(fd, &mut msg, 0) };
+    if amt < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let mut kernel_time = None;
+    unsafe {
+        let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+        while !cmsg.is_null() {
+            if (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == libc::SCM_TIMESTAMPNS
+            {
+                let ts: libc::timespec =
+                    std::ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const _);
+                kernel_time = Some(ts.tv_sec as u64 * 1_000_000 + ts.tv_nsec as u64 / 1_000);
+            }
+            cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+        }
+    }
+
+    Ok((amt as usize, kernel_time))
+}
+
+/// 把 Unix 微秒格式化为 RFC 3339 的 UTC 时间，如 `2024-05-01T08:30:00.123456Z`
+pub fn format_utc(micros: u64) -> String {
+    let seconds = micros / 1_000_000;
+    let days = (seconds / 86_400) as i64;
+    let time = seconds % 86_400;
+
+    // 由 1970-01-01 起的天数换算公历日期 (Howard Hinnant 的 civil_from_days)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:06}Z",
+        year,
+        month,
+        day,
+        time / 3_600,
+        time % 3_600 / 60,
+        time % 60,
+        micros % 1_000_000
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_utc_known_answers() {
+        let cases = [
+            (0, "1970-01-01T00:00:00.000000Z"),
+            // 闰日
+            (951_782_400_000_000, "2000-02-29T00:00:00.000000Z"),
+            (1_735_689_599_999_999, "2024-12-31T23:59:59.999999Z"),
+            // 32 位 time_t 溢出之后
+            (2_147_483_648_000_001, "2038-01-19T03:14:08.000001Z"),
+            // 2100 年不是闰年
+            (4_107_542_400_000_000, "2100-03-01T00:00:00.000000Z"),
+        ];
+        for (micros, expected) in cases {
+            assert_eq!(format_utc(micros), expected, "{}", micros);
+        }
+    }
+}
@@ -0,0 +1,435 @@
+//! 接收端校验和验证
+//!
+//! 对收到的 IPv4 数据报验证 IP 头部校验和以及 UDP / TCP / ICMP 校验和，
+//! 并累计统计，用于判断构造的报文是否完整到达、是否被中间设备改写。
+
+use std::fmt;
+use std::net::IpAddr;
+
+use pnet::packet::ip::{IpNextHeaderProtocol, IpNextHeaderProtocols};
+
+use crate::checksum;
+
+/// 单个校验和的验证结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChecksumStatus {
+    Valid,
+    Invalid {
+        expected: u16,
+        actual: u16,
+    },
+    /// UDP 校验和为 0，发送方未计算
+    Absent,
+    /// 字段中只有伪首部累加值，多半是本机发出、由网卡卸载计算的报文
+    PartialOffload,
+    /// 分片或被截断的报文，无法验证
+    Unverifiable,
+}
+
+impl ChecksumStatus {
+    pub fn is_valid(&self) -> bool {
+        matches!(self, ChecksumStatus::Valid)
+    }
+
+    /// 简短的英文标识，用于日志或机器可读输出
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChecksumStatus::Valid => "valid",
+            ChecksumStatus::Invalid { .. } => "invalid",
+            ChecksumStatus::Absent => "absent",
+            ChecksumStatus::PartialOffload => "partial_offload",
+            ChecksumStatus::Unverifiable => "unverifiable",
+        }
+    }
+}
+
+impl fmt::Display for ChecksumStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChecksumStatus::Valid => write!(f, "正确"),
+            ChecksumStatus::Invalid { expected, actual } => {
+                write!(f, "错误 (期望 0x{:04x}, 实际 0x{:04x})", expected, actual)
+            }
+            ChecksumStatus::Absent => write!(f, "未计算"),
+            ChecksumStatus::PartialOffload => write!(f, "疑似网卡卸载 (部分校验和)"),
+            ChecksumStatus::Unverifiable => write!(f, "无法验证"),
+        }
+    }
+}
+
+/// 一个 IPv4 数据报的校验和验证结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChecksumReport {
+    pub ip: ChecksumStatus,
+    /// 上层协议及其校验和状态；不认识的协议为 `None`
+    pub transport: Option<(IpNextHeaderProtocol, ChecksumStatus)>,
+}
+
+impl ChecksumReport {
+    pub fn all_valid(&self) -> bool {
+        self.ip.is_valid()
+            && self.transport.is_none_or(|(_, status)| {
+                matches!(status, ChecksumStatus::Valid | ChecksumStatus::Absent)
+            })
+    }
+}
+
+impl fmt::Display for ChecksumReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "IP 校验和: {}", self.ip)?;
+        if let Some((protocol, status)) = self.transport {
+            let name = match protocol {
+                IpNextHeaderProtocols::Udp => "UDP".to_string(),
+                IpNextHeaderProtocols::Tcp => "TCP".to_string(),
+                IpNextHeaderProtocols::Icmp => "ICMP".to_string(),
+                other => format!("协议 {}", other.0),
+            };
+            write!(f, ", {} 校验和: {}", name, status)?;
+        }
+        Ok(())
+    }
+}
+
+/// 验证一个完整的 IPv4 数据报
+///
+/// # 参数
+/// - `packet`: 从 IP 头部开始的字节
+///
+/// # 返回
+/// 各层校验和状态；不是合法的 IPv4 头部时返回 `None`
+pub fn validate_ipv4(packet: &[u8]) -> Option<ChecksumReport> {
+    if packet.len() < 20 || packet[0] >> 4 != 4 {
+        return None;
+    }
+    let header_len = (packet[0] & 0x0f) as usize * 4;
+    if header_len < 20 || packet.len() < header_len {
+        return None;
+    }
+
+    let header = &packet[..header_len];
+    let ip = verify(read_u16(header, 10), 10, header, checksum::checksum);
+
+    let protocol = IpNextHeaderProtocol(packet[9]);
+    let total_len = read_u16(packet, 2) as usize;
+    let fragmented = read_u16(packet, 6) & 0x3fff != 0;
+    let truncated = total_len < header_len || total_len > packet.len();
+
+    let offset = match protocol {
+        IpNextHeaderProtocols::Udp => 6,
+        IpNextHeaderProtocols::Tcp => 16,
+        IpNextHeaderProtocols::Icmp => 2,
+        _ => {
+            return Some(ChecksumReport {
+                ip,
+                transport: None,
+            });
+        }
+    };
+    if fragmented || truncated || total_len < header_len + offset + 2 {
+        return Some(ChecksumReport {
+            ip,
+            transport: Some((protocol, ChecksumStatus::Unverifiable)),
+        });
+    }
+
+    let segment = &packet[header_len..total_len];
+    let actual = read_u16(segment, offset);
+    let source = IpAddr::from([packet[12], packet[13], packet[14], packet[15]]);
+    let destination = IpAddr::from([packet[16], packet[17], packet[18], packet[19]]);
+
+    let status = if protocol == IpNextHeaderProtocols::Udp && actual == 0 {
+        ChecksumStatus::Absent
+    } else if protocol == IpNextHeaderProtocols::Icmp {
+        verify(actual, offset, segment, checksum::checksum)
+    } else {
+        let status = verify(actual, offset, segment, |data| {
+            checksum::transport_checksum(source, destination, protocol, data).unwrap_or_default()
+        });
+        match status {
+            ChecksumStatus::Invalid { .. } if is_partial_offload(actual, packet, segment) => {
+                ChecksumStatus::PartialOffload
+            }
+            status => status,
+        }
+    };
+
+    Some(ChecksumReport {
+        ip,
+        transport: Some((protocol, status)),
+    })
+}
+
+/// 将 `data` 中位于 `offset` 的校验和字段置零后重新计算并与 `actual` 比较
+fn verify(
+    actual: u16,
+    offset: usize,
+    data: &[u8],
+    compute: impl Fn(&[u8]) -> u16,
+) -> ChecksumStatus {
+    let mut zeroed = data.to_vec();
+    zeroed[offset] = 0;
+    zeroed[offset + 1] = 0;
+    let expected = compute(&zeroed);
+    if expected == actual {
+        ChecksumStatus::Valid
+    } else {
+        ChecksumStatus::Invalid { expected, actual }
+    }
+}
+
+/// 发送端启用校验和卸载时，抓到的本机报文中只填了伪首部累加值
+fn is_partial_offload(actual: u16, packet: &[u8], segment: &[u8]) -> bool {
+    let source = [packet[12], packet[13], packet[14], packet[15]].into();
+    let destination = [packet[16], packet[17], packet[18], packet[19]].into();
+    let pseudo = checksum::fold(checksum::ipv4_pseudo_header_sum(
+        source,
+        destination,
+        IpNextHeaderProtocol(packet[9]),
+        segment.len() as u16,
+    ));
+    actual == pseudo || actual == !pseudo
+}
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_be_bytes([data[offset], data[offset + 1]])
+}
+
+/// 某一层校验和的累计计数
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ChecksumCounters {
+    pub valid: u64,
+    pub invalid: u64,
+    pub absent: u64,
+    pub partial_offload: u64,
+    pub unverifiable: u64,
+}
+
+impl ChecksumCounters {
+    pub fn record(&mut self, status: ChecksumStatus) {
+        match status {
+            ChecksumStatus::Valid => self.valid += 1,
+            ChecksumStatus::Invalid { .. } => self.invalid += 1,
+            ChecksumStatus::Absent => self.absent += 1,
+            ChecksumStatus::PartialOffload => self.partial_offload += 1,
+            ChecksumStatus::Unverifiable => self.unverifiable += 1,
+        }
+    }
+
+    pub fn total(&self) -> u64 {
+        self.valid + self.invalid + self.absent + self.partial_offload + self.unverifiable
+    }
+}
+
+impl fmt::Display for ChecksumCounters {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "正确 {} / 错误 {}", self.valid, self.invalid)?;
+        if self.absent > 0 {
+            write!(f, " / 未计算 {}", self.absent)?;
+        }
+        if self.partial_offload > 0 {
+            write!(f, " / 卸载 {}", self.partial_offload)?;
+        }
+        if self.unverifiable > 0 {
+            write!(f, " / 无法验证 {}", self.unverifiable)?;
+        }
+        Ok(())
+    }
+}
+
+/// 接收端的累计校验和统计
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChecksumStats {
+    pub packets: u64,
+    pub ip: ChecksumCounters,
+    pub udp: ChecksumCounters,
+    pub tcp: ChecksumCounters,
+    pub icmp: ChecksumCounters,
+}
+
+impl ChecksumStats {
+    pub fn record(&mut self, report: &ChecksumReport) {
+        self.packets += 1;
+        self.ip.record(report.ip);
+        match report.transport {
+            Some((IpNextHeaderProtocols::Udp, status)) => self.udp.record(status),
+            Some((IpNextHeaderProtocols::Tcp, status)) => self.tcp.record(status),
+            Some((IpNextHeaderProtocols::Icmp, status)) => self.icmp.record(status),
+            _ => {}
+        }
+    }
+}
+
+impl fmt::Display for ChecksumStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "共 {} 个包, IP: {}", self.packets, self.ip)?;
+        for (name, counters) in [("UDP", &self.udp), ("TCP", &self.tcp), ("ICMP", &self.icmp)] {
+            if counters.total() > 0 {
+                write!(f, ", {}: {}", name, counters)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::{IcmpLayer, PacketBuilder, TcpLayer, UdpLayer};
+    use std::net::Ipv4Addr;
+
+    const SOURCE: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 1);
+    const DESTINATION: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 2);
+    const UDP: IpNextHeaderProtocol = IpNextHeaderProtocols::Udp;
+    const TCP: IpNextHeaderProtocol = IpNextHeaderProtocols::Tcp;
+    const ICMP: IpNextHeaderProtocol = IpNextHeaderProtocols::Icmp;
+
+    type Transport = Option<(IpNextHeaderProtocol, ChecksumStatus)>;
+
+    fn builder() -> PacketBuilder {
+        PacketBuilder::ipv4(SOURCE, DESTINATION).payload(b"checksum".to_vec())
+    }
+
+    fn udp() -> Vec<u8> {
+        builder().udp(UdpLayer::new(40000, 8001)).build().unwrap()
+    }
+
+    fn tcp() -> Vec<u8> {
+        builder().tcp(TcpLayer::new(40000, 8001)).build().unwrap()
+    }
+
+    fn icmp() -> Vec<u8> {
+        builder().icmp(IcmpLayer::new(8, 0)).build().unwrap()
+    }
+
+    /// 改写 `packet` 中 `at` 处的 16 位字段
+    fn with_u16(mut packet: Vec<u8>, at: usize, value: u16) -> Vec<u8> {
+        packet[at..at + 2].copy_from_slice(&value.to_be_bytes());
+        packet
+    }
+
+    /// 改写字段后重算 IP 首部校验和，只让传输层出错
+    fn with_ip_checksum(packet: Vec<u8>) -> Vec<u8> {
+        let packet = with_u16(packet, 10, 0);
+        let check = checksum::checksum(&packet[..20]);
+        with_u16(packet, 10, check)
+    }
+
+    /// 网卡卸载前 TCP 校验和字段中的伪首部累加值
+    fn offload(packet: Vec<u8>) -> Vec<u8> {
+        let pseudo = checksum::fold(checksum::ipv4_pseudo_header_sum(
+            SOURCE,
+            DESTINATION,
+            TCP,
+            (packet.len() - 20) as u16,
+        ));
+        with_u16(packet, 20 + 16, pseudo)
+    }
+
+    fn invalid(status: ChecksumStatus) -> bool {
+        matches!(status, ChecksumStatus::Invalid { .. })
+    }
+
+    #[test]
+    fn verdicts() {
+        let fragment = {
+            let mut packet = udp();
+            packet[6] |= 0x20;
+            with_ip_checksum(packet)
+        };
+        let bad_udp = with_u16(udp(), 20 + 6, 0x1234);
+        let other_protocol = {
+            let mut packet = udp();
+            packet[9] = 47;
+            with_ip_checksum(packet)
+        };
+        let cases: Vec<(&str, Vec<u8>, Transport)> = vec![
+            ("udp", udp(), Some((UDP, ChecksumStatus::Valid))),
+            ("tcp", tcp(), Some((TCP, ChecksumStatus::Valid))),
+            ("icmp", icmp(), Some((ICMP, ChecksumStatus::Valid))),
+            (
+                "udp 未计算",
+                with_u16(udp(), 20 + 6, 0),
+                Some((UDP, ChecksumStatus::Absent)),
+            ),
+            (
+                "网卡卸载",
+                offload(tcp()),
+                Some((TCP, ChecksumStatus::PartialOffload)),
+            ),
+            ("分片", fragment, Some((UDP, ChecksumStatus::Unverifiable))),
+            (
+                "截断",
+                udp()[..30].to_vec(),
+                Some((UDP, ChecksumStatus::Unverifiable)),
+            ),
+            ("其他协议", other_protocol, None),
+        ];
+        for (name, packet, transport) in cases {
+            let report = validate_ipv4(&packet).unwrap();
+            let ip = ChecksumStatus::Valid;
+            assert_eq!(report, ChecksumReport { ip, transport }, "{}", name);
+        }
+
+        let report = validate_ipv4(&bad_udp).unwrap();
+        assert!(report.ip.is_valid());
+        let (_, status) = report.transport.unwrap();
+        assert_eq!(
+            status,
+            ChecksumStatus::Invalid {
+                expected: u16::from_be_bytes([udp()[26], udp()[27]]),
+                actual: 0x1234
+            }
+        );
+        assert!(!report.all_valid());
+
+        for packet in [
+            with_u16(tcp(), 20 + 16, 0x1234),
+            with_u16(icmp(), 20 + 2, 0x1234),
+        ] {
+            let report = validate_ipv4(&packet).unwrap();
+            assert!(report.ip.is_valid() && invalid(report.transport.unwrap().1));
+        }
+    }
+
+    #[test]
+    fn bad_header_checksum() {
+        let packet = udp();
+        let check = u16::from_be_bytes([packet[10], packet[11]]);
+        let report = validate_ipv4(&with_u16(packet, 10, !check)).unwrap();
+        assert_eq!(
+            report.ip,
+            ChecksumStatus::Invalid {
+                expected: check,
+                actual: !check
+            }
+        );
+        // 首部错误不影响传输层的判断
+        assert_eq!(report.transport, Some((UDP, ChecksumStatus::Valid)));
+        assert!(!report.all_valid());
+    }
+
+    #[test]
+    fn not_ipv4() {
+        let packet = udp();
+        assert_eq!(validate_ipv4(&packet[..19]), None);
+        let mut ipv6 = packet.clone();
+        ipv6[0] = 0x65;
+        assert_eq!(validate_ipv4(&ipv6), None);
+        let mut long_ihl = packet;
+        long_ihl[0] = 0x4f;
+        assert_eq!(validate_ipv4(&long_ihl), None);
+    }
+
+    #[test]
+    fn stats_count_each_layer() {
+        let mut stats = ChecksumStats::default();
+        for packet in [udp(), tcp(), offload(tcp()), with_u16(udp(), 26, 0)] {
+            stats.record(&validate_ipv4(&packet).unwrap());
+        }
+        assert_eq!(stats.packets, 4);
+        assert_eq!(stats.ip.valid, 4);
+        assert_eq!((stats.udp.valid, stats.udp.absent), (1, 1));
+        assert_eq!((stats.tcp.valid, stats.tcp.partial_offload), (1, 1));
+        assert_eq!(stats.icmp.total(), 0);
+    }
+}